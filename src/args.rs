@@ -0,0 +1,426 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{ArgAction, Args, Parser, Subcommand};
+
+use crate::backend::{BackendKind, Conflict};
+use crate::notify::AttachmentFormat;
+use crate::preserve::Preserve;
+use crate::{
+    CompoundRule, color, completions, datetime, diff, hash, heartbeat, journal, logfile, mhl,
+    normalize, progress, publish, r3d, simulate, spool, tar, watch,
+};
+
+/// Renames .NEV files to .R3D (or vice versa with --invert) within the given
+/// paths, or just the clips given
+///
+/// rename is the default command, so r3dy PATH is r3dy rename PATH. r3dy
+/// watch keeps running and renames files as they appear, like --watch.
+#[derive(Parser)]
+#[command(
+    name = "r3dy",
+    version,
+    args_conflicts_with_subcommands = true,
+    disable_help_subcommand = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub rename: RenameArgs,
+}
+
+#[derive(Subcommand)]
+// Parsed once, so the size of the rename options does not matter.
+#[allow(clippy::large_enum_variant)]
+pub enum Command {
+    /// Rename .NEV files to .R3D (or vice versa with --invert); the default
+    Rename(RenameArgs),
+    /// Keep running and rename files as they appear, like rename --watch
+    Watch(RenameArgs),
+    /// Write the matching clips into a tar archive under their converted names
+    ///
+    /// Writes the matching clips into a tar archive under their converted
+    /// names, leaving the source tree untouched.
+    Archive(tar::ArchiveArgs),
+    /// Check a renamed tree against an MHL from the original offload
+    ///
+    /// Checks a renamed tree against an MHL from the original offload (MHL
+    /// 1.x or ASC MHL, MD5 or XXH64). Listed .NEV clips are looked up under
+    /// their .R3D name (the reverse with --invert, or the --from clips under
+    /// their --to name); other files are checked as listed.
+    ///
+    /// The tree defaults to the MHL's folder, or for an ASC MHL the folder
+    /// above its ascmhl directory.
+    Verify(mhl::VerifyArgs),
+    /// Print the metadata in the header of every R3D clip
+    ///
+    /// Prints the metadata in the header of every R3D clip (.R3D or .NEV)
+    /// under path, or of the given file: clip name, reel, resolution and
+    /// frame rate. A spanned clip is read from its first segment. Files
+    /// without a RED header are listed as such and make the command fail.
+    Inspect(r3d::InspectArgs),
+    /// Compare saved r3dy reports
+    #[command(disable_help_subcommand = true)]
+    Report {
+        #[command(subcommand)]
+        command: diff::ReportCommand,
+    },
+    /// Reverse the renames of an earlier run
+    ///
+    /// Reverses the renames of an earlier run, newest first, using the
+    /// journal every run writes. For a --copy run, the copies are removed
+    /// instead. Without options, undoes the most recent run that has not
+    /// been undone yet.
+    ///
+    /// Journals are kept in the per-user data directory (R3DY_JOURNAL_DIR
+    /// overrides it). A file that has been renamed again or replaced since
+    /// is left alone.
+    Undo(journal::UndoArgs),
+    /// Continue the most recent run after it was interrupted
+    ///
+    /// Continues the most recent run after it was interrupted, with the
+    /// options it was started with, from the folder it was started in. The
+    /// files its journal shows as done are counted as converted rather than
+    /// renamed or skipped again, and the rest of the run goes into the same
+    /// journal, so r3dy undo reverses all of it.
+    Resume(journal::ResumeArgs),
+    /// Rename just the files that failed in the most recent run again
+    ///
+    /// Renames just the files that failed in the most recent run again, with
+    /// the options it was started with, from the folder it was started in,
+    /// instead of scanning the whole tree. A failed file is retried with the
+    /// rest of its clip. The retry is a run of its own, with its own
+    /// journal, so running r3dy retry again picks up whatever failed this
+    /// time.
+    Retry(journal::RetryArgs),
+    /// Create synthetic RED footage for trying out settings
+    #[command(disable_help_subcommand = true)]
+    Simulate {
+        #[command(subcommand)]
+        command: simulate::SimulateCommand,
+    },
+    /// Print a tab completion script for the shell
+    ///
+    /// Prints a tab completion script for the shell. Commands, flags and the
+    /// values of flags such as --backend are completed; anything else
+    /// completes as a file name.
+    Completions { shell: completions::Shell },
+}
+
+/// The options of `r3dy rename` and `r3dy watch`. Their long flags are also
+/// the keys of the config files.
+#[derive(Args, Default)]
+pub struct RenameArgs {
+    /// Directories to scan, or clips to rename (default: the current directory)
+    #[arg(value_name = "PATH")]
+    pub paths: Vec<PathBuf>,
+    /// Rename .R3D back to .NEV (or swap --from and --to)
+    #[arg(long)]
+    pub invert: bool,
+    /// Rename another extension pair, e.g. --from mxf_tmp --to mxf
+    #[arg(long, value_name = "EXT")]
+    pub from: Option<String>,
+    /// The extension --from files are renamed to
+    #[arg(long, value_name = "EXT")]
+    pub to: Option<String>,
+    /// Mail the run report to ADDR (repeatable)
+    #[arg(long, value_name = "ADDR")]
+    pub email_to: Vec<String>,
+    /// Sender address (default r3dy@localhost)
+    #[arg(long, value_name = "ADDR")]
+    pub email_from: Option<String>,
+    /// SMTP server, e.g. smtps://mail.example.com:465 (default smtp://localhost:25)
+    #[arg(long, value_name = "URL")]
+    pub smtp_url: Option<String>,
+    /// SMTP login; the password is read from R3DY_SMTP_PASSWORD
+    #[arg(long, value_name = "USER")]
+    pub smtp_user: Option<String>,
+    /// Attach the report as csv (default) or json
+    #[arg(long, value_name = "FORMAT", value_parser = one_of(AttachmentFormat::parse, "csv or json"))]
+    pub email_attach: Option<AttachmentFormat>,
+    /// Post the run summary to a Slack incoming webhook; FOLDER=URL only for the files in FOLDER
+    #[arg(long, value_name = "URL")]
+    pub slack_webhook: Vec<String>,
+    /// Post the run summary to a Microsoft Teams incoming webhook, or FOLDER=URL
+    #[arg(long, value_name = "URL")]
+    pub teams_webhook: Vec<String>,
+    /// Publish run and per-file events to sns:<arn>, sqs:<url> or pubsub:<topic>
+    #[arg(long, value_name = "TARGET", value_parser = one_of(publish::Target::parse, "sns:<arn>, sqs:<url> or pubsub:<topic>"))]
+    pub publish: Vec<publish::Target>,
+    /// Write each failure as a JSON line to a file path or fd number
+    #[arg(long, value_name = "DEST")]
+    pub errors_json: Option<String>,
+    /// Write the final JSON summary to an inherited file descriptor
+    #[arg(long, value_name = "FD", conflicts_with = "summary_file")]
+    pub summary_fd: Option<i32>,
+    /// Write the final JSON summary to a file
+    #[arg(long, value_name = "PATH")]
+    pub summary_file: Option<String>,
+    /// Memory budget for file lists and results before spilling to a temp file (default 512M)
+    #[arg(long, value_name = "SIZE", value_parser = one_of(spool::parse_size, "a size such as 256M or 2G"))]
+    pub max_memory: Option<usize>,
+    /// Rename backend: std (default) or uring (Linux only)
+    #[arg(long, value_name = "KIND", value_parser = one_of(BackendKind::parse, "std or uring"))]
+    pub backend: Option<BackendKind>,
+    /// When the new name is taken: skip (default), overwrite, suffix (_1, _2, ...) or fail before renaming anything
+    #[arg(long, value_name = "POLICY", value_parser = one_of(Conflict::parse, "skip, overwrite, suffix or fail"))]
+    pub on_conflict: Option<Conflict>,
+    /// Run N renames at once with the std backend, for network volumes (default 1)
+    #[arg(short, long, value_name = "N", value_parser = above_zero)]
+    pub jobs: Option<usize>,
+    /// Log every filesystem call with its timing to a file path or fd number
+    #[arg(long, value_name = "DEST")]
+    pub trace: Option<String>,
+    /// Also append warnings and failures, with their time, to PATH
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+    /// How much to log: error, warn (default), info or debug; to stderr without --log-file
+    #[arg(long, value_name = "LEVEL", value_parser = one_of(logfile::Level::parse, "error, warn, info or debug"))]
+    pub log_level: Option<logfile::Level>,
+    /// Log lines as text (default) or json
+    #[arg(long, value_name = "FORMAT", value_parser = one_of(logfile::Format::parse, "text or json"))]
+    pub log_format: Option<logfile::Format>,
+    /// Write an XXH64 manifest of the renamed files, hashed during the same pass
+    #[arg(long, value_name = "DEST", conflicts_with = "archive")]
+    pub manifest: Option<String>,
+    /// Rename directory by directory and write a tape-ready manifest with sizes and XXH64 hashes
+    #[arg(long, value_name = "DEST")]
+    pub archive: Option<String>,
+    /// Write an MHL 1.1 file with the XXH64 hash, size and dates of every converted file
+    #[arg(long, value_name = "PATH")]
+    pub mhl: Option<PathBuf>,
+    /// Write the per-file report to PATH (JSON if it ends in .json, CSV otherwise)
+    #[arg(long, value_name = "PATH")]
+    pub report: Option<PathBuf>,
+    /// Write an FCPXML event listing the converted clips for Final Cut Pro
+    #[arg(long, value_name = "PATH")]
+    pub fcpxml: Option<PathBuf>,
+    /// Write a DaVinci Resolve Python script that imports the converted clips
+    #[arg(long, value_name = "PATH")]
+    pub resolve_script: Option<PathBuf>,
+    /// Media Pool bin for --resolve-script, e.g. Dailies/Day01 (default: the folder name)
+    #[arg(long, value_name = "BIN")]
+    pub resolve_bin: Option<String>,
+    /// Write a Premiere Pro metadata CSV (Clip Name, Tape Name, Description, Log Note)
+    #[arg(long, value_name = "PATH")]
+    pub premiere_csv: Option<PathBuf>,
+    /// Names like CLIP.bak.NEV: last (default) matches on the final extension, skip leaves them alone
+    #[arg(long, value_name = "RULE", value_parser = one_of(CompoundRule::parse, "last or skip"))]
+    pub compound: Option<CompoundRule>,
+    /// Only rename files matching GLOB, e.g. 'A00*/**' (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+    /// Leave files matching GLOB alone, e.g. '*_proxy.NEV' (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+    /// Do not scan folders named NAME, e.g. .Trashes or '*_proxy' (repeatable)
+    #[arg(long, value_name = "NAME")]
+    pub exclude_dir: Vec<String>,
+    /// Stop after N renames (finishing the clip in progress), to try settings on part of a volume
+    #[arg(long, value_name = "N", value_parser = above_zero)]
+    pub limit: Option<usize>,
+    /// Scan N levels of folders: 1 is just the files in the path itself
+    #[arg(long, value_name = "N", value_parser = above_zero)]
+    pub max_depth: Option<usize>,
+    /// Rename only the files directly in the path, like --max-depth 1
+    #[arg(long, conflicts_with = "max_depth")]
+    pub no_recursive: bool,
+    /// Also scan folders that symlinks point to, each folder once
+    #[arg(long, conflicts_with = "no_follow_symlinks")]
+    pub follow_symlinks: bool,
+    /// Skip symlinks to files too, with a warning
+    #[arg(long)]
+    pub no_follow_symlinks: bool,
+    /// Also rename dotfiles such as ._CLIP.NEV and scan hidden folders
+    #[arg(long)]
+    pub hidden: bool,
+    /// Scan what .r3dyignore files in the tree say to leave alone
+    #[arg(long)]
+    pub no_ignore: bool,
+    /// Do not scan into other file systems mounted inside the path, such as network shares
+    #[arg(short = 'x', long)]
+    pub one_file_system: bool,
+    /// If the path is missing or empty, wait up to TIME (e.g. 10m) for the volume to mount
+    #[arg(long, value_name = "TIME", value_parser = one_of(datetime::parse_duration, "a time such as 90s, 10m or 2h"))]
+    pub wait_for_path: Option<Duration>,
+    /// When started as root, switch to USER (and GROUP) before touching any files (Unix)
+    #[arg(long, value_name = "USER[:GROUP]")]
+    pub run_as: Option<String>,
+    /// Confine r3dy to the scanned tree and the temp directory with Landlock (Linux)
+    #[arg(long)]
+    pub sandbox: bool,
+    /// While hashing or archiving a large file, log its progress every TIME (e.g. 30s)
+    #[arg(long, value_name = "TIME", value_parser = heartbeat::parse_interval)]
+    pub heartbeat: Option<Duration>,
+    /// Keep every output byte-identical across runs and platforms for the same tree
+    #[arg(long)]
+    pub deterministic: bool,
+    /// Copy each file to its new name and leave the original untouched
+    #[arg(long)]
+    pub copy: bool,
+    /// Hash each file with XXH3 before and after, and fail it on a mismatch
+    #[arg(long)]
+    pub verify: bool,
+    /// The checksum --verify compares: xxh3 (default) or xxh64
+    #[arg(long, value_name = "ALGO", value_parser = one_of(hash::Digest::parse, "xxh3 or xxh64"))]
+    pub verify_hash: Option<hash::Digest>,
+    /// Put the renamed files under DIR, mirroring their folders under the path
+    #[arg(long, value_name = "DIR")]
+    pub dest: Option<PathBuf>,
+    /// Move files to another volume by copying, checking and removing the original
+    #[arg(long)]
+    pub allow_copy: bool,
+    /// Stop copying before the destination has less than SIZE (e.g. 50G) free
+    #[arg(long, value_name = "SIZE", value_parser = size)]
+    pub min_free: Option<u64>,
+    /// Give copies the original's modification, access and creation times
+    #[arg(long)]
+    pub preserve_times: bool,
+    /// What else copies keep: all, none, or perms, xattrs and times separated by commas
+    #[arg(long, value_name = "WHAT", value_parser = one_of(Preserve::parse, "all, none, or perms, xattrs and times separated by commas"))]
+    pub preserve: Option<Preserve>,
+    /// Leave off copies the Zone.Identifier stream Windows marks downloads with
+    #[arg(long)]
+    pub no_zone_identifier: bool,
+    /// Clear the read-only flag of each file to rename it, and set it again after
+    #[arg(long)]
+    pub chmod_writable: bool,
+    /// Wait up to TIME (e.g. 30s) for a file another program has open, instead of skipping it
+    #[arg(long, value_name = "TIME", value_parser = one_of(datetime::parse_duration, "a time such as 30s or 5m"))]
+    pub wait_for_unlock: Option<Duration>,
+    /// Run even if the lock file says another r3dy run is working on the path
+    #[arg(long)]
+    pub force_lock: bool,
+    /// If any file fails, roll back the renames already done and rename nothing
+    #[arg(long)]
+    pub atomic: bool,
+    /// Keep running and rename new files as they appear, until interrupted
+    #[arg(long)]
+    pub watch: bool,
+    /// In watch mode, how long a file must stay unchanged before it is renamed (default 5s); FOLDER=TIME only for the files in FOLDER
+    #[arg(long, value_name = "TIME", value_parser = one_of(watch::Settle::parse, "a time such as 5s or 2m, or FOLDER=TIME"))]
+    pub settle: Vec<watch::Settle>,
+    /// In watch mode, rename the files in FOLDER ahead of others: FOLDER=N, highest N first (default 0)
+    #[arg(long, value_name = "FOLDER=N", value_parser = one_of(watch::Priority::parse, "FOLDER=N, such as cards=10"))]
+    pub priority: Vec<watch::Priority>,
+    /// Treat each .RDC folder as one clip: rename all of its files or none (the folder keeps its name)
+    #[arg(long)]
+    pub clip_folders: bool,
+    /// Move a clip's .RMD, .rtn, .xml and .cube files along with it, e.g. with --dest
+    #[arg(long)]
+    pub sidecars: bool,
+    /// Fail files that do not start with a RED header instead of renaming them
+    #[arg(long)]
+    pub check_headers: bool,
+    /// Name files from their clip metadata, e.g. '{reel}_{clip}_{segment}.R3D'
+    #[arg(long, value_name = "NAME")]
+    pub template: Option<String>,
+    /// Read the metadata of clips whose header r3dy cannot decode with exiftool, if installed
+    #[arg(long)]
+    pub exiftool: bool,
+    /// Write new names composed (nfc, as Windows and Linux do) or decomposed (nfd, as macOS does)
+    #[arg(long, value_name = "FORM", value_parser = one_of(normalize::Form::parse, "nfc or nfd"))]
+    pub normalize: Option<normalize::Form>,
+    /// Replace characters Windows and exFAT refuse in new names (:<>?*|" and trailing dots or spaces)
+    #[arg(long)]
+    pub sanitize: bool,
+    /// Ask y/n/a/q before renaming each clip
+    #[arg(short, long, conflicts_with_all = ["json", "porcelain", "watch"])]
+    pub interactive: bool,
+    /// Full-screen dashboard with folders, failures and keys to pause (p) or abort (q)
+    #[arg(long, conflicts_with_all = ["json", "porcelain", "screen_reader", "interactive", "watch"])]
+    pub tui: bool,
+    /// Also apply the [profile.NAME] settings of config.toml and .r3dy.toml
+    #[arg(long, value_name = "NAME", conflicts_with = "no_config")]
+    pub profile: Option<String>,
+    /// Ignore config.toml and .r3dy.toml
+    #[arg(long)]
+    pub no_config: bool,
+    /// Print the planned renames and conflicts without renaming anything
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Do not record this run for r3dy undo
+    #[arg(long)]
+    pub no_journal: bool,
+    /// Colour warnings, failures and totals: auto (default), always or never
+    #[arg(long, value_name = "WHEN", value_parser = one_of(color::Choice::parse, "auto, always or never"))]
+    pub color: Option<color::Choice>,
+    /// Print only the totals line and errors, with no progress bar
+    #[arg(short, long, conflicts_with_all = ["verbose", "tui", "screen_reader"])]
+    pub quiet: bool,
+    /// Also print every file renamed; -vv adds the debug log on stderr
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+    /// Print one JSON object per event (discovered, renamed, skipped, failed, summary) on stdout
+    #[arg(long, conflicts_with_all = ["porcelain", "screen_reader"])]
+    pub json: bool,
+    /// Print one line per file on stdout for scripts: status, source and target, tab-separated
+    #[arg(long, value_name = "VERSION", num_args = 0..=1, require_equals = true, default_missing_value = "v1", value_parser = ["v1"], conflicts_with = "screen_reader")]
+    pub porcelain: Option<String>,
+    /// Print only the full path of each renamed file on stdout, NUL-terminated, for xargs -0
+    #[arg(long, conflicts_with_all = ["json", "porcelain"])]
+    pub print0: bool,
+    /// Work on the files listed in an MHL, an earlier r3dy report or one path per line (- for stdin) instead of scanning the path
+    #[arg(long, value_name = "LIST")]
+    pub files_from: Option<PathBuf>,
+    /// The --files-from list is NUL-delimited, as from find -print0
+    #[arg(short = '0', long)]
+    pub null: bool,
+    /// Progress bar layout: default, eta, compact, failures, or an indicatif template ({failed} shows failures so far)
+    #[arg(long, value_name = "T")]
+    pub progress_template: Option<String>,
+    /// Redraw the progress bar at most HZ times a second (default 20)
+    #[arg(long, value_name = "HZ")]
+    pub progress_refresh: Option<String>,
+    /// No progress bars; report progress in plain sentences instead
+    #[arg(long)]
+    pub screen_reader: bool,
+    /// How often --screen-reader reports progress (default 15s)
+    #[arg(long, value_name = "TIME", value_parser = progress::parse_status_interval)]
+    pub status_interval: Option<Duration>,
+}
+
+/// The options of `r3dy rename` in `args`, which come from the command
+/// line, the config files or the journal of an earlier run.
+pub fn rename_options(args: &[String]) -> Result<RenameArgs, clap::Error> {
+    let command = ["r3dy", "rename"].into_iter().map(String::from);
+    match Cli::try_parse_from(command.chain(args.iter().cloned()))?.command {
+        Some(Command::Rename(options)) => Ok(options),
+        _ => Err(clap::Error::new(clap::error::ErrorKind::InvalidSubcommand)),
+    }
+}
+
+/// The options of `r3dy archive` in `args`, which come from the command
+/// line and the config files.
+pub fn archive_options(args: &[String]) -> Result<tar::ArchiveArgs, clap::Error> {
+    let command = ["r3dy", "archive"].into_iter().map(String::from);
+    match Cli::try_parse_from(command.chain(args.iter().cloned()))?.command {
+        Some(Command::Archive(options)) => Ok(options),
+        _ => Err(clap::Error::new(clap::error::ErrorKind::InvalidSubcommand)),
+    }
+}
+
+/// A value parser for one of the `parse` functions that return None for
+/// a value they do not know, naming the values it takes.
+pub fn one_of<T: 'static>(
+    parse: fn(&str) -> Option<T>,
+    expected: &'static str,
+) -> impl Fn(&str) -> Result<T, String> + Clone + Send + Sync + 'static {
+    move |value| parse(value).ok_or_else(|| format!("expected {}", expected))
+}
+
+/// A count of 1 or more.
+pub fn above_zero(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err("expected a whole number above 0".to_string()),
+    }
+}
+
+/// A size such as `512M` or `4G`.
+pub fn size(value: &str) -> Result<u64, String> {
+    spool::parse_size(value)
+        .map(|bytes| bytes as u64)
+        .ok_or_else(|| "expected a size such as 512M or 4G".to_string())
+}
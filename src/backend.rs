@@ -0,0 +1,1301 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::hash::Digest;
+use crate::preserve::{self, Preserve};
+use crate::{color, hash, noreplace, readonly, trace};
+
+/// Which filesystem execution engine performs the renames.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    Std,
+    Uring,
+}
+
+impl BackendKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "std" => Some(BackendKind::Std),
+            "uring" | "io_uring" => Some(BackendKind::Uring),
+            _ => None,
+        }
+    }
+}
+
+/// What to do when a file's new name is taken, for `--on-conflict`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Conflict {
+    /// Leave the file alone; the default.
+    Skip,
+    /// Replace the existing file.
+    Overwrite,
+    /// Take the first free name with `_1`, `_2`, ... before the extension.
+    Suffix,
+    /// Fail the file. `r3dy` also checks every target before the first
+    /// rename and stops if any is taken.
+    Fail,
+}
+
+impl Conflict {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "skip" => Some(Conflict::Skip),
+            "overwrite" => Some(Conflict::Overwrite),
+            "suffix" => Some(Conflict::Suffix),
+            "fail" => Some(Conflict::Fail),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Conflict::Skip => "skip",
+            Conflict::Overwrite => "overwrite",
+            Conflict::Suffix => "suffix",
+            Conflict::Fail => "fail",
+        }
+    }
+
+    /// The result for a file whose target was found to exist.
+    fn exists(self) -> RenameResult {
+        match self {
+            Conflict::Fail => RenameResult::Failed(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "target already exists",
+            )),
+            _ => RenameResult::TargetExists,
+        }
+    }
+}
+
+/// The most `_N` suffixes tried for `--on-conflict suffix`.
+const MAX_SUFFIX: usize = 999;
+
+/// `target` with `_n` before its extension: `CLIP.R3D` becomes `CLIP_1.R3D`.
+fn suffixed(target: &Path, n: usize) -> PathBuf {
+    let mut name = target.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("_{}", n));
+    if let Some(extension) = target.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    target.with_file_name(name)
+}
+
+/// The result of asking a backend to rename one file.
+pub enum RenameResult {
+    Renamed,
+    /// Renamed to this suffixed name instead, as the target was taken.
+    Suffixed(PathBuf),
+    /// Moved to this path on another volume by copying and removing the
+    /// original, for `--allow-copy`.
+    Moved(PathBuf),
+    /// Done as in the inner result and checked by `--verify`: the contents
+    /// hashed to this digest before and after.
+    Verified(Box<RenameResult>, Digest, u64),
+    TargetExists,
+    Failed(io::Error),
+}
+
+impl RenameResult {
+    /// Whether the file now has a new name.
+    fn done(&self) -> bool {
+        match self {
+            RenameResult::Verified(done, ..) => done.done(),
+            RenameResult::TargetExists | RenameResult::Failed(_) => false,
+            _ => true,
+        }
+    }
+
+    /// The name the file ended up with, if not the one asked for.
+    fn path(&self) -> Option<&Path> {
+        match self {
+            RenameResult::Suffixed(path) | RenameResult::Moved(path) => Some(path),
+            RenameResult::Verified(done, ..) => done.path(),
+            _ => None,
+        }
+    }
+
+    /// Whether the file was moved to another volume by copying.
+    pub fn moved(&self) -> bool {
+        match self {
+            RenameResult::Moved(_) => true,
+            RenameResult::Verified(done, ..) => done.moved(),
+            _ => false,
+        }
+    }
+}
+
+/// Executes renames, possibly several at a time. Results come back in the
+/// same order as the requests.
+pub trait Backend {
+    /// How many renames the caller should hand over per call.
+    fn batch_size(&self) -> usize;
+
+    fn rename_batch(&mut self, jobs: &[(PathBuf, PathBuf)]) -> Vec<RenameResult>;
+}
+
+/// Opens the backend; `jobs` above 1 runs that many std renames at once.
+/// With `copy`, each file is copied to its new name instead of renamed, and
+/// with `verify` its contents are hashed before and after. `conflict`
+/// decides what happens to a file whose target exists, and `allow_copy`
+/// lets a rename to another volume fall back to `move_by_copy`. `preserve`
+/// says what copies keep of their originals, and `writable` clears the
+/// read-only flag of each file for its rename.
+#[allow(clippy::too_many_arguments)]
+pub fn open(
+    kind: BackendKind,
+    jobs: usize,
+    copy: bool,
+    verify: Option<Digest>,
+    conflict: Conflict,
+    allow_copy: bool,
+    preserve: Preserve,
+    writable: bool,
+) -> Result<Box<dyn Backend>, String> {
+    let operation = Operation {
+        copy,
+        verify,
+        conflict,
+        allow_copy,
+        preserve,
+        writable,
+    };
+    match kind {
+        BackendKind::Std if jobs > 1 => Ok(Box::new(ThreadedBackend { jobs, operation })),
+        BackendKind::Std => Ok(Box::new(StdBackend { operation })),
+        _ if copy => Err("--copy only works with the std backend".to_string()),
+        _ if verify.is_some() => Err("--verify only works with the std backend".to_string()),
+        _ if allow_copy => Err("--allow-copy only works with the std backend".to_string()),
+        _ if writable => Err("--chmod-writable only works with the std backend".to_string()),
+        _ if conflict != Conflict::Skip => Err(format!(
+            "--on-conflict {} only works with the std backend",
+            conflict.as_str()
+        )),
+        #[cfg(target_os = "linux")]
+        BackendKind::Uring => uring::UringBackend::new()
+            .map(|backend| Box::new(backend) as Box<dyn Backend>)
+            .map_err(|err| format!("io_uring is not available: {}", err)),
+        #[cfg(not(target_os = "linux"))]
+        BackendKind::Uring => Err("The uring backend is only available on Linux".to_string()),
+    }
+}
+
+/// Reverses one completed rename (or removes one copy), for rolling back a
+/// partly renamed clip. Like the rename itself, it never replaces a file,
+/// and a file moved to another volume is moved back the same way.
+pub fn revert(source: &Path, target: &Path, copy: bool) -> io::Result<()> {
+    if copy {
+        return trace::call("unlink", target, || fs::remove_file(target));
+    }
+    match noreplace::rename(target, source) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            move_by_copy(target, source, MOVED_BACK)
+        }
+        result => result,
+    }
+}
+
+/// What a file moved back to where it came from keeps, whatever the run was
+/// given: it should come back as it left.
+pub const MOVED_BACK: Preserve = Preserve::ALL;
+
+/// Moves `source` to `target` on another volume, where a rename cannot: it
+/// copies the file without replacing anything, checks the copy's XXH64
+/// against the original's, then removes the original. A copy that fails or
+/// does not match is removed and the original kept.
+pub fn move_by_copy(source: &Path, target: &Path, preserve: Preserve) -> io::Result<()> {
+    copy_new(source, target, preserve)?;
+    finish_move(source, target)
+}
+
+/// Checks the copy `target` against `source` and removes `source`, or
+/// removes the copy.
+fn finish_move(source: &Path, target: &Path) -> io::Result<()> {
+    let checked = hash::xxh64_file(source).and_then(|before| {
+        let after = hash::xxh64_file(target)?;
+        if after == before {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "checksum mismatch copying to another volume: XXH64 {:016x} before, {:016x} after; the copy was removed",
+                before, after
+            )))
+        }
+    });
+    if let Err(err) =
+        checked.and_then(|()| trace::call("unlink", source, || fs::remove_file(source)))
+    {
+        let _ = trace::call("unlink", target, || fs::remove_file(target));
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Predicts what a real run would do without touching the tree, for
+/// `--dry-run`.
+pub fn dry_run(conflict: Conflict) -> Box<dyn Backend> {
+    Box::new(DryRunBackend {
+        conflict,
+        claimed: HashSet::new(),
+    })
+}
+
+/// What the std backends do to each file.
+#[derive(Clone, Copy)]
+struct Operation {
+    copy: bool,
+    verify: Option<Digest>,
+    conflict: Conflict,
+    allow_copy: bool,
+    preserve: Preserve,
+    writable: bool,
+}
+
+impl Operation {
+    fn apply(self, source: &Path, target: &Path) -> RenameResult {
+        if !self.writable {
+            return self.checked(source, target);
+        }
+        let locked = match readonly::unlock(source) {
+            Ok(locked) => locked,
+            Err(err) => return RenameResult::Failed(err),
+        };
+        let result = self.checked(source, target);
+        if let Some(locked) = locked {
+            let now = if result.done() {
+                result.path().unwrap_or(target)
+            } else {
+                source
+            };
+            if let Err(err) = locked.restore(now) {
+                let message = format!("Could not make {} read-only again: {}", now.display(), err);
+                eprintln!("{}", color::warning(&message));
+                tracing::warn!("{}", message);
+            }
+        }
+        result
+    }
+
+    /// Transfers the file, hashing it before and after with `verify`.
+    fn checked(self, source: &Path, target: &Path) -> RenameResult {
+        let Some(digest) = self.verify else {
+            return self.transfer(source, target);
+        };
+
+        let before = match digest.file(source) {
+            Ok(digest) => digest,
+            Err(err) => return RenameResult::Failed(err),
+        };
+        let (done, target) = match self.transfer(source, target) {
+            RenameResult::Renamed => (RenameResult::Renamed, target.to_path_buf()),
+            RenameResult::Suffixed(suffixed) => {
+                (RenameResult::Suffixed(suffixed.clone()), suffixed)
+            }
+            RenameResult::Moved(moved) => (RenameResult::Moved(moved.clone()), moved),
+            other => return other,
+        };
+        let target = target.as_path();
+        match digest.file(target) {
+            Ok(after) if after == before => RenameResult::Verified(Box::new(done), digest, before),
+            Ok(after) => RenameResult::Failed(io::Error::other(format!(
+                "checksum mismatch: {} {:016x} before, {:016x} after{}",
+                digest.name(),
+                before,
+                after,
+                self.undo(source, target)
+            ))),
+            Err(err) => RenameResult::Failed(io::Error::new(
+                err.kind(),
+                format!(
+                    "could not verify {}: {}{}",
+                    target.display(),
+                    err,
+                    self.undo(source, target)
+                ),
+            )),
+        }
+    }
+
+    /// Undoes a transfer that failed `--verify`, so a failed file is where
+    /// it was and nothing needs journaling: a bad copy is removed so the
+    /// next run copies it again, and a renamed file is renamed back. Returns
+    /// what was done, to add to the error.
+    fn undo(self, source: &Path, target: &Path) -> String {
+        if self.copy {
+            return match trace::call("unlink", target, || fs::remove_file(target)) {
+                Ok(()) => "; the copy was removed".to_string(),
+                Err(err) => format!("; the copy could not be removed: {}", err),
+            };
+        }
+        match revert(source, target, false) {
+            Ok(()) => "; it was renamed back".to_string(),
+            Err(err) => format!("; it could not be renamed back: {}", err),
+        }
+    }
+
+    fn transfer(self, source: &Path, target: &Path) -> RenameResult {
+        match self.conflict {
+            Conflict::Overwrite if self.copy => overwrite_copy(source, target, self.preserve),
+            Conflict::Overwrite => match overwrite(source, target) {
+                RenameResult::Failed(err)
+                    if self.allow_copy && err.kind() == io::ErrorKind::CrossesDevices =>
+                {
+                    match overwrite_copy(source, target, self.preserve) {
+                        RenameResult::Renamed => match finish_move(source, target) {
+                            Ok(()) => RenameResult::Moved(target.to_path_buf()),
+                            Err(err) => RenameResult::Failed(err),
+                        },
+                        other => other,
+                    }
+                }
+                other => other,
+            },
+            Conflict::Suffix => {
+                for n in 0..=MAX_SUFFIX {
+                    let candidate = if n == 0 {
+                        target.to_path_buf()
+                    } else {
+                        suffixed(target, n)
+                    };
+                    match self.transfer_new(source, &candidate) {
+                        RenameResult::TargetExists => continue,
+                        RenameResult::Renamed if n > 0 => {
+                            return RenameResult::Suffixed(candidate);
+                        }
+                        other => return other,
+                    }
+                }
+                RenameResult::Failed(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "{} and its _1 to _{} names are all taken",
+                        target.display(),
+                        MAX_SUFFIX
+                    ),
+                ))
+            }
+            Conflict::Skip | Conflict::Fail => match self.transfer_new(source, target) {
+                RenameResult::TargetExists => self.conflict.exists(),
+                other => other,
+            },
+        }
+    }
+
+    /// Renames or copies to `target` only if nothing is there.
+    fn transfer_new(self, source: &Path, target: &Path) -> RenameResult {
+        if self.copy {
+            return copy_one(source, target, self.preserve);
+        }
+        match rename_one(source, target) {
+            RenameResult::Failed(err)
+                if self.allow_copy && err.kind() == io::ErrorKind::CrossesDevices =>
+            {
+                match with_parents(target, || move_by_copy(source, target, self.preserve)) {
+                    Ok(()) => RenameResult::Moved(target.to_path_buf()),
+                    Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                        RenameResult::TargetExists
+                    }
+                    Err(err) => RenameResult::Failed(err),
+                }
+            }
+            other => other,
+        }
+    }
+}
+
+/// One blocking no-clobber rename per file, in order.
+struct StdBackend {
+    operation: Operation,
+}
+
+impl Backend for StdBackend {
+    fn batch_size(&self) -> usize {
+        1
+    }
+
+    fn rename_batch(&mut self, jobs: &[(PathBuf, PathBuf)]) -> Vec<RenameResult> {
+        jobs.iter()
+            .map(|(source, target)| self.operation.apply(source, target))
+            .collect()
+    }
+}
+
+fn rename_one(source: &Path, target: &Path) -> RenameResult {
+    match with_parents(target, || noreplace::rename(source, target)) {
+        Ok(()) => RenameResult::Renamed,
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => RenameResult::TargetExists,
+        Err(err) => RenameResult::Failed(err),
+    }
+}
+
+/// Runs `operation`, and when it fails because the target's folder is
+/// missing (a fresh `--dest` tree), creates the folder and tries once more.
+/// A missing source fails the same way, so the folder is checked first.
+fn with_parents<T>(target: &Path, mut operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    match operation() {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let Some(parent) = target.parent() else {
+                return Err(err);
+            };
+            if trace::call("lstat", parent, || fs::symlink_metadata(parent)).is_ok() {
+                return Err(err);
+            }
+            trace::call("mkdir", parent, || fs::create_dir_all(parent))?;
+            operation()
+        }
+        result => result,
+    }
+}
+
+/// Renames `source` to `target`, replacing whatever is there other than
+/// `source` itself under another case.
+fn overwrite(source: &Path, target: &Path) -> RenameResult {
+    if noreplace::same_file(source, target) {
+        return rename_one(source, target);
+    }
+    match with_parents(target, || {
+        trace::call2("rename", source, Some(target), || {
+            fs::rename(source, target)
+        })
+    }) {
+        Ok(()) => RenameResult::Renamed,
+        Err(err) => RenameResult::Failed(err),
+    }
+}
+
+/// Copies `source` over `target`, truncating an existing file.
+fn overwrite_copy(source: &Path, target: &Path, preserve: Preserve) -> RenameResult {
+    let kept = match trace::call("lstat", source, || fs::symlink_metadata(source))
+        .and_then(|_| preserve::read(source, preserve))
+    {
+        Ok(kept) => kept,
+        Err(err) => return RenameResult::Failed(err),
+    };
+    match with_parents(target, || {
+        trace::call2("copy", source, Some(target), || fs::copy(source, target))
+    })
+    .and_then(|_| {
+        trace::call2("streams", source, Some(target), || {
+            preserve::copy_streams(source, target, preserve)
+        })
+    })
+    .and_then(|_| kept.apply(target))
+    {
+        Ok(()) => RenameResult::Renamed,
+        Err(err) => RenameResult::Failed(err),
+    }
+}
+
+/// Copies `source` to `target`, leaving `source` in place. The target name
+/// is claimed before any data is written, so an existing file is never
+/// replaced, and a partial copy is removed when the copy fails.
+fn copy_one(source: &Path, target: &Path, preserve: Preserve) -> RenameResult {
+    match with_parents(target, || copy_new(source, target, preserve)) {
+        Ok(()) => RenameResult::Renamed,
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => RenameResult::TargetExists,
+        Err(err) => RenameResult::Failed(err),
+    }
+}
+
+fn copy_new(source: &Path, target: &Path, preserve: Preserve) -> io::Result<()> {
+    let kept = preserve::read(source, preserve)?;
+    trace::call("create", target, || File::create_new(target))?;
+    match trace::call2("copy", source, Some(target), || fs::copy(source, target))
+        .and_then(|_| {
+            trace::call2("streams", source, Some(target), || {
+                preserve::copy_streams(source, target, preserve)
+            })
+        })
+        .and_then(|_| kept.apply(target))
+    {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let _ = fs::remove_file(target);
+            Err(err)
+        }
+    }
+}
+
+/// `StdBackend` on a pool of threads, for `--jobs`. On network volumes each
+/// rename is mostly a round trip to the server, so several in flight hide
+/// the latency. Threads take files off the batch one at a time, and the
+/// results are put back in request order.
+struct ThreadedBackend {
+    jobs: usize,
+    operation: Operation,
+}
+
+/// Files handed to each thread per batch, so threads stay busy between
+/// batches without holding back progress updates for long.
+const FILES_PER_JOB: usize = 16;
+
+impl Backend for ThreadedBackend {
+    fn batch_size(&self) -> usize {
+        self.jobs * FILES_PER_JOB
+    }
+
+    fn rename_batch(&mut self, jobs: &[(PathBuf, PathBuf)]) -> Vec<RenameResult> {
+        let next = AtomicUsize::new(0);
+        let mut results: Vec<Option<RenameResult>> = jobs.iter().map(|_| None).collect();
+
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.jobs.min(jobs.len()))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut done = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            let Some((source, target)) = jobs.get(index) else {
+                                return done;
+                            };
+                            done.push((index, self.operation.apply(source, target)));
+                        }
+                    })
+                })
+                .collect();
+
+            for worker in workers {
+                let done = worker.join().unwrap_or_default();
+                for (index, result) in done {
+                    results[index] = Some(result);
+                }
+            }
+        });
+
+        results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| {
+                    RenameResult::Failed(io::Error::other("rename worker panicked"))
+                })
+            })
+            .collect()
+    }
+}
+
+/// Answers each rename the way `StdBackend` would, from the tree as it is
+/// now plus the targets claimed earlier in the run: `CLIP.NEV` and
+/// `CLIP.nev` both want `CLIP.R3D`, and only the first would get it.
+struct DryRunBackend {
+    conflict: Conflict,
+    claimed: HashSet<PathBuf>,
+}
+
+impl DryRunBackend {
+    fn taken(&self, source: &Path, target: &Path) -> bool {
+        self.claimed.contains(target) || noreplace::taken(source, target)
+    }
+}
+
+impl Backend for DryRunBackend {
+    fn batch_size(&self) -> usize {
+        1
+    }
+
+    fn rename_batch(&mut self, jobs: &[(PathBuf, PathBuf)]) -> Vec<RenameResult> {
+        jobs.iter()
+            .map(|(source, target)| {
+                if let Err(err) = trace::call("lstat", source, || fs::symlink_metadata(source)) {
+                    return RenameResult::Failed(err);
+                }
+                if !self.taken(source, target) || self.conflict == Conflict::Overwrite {
+                    self.claimed.insert(target.clone());
+                    return RenameResult::Renamed;
+                }
+                if self.conflict != Conflict::Suffix {
+                    return self.conflict.exists();
+                }
+                match (1..=MAX_SUFFIX)
+                    .map(|n| suffixed(target, n))
+                    .find(|candidate| !self.taken(source, candidate))
+                {
+                    Some(candidate) => {
+                        self.claimed.insert(candidate.clone());
+                        RenameResult::Suffixed(candidate)
+                    }
+                    None => RenameResult::Failed(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!(
+                            "{} and its _1 to _{} names are all taken",
+                            target.display(),
+                            MAX_SUFFIX
+                        ),
+                    )),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod uring {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::ptr;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Instant;
+
+    use super::{Backend, RenameResult};
+    use crate::{noreplace, trace};
+
+    const ENTRIES: u32 = 256;
+
+    const IORING_OP_STATX: u8 = 21;
+    const IORING_OP_RENAMEAT: u8 = 35;
+    const IORING_ENTER_GETEVENTS: u32 = 1;
+    const IORING_FEAT_SINGLE_MMAP: u32 = 1;
+    const IORING_OFF_SQ_RING: i64 = 0;
+    const IORING_OFF_CQ_RING: i64 = 0x800_0000;
+    const IORING_OFF_SQES: i64 = 0x1000_0000;
+    const RENAME_NOREPLACE: u32 = 1;
+    const STATX_TYPE: u32 = 1;
+    const STATX_BUFFER_SIZE: usize = 256;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct SqringOffsets {
+        head: u32,
+        tail: u32,
+        ring_mask: u32,
+        ring_entries: u32,
+        flags: u32,
+        dropped: u32,
+        array: u32,
+        resv1: u32,
+        user_addr: u64,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct CqringOffsets {
+        head: u32,
+        tail: u32,
+        ring_mask: u32,
+        ring_entries: u32,
+        overflow: u32,
+        cqes: u32,
+        flags: u32,
+        resv1: u32,
+        user_addr: u64,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Params {
+        sq_entries: u32,
+        cq_entries: u32,
+        flags: u32,
+        sq_thread_cpu: u32,
+        sq_thread_idle: u32,
+        features: u32,
+        wq_fd: u32,
+        resv: [u32; 3],
+        sq_off: SqringOffsets,
+        cq_off: CqringOffsets,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct Sqe {
+        opcode: u8,
+        flags: u8,
+        ioprio: u16,
+        fd: i32,
+        off: u64,
+        addr: u64,
+        len: u32,
+        op_flags: u32,
+        user_data: u64,
+        buf_index: u16,
+        personality: u16,
+        splice_fd_in: i32,
+        addr3: u64,
+        pad: u64,
+    }
+
+    #[repr(C)]
+    struct Cqe {
+        user_data: u64,
+        res: i32,
+        flags: u32,
+    }
+
+    struct Mapping {
+        ptr: *mut libc::c_void,
+        len: usize,
+    }
+
+    impl Mapping {
+        fn new(fd: i32, len: usize, offset: i64) -> io::Result<Self> {
+            // SAFETY: maps a region the kernel set up for this ring fd.
+            let ptr = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED | libc::MAP_POPULATE,
+                    fd,
+                    offset,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { ptr, len })
+        }
+
+        /// # Safety
+        /// `offset` must be a kernel-reported offset inside this mapping.
+        unsafe fn at<T>(&self, offset: u32) -> *mut T {
+            unsafe { self.ptr.cast::<u8>().add(offset as usize).cast() }
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            // SAFETY: ptr/len came from a successful mmap.
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+
+    /// A minimal io_uring instance that batches statx and renameat requests.
+    pub struct UringBackend {
+        // Declared before `fd` so the mappings are released first.
+        sq_ring: Mapping,
+        cq_ring: Option<Mapping>,
+        sqes: Mapping,
+        params: Params,
+        fd: OwnedFd,
+        /// The ring failed with entries queued or in flight, so the rest of
+        /// the run renames the std way.
+        broken: bool,
+        /// Entries the kernel may still complete after the ring failed;
+        /// their paths and buffers are leaked rather than freed under it.
+        abandoned: bool,
+    }
+
+    impl UringBackend {
+        pub fn new() -> io::Result<Self> {
+            let mut params = Params::default();
+            // SAFETY: params is a properly sized, writable io_uring_params.
+            let fd = unsafe {
+                libc::syscall(
+                    libc::SYS_io_uring_setup,
+                    ENTRIES,
+                    &mut params as *mut Params,
+                )
+            };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: io_uring_setup returned a fresh descriptor we now own.
+            let fd = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+            let raw = fd.as_raw_fd();
+
+            let sq_len =
+                params.sq_off.array as usize + params.sq_entries as usize * size_of::<u32>();
+            let cq_len =
+                params.cq_off.cqes as usize + params.cq_entries as usize * size_of::<Cqe>();
+
+            let (sq_ring, cq_ring) = if params.features & IORING_FEAT_SINGLE_MMAP != 0 {
+                (
+                    Mapping::new(raw, sq_len.max(cq_len), IORING_OFF_SQ_RING)?,
+                    None,
+                )
+            } else {
+                (
+                    Mapping::new(raw, sq_len, IORING_OFF_SQ_RING)?,
+                    Some(Mapping::new(raw, cq_len, IORING_OFF_CQ_RING)?),
+                )
+            };
+            let sqes = Mapping::new(
+                raw,
+                params.sq_entries as usize * size_of::<Sqe>(),
+                IORING_OFF_SQES,
+            )?;
+
+            Ok(Self {
+                sq_ring,
+                cq_ring,
+                sqes,
+                params,
+                fd,
+                broken: false,
+                abandoned: false,
+            })
+        }
+
+        /// Submits every entry and waits for all completions, returning the
+        /// raw result for each in submission order, `None` for one that was
+        /// never submitted, and the error that stopped the submission if one
+        /// did. Whatever was submitted is waited for before returning, so
+        /// the buffers the entries point to can be freed and no completion
+        /// is left for a later batch. If even that fails, the ring is given
+        /// up and `abandoned` is set.
+        fn submit_all(&mut self, entries: &[Sqe]) -> (Vec<Option<i32>>, Option<io::Error>) {
+            let mut results = vec![None; entries.len()];
+
+            for (chunk_index, chunk) in entries.chunks(self.params.sq_entries as usize).enumerate()
+            {
+                let base = chunk_index * self.params.sq_entries as usize;
+
+                // SAFETY: offsets come from the kernel and stay within the mappings.
+                unsafe {
+                    let sq = &self.params.sq_off;
+                    let tail_ptr = &*self.sq_ring.at::<AtomicU32>(sq.tail);
+                    let mask = *self.sq_ring.at::<u32>(sq.ring_mask);
+                    let array = self.sq_ring.at::<u32>(sq.array);
+                    let sqes = self.sqes.ptr.cast::<Sqe>();
+
+                    let mut tail = tail_ptr.load(Ordering::Acquire);
+                    for (offset, entry) in chunk.iter().enumerate() {
+                        let index = tail & mask;
+                        let mut sqe = *entry;
+                        sqe.user_data = (base + offset) as u64;
+                        ptr::write(sqes.add(index as usize), sqe);
+                        *array.add(index as usize) = index;
+                        tail = tail.wrapping_add(1);
+                    }
+                    tail_ptr.store(tail, Ordering::Release);
+                }
+
+                let mut unsubmitted = chunk.len();
+                let mut in_flight = 0;
+                let mut failed = None;
+                while unsubmitted + in_flight > 0 {
+                    // Submit first, then block until the whole chunk has completed.
+                    let min_complete = if unsubmitted > 0 { 0 } else { in_flight };
+                    match self.enter(unsubmitted, min_complete) {
+                        Ok(submitted) => {
+                            let submitted = submitted.min(unsubmitted);
+                            unsubmitted -= submitted;
+                            in_flight += submitted;
+                        }
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                        // Short of resources: let some of the chunk finish first.
+                        Err(err)
+                            if in_flight > 0
+                                && matches!(
+                                    err.raw_os_error(),
+                                    Some(libc::EAGAIN | libc::EBUSY)
+                                ) =>
+                        {
+                            let _ = self.enter(0, 1);
+                        }
+                        Err(err) => {
+                            failed = Some(err);
+                            break;
+                        }
+                    }
+                    in_flight -= self.reap(&mut results).min(in_flight);
+                }
+
+                let Some(err) = failed else {
+                    continue;
+                };
+                while in_flight > 0 {
+                    match self.enter(0, in_flight) {
+                        Ok(_) => {}
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                        Err(_) => break,
+                    }
+                    in_flight -= self.reap(&mut results).min(in_flight);
+                }
+                // Entries left in the queue would go out with the next
+                // batch, pointing at freed buffers.
+                self.broken = unsubmitted > 0 || in_flight > 0;
+                self.abandoned = in_flight > 0;
+                return (results, Some(err));
+            }
+
+            (results, None)
+        }
+
+        /// `io_uring_enter`, returning how many entries were submitted.
+        fn enter(&self, to_submit: usize, min_complete: usize) -> io::Result<usize> {
+            // SAFETY: plain syscall on our ring descriptor.
+            let submitted = unsafe {
+                libc::syscall(
+                    libc::SYS_io_uring_enter,
+                    self.fd.as_raw_fd(),
+                    to_submit,
+                    min_complete,
+                    IORING_ENTER_GETEVENTS,
+                    ptr::null::<libc::c_void>(),
+                    0usize,
+                )
+            };
+            if submitted < 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(submitted as usize)
+            }
+        }
+
+        fn reap(&mut self, results: &mut [Option<i32>]) -> usize {
+            let ring = self.cq_ring.as_ref().unwrap_or(&self.sq_ring);
+            let cq = &self.params.cq_off;
+            let mut reaped = 0;
+
+            // SAFETY: offsets come from the kernel and stay within the mapping.
+            unsafe {
+                let head_ptr = &*ring.at::<AtomicU32>(cq.head);
+                let tail = (*ring.at::<AtomicU32>(cq.tail)).load(Ordering::Acquire);
+                let mask = *ring.at::<u32>(cq.ring_mask);
+                let cqes = ring.at::<Cqe>(cq.cqes);
+
+                let mut head = head_ptr.load(Ordering::Relaxed);
+                while head != tail {
+                    let cqe = &*cqes.add((head & mask) as usize);
+                    if let Some(slot) = results.get_mut(cqe.user_data as usize) {
+                        *slot = Some(cqe.res);
+                    }
+                    head = head.wrapping_add(1);
+                    reaped += 1;
+                }
+                head_ptr.store(head, Ordering::Release);
+            }
+
+            reaped
+        }
+    }
+
+    impl Backend for UringBackend {
+        fn batch_size(&self) -> usize {
+            ENTRIES as usize
+        }
+
+        fn rename_batch(&mut self, jobs: &[(PathBuf, PathBuf)]) -> Vec<RenameResult> {
+            if self.broken {
+                return jobs
+                    .iter()
+                    .map(|(source, target)| super::rename_one(source, target))
+                    .collect();
+            }
+            let mut results: Vec<Option<RenameResult>> = jobs.iter().map(|_| None).collect();
+
+            let paths: Vec<Option<(CString, CString)>> = jobs
+                .iter()
+                .map(|(source, target)| Some((c_path(source)?, c_path(target)?)))
+                .collect();
+            for (result, path) in results.iter_mut().zip(&paths) {
+                if path.is_none() {
+                    *result = Some(RenameResult::Failed(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "path contains a NUL byte",
+                    )));
+                }
+            }
+
+            // A change of case only is done the std way: on a case-insensitive
+            // volume the statx below would find the file itself.
+            for (index, (source, target)) in jobs.iter().enumerate() {
+                if results[index].is_none() && noreplace::same_file(source, target) {
+                    results[index] = Some(super::rename_one(source, target));
+                }
+            }
+
+            // Pass 1: statx every target that is still pending.
+            let mut statx_buffers = vec![[0u8; STATX_BUFFER_SIZE]; jobs.len()];
+            let mut statx_jobs = Vec::new();
+            let mut statx_entries = Vec::new();
+            for (index, path) in paths.iter().enumerate() {
+                if results[index].is_none()
+                    && let Some((_, target)) = path
+                {
+                    statx_jobs.push(index);
+                    statx_entries.push(Sqe {
+                        opcode: IORING_OP_STATX,
+                        fd: libc::AT_FDCWD,
+                        addr: target.as_ptr() as u64,
+                        len: STATX_TYPE,
+                        off: statx_buffers[index].as_mut_ptr() as u64,
+                        ..Sqe::default()
+                    });
+                }
+            }
+
+            let started = Instant::now();
+            let (statx_results, statx_error) = self.submit_all(&statx_entries);
+            if trace::enabled() {
+                let elapsed = started.elapsed();
+                for (&index, &res) in statx_jobs.iter().zip(&statx_results) {
+                    if let Some(res) = res {
+                        trace::record(
+                            "io_uring_statx",
+                            &jobs[index].1,
+                            None,
+                            elapsed,
+                            &describe(res),
+                        );
+                    }
+                }
+            }
+            for (&index, &res) in statx_jobs.iter().zip(&statx_results) {
+                match res {
+                    Some(0) => results[index] = Some(RenameResult::TargetExists),
+                    Some(res) if res != -libc::ENOENT => {
+                        results[index] =
+                            Some(RenameResult::Failed(io::Error::from_raw_os_error(-res)))
+                    }
+                    Some(_) => {}
+                    None => results[index] = Some(not_done(statx_error.as_ref())),
+                }
+            }
+
+            // Pass 2: rename what is left. RENAME_NOREPLACE closes the gap
+            // between the statx check and the rename. After the ring broke
+            // in pass 1 they are renamed the std way.
+            let mut rename_jobs = Vec::new();
+            let mut rename_entries = Vec::new();
+            for (index, path) in paths.iter().enumerate() {
+                if results[index].is_some() {
+                    continue;
+                }
+                if self.broken {
+                    let (source, target) = &jobs[index];
+                    results[index] = Some(super::rename_one(source, target));
+                } else if let Some((source, target)) = path {
+                    rename_jobs.push(index);
+                    rename_entries.push(Sqe {
+                        opcode: IORING_OP_RENAMEAT,
+                        fd: libc::AT_FDCWD,
+                        addr: source.as_ptr() as u64,
+                        len: libc::AT_FDCWD as u32,
+                        off: target.as_ptr() as u64,
+                        op_flags: RENAME_NOREPLACE,
+                        ..Sqe::default()
+                    });
+                }
+            }
+
+            let started = Instant::now();
+            let (rename_results, rename_error) = self.submit_all(&rename_entries);
+            if trace::enabled() {
+                let elapsed = started.elapsed();
+                for (&index, &res) in rename_jobs.iter().zip(&rename_results) {
+                    let (source, target) = &jobs[index];
+                    if let Some(res) = res {
+                        trace::record(
+                            "io_uring_renameat",
+                            source,
+                            Some(target),
+                            elapsed,
+                            &describe(res),
+                        );
+                    }
+                }
+            }
+            for (&index, &res) in rename_jobs.iter().zip(&rename_results) {
+                results[index] = Some(match res {
+                    Some(0) => RenameResult::Renamed,
+                    Some(res) if res == -libc::EEXIST => RenameResult::TargetExists,
+                    Some(res) => RenameResult::Failed(io::Error::from_raw_os_error(-res)),
+                    None => not_done(rename_error.as_ref()),
+                });
+            }
+
+            if self.abandoned {
+                std::mem::forget(paths);
+                std::mem::forget(statx_buffers);
+            }
+
+            results
+                .into_iter()
+                .map(|result| result.unwrap_or_else(|| not_done(None)))
+                .collect()
+        }
+    }
+
+    /// Formats a completion result the way the trace reports other calls.
+    fn describe(res: i32) -> String {
+        if res < 0 {
+            io::Error::from_raw_os_error(-res).to_string()
+        } else {
+            "ok".to_string()
+        }
+    }
+
+    fn c_path(path: &Path) -> Option<CString> {
+        CString::new(path.as_os_str().as_bytes()).ok()
+    }
+
+    /// The result of a request that never reached the kernel, because the
+    /// ring failed with `err`.
+    fn not_done(err: Option<&io::Error>) -> RenameResult {
+        RenameResult::Failed(match err {
+            Some(err) => io::Error::new(err.kind(), format!("not renamed: {}", err)),
+            None => io::Error::other("request was not completed"),
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::fs;
+        use std::path::PathBuf;
+
+        use super::*;
+        use crate::backend::{self, BackendKind, Conflict};
+        use crate::preserve::Preserve;
+
+        fn scratch(name: &str) -> PathBuf {
+            let dir =
+                std::env::temp_dir().join(format!("r3dy-uring-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            dir
+        }
+
+        /// A ring, or `None` where the kernel or a sandbox refuses one, in
+        /// which case `--backend uring` must be refused with the reason.
+        fn ring() -> Option<UringBackend> {
+            match UringBackend::new() {
+                Ok(ring) => Some(ring),
+                Err(_) => {
+                    let err = backend::open(
+                        BackendKind::Uring,
+                        1,
+                        false,
+                        None,
+                        Conflict::Skip,
+                        false,
+                        Preserve::default(),
+                        false,
+                    )
+                    .err()
+                    .unwrap();
+                    assert!(err.starts_with("io_uring is not available: "), "{}", err);
+                    None
+                }
+            }
+        }
+
+        /// More files than the ring holds, so the batch goes in chunks.
+        fn clips(dir: &Path, count: usize) -> Vec<(PathBuf, PathBuf)> {
+            (0..count)
+                .map(|index| {
+                    let source = dir.join(format!("{:03}.NEV", index));
+                    fs::write(&source, b"clip").unwrap();
+                    (source, dir.join(format!("{:03}.R3D", index)))
+                })
+                .collect()
+        }
+
+        #[test]
+        fn renames_a_batch() {
+            let Some(mut ring) = ring() else {
+                return;
+            };
+            let dir = scratch("batch");
+            let jobs = clips(&dir, ENTRIES as usize + 44);
+            let results = ring.rename_batch(&jobs);
+            assert_eq!(results.len(), jobs.len());
+            for ((source, target), result) in jobs.iter().zip(&results) {
+                assert!(
+                    matches!(result, RenameResult::Renamed),
+                    "{}",
+                    source.display()
+                );
+                assert!(!source.exists() && target.exists());
+            }
+            assert!(!ring.broken && !ring.abandoned);
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn reports_each_failed_entry_on_its_own() {
+            let Some(mut ring) = ring() else {
+                return;
+            };
+            let dir = scratch("failed");
+            let mut jobs = clips(&dir, 3);
+            fs::remove_file(&jobs[0].0).unwrap();
+            fs::write(&jobs[1].1, b"taken").unwrap();
+            jobs.push((dir.join("bad\0name.NEV"), dir.join("bad.R3D")));
+            let results = ring.rename_batch(&jobs);
+            assert!(
+                matches!(&results[0], RenameResult::Failed(err) if err.kind() == io::ErrorKind::NotFound)
+            );
+            assert!(matches!(results[1], RenameResult::TargetExists));
+            assert_eq!(fs::read(&jobs[1].1).unwrap(), b"taken");
+            assert!(matches!(results[2], RenameResult::Renamed));
+            assert!(
+                matches!(&results[3], RenameResult::Failed(err) if err.kind() == io::ErrorKind::InvalidInput)
+            );
+            assert!(!ring.broken);
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn renames_the_std_way_once_the_ring_broke() {
+            let Some(mut ring) = ring() else {
+                return;
+            };
+            let dir = scratch("broken");
+            let jobs = clips(&dir, 2);
+            fs::write(&jobs[1].1, b"taken").unwrap();
+            ring.broken = true;
+            let results = ring.rename_batch(&jobs);
+            assert!(matches!(results[0], RenameResult::Renamed));
+            assert!(jobs[0].1.exists());
+            assert!(matches!(results[1], RenameResult::TargetExists));
+            assert!(jobs[1].0.exists());
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("r3dy-backend-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn operation(copy: bool) -> Operation {
+        Operation {
+            copy,
+            verify: Some(Digest::Xxh3),
+            conflict: Conflict::Skip,
+            allow_copy: false,
+            preserve: Preserve::default(),
+            writable: false,
+        }
+    }
+
+    /// A rename that fails `--verify` is put back, and a bad copy removed.
+    #[test]
+    fn failed_verification_is_undone() {
+        let dir = scratch("undo");
+        let (source, target) = (dir.join("A.NEV"), dir.join("A.R3D"));
+        fs::write(&target, b"clip").unwrap();
+        assert_eq!(
+            operation(false).undo(&source, &target),
+            "; it was renamed back"
+        );
+        assert!(source.exists() && !target.exists());
+
+        fs::write(&target, b"clip").unwrap();
+        assert_eq!(
+            operation(true).undo(&source, &target),
+            "; the copy was removed"
+        );
+        assert!(source.exists() && !target.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
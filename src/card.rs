@@ -0,0 +1,146 @@
+use std::path::{Path, PathBuf};
+
+use crate::{Status, display_relative, encode_relative, json, pathenc};
+
+/// The card a directory marks, if any. A `DCIM` folder (Nikon and most
+/// stills-derived cameras) marks the volume above it; a RED `.RDM` magazine
+/// folder is a card by itself.
+pub fn card_root(dir: &Path) -> Option<&Path> {
+    let name = dir.file_name()?;
+    if name.as_encoded_bytes().eq_ignore_ascii_case(b"DCIM") {
+        return dir.parent();
+    }
+
+    let is_magazine = dir
+        .extension()
+        .is_some_and(|ext| pathenc::extension_eq(ext, "RDM"));
+    is_magazine.then_some(dir)
+}
+
+/// Counts for one detected card, or for files outside any card.
+pub struct Tally {
+    pub root: Option<PathBuf>,
+    pub converted: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+impl Tally {
+    fn new(root: Option<PathBuf>) -> Self {
+        Self {
+            root,
+            converted: 0,
+            skipped: 0,
+            failed: 0,
+        }
+    }
+
+    /// The card's label as DITs know it: the card folder's name when the scan
+    /// starts at (or inside) the card, otherwise its path relative to the
+    /// scan root.
+    pub fn name(&self, scan_root: &Path) -> String {
+        match &self.root {
+            Some(root) if !root.starts_with(scan_root) || root == scan_root => root
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| root.display().to_string()),
+            Some(root) => display_relative(scan_root, root),
+            None => "(no card)".to_string(),
+        }
+    }
+
+    fn encoded_name(&self, scan_root: &Path) -> String {
+        match &self.root {
+            Some(root) if !root.starts_with(scan_root) || root == scan_root => root
+                .file_name()
+                .map(|name| pathenc::encode(Path::new(name)))
+                .unwrap_or_else(|| pathenc::encode(root)),
+            Some(root) => encode_relative(scan_root, root),
+            None => String::new(),
+        }
+    }
+
+    pub fn to_json(&self, scan_root: &Path) -> String {
+        let card = if self.root.is_some() {
+            json::string(&self.encoded_name(scan_root))
+        } else {
+            "null".to_string()
+        };
+        format!(
+            "{{\"card\":{},\"converted\":{},\"skipped\":{},\"failed\":{}}}",
+            card, self.converted, self.skipped, self.failed
+        )
+    }
+}
+
+/// Per-card counts for a run. Files are attributed to the deepest card root
+/// that contains them; files outside every card share one tally.
+pub struct Cards {
+    cards: Vec<Tally>,
+    loose: Tally,
+}
+
+impl Cards {
+    pub fn new(mut roots: Vec<PathBuf>) -> Self {
+        roots.sort();
+        roots.dedup();
+        Self {
+            cards: roots.into_iter().map(|root| Tally::new(Some(root))).collect(),
+            loose: Tally::new(None),
+        }
+    }
+
+    /// The same cards with nothing counted yet.
+    pub fn cleared(&self) -> Self {
+        Self {
+            cards: self.cards.iter().map(|tally| Tally::new(tally.root.clone())).collect(),
+            loose: Tally::new(None),
+        }
+    }
+
+    pub fn record(&mut self, path: &Path, status: Status) {
+        let tally = self.find_mut(path);
+        match status {
+            Status::Converted => tally.converted += 1,
+            Status::Skipped | Status::Changed => tally.skipped += 1,
+            Status::Failed | Status::Denied => tally.failed += 1,
+        }
+    }
+
+    /// The card `path` belongs to, by name, or an empty string.
+    pub fn card_name(&self, scan_root: &Path, path: &Path) -> String {
+        self.find(path)
+            .map(|tally| tally.encoded_name(scan_root))
+            .unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cards.is_empty()
+    }
+
+    /// Every card with files, followed by the files outside any card.
+    pub fn tallies(&self) -> impl Iterator<Item = &Tally> {
+        self.cards
+            .iter()
+            .chain(Some(&self.loose))
+            .filter(|tally| tally.converted + tally.skipped + tally.failed > 0)
+    }
+
+    fn find(&self, path: &Path) -> Option<&Tally> {
+        self.position(path).map(|index| &self.cards[index])
+    }
+
+    fn find_mut(&mut self, path: &Path) -> &mut Tally {
+        match self.position(path) {
+            Some(index) => &mut self.cards[index],
+            None => &mut self.loose,
+        }
+    }
+
+    fn position(&self, path: &Path) -> Option<usize> {
+        // Roots are sorted, so the last match is the deepest one.
+        self.cards
+            .iter()
+            .rposition(|tally| tally.root.as_deref().is_some_and(|root| path.starts_with(root)))
+    }
+}
@@ -0,0 +1,28 @@
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::trace;
+
+pub const DISAPPEARED: &str = "disappeared since the scan";
+
+/// Looks a listed file up again right before it is renamed.
+pub fn stat(path: &Path) -> io::Result<Metadata> {
+    trace::call("stat", path, || fs::symlink_metadata(path))
+}
+
+/// Why a file no longer matches the scan that listed it, if it does not,
+/// given its fresh `stat`: it is gone, or something wrote to it after the
+/// scan began, typically an offload tool still copying into the tree. Such
+/// files are left alone rather than renamed on stale assumptions. Other stat
+/// errors are left for the rename itself to report.
+pub fn since_scan(stat: &io::Result<Metadata>, scan_started: SystemTime) -> Option<&'static str> {
+    match stat {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Some(DISAPPEARED),
+        Ok(metadata) if metadata.modified().is_ok_and(|at| at > scan_started) => {
+            Some("modified since the scan, it may still be being written")
+        }
+        _ => None,
+    }
+}
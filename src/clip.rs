@@ -0,0 +1,161 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+use crate::{Outcome, Status, backend, display_relative, pathenc, sidecar};
+
+/// The clip `path` belongs to. Files with the same key are renamed all or
+/// nothing, so a clip is never left half renamed.
+///
+/// The segments of a spanned clip share the key: the clip's [`name`] and
+/// their extension, as it is spelled, in the file's folder. Any other file
+/// is a clip of its own, keyed by its path, so `CLIP.NEV` and `CLIP.nev`
+/// are two clips. With `folders` (`--clip-folders`), everything inside a
+/// RED `.RDC` clip folder is one clip, keyed by the folder. Sidecars are
+/// not keyed with their clip; [`roll_back_partial`] takes them along.
+pub fn key(path: &Path, folders: bool) -> PathBuf {
+    if folders
+        && let Some(folder) = path.parent()
+        && folder
+            .extension()
+            .is_some_and(|ext| pathenc::extension_eq(ext, "RDC"))
+    {
+        return folder.to_path_buf();
+    }
+    let Some((clip, _)) = path
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .and_then(split_segment)
+    else {
+        return path.to_path_buf();
+    };
+    let mut name = OsString::from(clip);
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// Whether `path` is a sidecar of the clip `first` is a file of: in the
+/// same folder, named after the clip, with a sidecar extension.
+fn is_sidecar_of(path: &Path, first: &Path) -> bool {
+    sidecar::is_sidecar(path)
+        && path.parent() == first.parent()
+        && path.file_stem() == name(first)
+}
+
+/// The name of the clip `path` belongs to: the file name without its
+/// extension.
+///
+/// RED spans clips over 4 GB as `A001_C001_0101AB_001.R3D`,
+/// `..._002.R3D` and so on; for those the segment number is dropped too.
+/// The part before the segment number must itself contain a `_`, so
+/// stills-style names such as `DSC_001.NEV` keep theirs.
+pub fn name(path: &Path) -> Option<&OsStr> {
+    let stem = path.file_stem()?;
+    let spanned = stem.to_str().and_then(split_segment);
+    Some(spanned.map_or(stem, |(clip, _)| OsStr::new(clip)))
+}
+
+/// The segment number of a spanned clip's file, e.g. `001`.
+pub fn segment(path: &Path) -> Option<&str> {
+    let stem = path.file_stem()?.to_str()?;
+    split_segment(stem).map(|(_, segment)| segment)
+}
+
+fn split_segment(stem: &str) -> Option<(&str, &str)> {
+    let (clip, segment) = stem.rsplit_once('_')?;
+    let spanned = segment.len() == 3
+        && segment.bytes().all(|byte| byte.is_ascii_digit())
+        && clip.contains('_');
+    spanned.then_some((clip, segment))
+}
+
+/// Splits `files` into batches of about `size`, only between clips.
+pub fn batches(files: &[PathBuf], size: usize, folders: bool) -> Vec<&[PathBuf]> {
+    let mut batches = Vec::new();
+    let mut start = 0;
+    for index in 1..=files.len() {
+        let full = index - start >= size;
+        if index == files.len()
+            || (full && key(&files[index], folders) != key(&files[index - 1], folders))
+        {
+            batches.push(&files[start..index]);
+            start = index;
+        }
+    }
+    batches
+}
+
+/// Undoes the renames of every clip in `outcomes` that was only partly
+/// renamed: renamed files go back to their old names (or their copies
+/// are removed) and become skipped. A clip's files must be next to each
+/// other, as they are in path order, with its sidecars following them.
+/// Returns a line for each file rolled back or that could not be.
+pub fn roll_back_partial(
+    root: &Path,
+    outcomes: &mut [Outcome],
+    folders: bool,
+    copy: bool,
+    dry_run: bool,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while start < outcomes.len() {
+        let first = outcomes[start].source.clone();
+        let clip = key(&first, folders);
+        let len = outcomes[start..]
+            .iter()
+            .take_while(|outcome| {
+                key(&outcome.source, folders) == clip || is_sidecar_of(&outcome.source, &first)
+            })
+            .count();
+        let files = &mut outcomes[start..start + len];
+        start += len;
+
+        let Some(blocking) = files
+            .iter()
+            .find(|outcome| outcome.status != Status::Converted)
+            .map(|outcome| display_relative(root, &outcome.source))
+        else {
+            continue;
+        };
+
+        for outcome in files
+            .iter_mut()
+            .filter(|outcome| outcome.status == Status::Converted)
+        {
+            let source = display_relative(root, &outcome.source);
+            if !dry_run && let Err(err) = backend::revert(&outcome.source, &outcome.target, copy) {
+                lines.push(format!(
+                    "Could not roll back {}, left as {}: {}",
+                    source,
+                    display_relative(root, &outcome.target),
+                    err
+                ));
+                continue;
+            }
+            outcome.status = Status::Skipped;
+            if dry_run {
+                outcome.error = Some(format!(
+                    "{} of the same clip would not be renamed",
+                    blocking
+                ));
+                lines.push(format!(
+                    "Would leave {} alone ({} of the same clip would not be renamed)",
+                    source, blocking
+                ));
+            } else {
+                outcome.error = Some(format!(
+                    "rolled back: {} of the same clip was not renamed",
+                    blocking
+                ));
+                lines.push(format!(
+                    "Rolled back {} ({} of the same clip was not renamed)",
+                    source, blocking
+                ));
+            }
+        }
+    }
+    lines
+}
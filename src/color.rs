@@ -0,0 +1,73 @@
+use std::env;
+
+use console::{StyledObject, style};
+
+/// When to colour the output, from `--color`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Choice {
+    /// Only on a terminal, and not when NO_COLOR is set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl Choice {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(Self::Auto),
+            "always" => Some(Self::Always),
+            "never" => Some(Self::Never),
+            _ => None,
+        }
+    }
+}
+
+/// Turns colour on or off for stdout, stderr and the progress bars. `Auto`
+/// keeps console's terminal detection (which honours CLICOLOR), except that
+/// a non-empty NO_COLOR turns colour off.
+pub fn init(choice: Choice) {
+    let enabled = match choice {
+        Choice::Always => true,
+        Choice::Never => false,
+        Choice::Auto if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) => false,
+        Choice::Auto => return,
+    };
+    console::set_colors_enabled(enabled);
+    console::set_colors_enabled_stderr(enabled);
+}
+
+/// A warning line on stderr, in yellow.
+pub fn warning(line: &str) -> String {
+    style(line).yellow().for_stderr().to_string()
+}
+
+/// A failed file or error line on stderr, in red.
+pub fn failure(line: &str) -> String {
+    style(line).red().for_stderr().to_string()
+}
+
+/// A count of files done for a summary on stdout, in green when above zero.
+pub fn done(count: usize) -> String {
+    paint(count, |styled| styled.green())
+}
+
+/// A count of files skipped for a summary on stdout, in yellow when above
+/// zero.
+pub fn skipped(count: usize) -> String {
+    paint(count, |styled| styled.yellow())
+}
+
+/// A count of files failed for a summary on stdout, in bold red when above
+/// zero.
+pub fn failed(count: usize) -> String {
+    paint(count, |styled| styled.red().bold())
+}
+
+fn paint(count: usize, color: impl FnOnce(StyledObject<usize>) -> StyledObject<usize>) -> String {
+    if count == 0 {
+        count.to_string()
+    } else {
+        color(style(count)).to_string()
+    }
+}
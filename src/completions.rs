@@ -0,0 +1,324 @@
+use clap::{CommandFactory, ValueEnum};
+
+use crate::args::Cli;
+
+/// The shells `r3dy completions` writes a script for.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Shell {
+    /// r3dy completions bash > ~/.local/share/bash-completion/completions/r3dy
+    Bash,
+    /// r3dy completions zsh > "${fpath[1]}/_r3dy"
+    Zsh,
+    /// r3dy completions fish > ~/.config/fish/completions/r3dy.fish
+    Fish,
+    /// r3dy completions powershell >> $PROFILE
+    Powershell,
+}
+
+/// A command offered after `r3dy`, with its flags and the words that may
+/// follow the command itself.
+struct Completion {
+    name: String,
+    flags: Vec<String>,
+    words: Vec<String>,
+}
+
+/// The commands offered after `r3dy`, read from the command line parser.
+fn commands() -> Vec<Completion> {
+    let mut cli = Cli::command();
+    cli.build();
+    cli.get_subcommands()
+        .map(|command| {
+            let words = if command.has_subcommands() {
+                command
+                    .get_subcommands()
+                    .map(|sub| sub.get_name().to_string())
+                    .collect()
+            } else {
+                command
+                    .get_positionals()
+                    .next()
+                    .map(|arg| {
+                        arg.get_possible_values()
+                            .iter()
+                            .map(|value| value.get_name().to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            };
+            Completion {
+                name: command.get_name().to_string(),
+                flags: flags(command),
+                words,
+            }
+        })
+        .collect()
+}
+
+/// Flags whose value is one of a few words.
+const CHOICES: [(&str, &str); 6] = [
+    ("--backend", "std uring"),
+    ("--on-conflict", "skip overwrite suffix fail"),
+    ("--compound", "last skip"),
+    ("--email-attach", "csv json"),
+    ("--progress-template", "default eta compact failures"),
+    ("--content", "sparse truncated full"),
+];
+
+/// `r3dy completions <shell>`.
+pub fn completions_command(shell: Shell) {
+    let script = match shell {
+        Shell::Bash => bash(),
+        Shell::Zsh => zsh(),
+        Shell::Fish => fish(),
+        Shell::Powershell => powershell(),
+    };
+    print!("{}", script);
+}
+
+/// The long and short flags of `command` and of the commands under it,
+/// `--help` included.
+fn flags(command: &clap::Command) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut add = |flag: String| {
+        if !found.contains(&flag) {
+            found.push(flag);
+        }
+    };
+    add("--help".to_string());
+    for arg in command.get_arguments() {
+        if let Some(long) = arg.get_long() {
+            add(format!("--{}", long));
+        }
+        if let Some(short) = arg.get_short() {
+            add(format!("-{}", short));
+        }
+    }
+    for sub in command.get_subcommands() {
+        for flag in flags(sub) {
+            add(flag);
+        }
+    }
+    found
+}
+
+fn command_names() -> String {
+    commands()
+        .iter()
+        .map(|command| command.name.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn bash() -> String {
+    let mut cases = String::new();
+    for Completion { name, flags, words } in commands() {
+        cases.push_str(&format!(
+            "        {}) flags=\"{}\"; words=\"{}\" ;;\n",
+            name,
+            flags.join(" "),
+            words.join(" ")
+        ));
+    }
+    let mut choices = String::new();
+    for (flag, values) in CHOICES {
+        choices.push_str(&format!(
+            "        {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;\n",
+            flag, values
+        ));
+    }
+
+    format!(
+        r#"# bash completion for r3dy
+_r3dy() {{
+    local cur=${{COMP_WORDS[COMP_CWORD]}}
+    local prev=${{COMP_WORDS[COMP_CWORD-1]}}
+    local commands="{commands}"
+    local command=rename flags words first=1
+    if [[ $COMP_CWORD -gt 1 && " $commands " == *" ${{COMP_WORDS[1]}} "* ]]; then
+        command=${{COMP_WORDS[1]}}
+        first=2
+    fi
+    case $command in
+{cases}    esac
+    case $prev in
+{choices}    esac
+    if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "$flags" -- "$cur"))
+    elif [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "$commands" -- "$cur"))
+    elif [[ $COMP_CWORD -eq $first && -n $words ]]; then
+        COMPREPLY=($(compgen -W "$words" -- "$cur"))
+    fi
+}}
+complete -o default -F _r3dy r3dy
+"#,
+        commands = command_names(),
+    )
+}
+
+fn zsh() -> String {
+    // `words` is zsh's array of the command line, so the words that may
+    // follow a command are called `follow` here.
+    let mut cases = String::new();
+    for Completion { name, flags, words } in commands() {
+        cases.push_str(&format!(
+            "    {}) flags=({}); follow=({}) ;;\n",
+            name,
+            flags.join(" "),
+            words.join(" ")
+        ));
+    }
+    let mut choices = String::new();
+    for (flag, values) in CHOICES {
+        choices.push_str(&format!("    {}) compadd -- {}; return ;;\n", flag, values));
+    }
+
+    format!(
+        r#"#compdef r3dy
+
+_r3dy() {{
+  local -a commands=({commands})
+  local -a flags follow
+  local command=rename first=2
+  if (( CURRENT > 2 && ${{commands[(Ie)$words[2]]}} )); then
+    command=$words[2]
+    first=3
+  fi
+  case $command in
+{cases}  esac
+  case $words[CURRENT-1] in
+{choices}  esac
+  if [[ $PREFIX == -* ]]; then
+    compadd -- $flags
+  elif (( CURRENT == 2 )); then
+    compadd -- $commands
+    _files
+  elif (( CURRENT == first && $#follow )); then
+    compadd -- $follow
+  else
+    _files
+  fi
+}}
+
+if [ "$funcstack[1]" = "_r3dy" ]; then
+  _r3dy "$@"
+else
+  compdef _r3dy r3dy
+fi
+"#,
+        commands = command_names(),
+    )
+}
+
+fn fish() -> String {
+    let mut script = format!(
+        r#"# fish completion for r3dy
+function __r3dy_command
+    set -l words (commandline -opc)
+    set -l command rename
+    if test (count $words) -gt 1; and contains -- $words[2] {commands}
+        set command $words[2]
+    end
+    contains -- $command $argv
+end
+
+complete -c r3dy -n "test (count (commandline -opc)) -eq 1" -a "{commands}"
+"#,
+        commands = command_names(),
+    );
+    for Completion { name, flags, words } in commands() {
+        if !words.is_empty() {
+            script.push_str(&format!(
+                "complete -c r3dy -n \"__r3dy_command {}; and test (count (commandline -opc)) -eq 2\" -f -a \"{}\"\n",
+                name,
+                words.join(" ")
+            ));
+        }
+        for flag in &flags {
+            let option = match flag.strip_prefix("--") {
+                Some(long) => format!("-l {}", long),
+                None => format!("-s {}", &flag[1..]),
+            };
+            let values = CHOICES
+                .iter()
+                .find(|(choice, _)| *choice == flag)
+                .map(|(_, values)| format!(" -x -a \"{}\"", values))
+                .unwrap_or_default();
+            script.push_str(&format!(
+                "complete -c r3dy -n \"__r3dy_command {}\" {}{}\n",
+                name, option, values
+            ));
+        }
+    }
+    script
+}
+
+fn powershell() -> String {
+    let mut cases = String::new();
+    for Completion { name, flags, words } in commands() {
+        cases.push_str(&format!(
+            "        '{}' {{ $flags = @({}); $words = @({}) }}\n",
+            name,
+            quoted(&flags),
+            quoted(&words)
+        ));
+    }
+    let mut choices = String::new();
+    for (flag, values) in CHOICES {
+        let values: Vec<&str> = values.split(' ').collect();
+        choices.push_str(&format!(
+            "        '{}' {{ $candidates = @({}) }}\n",
+            flag,
+            quoted(&values)
+        ));
+    }
+
+    format!(
+        r#"# PowerShell completion for r3dy
+Register-ArgumentCompleter -Native -CommandName r3dy -ScriptBlock {{
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $commands = @({commands})
+    $typed = @($commandAst.CommandElements | ForEach-Object {{ $_.ToString() }})
+    if ($wordToComplete) {{ $typed = $typed[0..($typed.Count - 2)] }}
+    $command = 'rename'
+    $first = 1
+    if ($typed.Count -gt 1 -and $commands -contains $typed[1]) {{
+        $command = $typed[1]
+        $first = 2
+    }}
+    $flags = @()
+    $words = @()
+    switch ($command) {{
+{cases}    }}
+    $candidates = $null
+    switch ($typed[-1]) {{
+{choices}    }}
+    if ($null -eq $candidates) {{
+        if ($wordToComplete -like '-*') {{ $candidates = $flags }}
+        elseif ($typed.Count -eq 1) {{ $candidates = $commands }}
+        elseif ($typed.Count -eq $first -and $words.Count -gt 0) {{ $candidates = $words }}
+        else {{ return }}
+    }}
+    $candidates | Where-Object {{ $_ -like "$wordToComplete*" }} | ForEach-Object {{
+        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+    }}
+}}
+"#,
+        commands = quoted(
+            &commands()
+                .into_iter()
+                .map(|command| command.name)
+                .collect::<Vec<_>>()
+        ),
+    )
+}
+
+/// Words as a PowerShell list body: `'a', 'b'`.
+fn quoted(words: &[impl AsRef<str>]) -> String {
+    words
+        .iter()
+        .map(|word| format!("'{}'", word.as_ref()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,79 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Seconds since the Unix epoch, clamped to zero for clocks set before 1970.
+pub fn unix_seconds(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Formats a time as an RFC 3339 UTC timestamp, e.g. `2024-03-01T18:04:05Z`.
+pub fn rfc3339(time: SystemTime) -> String {
+    let secs = unix_seconds(time);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let (hour, minute, second) = time_of_day(secs);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+/// Formats a time as an RFC 2822 date, as used in mail headers.
+pub fn rfc2822(time: SystemTime) -> String {
+    let secs = unix_seconds(time);
+    let days = secs.div_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    let (hour, minute, second) = time_of_day(secs);
+
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} +0000",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        hour,
+        minute,
+        second
+    )
+}
+
+fn time_of_day(secs: i64) -> (i64, i64, i64) {
+    let seconds = secs.rem_euclid(86_400);
+    (seconds / 3600, seconds % 3600 / 60, seconds % 60)
+}
+
+// Howard Hinnant's days-to-civil conversion for the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// Parses durations such as `90`, `30s`, `10m` or `2h` (a bare number is
+/// seconds).
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (digits, multiplier) = match value.chars().last()?.to_ascii_lowercase() {
+        's' => (&value[..value.len() - 1], 1),
+        'm' => (&value[..value.len() - 1], 60),
+        'h' => (&value[..value.len() - 1], 3600),
+        _ => (value, 1),
+    };
+
+    let seconds = digits.trim().parse::<u64>().ok()?.checked_mul(multiplier)?;
+    Some(Duration::from_secs(seconds))
+}
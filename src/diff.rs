@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use console::style;
+
+use crate::report::{self, Entry};
+
+/// Files whose status changed between two saved reports.
+pub struct ReportDiff {
+    pub newly_failed: Vec<Entry>,
+    pub appeared: Vec<Entry>,
+    pub resolved: Vec<Entry>,
+    pub still_failing: usize,
+}
+
+/// Compares two reports keyed by source path. `before` is the older run.
+pub fn compare(before: Vec<Entry>, after: Vec<Entry>) -> ReportDiff {
+    let before: BTreeMap<String, Entry> = before
+        .into_iter()
+        .map(|entry| (entry.source.clone(), entry))
+        .collect();
+
+    let mut diff = ReportDiff {
+        newly_failed: Vec::new(),
+        appeared: Vec::new(),
+        resolved: Vec::new(),
+        still_failing: 0,
+    };
+
+    let mut after_sorted: Vec<Entry> = after;
+    after_sorted.sort_by(|a, b| a.source.cmp(&b.source));
+
+    for entry in after_sorted {
+        let failed_now = report::is_failure(&entry.status);
+
+        match before.get(&entry.source) {
+            None if failed_now => diff.newly_failed.push(entry),
+            None => diff.appeared.push(entry),
+            Some(previous) => {
+                let failed_before = report::is_failure(&previous.status);
+                match (failed_before, failed_now) {
+                    (false, true) => diff.newly_failed.push(entry),
+                    (true, false) => diff.resolved.push(entry),
+                    (true, true) => diff.still_failing += 1,
+                    (false, false) => {}
+                }
+            }
+        }
+    }
+
+    diff
+}
+
+#[derive(clap::Subcommand)]
+pub enum ReportCommand {
+    /// List what changed between two saved reports
+    ///
+    /// Compares two saved r3dy reports (CSV or JSON) and lists newly failed
+    /// files, newly appeared clips, and resolved failures.
+    Diff {
+        /// The report of the earlier run
+        #[arg(value_name = "RUN_A")]
+        before: PathBuf,
+        /// The report of the later run
+        #[arg(value_name = "RUN_B")]
+        after: PathBuf,
+    },
+}
+
+/// Entry point for `r3dy report diff <runA> <runB>`.
+pub fn report_command(command: ReportCommand) -> Result<(), String> {
+    let ReportCommand::Diff { before, after } = command;
+    let diff = compare(report::load(&before)?, report::load(&after)?);
+    print_diff(&diff);
+    Ok(())
+}
+
+fn print_diff(diff: &ReportDiff) {
+    print_section(
+        &style("Newly failed").red().bold().to_string(),
+        &diff.newly_failed,
+        true,
+    );
+    print_section(
+        &style("Newly appeared").cyan().bold().to_string(),
+        &diff.appeared,
+        false,
+    );
+    print_section(
+        &style("Resolved failures").green().bold().to_string(),
+        &diff.resolved,
+        false,
+    );
+
+    println!(
+        "{} newly failed, {} newly appeared, {} resolved, {} still failing",
+        diff.newly_failed.len(),
+        diff.appeared.len(),
+        diff.resolved.len(),
+        diff.still_failing
+    );
+}
+
+fn print_section(title: &str, entries: &[Entry], show_errors: bool) {
+    if entries.is_empty() {
+        return;
+    }
+
+    println!("{} ({})", title, entries.len());
+    for entry in entries {
+        if show_errors && !entry.error.is_empty() {
+            println!("  {}: {}", entry.source, entry.error);
+        } else if entry.target.is_empty() {
+            println!("  {} [{}]", entry.source, entry.status);
+        } else {
+            println!("  {} -> {} [{}]", entry.source, entry.target, entry.status);
+        }
+    }
+    println!();
+}
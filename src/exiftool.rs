@@ -0,0 +1,74 @@
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::json::{self, Value};
+use crate::r3d::{Details, Provider};
+
+/// Set once `exiftool` turns out not to be installed, so it is not looked
+/// for again on every clip.
+static MISSING: AtomicBool = AtomicBool::new(false);
+
+/// The clip metadata ExifTool reads from `path`, for `--exiftool`. None
+/// when ExifTool is not installed, fails on the file, or finds no picture
+/// size in it.
+pub fn details(path: &Path) -> Option<Details> {
+    if MISSING.load(Ordering::Relaxed) {
+        return None;
+    }
+    let output = match Command::new("exiftool")
+        .args(["-json", "-n"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) => {
+            if err.kind() == io::ErrorKind::NotFound {
+                MISSING.store(true, Ordering::Relaxed);
+            }
+            return None;
+        }
+    };
+    if !output.status.success() {
+        return None;
+    }
+
+    let document = json::parse(&String::from_utf8_lossy(&output.stdout)).ok()?;
+    let tags = document.as_array()?.first()?;
+    let number = |name: &str| match tags.get(name) {
+        Some(Value::Number(value)) => Some(*value),
+        _ => None,
+    };
+    Some(Details {
+        version: (0, 0),
+        width: number("ImageWidth")? as u32,
+        height: number("ImageHeight")? as u32,
+        frame_rate: number("FrameRate")
+            .or_else(|| number("VideoFrameRate"))
+            .map_or((0, 0), fraction),
+        file_name: tags
+            .get("OriginalFileName")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        provider: Provider::ExifTool,
+    })
+}
+
+/// A frame rate ExifTool prints as a decimal, such as 23.976, as the
+/// fraction a RED header stores: 24000/1001. `(0, 0)` when none fits.
+fn fraction(rate: f64) -> (u16, u16) {
+    for denominator in [1u16, 1001, 1000, 100] {
+        let numerator = (rate * f64::from(denominator)).round();
+        if numerator >= 1.0
+            && numerator <= f64::from(u16::MAX)
+            && (numerator / f64::from(denominator) - rate).abs() < 0.001
+        {
+            return (numerator as u16, denominator);
+        }
+    }
+    (0, 0)
+}
@@ -0,0 +1,89 @@
+use std::path::Path;
+
+use crate::{Status, Summary};
+
+/// Renders the converted clips as an FCPXML document: one asset per clip and
+/// an event named after the scan root that holds them, so Final Cut Pro
+/// imports the renamed media without relinking. Clips on a detected card get
+/// the card name as a keyword, which Final Cut shows as a keyword
+/// collection per card. Durations are left for Final Cut to read from the
+/// media.
+pub fn render(root: &Path, summary: &Summary) -> String {
+    let mut assets = String::new();
+    let mut clips = String::new();
+
+    let converted = summary
+        .outcomes()
+        .filter(|outcome| outcome.status == Status::Converted);
+    for (index, outcome) in converted.enumerate() {
+        let id = format!("r{}", index + 1);
+        let name = outcome
+            .target
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        assets.push_str(&format!(
+            "    <asset id=\"{}\" name=\"{}\" start=\"0s\" hasVideo=\"1\" hasAudio=\"1\">\n      <media-rep kind=\"original-media\" src=\"{}\"/>\n    </asset>\n",
+            id,
+            escape(&name),
+            escape(&file_url(&outcome.target))
+        ));
+
+        let card = summary.cards.card_name(root, &outcome.source);
+        if card.is_empty() {
+            clips.push_str(&format!(
+                "    <asset-clip ref=\"{}\" name=\"{}\"/>\n",
+                id,
+                escape(&name)
+            ));
+        } else {
+            clips.push_str(&format!(
+                "    <asset-clip ref=\"{}\" name=\"{}\">\n      <keyword start=\"0s\" value=\"{}\"/>\n    </asset-clip>\n",
+                id,
+                escape(&name),
+                escape(&card)
+            ));
+        }
+    }
+
+    let event = root
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "r3dy".to_string());
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE fcpxml>\n<fcpxml version=\"1.10\">\n  <resources>\n{}  </resources>\n  <event name=\"{}\">\n{}  </event>\n</fcpxml>\n",
+        assets,
+        escape(&event),
+        clips
+    )
+}
+
+/// A `file://` URL for an absolute path, percent-encoding every byte outside
+/// the unreserved set so any file name survives.
+fn file_url(path: &Path) -> String {
+    let mut url = String::from("file://");
+    for component in path.components() {
+        let bytes = component.as_os_str().as_encoded_bytes();
+        if bytes == b"/" || bytes == b"\\" {
+            continue;
+        }
+        url.push('/');
+        for &byte in bytes {
+            if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                url.push(byte as char);
+            } else {
+                url.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    url
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,131 @@
+use std::path::{Component, Path};
+
+/// A shell-style pattern for `--include` and `--exclude`. `*` matches within
+/// one path component, `?` one character and `**` any number of folders.
+/// A pattern without `/` matches the file name wherever the file is; one
+/// with `/` matches the path relative to the root. Case is ignored, as it
+/// is for extensions.
+pub struct Glob {
+    segments: Vec<Vec<char>>,
+    /// Whether the pattern only names the file, not its folders.
+    name_only: bool,
+}
+
+impl Glob {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let trimmed = pattern.trim_start_matches("./");
+        if trimmed.is_empty() {
+            return Err("Empty pattern".to_string());
+        }
+        if trimmed.starts_with('/') || Path::new(trimmed).is_absolute() {
+            return Err(format!(
+                "{} is absolute; patterns are relative to the path being renamed",
+                pattern
+            ));
+        }
+
+        let segments = trimmed
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| segment.to_lowercase().chars().collect())
+            .collect::<Vec<_>>();
+        Ok(Self {
+            name_only: segments.len() == 1 && !trimmed.contains('/'),
+            segments,
+        })
+    }
+
+    /// Matches the path from the root even when the pattern is a bare name,
+    /// as a `.r3dyignore` pattern with a leading `/` does.
+    pub fn anchored(mut self) -> Self {
+        self.name_only = false;
+        self
+    }
+
+    /// Whether `relative` (a path below the root) matches.
+    pub fn matches(&self, relative: &Path) -> bool {
+        let components: Vec<Vec<char>> = relative
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => {
+                    Some(name.to_string_lossy().to_lowercase().chars().collect())
+                }
+                _ => None,
+            })
+            .collect();
+
+        if self.name_only {
+            return components
+                .last()
+                .is_some_and(|name| matches_segment(&self.segments[0], name));
+        }
+        matches_path(&self.segments, &components)
+    }
+}
+
+fn matches_path(pattern: &[Vec<char>], path: &[Vec<char>]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first.as_slice() == ['*', '*'] => {
+            (0..=path.len()).any(|skip| matches_path(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((name, remaining)) => {
+                matches_segment(first, name) && matches_path(rest, remaining)
+            }
+            None => false,
+        },
+    }
+}
+
+fn matches_segment(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| matches_segment(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && matches_segment(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && matches_segment(rest, &name[1..]),
+    }
+}
+
+/// The `--include` and `--exclude` patterns of a run. A file is renamed when
+/// it matches an include pattern (or none were given) and no exclude pattern.
+/// Folders matching an `--exclude-dir` pattern are not scanned at all.
+#[derive(Default)]
+pub struct Filter {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    exclude_dirs: Vec<Glob>,
+}
+
+impl Filter {
+    pub fn include(&mut self, pattern: &str) -> Result<(), String> {
+        let glob = Glob::parse(pattern).map_err(|err| format!("Invalid --include: {}", err))?;
+        self.include.push(glob);
+        Ok(())
+    }
+
+    pub fn exclude(&mut self, pattern: &str) -> Result<(), String> {
+        let glob = Glob::parse(pattern).map_err(|err| format!("Invalid --exclude: {}", err))?;
+        self.exclude.push(glob);
+        Ok(())
+    }
+
+    pub fn exclude_dir(&mut self, pattern: &str) -> Result<(), String> {
+        let glob = Glob::parse(pattern.trim_end_matches('/'))
+            .map_err(|err| format!("Invalid --exclude-dir: {}", err))?;
+        self.exclude_dirs.push(glob);
+        Ok(())
+    }
+
+    /// Whether the folder at `relative` below the root is left out, along
+    /// with everything in it.
+    pub fn prunes(&self, relative: &Path) -> bool {
+        self.exclude_dirs.iter().any(|glob| glob.matches(relative))
+    }
+
+    /// Whether the file at `relative` below the root should be renamed.
+    pub fn allows(&self, relative: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|glob| glob.matches(relative)))
+            && !self.exclude.iter().any(|glob| glob.matches(relative))
+    }
+}
@@ -0,0 +1,328 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use xxhash_rust::xxh3::Xxh3;
+use xxhash_rust::xxh64::Xxh64;
+
+use crate::{heartbeat, trace};
+
+const READ_BUFFER: usize = 1024 * 1024;
+
+/// The bytes per second hashing may read, or 0 for no limit.
+static READ_LIMIT: AtomicU64 = AtomicU64::new(0);
+
+/// Keeps the files hashed from here on to `bytes_per_second`, so a sweep
+/// over a near-line store leaves it room for other work.
+pub fn limit_reads(bytes_per_second: u64) {
+    READ_LIMIT.store(bytes_per_second, Ordering::Relaxed);
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().expect("4 bytes"))
+}
+
+/// Streaming MD5, still the default checksum in many offload tools' MHLs.
+pub struct Md5 {
+    state: [u32; 4],
+    buffer: [u8; 64],
+    buffered: usize,
+    total: u64,
+}
+
+const MD5_SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+    14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+    21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+const MD5_CONSTANTS: [u32; 64] = [
+    0xD76A_A478,
+    0xE8C7_B756,
+    0x2420_70DB,
+    0xC1BD_CEEE,
+    0xF57C_0FAF,
+    0x4787_C62A,
+    0xA830_4613,
+    0xFD46_9501,
+    0x6980_98D8,
+    0x8B44_F7AF,
+    0xFFFF_5BB1,
+    0x895C_D7BE,
+    0x6B90_1122,
+    0xFD98_7193,
+    0xA679_438E,
+    0x49B4_0821,
+    0xF61E_2562,
+    0xC040_B340,
+    0x265E_5A51,
+    0xE9B6_C7AA,
+    0xD62F_105D,
+    0x0244_1453,
+    0xD8A1_E681,
+    0xE7D3_FBC8,
+    0x21E1_CDE6,
+    0xC337_07D6,
+    0xF4D5_0D87,
+    0x455A_14ED,
+    0xA9E3_E905,
+    0xFCEF_A3F8,
+    0x676F_02D9,
+    0x8D2A_4C8A,
+    0xFFFA_3942,
+    0x8771_F681,
+    0x6D9D_6122,
+    0xFDE5_380C,
+    0xA4BE_EA44,
+    0x4BDE_CFA9,
+    0xF6BB_4B60,
+    0xBEBF_BC70,
+    0x289B_7EC6,
+    0xEAA1_27FA,
+    0xD4EF_3085,
+    0x0488_1D05,
+    0xD9D4_D039,
+    0xE6DB_99E5,
+    0x1FA2_7CF8,
+    0xC4AC_5665,
+    0xF429_2244,
+    0x432A_FF97,
+    0xAB94_23A7,
+    0xFC93_A039,
+    0x655B_59C3,
+    0x8F0C_CC92,
+    0xFFEF_F47D,
+    0x8584_5DD1,
+    0x6FA8_7E4F,
+    0xFE2C_E6E0,
+    0xA301_4314,
+    0x4E08_11A1,
+    0xF753_7E82,
+    0xBD3A_F235,
+    0x2AD7_D2BB,
+    0xEB86_D391,
+];
+
+impl Md5 {
+    pub fn new() -> Self {
+        Self {
+            state: [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476],
+            buffer: [0; 64],
+            buffered: 0,
+            total: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut input: &[u8]) {
+        self.total += input.len() as u64;
+
+        if self.buffered > 0 {
+            let take = input.len().min(64 - self.buffered);
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&input[..take]);
+            self.buffered += take;
+            input = &input[take..];
+
+            if self.buffered < 64 {
+                return;
+            }
+            let block = self.buffer;
+            self.consume(&block);
+            self.buffered = 0;
+        }
+
+        let mut blocks = input.chunks_exact(64);
+        for block in blocks.by_ref() {
+            self.consume(block);
+        }
+
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    pub fn finish(mut self) -> [u8; 16] {
+        let bits = self.total.wrapping_mul(8);
+        let padding = if self.buffered < 56 {
+            56 - self.buffered
+        } else {
+            120 - self.buffered
+        };
+        let mut tail = vec![0u8; padding];
+        tail[0] = 0x80;
+        self.update(&tail);
+        self.update(&bits.to_le_bytes());
+
+        let mut digest = [0u8; 16];
+        for (out, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            out.copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+
+    fn consume(&mut self, block: &[u8]) {
+        let words: Vec<u32> = block.chunks_exact(4).map(read_u32).collect();
+        let [mut a, mut b, mut c, mut d] = self.state;
+
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(MD5_CONSTANTS[i])
+                .wrapping_add(words[g])
+                .rotate_left(MD5_SHIFTS[i]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+/// The checksum `--verify` compares before and after, picked with
+/// `--verify-hash`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Digest {
+    /// XXH3-64, the fastest on large clips.
+    #[default]
+    Xxh3,
+    /// XXH64, what `xxhsum` and most offload tools check by default.
+    Xxh64,
+}
+
+impl Digest {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "xxh3" => Some(Digest::Xxh3),
+            "xxh64" => Some(Digest::Xxh64),
+            _ => None,
+        }
+    }
+
+    /// The name in messages, as `xxhsum` spells it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Digest::Xxh3 => "XXH3",
+            Digest::Xxh64 => "XXH64",
+        }
+    }
+
+    /// Hashes a file's contents.
+    pub fn file(self, path: &Path) -> io::Result<u64> {
+        match self {
+            Digest::Xxh3 => xxh3_file(path),
+            Digest::Xxh64 => xxh64_file(path),
+        }
+    }
+}
+
+/// Hashes a file's contents with XXH3-64 (seed 0).
+pub fn xxh3_file(path: &Path) -> io::Result<u64> {
+    trace::call("hash", path, || {
+        let mut hasher = Xxh3::new();
+        read_file(path, |chunk| hasher.update(chunk))?;
+        Ok(hasher.digest())
+    })
+}
+
+/// Hashes a file's contents with XXH64 (seed 0).
+pub fn xxh64_file(path: &Path) -> io::Result<u64> {
+    trace::call("hash", path, || {
+        let mut hasher = Xxh64::new(0);
+        read_file(path, |chunk| hasher.update(chunk))?;
+        Ok(hasher.digest())
+    })
+}
+
+/// Hashes a file's contents with MD5.
+pub fn md5_file(path: &Path) -> io::Result<[u8; 16]> {
+    trace::call("hash", path, || {
+        let mut hasher = Md5::new();
+        read_file(path, |chunk| hasher.update(chunk))?;
+        Ok(hasher.finish())
+    })
+}
+
+fn read_file(path: &Path, mut consume: impl FnMut(&[u8])) -> io::Result<()> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut file = heartbeat::Reader::new(file, "hashing", path, size);
+    let mut buffer = vec![0; READ_BUFFER];
+    let limit = READ_LIMIT.load(Ordering::Relaxed);
+    let started = Instant::now();
+    let mut done = 0u64;
+
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        consume(&buffer[..read]);
+        if limit > 0 {
+            done += read as u64;
+            let due = Duration::from_secs_f64(done as f64 / limit as f64);
+            if let Some(ahead) = due.checked_sub(started.elapsed()) {
+                thread::sleep(ahead);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn md5_hex(input: &[u8], chunk: usize) -> String {
+        let mut hasher = Md5::new();
+        for part in input.chunks(chunk.max(1)) {
+            hasher.update(part);
+        }
+        hasher
+            .finish()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// The test suite of RFC 1321, appendix A.5, fed whole and in pieces
+    /// that straddle the 64-byte blocks.
+    #[test]
+    fn md5_matches_rfc_1321() {
+        let vectors: [(&str, &str); 7] = [
+            ("", "d41d8cd98f00b204e9800998ecf8427e"),
+            ("a", "0cc175b9c0f1b6a831c399e269772661"),
+            ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+            ("message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+            (
+                "abcdefghijklmnopqrstuvwxyz",
+                "c3fcd3d76192e4007dfb496cca67e13b",
+            ),
+            (
+                "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+                "d174ab98d277d9f5a5611c2c9f419d9f",
+            ),
+            (
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+        ];
+        for (input, expected) in vectors {
+            for chunk in [input.len(), 1, 7, 63] {
+                assert_eq!(md5_hex(input.as_bytes(), chunk), expected, "{:?}", input);
+            }
+        }
+    }
+}
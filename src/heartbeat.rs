@@ -0,0 +1,191 @@
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::{datetime, format_size};
+
+struct Heartbeat {
+    interval: Option<Duration>,
+    watchdog: Option<Watchdog>,
+}
+
+static HEARTBEAT: OnceLock<Heartbeat> = OnceLock::new();
+
+/// Turns on heartbeat lines every `interval` during long reads and, when r3dy
+/// runs as a systemd service with `WatchdogSec=`, the watchdog pings. Both
+/// are driven by progress: a read that hangs stops the heartbeat, so a
+/// monitor (or systemd) can tell a slow transfer from a stuck one.
+pub fn init(interval: Option<Duration>) {
+    let watchdog = Watchdog::from_env();
+    if interval.is_none() && watchdog.is_none() {
+        return;
+    }
+    let _ = HEARTBEAT.set(Heartbeat { interval, watchdog });
+}
+
+/// Parses a `--heartbeat` interval such as `30s` or `5m`.
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    match datetime::parse_duration(value) {
+        Some(interval) if !interval.is_zero() => Ok(interval),
+        _ => Err(format!(
+            "Invalid --heartbeat interval: {} (e.g. 30s or 5m)",
+            value
+        )),
+    }
+}
+
+/// Marks the run as alive between files.
+pub fn pulse() {
+    if let Some(watchdog) = HEARTBEAT.get().and_then(|beat| beat.watchdog.as_ref()) {
+        watchdog.ping();
+    }
+}
+
+/// Wraps a reader for a single file so each read counts as progress.
+pub struct Reader<'a, R> {
+    inner: R,
+    op: &'static str,
+    path: &'a Path,
+    total: u64,
+    done: u64,
+    started: Instant,
+    last_beat: Instant,
+}
+
+impl<'a, R: Read> Reader<'a, R> {
+    /// `op` names the work in heartbeat lines ("hashing", "archiving");
+    /// `total` is the expected size, or 0 when unknown.
+    pub fn new(inner: R, op: &'static str, path: &'a Path, total: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            inner,
+            op,
+            path,
+            total,
+            done: 0,
+            started: now,
+            last_beat: now,
+        }
+    }
+}
+
+impl<R: Read> Read for Reader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.done += read as u64;
+
+        if let Some(beat) = HEARTBEAT.get() {
+            if let Some(watchdog) = &beat.watchdog {
+                watchdog.ping();
+            }
+            if let Some(interval) = beat.interval
+                && self.last_beat.elapsed() >= interval
+            {
+                self.last_beat = Instant::now();
+                eprintln!(
+                    "Still {} {}: {} after {}",
+                    self.op,
+                    self.path.display(),
+                    self.position(),
+                    format_elapsed(self.started.elapsed())
+                );
+            }
+        }
+
+        Ok(read)
+    }
+}
+
+impl<R> Reader<'_, R> {
+    fn position(&self) -> String {
+        if self.total == 0 {
+            return format_size(self.done);
+        }
+        format!(
+            "{} of {} ({}%)",
+            format_size(self.done),
+            format_size(self.total),
+            self.done * 100 / self.total
+        )
+    }
+}
+
+/// The systemd notify socket, pinged at half the watchdog timeout as
+/// systemd recommends.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+struct Watchdog {
+    #[cfg(target_os = "linux")]
+    socket: std::os::unix::net::UnixDatagram,
+    interval: Duration,
+    last: Mutex<Option<Instant>>,
+}
+
+impl Watchdog {
+    #[cfg(target_os = "linux")]
+    fn from_env() -> Option<Self> {
+        use std::env;
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+        let path = env::var_os("NOTIFY_SOCKET")?;
+        let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+        // WATCHDOG_PID, when set, names the process systemd expects pings
+        // from; a child of the service must stay quiet.
+        if let Ok(pid) = env::var("WATCHDOG_PID")
+            && pid.parse::<u32>().ok() != Some(std::process::id())
+        {
+            return None;
+        }
+
+        let bytes = path.as_encoded_bytes();
+        let addr = match bytes.strip_prefix(b"@") {
+            Some(name) => SocketAddr::from_abstract_name(name),
+            None => SocketAddr::from_pathname(&path),
+        };
+        let socket =
+            UnixDatagram::unbound().and_then(|socket| socket.connect_addr(&addr?).map(|()| socket));
+        match socket {
+            Ok(socket) => Some(Self {
+                socket,
+                interval: Duration::from_micros(usec / 2),
+                last: Mutex::new(None),
+            }),
+            Err(err) => {
+                eprintln!(
+                    "Warning: systemd watchdog is enabled but its socket cannot be reached: {}",
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn from_env() -> Option<Self> {
+        None
+    }
+
+    fn ping(&self) {
+        let Ok(mut last) = self.last.lock() else {
+            return;
+        };
+        if last.is_some_and(|at| at.elapsed() < self.interval) {
+            return;
+        }
+        *last = Some(Instant::now());
+
+        #[cfg(target_os = "linux")]
+        let _ = self.socket.send(b"WATCHDOG=1");
+    }
+}
+
+/// A duration as `45s`, `3m05s` or `2h10m`.
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
@@ -0,0 +1,90 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::glob::Glob;
+use crate::trace;
+
+/// The name of the files that mark what a scan must leave alone.
+pub const FILE_NAME: &str = ".r3dyignore";
+
+/// The `.r3dyignore` rules in force in a folder: its own file's, then those
+/// of the folders above it that the scan came through. Patterns follow
+/// gitignore: `#` starts a comment, `!` re-includes, a trailing `/` only
+/// matches folders, and a pattern with a `/` elsewhere is anchored to the
+/// folder the file is in; otherwise it matches the name at any depth.
+pub struct Ignore {
+    dir: PathBuf,
+    rules: Vec<Rule>,
+    parent: Option<Rc<Ignore>>,
+}
+
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// The rules for `dir`: `parent` plus those of its `.r3dyignore`, if it has
+/// one.
+pub fn load(dir: &Path, parent: Option<Rc<Ignore>>) -> Result<Option<Rc<Ignore>>, String> {
+    let path = dir.join(FILE_NAME);
+    let text = match trace::call("read", &path, || fs::read_to_string(&path)) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(parent),
+        Err(err) => return Err(format!("Failed to read {}: {}", path.display(), err)),
+    };
+
+    let mut rules = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        let glob = Glob::parse(pattern.trim_start_matches('/'))
+            .map_err(|err| format!("{} line {}: {}", path.display(), number + 1, err))?;
+        rules.push(Rule {
+            glob: if anchored { glob.anchored() } else { glob },
+            negated,
+            dir_only,
+        });
+    }
+    if rules.is_empty() {
+        return Ok(parent);
+    }
+    Ok(Some(Rc::new(Ignore {
+        dir: dir.to_path_buf(),
+        rules,
+        parent,
+    })))
+}
+
+impl Ignore {
+    /// Whether `path`, a file or folder below this one, is ignored. The
+    /// deepest file with a matching rule decides, and within a file the
+    /// last matching rule.
+    pub fn ignores(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ignore = Some(self);
+        while let Some(level) = ignore {
+            let relative = path.strip_prefix(&level.dir).unwrap_or(path);
+            let decided = level
+                .rules
+                .iter()
+                .rev()
+                .find(|rule| (is_dir || !rule.dir_only) && rule.glob.matches(relative));
+            if let Some(rule) = decided {
+                return !rule.negated;
+            }
+            ignore = level.parent.as_deref();
+        }
+        false
+    }
+}
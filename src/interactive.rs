@@ -0,0 +1,80 @@
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use indicatif::ProgressBar;
+
+use crate::display_relative;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Answer {
+    Rename,
+    Skip,
+    /// Stop asking and leave the remaining files alone.
+    Quit,
+}
+
+/// Asks on the terminal before each clip is renamed, for `--interactive`.
+/// A spanned clip is asked about once, at its first segment, and its other
+/// segments follow the answer, since a partly renamed clip is rolled back.
+#[derive(Default)]
+pub struct Prompt {
+    /// Set once "a" is answered.
+    all: bool,
+    /// The clip last asked about and the answer.
+    last: Option<(PathBuf, Answer)>,
+}
+
+impl Prompt {
+    /// The answer for `path`, which is renamed to `target` and belongs to
+    /// the clip `clip`.
+    pub fn ask(
+        &mut self,
+        root: &Path,
+        path: &Path,
+        target: &Path,
+        clip: &Path,
+        progress: &ProgressBar,
+    ) -> Answer {
+        if self.all {
+            return Answer::Rename;
+        }
+        if let Some((last, answer)) = &self.last
+            && last == clip
+        {
+            return *answer;
+        }
+
+        let question = format!(
+            "Rename {} \u{2192} {}? [y]es, [n]o, [a]ll, [q]uit ",
+            display_relative(root, path),
+            display_relative(root, target)
+        );
+        let answer = progress.suspend(|| loop {
+            eprint!("{}", question);
+            let _ = io::stderr().flush();
+            let mut line = String::new();
+            // Like a "q" once there is nothing left to read.
+            match io::stdin().lock().read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    eprintln!();
+                    break Answer::Quit;
+                }
+                Ok(_) => {}
+            }
+            match line.trim().to_ascii_lowercase().as_str() {
+                "y" | "yes" => break Answer::Rename,
+                "n" | "no" => break Answer::Skip,
+                "a" | "all" => {
+                    self.all = true;
+                    break Answer::Rename;
+                }
+                "q" | "quit" => break Answer::Quit,
+                _ => eprintln!(
+                    "Answer y to rename it, n to leave it, a to rename it and all the rest, or q to stop"
+                ),
+            }
+        });
+        self.last = Some((clip.to_path_buf(), answer));
+        answer
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by Ctrl-C or SIGTERM once `catch` has been called.
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// Whether Ctrl-C or SIGTERM has asked the process to stop.
+pub fn stopped() -> bool {
+    STOPPED.load(Ordering::SeqCst)
+}
+
+/// Makes Ctrl-C (SIGINT) and SIGTERM ask the process to stop instead of
+/// killing it, for loops that run until interrupted and check `stopped` to
+/// close their journal and lock first. A wait in progress is cut short.
+#[cfg(unix)]
+pub fn catch() {
+    extern "C" fn handle(_signal: libc::c_int) {
+        STOPPED.store(true, Ordering::SeqCst);
+    }
+
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the handler only stores to an atomic, which is safe in a
+        // signal handler. Without SA_RESTART, a poll or read in progress
+        // returns EINTR so the loop sees the flag straight away.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+#[cfg(windows)]
+pub fn catch() {
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn SetConsoleCtrlHandler(
+            handler: Option<unsafe extern "system" fn(u32) -> i32>,
+            add: i32,
+        ) -> i32;
+    }
+
+    /// Handles Ctrl-C, Ctrl-Break and the console closing.
+    unsafe extern "system" fn handle(_event: u32) -> i32 {
+        STOPPED.store(true, Ordering::SeqCst);
+        1
+    }
+
+    // SAFETY: registers a handler that only stores to an atomic.
+    unsafe {
+        SetConsoleCtrlHandler(Some(handle), 1);
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn catch() {}
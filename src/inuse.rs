@@ -0,0 +1,241 @@
+use std::fs::Metadata;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a list of the files other processes have open is trusted
+/// before it is taken again.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const FRESH_FOR: Duration = Duration::from_secs(2);
+
+/// How often `wait` looks again while a file stays in use.
+const POLL: Duration = Duration::from_millis(500);
+
+/// Finds files another program, such as REDCINE-X or Resolve, has open, so
+/// they are left alone with a clear reason instead of failing with an OS
+/// error or being renamed under the program.
+#[derive(Default)]
+pub struct Probe {
+    /// The files open in other processes by device and inode, with the
+    /// process holding each, and when that was read.
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    open: Option<(Instant, sys::OpenFiles)>,
+}
+
+impl Probe {
+    /// Why `path` cannot be renamed now, such as "in use by Resolve (pid
+    /// 4120)", if another process has it open.
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub fn in_use(&mut self, _path: &Path, metadata: &Metadata) -> Option<String> {
+        use std::os::unix::fs::MetadataExt;
+
+        if self
+            .open
+            .as_ref()
+            .is_none_or(|(read, _)| read.elapsed() > FRESH_FOR)
+        {
+            self.open = Some((Instant::now(), sys::open_files()));
+        }
+        let (_, open) = self.open.as_ref()?;
+        let pid = *open.get(&(metadata.dev(), metadata.ino()))?;
+        Some(match sys::name(pid) {
+            Some(name) => format!("in use by {} (pid {})", name, pid),
+            None => format!("in use by pid {}", pid),
+        })
+    }
+
+    /// Windows will not rename a file another program holds open, and says
+    /// which only through the Restart Manager, so this only asks whether
+    /// the file can be opened exclusively.
+    #[cfg(windows)]
+    pub fn in_use(&mut self, path: &Path, _metadata: &Metadata) -> Option<String> {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        const ERROR_SHARING_VIOLATION: i32 = 32;
+        const ERROR_LOCK_VIOLATION: i32 = 33;
+
+        match std::fs::File::options().read(true).share_mode(0).open(path) {
+            Err(err)
+                if matches!(
+                    err.raw_os_error(),
+                    Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+                ) =>
+            {
+                Some("in use by another program".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    pub fn in_use(&mut self, _path: &Path, _metadata: &Metadata) -> Option<String> {
+        None
+    }
+
+    /// Like `in_use`, but first waits up to `timeout` for the file to be
+    /// let go of, for `--wait-for-unlock`.
+    pub fn wait(&mut self, path: &Path, metadata: &Metadata, timeout: Duration) -> Option<String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let reason = self.in_use(path, metadata)?;
+            if Instant::now() >= deadline {
+                return Some(reason);
+            }
+            thread::sleep(POLL);
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            {
+                self.open = None;
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+
+    pub type OpenFiles = HashMap<(u64, u64), i32>;
+
+    /// Every regular file open in another process that this user may look
+    /// at, from `/proc`.
+    pub fn open_files() -> OpenFiles {
+        let mut open = HashMap::new();
+        let own = std::process::id() as i32;
+        let Ok(processes) = fs::read_dir("/proc") else {
+            return open;
+        };
+        for process in processes.flatten() {
+            let Some(pid) = process
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<i32>().ok())
+            else {
+                continue;
+            };
+            if pid == own {
+                continue;
+            }
+            let Ok(fds) = fs::read_dir(process.path().join("fd")) else {
+                continue;
+            };
+            for fd in fds.flatten() {
+                if let Ok(metadata) = fs::metadata(fd.path())
+                    && metadata.is_file()
+                {
+                    open.insert((metadata.dev(), metadata.ino()), pid);
+                }
+            }
+        }
+        open
+    }
+
+    pub fn name(pid: i32) -> Option<String> {
+        let name = fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+        Some(name.trim_end().to_string())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod sys {
+    use std::collections::HashMap;
+    use std::ffi::{CStr, c_int, c_void};
+    use std::mem;
+
+    /// `struct vnode_fdinfowithpath` from <sys/proc_info.h>, which libc does
+    /// not declare.
+    #[repr(C)]
+    struct VnodeFdInfoWithPath {
+        /// `struct proc_fileinfo`, not needed here.
+        _file: [u64; 3],
+        path: libc::vnode_info_path,
+    }
+
+    const PROC_PIDFDVNODEPATHINFO: c_int = 2;
+
+    pub type OpenFiles = HashMap<(u64, u64), i32>;
+
+    /// Every file open in another process that this user may look at, from
+    /// libproc.
+    pub fn open_files() -> OpenFiles {
+        let mut open = HashMap::new();
+        let own = std::process::id() as i32;
+
+        // SAFETY: a null buffer asks for the number of processes.
+        let count = unsafe { libc::proc_listallpids(std::ptr::null_mut(), 0) };
+        if count <= 0 {
+            return open;
+        }
+        // Room for processes started since.
+        let mut pids: Vec<c_int> = vec![0; count as usize + 64];
+        let size = (pids.len() * mem::size_of::<c_int>()) as c_int;
+        // SAFETY: the buffer holds `size` bytes.
+        let count = unsafe { libc::proc_listallpids(pids.as_mut_ptr().cast(), size) };
+        pids.truncate(count.max(0) as usize);
+
+        for pid in pids.into_iter().filter(|&pid| pid != own) {
+            for fd in fds(pid) {
+                if fd.proc_fdtype != libc::PROX_FDTYPE_VNODE as u32 {
+                    continue;
+                }
+                // SAFETY: all-zero is a valid value of this plain C struct.
+                let mut info: VnodeFdInfoWithPath = unsafe { mem::zeroed() };
+                let size = mem::size_of::<VnodeFdInfoWithPath>() as c_int;
+                // SAFETY: `info` holds `size` bytes of the flavor asked for.
+                let filled = unsafe {
+                    libc::proc_pidfdinfo(
+                        pid,
+                        fd.proc_fd,
+                        PROC_PIDFDVNODEPATHINFO,
+                        (&mut info as *mut VnodeFdInfoWithPath).cast::<c_void>(),
+                        size,
+                    )
+                };
+                if filled == size {
+                    let stat = &info.path.vip_vi.vi_stat;
+                    open.insert((stat.vst_dev as u64, stat.vst_ino), pid);
+                }
+            }
+        }
+        open
+    }
+
+    /// The open descriptors of `pid`, or none if it is not this user's.
+    fn fds(pid: c_int) -> Vec<libc::proc_fdinfo> {
+        // SAFETY: a null buffer asks for the size of the list.
+        let size =
+            unsafe { libc::proc_pidinfo(pid, libc::PROC_PIDLISTFDS, 0, std::ptr::null_mut(), 0) };
+        if size <= 0 {
+            return Vec::new();
+        }
+        let entry = mem::size_of::<libc::proc_fdinfo>();
+        let mut fds: Vec<libc::proc_fdinfo> = Vec::with_capacity(size as usize / entry + 16);
+        let capacity = (fds.capacity() * entry) as c_int;
+        // SAFETY: the buffer holds `capacity` bytes, and the entries the
+        // call fills are initialized before the length is set.
+        unsafe {
+            let filled = libc::proc_pidinfo(
+                pid,
+                libc::PROC_PIDLISTFDS,
+                0,
+                fds.as_mut_ptr().cast(),
+                capacity,
+            );
+            fds.set_len(filled.max(0) as usize / entry);
+        }
+        fds
+    }
+
+    pub fn name(pid: i32) -> Option<String> {
+        let mut buffer = [0u8; 256];
+        // SAFETY: the buffer holds the length given.
+        let length =
+            unsafe { libc::proc_name(pid, buffer.as_mut_ptr().cast(), buffer.len() as u32) };
+        if length <= 0 {
+            return None;
+        }
+        let name = CStr::from_bytes_until_nul(&buffer).ok()?;
+        Some(name.to_string_lossy().into_owned())
+    }
+}
@@ -0,0 +1,1044 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::mhl::hostname;
+use crate::{backend, datetime, json, manifest, noreplace, pathenc, report, runlock, trace};
+
+const EXTENSION: &str = "jsonl";
+/// Appended to a journal's name once every rename in it has been undone.
+const UNDONE_SUFFIX: &str = ".undone";
+/// How long a writer waits for the lock file of a journal on a volume
+/// without OS locks, and how old one must be to be taken for a crashed
+/// writer's.
+const LOCK_WAIT: Duration = Duration::from_secs(10);
+
+/// The record of one run's renames, written as they happen so it survives a
+/// crash. JSON Lines: a header with the root, start time, extension pair and
+/// the arguments the run was started with, then one line per rename with
+/// absolute, `pathenc`-encoded paths, then a `finished` line once the run
+/// has ended. A run over several directories lists them as `roots` in the
+/// header, and each line gives the index of the one its file came from.
+///
+/// The header and every rename are stamped with the operator and host, as
+/// `R3DY_JOURNAL_DIR` may be a volume several stations share, and each
+/// line is appended under a lock so writers on different stations cannot
+/// interleave.
+pub struct Journal {
+    path: PathBuf,
+    out: LineWriter<File>,
+    /// `,"operator":...,"host":...`, added to every line.
+    stamp: String,
+    /// The directories scanned, when there are several.
+    roots: Vec<PathBuf>,
+    renames: usize,
+    failures: usize,
+}
+
+/// What `Journal::roll_back` left behind.
+pub struct RolledBack {
+    pub restored: usize,
+    /// Renamed files that are still at their new name.
+    pub left: HashSet<PathBuf>,
+    /// The journal, kept when some files were left so a later `r3dy undo`
+    /// can finish the job.
+    pub journal: Option<PathBuf>,
+}
+
+impl Journal {
+    /// Starts a journal for a run under `root`, scanning `roots`. `copy`
+    /// records that the run copied files rather than renamed them, so undo
+    /// removes the copies. `args` are kept with the working directory for
+    /// `r3dy resume`; a run through the library has none, and cannot be
+    /// resumed.
+    pub fn create(
+        root: &Path,
+        roots: &[PathBuf],
+        from: &str,
+        to: &str,
+        copy: bool,
+        args: Option<&[String]>,
+    ) -> Result<Self, String> {
+        let dir = dir().ok_or("Cannot find a home directory for the undo journal")?;
+        fs::create_dir_all(&dir)
+            .map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+
+        // Timestamped names sort by run; the pid keeps concurrent runs apart.
+        let started = SystemTime::now();
+        let name = format!(
+            "{}-{}.{}",
+            datetime::rfc3339(started).replace(':', "-"),
+            std::process::id(),
+            EXTENSION
+        );
+        let path = dir.join(name);
+        let file = OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|err| format!("Failed to create {}: {}", path.display(), err))?;
+
+        let mut journal = Self {
+            path,
+            out: LineWriter::new(file),
+            stamp: stamp(),
+            roots: several(roots),
+            renames: 0,
+            failures: 0,
+        };
+        let cwd = env::current_dir().unwrap_or_default();
+        let args = match args {
+            Some(args) => {
+                let args: Vec<String> = args.iter().map(|arg| json::string(arg)).collect();
+                format!(",\"args\":[{}]", args.join(","))
+            }
+            None => String::new(),
+        };
+        let roots = if journal.roots.is_empty() {
+            String::new()
+        } else {
+            let roots: Vec<String> = journal
+                .roots
+                .iter()
+                .map(|root| json::string(&pathenc::encode(root)))
+                .collect();
+            format!(",\"roots\":[{}]", roots.join(","))
+        };
+        let header = format!(
+            "{{\"journal\":1,\"root\":{}{},\"started\":{},\"from\":{},\"to\":{},\"copy\":{},\"cwd\":{}{}",
+            json::string(&pathenc::encode(root)),
+            roots,
+            json::string(&datetime::rfc3339(started)),
+            json::string(from),
+            json::string(to),
+            copy,
+            json::string(&pathenc::encode(&cwd)),
+            args
+        );
+        journal.append(&header)?;
+        Ok(journal)
+    }
+
+    /// Opens the journal of an interrupted run to record the rest of it.
+    /// `roots` are the directories it scans, the same as when it started.
+    pub fn reopen(resumed: &Resumed, roots: &[PathBuf]) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&resumed.path)
+            .map_err(|err| format!("Failed to open {}: {}", resumed.path.display(), err))?;
+        Ok(Self {
+            path: resumed.path.clone(),
+            out: LineWriter::new(file),
+            stamp: stamp(),
+            roots: several(roots),
+            renames: resumed.done.len(),
+            failures: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&mut self, source: &Path, target: &Path) -> Result<(), String> {
+        self.append(&format!(
+            "{{\"source\":{},\"target\":{}{}",
+            json::string(&pathenc::encode(source)),
+            json::string(&pathenc::encode(target)),
+            self.root_of(source)
+        ))?;
+        self.renames += 1;
+        Ok(())
+    }
+
+    /// Closes the journal, removing it if the run renamed nothing and had
+    /// no failures to retry. Returns the path when there is something to
+    /// undo.
+    pub fn finish(mut self) -> Option<PathBuf> {
+        self.mark_finished();
+        if self.renames == 0 && self.failures == 0 {
+            let _ = fs::remove_file(&self.path);
+            return None;
+        }
+        (self.renames > 0).then_some(self.path)
+    }
+
+    /// Records a file that could not be renamed, for `r3dy retry`.
+    pub fn record_failure(&mut self, source: &Path, target: &Path) -> Result<(), String> {
+        self.append(&format!(
+            "{{\"source\":{},\"target\":{}{},\"failed\":true",
+            json::string(&pathenc::encode(source)),
+            json::string(&pathenc::encode(target)),
+            self.root_of(source)
+        ))?;
+        self.failures += 1;
+        Ok(())
+    }
+
+    /// Writes `line`, a JSON object missing its closing brace, with the
+    /// stamp added. The file is locked for the write: through the OS where
+    /// the volume supports it (`flock` on Unix, `LockFileEx` on Windows),
+    /// otherwise, as on some SMB and NFS mounts, with a lock file beside it.
+    fn append(&mut self, line: &str) -> Result<(), String> {
+        let failed = |err: io::Error| format!("Failed to write {}: {}", self.path.display(), err);
+        let locked = self.out.get_ref().lock().is_ok();
+        let lock_file = if locked {
+            None
+        } else {
+            Some(LockFile::take(&self.path).map_err(failed)?)
+        };
+        let written =
+            writeln!(self.out, "{}{}}}", line, self.stamp).and_then(|()| self.out.flush());
+        if locked {
+            let _ = self.out.get_ref().unlock();
+        }
+        drop(lock_file);
+        written.map_err(failed)
+    }
+
+    /// `,"root":N` for the directory `source` came from, when there are
+    /// several.
+    fn root_of(&self, source: &Path) -> String {
+        self.roots
+            .iter()
+            .position(|root| source.starts_with(root))
+            .map(|index| format!(",\"root\":{}", index))
+            .unwrap_or_default()
+    }
+
+    /// Records that the run ended, so `r3dy resume` leaves it alone.
+    fn mark_finished(&mut self) {
+        let _ = self.append(&format!(
+            "{{\"finished\":{}",
+            json::string(&datetime::rfc3339(SystemTime::now()))
+        ));
+    }
+
+    /// Reverses every rename recorded so far, newest first, for `--atomic`.
+    /// The journal is removed once nothing is left to undo.
+    pub fn roll_back(mut self) -> Result<RolledBack, String> {
+        self.out
+            .flush()
+            .map_err(|err| format!("Failed to write {}: {}", self.path.display(), err))?;
+        let recorded = read(&self.path)?;
+        let mut rolled_back = RolledBack {
+            restored: 0,
+            left: HashSet::new(),
+            journal: None,
+        };
+        for (source, target) in recorded.renames.into_iter().rev() {
+            let undone = if recorded.copy {
+                remove_copy(&source, &target)
+            } else {
+                restore(&source, &target)
+            };
+            match undone {
+                Undone::Done => rolled_back.restored += 1,
+                Undone::Skipped | Undone::Failed => {
+                    rolled_back.left.insert(target);
+                }
+            }
+        }
+        if rolled_back.left.is_empty() {
+            let _ = fs::remove_file(&self.path);
+        } else {
+            self.mark_finished();
+            rolled_back.journal = Some(self.path);
+        }
+        Ok(rolled_back)
+    }
+}
+
+/// The lock file that stands in for an OS lock on `journal` while one line
+/// is appended, removed when dropped.
+struct LockFile {
+    path: PathBuf,
+}
+
+impl LockFile {
+    /// Creates the lock file, waiting while another writer has it. One
+    /// older than `LOCK_WAIT` was left by a writer that died, and is
+    /// replaced.
+    fn take(journal: &Path) -> io::Result<Self> {
+        let mut name = journal.as_os_str().to_owned();
+        name.push(".lock");
+        let path = PathBuf::from(name);
+        let started = SystemTime::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    let _ = writeln!(file, "pid {}\nhost {}", std::process::id(), hostname());
+                    return Ok(Self { path });
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(err),
+            }
+            let stale = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age > LOCK_WAIT));
+            if stale {
+                let _ = fs::remove_file(&path);
+            } else if started.elapsed().is_ok_and(|waited| waited > LOCK_WAIT) {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("{} is held by another writer", path.display()),
+                ));
+            } else {
+                thread::sleep(Duration::from_millis(20));
+            }
+        }
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// `,"operator":...,"host":...` for this station: the operator is
+/// `R3DY_OPERATOR` when set, otherwise the login name.
+fn stamp() -> String {
+    let operator = ["R3DY_OPERATOR", "USER", "USERNAME"]
+        .into_iter()
+        .find_map(|var| env::var(var).ok().filter(|value| !value.is_empty()))
+        .unwrap_or_default();
+    format!(
+        ",\"operator\":{},\"host\":{}",
+        json::string(&operator),
+        json::string(&hostname())
+    )
+}
+
+/// The roots worth recording: none when there is only the one.
+fn several(roots: &[PathBuf]) -> Vec<PathBuf> {
+    if roots.len() > 1 {
+        roots.to_vec()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Where journals are kept: `R3DY_JOURNAL_DIR` when set, otherwise the
+/// per-user data directory of the platform.
+pub fn dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("R3DY_JOURNAL_DIR").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+
+    let data = if cfg!(windows) {
+        PathBuf::from(env::var_os("LOCALAPPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(env::var_os("HOME")?).join("Library/Application Support")
+    } else {
+        match env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+        }
+    };
+    Some(data.join("r3dy").join("journal"))
+}
+
+/// A journal read back for `r3dy undo` and `r3dy resume`.
+struct Recorded {
+    root: String,
+    /// The directories the run scanned, for the run lock.
+    roots: Vec<PathBuf>,
+    started: String,
+    /// The run copied rather than renamed.
+    copy: bool,
+    /// Where and with which arguments the run was started; missing from
+    /// journals written before `r3dy resume`.
+    cwd: Option<PathBuf>,
+    args: Option<Vec<String>>,
+    /// Who started the run, and on which host; empty in journals written
+    /// before they were stamped.
+    operator: String,
+    host: String,
+    renames: Vec<(PathBuf, PathBuf)>,
+    /// The host each rename was made on, which differs from `host` for a
+    /// run resumed on another station.
+    stations: Vec<String>,
+    /// The files that could not be renamed.
+    failed: Vec<PathBuf>,
+    /// The run ended rather than being interrupted.
+    finished: bool,
+}
+
+impl Recorded {
+    /// The run was started on this host, or by an r3dy too old to say.
+    fn started_here(&self) -> bool {
+        self.host.is_empty() || self.host == hostname()
+    }
+
+    /// `" by OPERATOR on HOST"`, when the journal says.
+    fn by(&self) -> String {
+        match (self.operator.as_str(), self.host.as_str()) {
+            ("", "") => String::new(),
+            ("", host) => format!(" on {}", host),
+            (operator, "") => format!(" by {}", operator),
+            (operator, host) => format!(" by {} on {}", operator, host),
+        }
+    }
+
+    /// The run was a `r3dy watch` session, which has no end to resume from
+    /// and no failures recorded to retry.
+    fn watched(&self) -> bool {
+        self.args
+            .as_ref()
+            .is_some_and(|args| args.iter().any(|arg| arg == "--watch"))
+    }
+
+    /// Fails for a watch session, which `r3dy resume` and `r3dy retry`
+    /// cannot pick up.
+    fn not_watched(&self) -> Result<(), String> {
+        if self.watched() {
+            return Err(format!(
+                "The run of {} on {} was a watch session, which cannot be resumed or retried; start r3dy watch again instead",
+                self.started, self.root
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn read(path: &Path) -> Result<Recorded, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    let invalid = |line: usize, err: String| {
+        format!(
+            "{} is not an r3dy journal (line {}: {})",
+            path.display(),
+            line,
+            err
+        )
+    };
+
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty());
+    let (_, header) = lines
+        .next()
+        .ok_or_else(|| invalid(1, "empty".to_string()))?;
+    let header = json::parse(header).map_err(|err| invalid(1, err))?;
+    if header.get("journal").is_none() {
+        return Err(invalid(1, "missing header".to_string()));
+    }
+    let field = |value: &json::Value, name: &str| {
+        value
+            .get(name)
+            .and_then(json::Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let roots = match header.get("roots").and_then(json::Value::as_array) {
+        Some(roots) => roots
+            .iter()
+            .filter_map(json::Value::as_str)
+            .filter_map(|root| pathenc::decode(root).ok())
+            .collect(),
+        None => pathenc::decode(&field(&header, "root"))
+            .into_iter()
+            .collect(),
+    };
+    let mut recorded = Recorded {
+        root: field(&header, "root"),
+        roots,
+        started: field(&header, "started"),
+        copy: header.get("copy") == Some(&json::Value::Bool(true)),
+        cwd: header
+            .get("cwd")
+            .and_then(json::Value::as_str)
+            .and_then(|cwd| pathenc::decode(cwd).ok()),
+        args: header
+            .get("args")
+            .and_then(json::Value::as_array)
+            .map(|args| {
+                args.iter()
+                    .filter_map(|arg| arg.as_str().map(str::to_string))
+                    .collect()
+            }),
+        operator: field(&header, "operator"),
+        host: field(&header, "host"),
+        renames: Vec::new(),
+        stations: Vec::new(),
+        failed: Vec::new(),
+        finished: false,
+    };
+    let total = text.lines().count();
+    for (index, line) in lines {
+        let entry = match json::parse(line) {
+            Ok(entry) => entry,
+            // A run killed mid-write can leave half a line at the end; the
+            // renames before it are still good.
+            Err(_) if index + 1 == total => {
+                eprintln!("Ignoring an incomplete last line in {}", path.display());
+                break;
+            }
+            Err(err) => return Err(invalid(index + 1, err)),
+        };
+        if entry.get("finished").is_some() {
+            recorded.finished = true;
+            continue;
+        }
+        let decode = |name: &str| match field(&entry, name) {
+            text if text.is_empty() => Err(invalid(index + 1, format!("missing {}", name))),
+            text => pathenc::decode(&text).map_err(|err| invalid(index + 1, err)),
+        };
+        if entry.get("failed").is_some() {
+            recorded.failed.push(decode("source")?);
+        } else {
+            recorded
+                .renames
+                .push((decode("source")?, decode("target")?));
+            let station = match field(&entry, "host") {
+                host if host.is_empty() => recorded.host.clone(),
+                host => host,
+            };
+            recorded.stations.push(station);
+        }
+    }
+    Ok(recorded)
+}
+
+/// Journals in the journal directory, oldest first.
+fn journals() -> Result<Vec<PathBuf>, String> {
+    let Some(dir) = dir() else {
+        return Ok(Vec::new());
+    };
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("Failed to read {}: {}", dir.display(), err)),
+    };
+
+    let mut journals: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == EXTENSION))
+        .collect();
+    journals.sort();
+    Ok(journals)
+}
+
+fn is_undone(path: &Path) -> bool {
+    path.file_stem()
+        .is_some_and(|stem| stem.to_string_lossy().ends_with(UNDONE_SUFFIX))
+}
+
+/// An interrupted run picked up by `r3dy resume`.
+pub struct Resumed {
+    pub path: PathBuf,
+    /// The renames recorded before the run stopped, oldest first.
+    pub done: Vec<(PathBuf, PathBuf)>,
+}
+
+/// The arguments of `r3dy resume`.
+#[derive(clap::Args)]
+pub struct ResumeArgs {
+    /// Resume the run recorded in FILE
+    #[arg(long, value_name = "FILE")]
+    journal: Option<PathBuf>,
+}
+
+/// Entry point for `r3dy resume`: finds the interrupted run, moves to the
+/// folder it was started in and returns its arguments.
+pub fn resume(args: ResumeArgs) -> Result<(Vec<String>, Resumed), String> {
+    let path = chosen(args.journal)?;
+    let recorded = read(&path)?;
+    recorded.not_watched()?;
+    if recorded.finished {
+        return Err(format!(
+            "The run of {} on {} finished; there is nothing to resume",
+            recorded.started, recorded.root
+        ));
+    }
+    let run_args = restart(&path, &recorded)?;
+    let done = recorded.renames.len();
+    eprintln!(
+        "Resuming the run of {} on {} ({} file{} already done)",
+        recorded.started,
+        recorded.root,
+        done,
+        if done == 1 { "" } else { "s" }
+    );
+    Ok((
+        run_args,
+        Resumed {
+            path,
+            done: recorded.renames,
+        },
+    ))
+}
+
+/// A finished run whose failures `r3dy retry` renames again.
+pub struct Retried {
+    /// The files that failed, sidecars included.
+    pub failed: Vec<PathBuf>,
+    /// The failures come from a report or manifest, relative to the root,
+    /// rather than from a journal, and the options from the command line.
+    pub from_report: bool,
+}
+
+/// The arguments of `r3dy retry`.
+#[derive(clap::Args)]
+pub struct RetryArgs {
+    /// Retry the failures of the run recorded in FILE
+    #[arg(long, value_name = "FILE", conflicts_with = "listed")]
+    journal: Option<PathBuf>,
+    /// Retry the failures listed in a CSV or JSON report written with
+    /// --report, under the path and with the options given after it
+    #[arg(long, value_name = "FILE", group = "listed")]
+    report: Option<PathBuf>,
+    /// Retry the failures listed in a manifest written with --manifest or
+    /// --archive, under the path and with the options given after it
+    #[arg(long, value_name = "FILE", group = "listed")]
+    manifest: Option<PathBuf>,
+    /// The options and path of r3dy rename to retry the listed failures
+    /// with; a --report or --manifest among them is the retry's own
+    #[arg(
+        value_name = "OPTIONS",
+        requires = "listed",
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    options: Vec<String>,
+}
+
+/// Entry point for `r3dy retry`: finds the run, moves to the folder it was
+/// started in and returns its arguments.
+pub fn retry(args: RetryArgs) -> Result<(Vec<String>, Retried), String> {
+    if let Some(report) = &args.report {
+        let failed = report::load(report)?
+            .into_iter()
+            .filter(|entry| report::is_failure(&entry.status))
+            .map(|entry| {
+                pathenc::decode(&entry.source)
+                    .map_err(|err| format!("{}: {}", report.display(), err))
+            })
+            .collect::<Result<_, _>>()?;
+        return retry_listed(report, failed, args.options);
+    }
+    if let Some(manifest) = &args.manifest {
+        return retry_listed(manifest, manifest::failures(manifest)?, args.options);
+    }
+    let path = chosen(args.journal)?;
+    let recorded = read(&path)?;
+    recorded.not_watched()?;
+    if !recorded.finished {
+        return Err(format!(
+            "The run of {} on {} was interrupted; r3dy resume continues it, failed files included",
+            recorded.started, recorded.root
+        ));
+    }
+    if recorded.failed.is_empty() {
+        return Err(format!(
+            "The run of {} on {} had no failures to retry",
+            recorded.started, recorded.root
+        ));
+    }
+    let run_args = restart(&path, &recorded)?;
+    eprintln!(
+        "Retrying {} failed file{} from the run of {} on {}",
+        recorded.failed.len(),
+        if recorded.failed.len() == 1 { "" } else { "s" },
+        recorded.started,
+        recorded.root
+    );
+    Ok((
+        run_args,
+        Retried {
+            failed: recorded.failed,
+            from_report: false,
+        },
+    ))
+}
+
+/// `r3dy retry --report FILE` and `--manifest FILE`: the failures `file`
+/// lists, to rename with `args`.
+fn retry_listed(
+    file: &Path,
+    failed: Vec<PathBuf>,
+    args: Vec<String>,
+) -> Result<(Vec<String>, Retried), String> {
+    if failed.is_empty() {
+        return Err(format!("{} lists no failures to retry", file.display()));
+    }
+    eprintln!(
+        "Retrying {} failed file{} from {}",
+        failed.len(),
+        if failed.len() == 1 { "" } else { "s" },
+        file.display()
+    );
+    Ok((
+        args,
+        Retried {
+            failed,
+            from_report: true,
+        },
+    ))
+}
+
+/// The journal named by `--journal`, or the most recent one not undone
+/// yet that was started on this host, passing over watch sessions. A
+/// journal that cannot be read is picked so its error shows.
+fn chosen(journal: Option<PathBuf>) -> Result<PathBuf, String> {
+    match journal {
+        Some(path) => Ok(path),
+        None => journals()?
+            .into_iter()
+            .rev()
+            .find(|path| {
+                !is_undone(path)
+                    && read(path).map_or(true, |recorded| {
+                        recorded.started_here() && !recorded.watched()
+                    })
+            })
+            .ok_or_else(|| "No run recorded yet".to_string()),
+    }
+}
+
+/// Moves to the folder the run in `path` was started in and returns its
+/// arguments, to start it again.
+fn restart(path: &Path, recorded: &Recorded) -> Result<Vec<String>, String> {
+    if is_undone(path) {
+        return Err(format!(
+            "The run of {} on {} has been undone",
+            recorded.started, recorded.root
+        ));
+    }
+    let (Some(cwd), Some(args)) = (&recorded.cwd, &recorded.args) else {
+        return Err(format!(
+            "{} does not record the options of the run, as it was written by an older r3dy or through the library",
+            path.display()
+        ));
+    };
+    env::set_current_dir(cwd)
+        .map_err(|err| format!("Failed to change to {}: {}", cwd.display(), err))?;
+    Ok(args.clone())
+}
+
+/// The arguments of `r3dy undo`.
+#[derive(clap::Args)]
+pub struct UndoArgs {
+    /// Undo the most recent run (default)
+    #[arg(long, conflicts_with = "journal")]
+    last: bool,
+    /// Undo the run recorded in FILE
+    #[arg(long, value_name = "FILE")]
+    journal: Option<PathBuf>,
+    /// Print what would be restored without renaming anything
+    #[arg(long)]
+    dry_run: bool,
+    /// Undo even if the lock file says an r3dy run is working on the path
+    #[arg(long)]
+    force_lock: bool,
+    /// List the journals, oldest first
+    #[arg(long, conflicts_with_all = ["last", "journal", "dry_run", "force_lock"])]
+    list: bool,
+}
+
+/// Entry point for `r3dy undo`.
+pub fn undo_command(args: UndoArgs) -> Result<(), String> {
+    let UndoArgs {
+        last: _,
+        journal,
+        dry_run,
+        list,
+        force_lock,
+    } = args;
+
+    if list {
+        return list_journals();
+    }
+
+    let path = match journal {
+        Some(path) => path,
+        // Runs that only recorded failures have nothing to undo, and runs
+        // from other stations sharing the journal folder are theirs to
+        // undo; a journal that cannot be read is picked so its error shows.
+        None => journals()?
+            .into_iter()
+            .rev()
+            .find(|path| {
+                !is_undone(path)
+                    && read(path).map_or(true, |recorded| {
+                        recorded.started_here() && !recorded.renames.is_empty()
+                    })
+            })
+            .ok_or("No run left to undo")?,
+    };
+    let recorded = read(&path)?;
+    check_later(&path, &recorded)?;
+    // Kept off the trees while a run works on them, as a run is kept off
+    // them while this undoes. A folder that is gone has nothing to guard.
+    let _lock = if dry_run {
+        None
+    } else {
+        let roots: Vec<PathBuf> = recorded
+            .roots
+            .iter()
+            .filter(|root| root.is_dir())
+            .cloned()
+            .collect();
+        let lock = runlock::acquire(&roots, force_lock)?;
+        runlock::check_below(&locks_below(&roots, &recorded.renames), force_lock)?;
+        Some(lock)
+    };
+    println!(
+        "Undoing the run of {} on {}{} ({} {})",
+        recorded.started,
+        recorded.root,
+        recorded.by(),
+        recorded.renames.len(),
+        match (recorded.renames.len(), recorded.copy) {
+            (1, true) => "copy",
+            (_, true) => "copies",
+            (1, false) => "rename",
+            (_, false) => "renames",
+        }
+    );
+
+    let (mut restored, mut skipped, mut failed) = (0, 0, 0);
+    for (source, target) in recorded.renames.iter().rev() {
+        let undone = match (recorded.copy, dry_run) {
+            (false, false) => restore(source, target),
+            (true, false) => remove_copy(source, target),
+            (copy, true) => {
+                // A copy can only be removed while its original is there; a
+                // rename can only be reversed while the old name is free.
+                let possible = target.exists()
+                    && if copy {
+                        source.exists()
+                    } else {
+                        !noreplace::taken(target, source)
+                    };
+                if possible && copy {
+                    println!("remove {}", target.display());
+                } else if possible {
+                    println!("{} \u{2192} {}", target.display(), source.display());
+                }
+                if possible {
+                    Undone::Done
+                } else {
+                    Undone::Skipped
+                }
+            }
+        };
+        match undone {
+            Undone::Done => restored += 1,
+            Undone::Skipped => skipped += 1,
+            Undone::Failed => failed += 1,
+        }
+    }
+
+    println!(
+        "{} {} file{} (skipped: {}, failed: {})",
+        match (dry_run, recorded.copy) {
+            (true, false) => "Would restore",
+            (true, true) => "Would remove copies of",
+            (false, false) => "Restored",
+            (false, true) => "Removed copies of",
+        },
+        restored,
+        if restored == 1 { "" } else { "s" },
+        skipped,
+        failed
+    );
+
+    if failed > 0 {
+        return Err(format!(
+            "{} file{} could not be restored; run the same undo again once fixed",
+            failed,
+            if failed == 1 { "" } else { "s" }
+        ));
+    }
+    if !dry_run && !is_undone(&path) {
+        let done = path.with_file_name(format!(
+            "{}{}.{}",
+            path.file_stem().unwrap_or_default().to_string_lossy(),
+            UNDONE_SUFFIX,
+            EXTENSION
+        ));
+        fs::rename(&path, &done)
+            .map_err(|err| format!("Failed to mark {} as undone: {}", path.display(), err))?;
+    }
+    Ok(())
+}
+
+/// Fails if a later run from another station, not undone yet, renamed one
+/// of the files the run in `path` left, as undoing it would pull files out
+/// from under that station's work. That run is undone first, on its own
+/// station.
+fn check_later(path: &Path, recorded: &Recorded) -> Result<(), String> {
+    let here = hostname();
+    let targets: HashSet<&Path> = recorded
+        .renames
+        .iter()
+        .map(|(_, target)| target.as_path())
+        .collect();
+    let later = journals()?
+        .into_iter()
+        .filter(|later| later.file_name() > path.file_name() && !is_undone(later));
+    for later in later {
+        let Ok(other) = read(&later) else {
+            continue;
+        };
+        let touched = other
+            .renames
+            .iter()
+            .zip(&other.stations)
+            .find(|((source, _), station)| {
+                !station.is_empty() && **station != here && targets.contains(source.as_path())
+            });
+        if let Some(((source, _), station)) = touched {
+            return Err(format!(
+                "{} was renamed again by the run of {} on {}{}; undo that run on {} first ({})",
+                source.display(),
+                other.started,
+                other.root,
+                other.by(),
+                station,
+                later.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The lock files of runs on folders between `roots` and the files of
+/// `renames`, which undo does not scan for.
+fn locks_below(roots: &[PathBuf], renames: &[(PathBuf, PathBuf)]) -> Vec<PathBuf> {
+    let mut dirs = HashSet::new();
+    for path in renames.iter().flat_map(|(source, target)| [source, target]) {
+        if !roots.iter().any(|root| path.starts_with(root)) {
+            continue;
+        }
+        for dir in path.ancestors().skip(1) {
+            if roots.iter().any(|root| root == dir) || !dirs.insert(dir) {
+                break;
+            }
+        }
+    }
+    dirs.into_iter()
+        .map(|dir| dir.join(runlock::FILE_NAME))
+        .filter(|lock| lock.is_file())
+        .collect()
+}
+
+enum Undone {
+    Done,
+    Skipped,
+    Failed,
+}
+
+/// Renames `target` back to `source`, or moves it back by copying when the
+/// run moved it to another volume.
+fn restore(source: &Path, target: &Path) -> Undone {
+    let restored = match noreplace::rename(target, source) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            backend::move_by_copy(target, source, backend::MOVED_BACK)
+        }
+        result => result,
+    };
+    match restored {
+        Ok(()) => Undone::Done,
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            eprintln!(
+                "Skipping {} ({} already exists)",
+                target.display(),
+                source.display()
+            );
+            Undone::Skipped
+        }
+        // Either restored by an earlier, interrupted undo, or renamed or
+        // removed since; both are left as they are.
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            if trace::call("lstat", source, || fs::symlink_metadata(source)).is_err() {
+                eprintln!("Skipping {} (no longer there)", target.display());
+            }
+            Undone::Skipped
+        }
+        Err(err) => {
+            eprintln!("Failed to restore {}: {}", source.display(), err);
+            Undone::Failed
+        }
+    }
+}
+
+/// Removes the copy `target` made of `source`, as long as the original is
+/// still there and the copy still matches it in size.
+fn remove_copy(source: &Path, target: &Path) -> Undone {
+    let original = trace::call("lstat", source, || fs::symlink_metadata(source));
+    let copy = match trace::call("lstat", target, || fs::symlink_metadata(target)) {
+        Ok(copy) => copy,
+        // Removed by an earlier, interrupted undo, or by hand.
+        Err(_) => return Undone::Skipped,
+    };
+    match original {
+        Ok(original) if original.len() == copy.len() => {}
+        Ok(_) => {
+            eprintln!(
+                "Skipping {} (no longer matches {})",
+                target.display(),
+                source.display()
+            );
+            return Undone::Skipped;
+        }
+        Err(_) => {
+            eprintln!(
+                "Skipping {} ({} is gone, so this is the only copy)",
+                target.display(),
+                source.display()
+            );
+            return Undone::Skipped;
+        }
+    }
+
+    match trace::call("unlink", target, || fs::remove_file(target)) {
+        Ok(()) => Undone::Done,
+        Err(err) => {
+            eprintln!("Failed to remove {}: {}", target.display(), err);
+            Undone::Failed
+        }
+    }
+}
+
+fn list_journals() -> Result<(), String> {
+    let journals = journals()?;
+    if journals.is_empty() {
+        println!("No journals yet");
+        return Ok(());
+    }
+
+    for path in journals {
+        match read(&path) {
+            Ok(recorded) => println!(
+                "{}  {}{}  {} rename{}{}{}{}  {}",
+                recorded.started,
+                recorded.root,
+                recorded.by(),
+                recorded.renames.len(),
+                if recorded.renames.len() == 1 { "" } else { "s" },
+                match recorded.failed.len() {
+                    0 => String::new(),
+                    failed => format!(", {} failed", failed),
+                },
+                if recorded.finished {
+                    ""
+                } else {
+                    " (interrupted)"
+                },
+                if is_undone(&path) { " (undone)" } else { "" },
+                path.display()
+            ),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+    Ok(())
+}
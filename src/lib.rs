@@ -0,0 +1,3436 @@
+//! Renames RED `.NEV` clips to `.R3D` (and back), in place and safely.
+//!
+//! The `r3dy` binary is a thin wrapper around [`cli`]. To embed the renaming
+//! in another program, use [`Renamer`]:
+//!
+//! ```no_run
+//! let renamer = r3dy::Renamer::new("/Volumes/CAM_DAY01");
+//! let scan = renamer.scan()?;
+//! let execution = renamer.execute(&scan)?;
+//! println!("{} converted, {} failed", execution.converted, execution.failed);
+//! # Ok::<(), String>(())
+//! ```
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use clap::{CommandFactory, Parser};
+use indicatif::{MultiProgress, ProgressBar};
+
+use args::{Cli, RenameArgs};
+use backend::{BackendKind, RenameResult};
+use card::Cards;
+use dirent::Kind;
+use glob::Filter;
+use ignore::Ignore;
+use interactive::{Answer, Prompt};
+use journal::Journal;
+use manifest::{Manifest, ManifestFormat};
+use notify::{AttachmentFormat, Digest, EmailConfig, Webhook, WebhookKind};
+use preview::Preview;
+use progress::{Announcer, BarLayout, FolderProgress};
+use publish::Publisher;
+use renamer::{Engine, Planned, Renamed};
+use spool::{PathOrder, PathSorter, Record, SortedPaths, Spool};
+use template::Template;
+use tui::Dashboard;
+use volumes::Volumes;
+
+mod args;
+mod backend;
+mod card;
+mod changed;
+mod clip;
+mod color;
+mod completions;
+mod datetime;
+mod diff;
+mod dirent;
+mod exiftool;
+mod fcpxml;
+mod glob;
+mod hash;
+mod heartbeat;
+mod ignore;
+mod interactive;
+mod interrupt;
+mod inuse;
+mod journal;
+mod json;
+mod listing;
+mod logfile;
+mod manifest;
+mod mhl;
+mod noreplace;
+mod normalize;
+mod notify;
+mod output;
+mod pathenc;
+mod preserve;
+mod preview;
+mod privilege;
+mod progress;
+mod publish;
+mod r3d;
+mod readonly;
+mod renamer;
+mod report;
+mod resolve;
+mod runlock;
+mod sandbox;
+mod sanitize;
+mod settings;
+mod sidecar;
+mod simulate;
+mod space;
+mod spool;
+mod tar;
+mod tcc;
+mod template;
+mod trace;
+mod tui;
+mod volumes;
+mod wait;
+mod watch;
+mod winpath;
+
+pub use backend::Conflict;
+pub use preserve::Preserve;
+pub use renamer::{Execution, Renamer, Scan};
+
+/// Exit status when the run itself failed: it could not scan the tree or
+/// write an output, or `--atomic` rolled it back.
+const EXIT_ERROR: i32 = 1;
+/// Exit status when the run finished but some files failed.
+const EXIT_FILES_FAILED: i32 = 2;
+/// Exit status when no files matched.
+const EXIT_NOTHING_MATCHED: i32 = 3;
+/// Exit status for bad arguments or config files.
+const EXIT_CONFIG: i32 = 4;
+
+/// Runs the `r3dy` command line with the process arguments, exiting the
+/// process with a nonzero status on failure. Subcommands other than rename
+/// and watch exit with status 1 on any failure.
+pub fn cli() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // NO_COLOR applies to every command; --color overrides it below.
+    color::init(color::Choice::Auto);
+
+    let command = match Cli::try_parse_from(env::args()) {
+        Ok(cli) => cli.command,
+        Err(err) => {
+            // Bad options to rename and watch are a config error like any
+            // other; the other commands fail with 1.
+            let other_command = args.first().is_some_and(|name| {
+                !matches!(name.as_str(), "rename" | "watch")
+                    && Cli::command().find_subcommand(name).is_some()
+            });
+            let code = if !err.use_stderr() {
+                0
+            } else if other_command {
+                1
+            } else {
+                EXIT_CONFIG
+            };
+            let _ = err.print();
+            process::exit(code);
+        }
+    };
+
+    // `rename`, the default, and `watch` take the options parsed again
+    // below, after the config files have been put in front of them. The
+    // other commands have arguments of their own.
+    let mut resumed = None;
+    let mut retried = None;
+    let other = match command {
+        None => None,
+        Some(args::Command::Rename(_)) => {
+            args.remove(0);
+            None
+        }
+        // `r3dy watch` is `r3dy rename --watch`.
+        Some(args::Command::Watch(_)) => {
+            args[0] = "--watch".to_string();
+            None
+        }
+        // The journal holds the arguments with the config files applied.
+        Some(args::Command::Resume(options)) => match journal::resume(options) {
+            Ok((recorded, run)) => {
+                args = recorded;
+                resumed = Some(run);
+                None
+            }
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                process::exit(EXIT_ERROR);
+            }
+        },
+        Some(args::Command::Retry(options)) => match journal::retry(options) {
+            Ok((recorded, run)) => {
+                args = recorded;
+                retried = Some(run);
+                None
+            }
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                process::exit(EXIT_ERROR);
+            }
+        },
+        Some(args::Command::Verify(options)) => Some(mhl::verify_command(options)),
+        Some(args::Command::Undo(options)) => Some(journal::undo_command(options)),
+        Some(args::Command::Inspect(options)) => Some(r3d::inspect_command(options)),
+        // Parsed again after the config files are put in front of them.
+        Some(args::Command::Archive(_)) => {
+            let args = match settings::with_defaults::<tar::ArchiveArgs>(args.split_off(1)) {
+                Ok(args) => args,
+                Err(err) => {
+                    eprintln!("Error: {}", err);
+                    process::exit(EXIT_ERROR);
+                }
+            };
+            match args::archive_options(&args) {
+                Ok(options) => Some(tar::archive_command(options)),
+                Err(err) => {
+                    let _ = err.print();
+                    process::exit(if err.use_stderr() { EXIT_ERROR } else { 0 });
+                }
+            }
+        }
+        Some(args::Command::Report { command }) => Some(diff::report_command(command)),
+        Some(args::Command::Simulate { command }) => Some(simulate::simulate_command(command)),
+        Some(args::Command::Completions { shell }) => {
+            completions::completions_command(shell);
+            Some(Ok(()))
+        }
+    };
+    if let Some(result) = other {
+        if let Err(err) = result {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+    let recorded =
+        resumed.is_some() || retried.as_ref().is_some_and(|retried| !retried.from_report);
+    let args = if recorded {
+        args
+    } else {
+        match settings::with_defaults::<args::RenameArgs>(args) {
+            Ok(args) => args,
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                process::exit(EXIT_CONFIG);
+            }
+        }
+    };
+
+    let mut config = match Config::from_args(args) {
+        Ok(config) => config,
+        Err(ConfigError::Args(err)) => {
+            let _ = err.print();
+            process::exit(if err.use_stderr() { EXIT_CONFIG } else { 0 });
+        }
+        Err(ConfigError::Message(err)) => {
+            eprintln!("Error: {}", err);
+            eprintln!();
+            eprintln!("{}", Cli::command().render_usage());
+            process::exit(EXIT_CONFIG);
+        }
+    };
+    if (resumed.is_some() || retried.is_some()) && config.watch {
+        eprintln!(
+            "Error: a watch session cannot be resumed or retried; start r3dy watch again instead"
+        );
+        process::exit(EXIT_CONFIG);
+    }
+    config.resumed = resumed;
+    if let Some(retried) = retried {
+        let failed = if retried.from_report {
+            let root = &config.root;
+            retried.failed.iter().map(|path| root.join(path)).collect()
+        } else {
+            retried.failed
+        };
+        config.clips = retry_files(&config, &failed);
+        config.files_from = None;
+        // No clips would mean the whole tree.
+        if config.clips.is_empty() {
+            println!("None of the failed files are left under their old names");
+            process::exit(EXIT_NOTHING_MATCHED);
+        }
+    }
+
+    // Before anything is opened or written, so every file r3dy touches is
+    // touched as the target user.
+    if let Some(spec) = &config.run_as {
+        match privilege::drop_to(spec) {
+            Ok(identity) => eprintln!("Running as {}", identity),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                process::exit(EXIT_ERROR);
+            }
+        }
+    }
+
+    if config.dry_run
+        && let Some(flag) = config.rename_only_flag()
+    {
+        eprintln!("Error: --dry-run cannot be combined with {}", flag);
+        process::exit(EXIT_CONFIG);
+    }
+
+    if config.watch
+        && let Some(flag) = config.end_of_run_flag()
+    {
+        eprintln!("Error: --watch cannot be combined with {}", flag);
+        process::exit(EXIT_CONFIG);
+    }
+
+    color::init(config.color);
+    if let Some(spec) = &config.trace
+        && let Err(err) = trace::init(spec)
+    {
+        eprintln!("Error: {}", err);
+        process::exit(EXIT_ERROR);
+    }
+    let log_level = match config.log_level {
+        None if config.log_file.is_none() && config.verbosity == Verbosity::Debug => {
+            Some(logfile::Level::Debug)
+        }
+        level => level,
+    };
+    if config.log_file.is_some() || log_level.is_some() || logfile::env_filter().is_some() {
+        if let Err(err) = logfile::init(config.log_file.as_deref(), log_level, config.log_format) {
+            eprintln!("Error: {}", err);
+            process::exit(EXIT_ERROR);
+        }
+        tracing::info!(
+            "Started r3dy {} in {}",
+            config.args.as_deref().unwrap_or_default().join(" "),
+            config.root.display()
+        );
+    }
+    heartbeat::init(config.heartbeat);
+    if config.deterministic {
+        pathenc::use_forward_slashes();
+    }
+
+    if config.watch {
+        if let Err(err) = watch::watch(&config) {
+            eprintln!("Error: {}", err);
+            tracing::error!("{}", err);
+            process::exit(EXIT_ERROR);
+        }
+        return;
+    }
+    if let Some(interval) = config.screen_reader {
+        progress::enable_screen_reader(interval);
+    }
+
+    let code = rename_and_report(&config);
+    if code != 0 {
+        process::exit(code);
+    }
+}
+
+/// Runs the rename and writes its reports and notifications, holding the
+/// run lock throughout. Returns the exit status, so the lock is released
+/// before the process exits.
+fn rename_and_report(config: &Config) -> i32 {
+    // Taken before any output is opened, so a run turned away leaves the
+    // reports of the run it found alone. A dry run changes nothing, so it
+    // needs none.
+    let _lock = if config.dry_run {
+        None
+    } else {
+        match runlock::acquire(&config.roots, config.force_lock) {
+            Ok(lock) => Some(lock),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                return EXIT_ERROR;
+            }
+        }
+    };
+
+    let mut summary_out = match config.summary_out.as_deref().map(output::open) {
+        Some(Ok(out)) => Some(out),
+        Some(Err(err)) => {
+            eprintln!("Error: {}", err);
+            return EXIT_ERROR;
+        }
+        None => None,
+    };
+
+    // Created up front, so a bad path fails before any renames.
+    let create = |path: &Option<PathBuf>| match path {
+        Some(path) => fs::File::create(path)
+            .map(Some)
+            .map_err(|err| format!("Failed to create {}: {}", path.display(), err)),
+        None => Ok(None),
+    };
+    let outputs = (|| {
+        Ok::<_, String>((
+            create(&config.report)?,
+            create(&config.fcpxml)?,
+            create(&config.resolve_script)?,
+            create(&config.premiere_csv)?,
+        ))
+    })();
+    let (mut report_out, mut fcpxml_out, mut resolve_out, mut premiere_out) = match outputs {
+        Ok(outputs) => outputs,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return EXIT_ERROR;
+        }
+    };
+
+    let publisher = Publisher::start(&config.publish);
+
+    let summary = match run(config, publisher.as_ref()) {
+        Ok(summary) => summary,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            tracing::error!("{}", err);
+            return EXIT_ERROR;
+        }
+    };
+
+    let mut notifications_failed = summary.unhashed > 0;
+
+    if let (Some(out), Some(path)) = (report_out.as_mut(), &config.report) {
+        let rendered = if has_extension(path, "json") {
+            report::to_json(
+                &config.root,
+                &summary,
+                (!config.deterministic).then(SystemTime::now),
+            )
+        } else {
+            report::to_csv(&config.root, &summary, !config.deterministic)
+        };
+        if let Err(err) = out.write_all(rendered.as_bytes()) {
+            eprintln!("Error: Failed to write {}: {}", path.display(), err);
+            notifications_failed = true;
+        }
+    }
+
+    if let (Some(out), Some(path)) = (fcpxml_out.as_mut(), &config.fcpxml)
+        && let Err(err) = out.write_all(fcpxml::render(&config.root, &summary).as_bytes())
+    {
+        eprintln!("Error: Failed to write {}: {}", path.display(), err);
+        notifications_failed = true;
+    }
+
+    if let (Some(out), Some(path)) = (premiere_out.as_mut(), &config.premiere_csv)
+        && let Err(err) = out.write_all(report::to_premiere_csv(&config.root, &summary).as_bytes())
+    {
+        eprintln!("Error: Failed to write {}: {}", path.display(), err);
+        notifications_failed = true;
+    }
+
+    if let (Some(out), Some(path)) = (resolve_out.as_mut(), &config.resolve_script) {
+        let bin = config
+            .resolve_bin
+            .clone()
+            .unwrap_or_else(|| resolve::default_bin(&config.root));
+        if let Err(err) = out.write_all(resolve::render(&summary, &bin).as_bytes()) {
+            eprintln!("Error: Failed to write {}: {}", path.display(), err);
+            notifications_failed = true;
+        }
+    }
+
+    if let Some(out) = summary_out.as_mut()
+        && let Err(err) = out
+            .write_all(report::summary_json(&config.root, &summary).as_bytes())
+            .and_then(|()| out.flush())
+    {
+        eprintln!("Error: Failed to write summary: {}", err);
+        notifications_failed = true;
+    }
+
+    if let Some(email) = &config.email
+        && let Err(err) = notify::send_email(email, &config.root, &summary, false)
+    {
+        eprintln!("Error: {}", err);
+        notifications_failed = true;
+    }
+
+    for webhook in &config.webhooks {
+        let digest = Digest::new(webhook, &config.root, summary.outcomes(), false);
+        if let Err(err) = notify::post_webhook(webhook, &digest) {
+            eprintln!("Error: {}", err);
+            notifications_failed = true;
+        }
+    }
+
+    if let Some(publisher) = publisher {
+        publisher.run_event(&config.root, &summary);
+        for err in publisher.finish() {
+            eprintln!("Error: {}", err);
+            notifications_failed = true;
+        }
+    }
+
+    if notifications_failed {
+        return EXIT_ERROR;
+    }
+
+    // A failure stopped and rolled back an --atomic run.
+    if config.atomic && summary.failed > 0 {
+        return EXIT_ERROR;
+    }
+    if summary.low_space.is_some() {
+        return EXIT_ERROR;
+    }
+    if summary.failed > 0 {
+        return EXIT_FILES_FAILED;
+    }
+    if summary.converted + summary.skipped == 0 {
+        return EXIT_NOTHING_MATCHED;
+    }
+    0
+}
+
+/// The human-readable end of a run on stdout: totals, then per card and per
+/// volume, then where the journal went.
+fn print_results(config: &Config, summary: &Summary, volumes: &Volumes, journal: Option<&Path>) {
+    if config.dry_run {
+        println!(
+            "Would convert {} file{} (skipped: {}, conflicts: {}, failed: {})",
+            color::done(summary.converted),
+            if summary.converted == 1 { "" } else { "s" },
+            color::skipped(summary.skipped - summary.conflicts),
+            color::skipped(summary.conflicts),
+            color::failed(summary.failed)
+        );
+    } else {
+        // The log gets the line without colour.
+        let totals = |converted: String, skipped: String, failed: String| {
+            format!(
+                "{} {} file{} (skipped: {}, failed: {})",
+                if config.copy { "Copied" } else { "Converted" },
+                converted,
+                if summary.converted == 1 { "" } else { "s" },
+                skipped,
+                failed
+            )
+        };
+        println!(
+            "{}",
+            totals(
+                color::done(summary.converted),
+                color::skipped(summary.skipped),
+                color::failed(summary.failed)
+            )
+        );
+        tracing::info!(
+            "{}",
+            totals(
+                summary.converted.to_string(),
+                summary.skipped.to_string(),
+                summary.failed.to_string(),
+            )
+        );
+        if summary.moved > 0 {
+            println!(
+                "Moved {} of them to another volume by copying, checking and removing the original",
+                summary.moved
+            );
+        }
+        if config.verify && summary.converted > 0 {
+            println!(
+                "Checksums matched before and after for all {} file{}",
+                summary.converted,
+                if summary.converted == 1 { "" } else { "s" }
+            );
+        }
+    }
+
+    // --quiet stops at the totals.
+    if config.verbosity == Verbosity::Quiet {
+        return;
+    }
+
+    if !summary.cards.is_empty() {
+        for tally in summary.cards.tallies() {
+            println!(
+                "  {}: {} converted, {} skipped, {} failed",
+                tally.name(&config.root),
+                color::done(tally.converted),
+                color::skipped(tally.skipped),
+                color::failed(tally.failed)
+            );
+        }
+    }
+
+    let volume_lines = volumes.lines();
+    if !volume_lines.is_empty() {
+        println!("By volume:");
+        for line in volume_lines {
+            println!("{}", line);
+        }
+    }
+
+    if let Some(path) = &config.mhl {
+        println!("MHL: {}", winpath::display(path));
+    }
+
+    if let Some(path) = journal {
+        println!(
+            "Journal: {} (r3dy undo reverses this run)",
+            winpath::display(path)
+        );
+    }
+}
+
+fn run(config: &Config, publisher: Option<&Publisher>) -> Result<Summary, String> {
+    let mut errors_json = match &config.errors_json {
+        Some(spec) => Some(output::open(spec)?),
+        None => None,
+    };
+    let mut manifest = match (&config.manifest, &config.archive) {
+        (Some(spec), _) => Some(Manifest::create(spec, ManifestFormat::Xxhsum)?),
+        (None, Some(spec)) => Some(Manifest::create(spec, ManifestFormat::Archive)?),
+        (None, None) => None,
+    };
+    // The --dry-run preview lists the renames folder by folder, so it takes
+    // the files in that order, unless --limit or --atomic make the order
+    // decide which files a real run would rename; a folder may then be
+    // listed in more than one stretch.
+    let mut preview = (config.dry_run
+        && !config.json
+        && !config.porcelain
+        && !config.print0
+        && config.verbosity > Verbosity::Quiet)
+        .then(Preview::default);
+    let grouped_preview = preview.is_some() && config.limit.is_none() && !config.atomic;
+    let order = if config.archive.is_some() || grouped_preview {
+        PathOrder::Grouped
+    } else {
+        PathOrder::Path
+    };
+
+    // A missing journal only costs the ability to undo, so it does not stop
+    // the run, unless --atomic needs it to roll back.
+    let mut journal = if config.journal && !config.dry_run {
+        let opened = match &config.resumed {
+            Some(resumed) => Journal::reopen(resumed, &config.roots),
+            None => Journal::create(
+                &config.root,
+                &config.roots,
+                config.source_extension(),
+                config.target_extension(),
+                config.copy,
+                config.args.as_deref(),
+            ),
+        };
+        match opened {
+            Ok(journal) => Some(journal),
+            Err(err) if config.atomic => return Err(err),
+            Err(err) => {
+                eprintln!(
+                    "{}",
+                    color::warning(&format!("Warning: {}; this run cannot be undone", err))
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let memory_limit = config.memory_limit / 2;
+    let mut mhl = match &config.mhl {
+        Some(path) => Some(mhl::Writer::create(path, SystemTime::now(), memory_limit)?),
+        None => None,
+    };
+
+    // The list may be outside the tree, which the sandbox closes off.
+    let listed = config.read_list()?;
+    prepare_tree(config)?;
+
+    let scan_started = SystemTime::now();
+    let collected = config.collect(order, memory_limit, listed)?;
+    if !config.dry_run
+        && let Err(err) = runlock::check_below(&collected.locks, config.force_lock)
+    {
+        if let Some(journal) = journal {
+            journal.finish();
+        }
+        return Err(err);
+    }
+
+    print_warnings(&collected, config.verbosity == Verbosity::Quiet);
+
+    // A resumed run may have nothing left but still reports what was done.
+    if collected.files.is_empty() && config.resumed.is_none() {
+        let summary = Summary::new(memory_limit, Cards::new(Vec::new()));
+        if config.json {
+            println!("{}", report::summary_event(&config.root, &summary));
+        } else if !config.porcelain && !config.print0 {
+            let roots: Vec<String> = config
+                .roots
+                .iter()
+                .map(|root| winpath::display(root))
+                .collect();
+            println!(
+                "No .{} files found under {}",
+                config.source_extension(),
+                roots.join(", ")
+            );
+        }
+        if let Some(journal) = journal {
+            journal.finish();
+        }
+        if let Some(mhl) = mhl {
+            mhl.finish()?;
+        }
+        return Ok(summary);
+    }
+
+    // With --template every file is named, and with --on-conflict fail every
+    // target checked, before the first one is renamed. A resumed run leaves
+    // out the files it already did, which --copy keeps in place.
+    let (files, names, file_count): (Box<dyn Iterator<Item = std::io::Result<PathBuf>>>, _, _) =
+        if config.template.is_some()
+            || config.on_conflict == Conflict::Fail
+            || config.resumed.is_some()
+        {
+            let mut listed = collected
+                .files
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| format!("Failed to read back the file list: {}", err))?;
+            if let Some(resumed) = &config.resumed {
+                let done: HashSet<&Path> = resumed
+                    .done
+                    .iter()
+                    .map(|(source, _)| source.as_path())
+                    .collect();
+                listed.retain(|path| !done.contains(path.as_path()));
+            }
+            let names = match config.plan_names(&listed) {
+                Ok(names) => names,
+                Err(err) => {
+                    if let Some(journal) = journal {
+                        journal.finish();
+                    }
+                    return Err(err);
+                }
+            };
+            let count = listed.len();
+            (Box::new(listed.into_iter().map(Ok)), names, count)
+        } else {
+            let count = collected.files.len();
+            (Box::new(collected.files), Names::default(), count)
+        };
+    let Names {
+        mut named,
+        mut sanitized,
+    } = names;
+    let multi = MultiProgress::with_draw_target(config.progress.draw_target());
+    let failed_so_far = Arc::new(AtomicUsize::new(0));
+    let progress = multi.add(config.progress.bar(file_count, failed_so_far.clone())?);
+    let verb = match (config.dry_run, config.copy) {
+        (true, _) => "Checked",
+        (false, true) => "Copied",
+        (false, false) => "Renamed",
+    };
+    let mut announcer = Announcer::new(file_count, verb);
+    let mut dashboard = if config.tui {
+        Some(Dashboard::start(
+            &config.root,
+            file_count,
+            &collected.folders,
+            verb,
+        )?)
+    } else {
+        None
+    };
+    let mut folders = if collected.folders.len() > 1 {
+        Some(FolderProgress::new(&multi, collected.folders)?)
+    } else {
+        None
+    };
+
+    let mut engine = Engine::open(config, scan_started)?;
+    let mut summary = Summary::new(memory_limit, Cards::new(collected.cards));
+    // The files done before the run was interrupted count as converted and
+    // go into the manifests, which start afresh.
+    if let Some(resumed) = &config.resumed {
+        for (source, target) in &resumed.done {
+            let outcome = Outcome::new(source.clone(), target.clone(), Status::Converted, None);
+            if let Some(manifest) = manifest.as_mut()
+                && let Err(err) = manifest.add(&config.root, &outcome)
+            {
+                eprintln!("{}", err);
+                summary.unhashed += 1;
+            }
+            if let Some(mhl) = mhl.as_mut()
+                && let Err(err) = mhl.add(&outcome.target)
+            {
+                eprintln!("{}", err);
+                summary.unhashed += 1;
+            }
+            summary
+                .record(outcome)
+                .map_err(|err| format!("Failed to spool run results: {}", err))?;
+        }
+    }
+    let mut files = files.peekable();
+    // One entry per file in list order.
+    let mut planned: Vec<Planned> = Vec::with_capacity(engine.batch_size());
+    let mut volumes = Volumes::default();
+    let mut prompt = config.interactive.then(Prompt::default);
+    let mut quit = false;
+    let mut batches = 0;
+    // --limit counts renames, the ones done and the ones in the batch, and
+    // stops at the first clip boundary after that many, so a spanned clip
+    // is never left half renamed.
+    let mut renamed_so_far = 0;
+
+    loop {
+        if let Some(dashboard) = dashboard.as_mut()
+            && !dashboard.proceed()
+        {
+            quit = true;
+        }
+        if quit || files.peek().is_none() {
+            break;
+        }
+        // Files that fail before the rename are logged inside the span too.
+        batches += 1;
+        let span = tracing::debug_span!(
+            "batch",
+            number = batches,
+            files = tracing::field::Empty,
+            failed = tracing::field::Empty
+        )
+        .entered();
+        let failed_before = summary.failed;
+        // A clip's files stay in one batch, so a partly renamed clip can be
+        // rolled back as a whole.
+        let mut last_clip: Option<PathBuf> = None;
+        let mut pending = 0;
+        while !quit && let Some(next) = files.peek() {
+            let full = planned.len() >= engine.batch_size()
+                || config
+                    .limit
+                    .is_some_and(|limit| renamed_so_far + pending >= limit);
+            if full
+                && next
+                    .as_ref()
+                    .map(|path| clip::key(path, config.clip_folders))
+                    .ok()
+                    != last_clip
+            {
+                break;
+            }
+            let Some(path) = files.next() else {
+                break;
+            };
+            let path = path.map_err(|err| format!("Failed to read back the file list: {}", err))?;
+            let key = clip::key(&path, config.clip_folders);
+            if config.json {
+                println!("{}", report::discovered_event(&config.root, &path));
+            }
+            let target = match named.remove(&path) {
+                Some(target) => target,
+                None => {
+                    let (target, original) = config.planned_target(&path);
+                    if let Some(original) = original {
+                        sanitized.insert(path.clone(), original);
+                    }
+                    target
+                }
+            };
+            if let Some(prompt) = prompt.as_mut() {
+                match prompt.ask(&config.root, &path, &target, &key, &progress) {
+                    Answer::Rename => {}
+                    Answer::Skip => {
+                        let outcome = Outcome::new(
+                            path,
+                            target,
+                            Status::Skipped,
+                            Some("declined at the prompt".to_string()),
+                        );
+                        planned.push(Planned {
+                            metadata: None,
+                            sidecar: false,
+                            settled: Some(outcome),
+                        });
+                        last_clip = Some(key);
+                        continue;
+                    }
+                    Answer::Quit => {
+                        progress::println(&progress, "Quit; the remaining files were left alone");
+                        quit = true;
+                        break;
+                    }
+                }
+            }
+            last_clip = Some(key);
+            for file in engine.plan(path, target) {
+                match &file.settled {
+                    // It failed the header check.
+                    Some(outcome) if outcome.status.is_failure() => {
+                        report_outcome(config, outcome, errors_json.as_mut(), &progress);
+                    }
+                    Some(outcome) => progress::println(
+                        &progress,
+                        format!(
+                            "Skipping {} ({})",
+                            display_relative(&config.root, &outcome.source),
+                            outcome.error.as_deref().unwrap_or_default()
+                        ),
+                    ),
+                    None if !file.sidecar => pending += 1,
+                    None => {}
+                }
+                planned.push(file);
+            }
+        }
+
+        if planned.is_empty() {
+            break;
+        }
+        span.record("files", planned.len());
+        if let Some((first, _)) = engine.batch().first() {
+            progress.set_message(display_relative(&config.root, first));
+        }
+
+        // --min-free leaves alone a batch that would take the destination
+        // below the threshold, and ends the run there, so no clip is cut
+        // off partway through a copy.
+        if let Some(min_free) = config.min_free
+            && !config.dry_run
+            && !engine.batch().is_empty()
+        {
+            let sizes = planned
+                .iter()
+                .filter(|planned| planned.settled.is_none())
+                .map(|planned| planned.metadata.as_ref().map_or(0, fs::Metadata::len));
+            let targets = engine.batch().iter().map(|(_, target)| target);
+            if let Some(reason) = space::short(targets.zip(sizes), min_free) {
+                engine.hold(&mut planned, "not enough free space (--min-free)");
+                summary.low_space = Some(reason);
+                quit = true;
+            }
+        }
+
+        if let Some(dashboard) = dashboard.as_mut() {
+            dashboard.in_flight(engine.batch());
+        }
+        let in_flight = engine.batch().len();
+        let started = Instant::now();
+        let renamed = engine.rename(std::mem::take(&mut planned))?;
+        // Batched renames complete together; each file gets an equal share.
+        let per_rename = started.elapsed() / in_flight.max(1) as u32;
+
+        let mut outcomes = Vec::with_capacity(renamed.len());
+        let mut measured = Vec::with_capacity(renamed.len());
+        for Renamed {
+            outcome,
+            metadata,
+            sidecar,
+            attempted,
+            moved,
+        } in renamed
+        {
+            let renaming = if attempted {
+                report_outcome(config, &outcome, errors_json.as_mut(), &progress);
+                per_rename
+            } else {
+                Duration::ZERO
+            };
+            let volume = metadata
+                .as_ref()
+                .map(|metadata| (volumes.volume_of(&outcome.source, metadata), metadata.len()));
+            outcomes.push(outcome);
+            measured.push((volume, renaming, sidecar, moved));
+        }
+        let rolled_back = clip::roll_back_partial(
+            &config.root,
+            &mut outcomes,
+            config.clip_folders,
+            config.copy,
+            config.dry_run,
+        );
+        for line in rolled_back {
+            progress::println(&progress, line);
+        }
+
+        for (mut outcome, (volume, renaming, sidecar, moved)) in outcomes.into_iter().zip(measured)
+        {
+            if let Some(warning) = renamer::record(config, &mut journal, &mut outcome) {
+                progress::println(&progress, warning);
+            }
+
+            if !sidecar && outcome.status == Status::Converted {
+                renamed_so_far += 1;
+            }
+            // A rolled-back move is back where it was.
+            if moved && outcome.status == Status::Converted {
+                summary.moved += 1;
+            }
+            if config.json {
+                println!(
+                    "{}",
+                    report::outcome_event(&config.root, &outcome, config.dry_run)
+                );
+            } else if config.porcelain {
+                println!(
+                    "{}",
+                    report::porcelain_line(&config.root, &outcome, config.dry_run)
+                );
+            } else if config.print0 {
+                // With --atomic the files might still be renamed back, so
+                // they are printed once the run has succeeded.
+                if outcome.status == Status::Converted && !config.atomic {
+                    print0(&outcome.target);
+                }
+            } else if let Some(preview) = preview.as_mut()
+                && outcome.status == Status::Converted
+            {
+                let lines = preview.add(&config.root, &outcome.source, &outcome.target);
+                print_lines(&progress, lines);
+            }
+
+            if !sidecar && let Some(folders) = folders.as_mut() {
+                folders.record(&config.root, &outcome.source);
+            }
+
+            if let Some(publisher) = publisher {
+                publisher.file_event(&config.root, &outcome);
+            }
+
+            let original = sanitized.remove(&outcome.source);
+            let mut hashing = None;
+            if let Some(manifest) = manifest.as_mut() {
+                if let Some(original) = original
+                    && outcome.status == Status::Converted
+                    && let Err(err) = manifest.sanitized(&config.root, &original, &outcome.target)
+                {
+                    progress::println(&progress, err);
+                }
+                let started = Instant::now();
+                match manifest.add(&config.root, &outcome) {
+                    Ok(()) => hashing = Some(started.elapsed()),
+                    Err(err) => {
+                        progress::println(&progress, err);
+                        summary.unhashed += 1;
+                    }
+                }
+            }
+
+            if outcome.status == Status::Converted
+                && let Some(mhl) = mhl.as_mut()
+                && let Err(err) = mhl.add(&outcome.target)
+            {
+                progress::println(&progress, err);
+                summary.unhashed += 1;
+            }
+
+            if outcome.status.is_failure() {
+                failed_so_far.fetch_add(1, Ordering::Relaxed);
+            }
+
+            if let Some((volume, bytes)) = volume {
+                let failed = outcome.status.is_failure();
+                volumes.record(volume, bytes, renaming, hashing, failed);
+            }
+
+            if let Some(dashboard) = dashboard.as_mut() {
+                dashboard.record(&outcome, sidecar);
+            }
+            summary
+                .record(outcome)
+                .map_err(|err| format!("Failed to spool run results: {}", err))?;
+            if !sidecar {
+                progress.inc(1);
+                announcer.update(
+                    progress.position() as usize,
+                    failed_so_far.load(Ordering::Relaxed),
+                );
+            }
+            heartbeat::pulse();
+        }
+
+        span.record("failed", summary.failed - failed_before);
+
+        // --atomic stops at the batch with the first failure.
+        if config.atomic && summary.failed > 0 {
+            quit = true;
+        }
+    }
+
+    if let Some(preview) = preview.as_mut() {
+        print_lines(&progress, preview.finish(&config.root));
+    }
+
+    // Back to the normal screen before the results are printed.
+    if let Some(dashboard) = dashboard.take() {
+        let aborted = dashboard.aborted();
+        drop(dashboard);
+        if aborted {
+            eprintln!("Aborted; the remaining files were left alone");
+        }
+    }
+    if let Some(reason) = &summary.low_space {
+        eprintln!(
+            "{}",
+            color::failure(&format!(
+                "Stopped: {}; the remaining files were left alone",
+                reason
+            ))
+        );
+        tracing::error!("Stopped: {}", reason);
+    }
+    if let Some(limit) = config.limit
+        && renamed_so_far >= limit
+        && !quit
+        && files.peek().is_some()
+    {
+        eprintln!(
+            "Stopped at --limit {}; the remaining files were left alone",
+            limit
+        );
+    }
+    if let Some(folders) = &folders {
+        folders.finish();
+    }
+    progress.finish_with_message(if config.dry_run {
+        "dry run complete"
+    } else {
+        "renaming complete"
+    });
+    announcer.finish();
+
+    let journal = match journal {
+        Some(journal) if config.atomic && summary.failed > 0 => {
+            let rolled_back = journal
+                .roll_back()
+                .map_err(|err| format!("Could not roll back the run: {}", err))?;
+            summary =
+                roll_back_summary(config, summary, &rolled_back.left, memory_limit, publisher)?;
+            rolled_back.journal
+        }
+        journal => journal.and_then(Journal::finish),
+    };
+    // A dry run has no journal, but shows what would be rolled back.
+    if config.atomic && config.dry_run && summary.failed > 0 {
+        summary = roll_back_summary(config, summary, &HashSet::new(), memory_limit, publisher)?;
+    }
+    if config.print0 && config.atomic {
+        for outcome in summary.outcomes() {
+            if outcome.status == Status::Converted {
+                print0(&outcome.target);
+            }
+        }
+    }
+
+    if let Some(manifest) = manifest {
+        manifest.finish()?;
+    }
+    if let Some(mhl) = mhl {
+        mhl.finish()?;
+    }
+    if config.json {
+        println!("{}", report::summary_event(&config.root, &summary));
+    } else if !config.porcelain && !config.print0 {
+        print_results(config, &summary, &volumes, journal.as_deref());
+    }
+
+    if summary.unhashed > 0 {
+        let lists = match (
+            config.manifest.is_some() || config.archive.is_some(),
+            config.mhl.is_some(),
+        ) {
+            (true, true) => "Manifest and MHL are",
+            (false, true) => "MHL is",
+            _ => "Manifest is",
+        };
+        eprintln!(
+            "{} missing {} file{} that could not be hashed",
+            lists,
+            summary.unhashed,
+            if summary.unhashed == 1 { "" } else { "s" }
+        );
+    }
+
+    for outcome in summary.failures() {
+        eprintln!(
+            "{}",
+            color::failure(&format!(
+                "Could not rename {}: {}",
+                display_relative(&config.root, &outcome.source),
+                outcome.error.as_deref().unwrap_or("unknown error")
+            ))
+        );
+    }
+
+    if summary.changed > 0 {
+        eprintln!(
+            "{} of the skipped files changed during the run and {} left as {}. Run r3dy again once nothing is writing to the tree.",
+            summary.changed,
+            if summary.changed == 1 { "was" } else { "were" },
+            if summary.changed == 1 {
+                "it was"
+            } else {
+                "they were"
+            }
+        );
+    }
+
+    if summary.denied > 0 {
+        eprintln!();
+        eprintln!(
+            "{} of the failures {} blocked by macOS privacy protection, not by file permissions. To allow access:",
+            summary.denied,
+            if summary.denied == 1 { "was" } else { "were" }
+        );
+        for step in tcc::remediation(&config.root) {
+            eprintln!("  {}", step);
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Creates the --dest folder and, with --sandbox, confines the process to
+/// the tree, the destination, the spool directory and the journal's folder,
+/// which `--atomic` reads back to roll a run back and a watch session only
+/// writes to once it renames something. Called once outputs are open, since
+/// nothing else is reachable afterwards.
+fn prepare_tree(config: &Config) -> Result<(), String> {
+    if let Some(dest) = &config.dest
+        && !config.dry_run
+    {
+        fs::create_dir_all(dest)
+            .map_err(|err| format!("Failed to create {}: {}", dest.display(), err))?;
+    }
+
+    if config.sandbox {
+        let temp = env::temp_dir();
+        let journals = match &config.resumed {
+            Some(resumed) => resumed.path.parent().map(Path::to_path_buf),
+            None => journal::dir(),
+        }
+        .filter(|_| config.journal && !config.dry_run);
+        if let Some(dir) = &journals {
+            fs::create_dir_all(dir)
+                .map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+        }
+        let mut dirs: Vec<&Path> = config.roots.iter().map(PathBuf::as_path).collect();
+        dirs.push(temp.as_path());
+        dirs.extend(config.dest.as_deref());
+        dirs.extend(journals.as_deref());
+        sandbox::confine(&dirs)?;
+    }
+    Ok(())
+}
+
+/// For `--on-conflict fail`: reports every target that exists already or
+/// that two files would both get, and fails if there is any, so nothing is
+/// renamed.
+fn check_conflicts<'a>(
+    root: &Path,
+    plan: impl Iterator<Item = (&'a Path, PathBuf)>,
+) -> Result<(), String> {
+    let mut claimed: HashMap<PathBuf, &Path> = HashMap::new();
+    let mut problems = Vec::new();
+    for (path, target) in plan {
+        if noreplace::taken(path, &target) {
+            problems.push(format!(
+                "{}: {} already exists",
+                display_relative(root, path),
+                display_relative(root, &target)
+            ));
+        } else if let Some(other) = claimed.get(&target) {
+            problems.push(format!(
+                "{} and {} would both be named {}",
+                display_relative(root, other),
+                display_relative(root, path),
+                display_relative(root, &target)
+            ));
+        } else {
+            claimed.insert(target, path);
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    Err(format!(
+        "{} conflict{} with --on-conflict fail; nothing was renamed",
+        problems.len(),
+        if problems.len() == 1 { "" } else { "s" }
+    ))
+}
+
+/// The results of an `--atomic` run once it has been rolled back: every
+/// converted file not in `left` becomes a skip, reported again with --json
+/// and to the publisher.
+fn roll_back_summary(
+    config: &Config,
+    summary: Summary,
+    left: &HashSet<PathBuf>,
+    memory_limit: usize,
+    publisher: Option<&Publisher>,
+) -> Result<Summary, String> {
+    let Some(failure) = summary.failures().next() else {
+        return Ok(summary);
+    };
+    let failure = display_relative(&config.root, &failure.source);
+    let reason = if config.dry_run {
+        format!("would be rolled back: {} failed", failure)
+    } else {
+        format!("rolled back: {} failed", failure)
+    };
+
+    let mut rolled_back = Summary::new(memory_limit, summary.cards.cleared());
+    let mut count = 0;
+    for mut outcome in summary.outcomes() {
+        if outcome.status == Status::Converted && !left.contains(&outcome.target) {
+            outcome.status = Status::Skipped;
+            outcome.error = Some(reason.clone());
+            count += 1;
+            if config.json {
+                println!(
+                    "{}",
+                    report::outcome_event(&config.root, &outcome, config.dry_run)
+                );
+            } else if config.porcelain {
+                println!(
+                    "{}",
+                    report::porcelain_line(&config.root, &outcome, config.dry_run)
+                );
+            }
+            if let Some(publisher) = publisher {
+                publisher.file_event(&config.root, &outcome);
+            }
+        }
+        rolled_back
+            .record(outcome)
+            .map_err(|err| format!("Failed to spool run results: {}", err))?;
+    }
+    rolled_back.unhashed = summary.unhashed;
+    rolled_back.low_space = summary.low_space;
+    if !left.is_empty() {
+        rolled_back.moved = summary.moved;
+    }
+
+    if count > 0 {
+        let message = format!(
+            "{} {} rename{} because {} failed (--atomic)",
+            if config.dry_run {
+                "Would roll back"
+            } else {
+                "Rolled back"
+            },
+            count,
+            if count == 1 { "" } else { "s" },
+            failure
+        );
+        eprintln!("{}", color::warning(&message));
+        tracing::warn!("{}", message);
+    }
+    if !left.is_empty() {
+        let message = format!(
+            "{} file{} could not be rolled back; r3dy undo retries {}",
+            left.len(),
+            if left.len() == 1 { "" } else { "s" },
+            if left.len() == 1 { "it" } else { "them" }
+        );
+        eprintln!("{}", color::failure(&message));
+        tracing::error!("{}", message);
+    }
+    Ok(rolled_back)
+}
+
+/// Why a file was skipped under `--on-conflict skip`, in reports.
+const TARGET_EXISTS: &str = "target already exists";
+
+/// The outcome of renaming `path` to `target`. A file that vanished before
+/// the rename reached it changed during the run rather than failed.
+fn outcome_of(path: PathBuf, target: PathBuf, result: RenameResult) -> Outcome {
+    match result {
+        RenameResult::Renamed => Outcome::new(path, target, Status::Converted, None),
+        RenameResult::Suffixed(target) | RenameResult::Moved(target) => {
+            Outcome::new(path, target, Status::Converted, None)
+        }
+        RenameResult::Verified(done, digest, value) => {
+            let mut outcome = outcome_of(path, target, *done);
+            match digest {
+                hash::Digest::Xxh3 => outcome.xxh3 = Some(value),
+                hash::Digest::Xxh64 => outcome.xxh64 = Some(value),
+            }
+            outcome
+        }
+        RenameResult::TargetExists => Outcome::new(
+            path,
+            target,
+            Status::Skipped,
+            Some(TARGET_EXISTS.to_string()),
+        ),
+        RenameResult::Failed(err) if err.kind() == std::io::ErrorKind::NotFound => Outcome::new(
+            path,
+            target,
+            Status::Changed,
+            Some(changed::DISAPPEARED.to_string()),
+        ),
+        RenameResult::Failed(err) => {
+            let status = if tcc::is_privacy_denial(&err, &path) {
+                Status::Denied
+            } else {
+                Status::Failed
+            };
+            Outcome::new(path, target, status, Some(err.to_string()))
+        }
+    }
+}
+
+/// Logs the outcome of a rename, and prints skips and failures above the
+/// progress bar. Failures are also written to the --errors-json sink.
+fn report_outcome(
+    config: &Config,
+    outcome: &Outcome,
+    errors_json: Option<&mut Box<dyn Write + Send>>,
+    progress: &ProgressBar,
+) {
+    let display_path = display_relative(&config.root, &outcome.source);
+    let error_text = outcome.error.as_deref().unwrap_or_default();
+
+    // The end of the run lists failures again, so --quiet leaves them out
+    // here.
+    let print = config.verbosity > Verbosity::Quiet;
+    match outcome.status {
+        Status::Converted => {
+            let message = format!(
+                "{} {} \u{2192} {}",
+                match (config.dry_run, config.copy) {
+                    (true, true) => "Would copy",
+                    (true, false) => "Would rename",
+                    (false, true) => "Copied",
+                    (false, false) => "Renamed",
+                },
+                display_path,
+                display_relative(&config.root, &outcome.target)
+            );
+            tracing::info!("{}", message);
+            // Dry runs print every planned rename anyway.
+            if config.verbosity >= Verbosity::Verbose
+                && !config.dry_run
+                && !config.json
+                && !config.porcelain
+                && !config.print0
+            {
+                progress.suspend(|| println!("{}", message));
+            }
+        }
+        Status::Skipped => {
+            let message = format!(
+                "Skipping {} ({} already exists)",
+                display_path,
+                display_relative(&config.root, &outcome.target)
+            );
+            tracing::info!("{}", message);
+            if print {
+                progress::println(progress, message);
+            }
+        }
+        Status::Changed => {
+            let message = format!("Skipping {} ({})", display_path, error_text);
+            tracing::info!("{}", message);
+            if print {
+                progress::println(progress, message);
+            }
+        }
+        Status::Failed | Status::Denied => {
+            let message = format!(
+                "Failed to {} {}: {}",
+                if config.copy { "copy" } else { "rename" },
+                display_path,
+                error_text
+            );
+            tracing::error!("{}", message);
+            if print {
+                progress::println(progress, color::failure(&message));
+            }
+            if let Some(sink) = errors_json
+                && let Err(err) = writeln!(sink, "{}", report::outcome_json(&config.root, outcome))
+            {
+                progress::println(
+                    progress,
+                    format!("Failed to write --errors-json output: {}", err),
+                );
+            }
+        }
+    }
+}
+
+fn progress_bar(len: usize) -> Result<ProgressBar, String> {
+    BarLayout::default().bar(len, Arc::default())
+}
+
+/// Prints `lines` on stdout, above the progress bar.
+fn print_lines(progress: &ProgressBar, lines: Vec<String>) {
+    if !lines.is_empty() {
+        progress.suspend(|| {
+            for line in lines {
+                println!("{}", line);
+            }
+        });
+    }
+}
+
+/// Prints the scan's warnings, unless `quiet`, and logs them.
+fn print_warnings(collected: &CollectedFiles, quiet: bool) {
+    for warning in &collected.warnings {
+        if !quiet {
+            eprintln!("{}", color::warning(warning));
+        }
+        tracing::warn!("{}", warning);
+    }
+    if collected.suppressed_warnings > 0 {
+        let message = format!(
+            "...and {} more warning{}",
+            collected.suppressed_warnings,
+            if collected.suppressed_warnings == 1 {
+                ""
+            } else {
+                "s"
+            }
+        );
+        if !quiet {
+            eprintln!("{}", color::warning(&message));
+        }
+        tracing::warn!("{}", message);
+    }
+}
+
+/// Writes `path` to stdout for `--print0`, flushed so that a consumer like
+/// `xargs -0` can start on it while the run goes on.
+fn print0(path: &Path) {
+    let mut out = io::stdout().lock();
+    let _ = out
+        .write_all(path.as_os_str().as_encoded_bytes())
+        .and_then(|()| out.write_all(b"\0"))
+        .and_then(|()| out.flush());
+}
+
+fn display_relative(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+/// A byte count in binary units, e.g. `48.2 GiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// The name `path` gets: the same name with `extension`, in place, or at the
+/// same place relative to `root` under `dest`.
+fn target_path(root: &Path, dest: Option<&Path>, path: &Path, extension: &str) -> PathBuf {
+    match dest {
+        Some(dest) => dest
+            .join(path.strip_prefix(root).unwrap_or(path))
+            .with_extension(extension),
+        None => path.with_extension(extension),
+    }
+}
+
+/// Like `display_relative`, but lossless for names that are not valid UTF-8.
+/// Used wherever the path is written for another program to read.
+fn encode_relative(root: &Path, path: &Path) -> String {
+    pathenc::encode_relative(path.strip_prefix(root).unwrap_or(path))
+}
+
+/// Walks `dirs` (`root` itself, or folders below it) depth-first, in the
+/// order given. Only directories wait on the stack; matching files go
+/// straight into a `PathSorter`, which spills to disk once the listing
+/// outgrows `memory_limit` bytes. Per-directory counts stay in memory, one
+/// entry per folder with matching files. `walk` says how deep to go and in
+/// which order. Files `filter` rules out are never listed.
+#[allow(clippy::too_many_arguments)]
+fn collect_files(
+    root: &Path,
+    dirs: &[PathBuf],
+    extension: &str,
+    compound: CompoundRule,
+    filter: &Filter,
+    order: PathOrder,
+    walk: Walk,
+    memory_limit: usize,
+) -> Result<CollectedFiles, String> {
+    let mut stack: Vec<Pending> = dirs
+        .iter()
+        .rev()
+        .map(|dir| Pending {
+            dir: dir.clone(),
+            depth: 1,
+            volume: None,
+            ignore: None,
+        })
+        .collect();
+    // When following links to folders: where each folder scanned really is,
+    // and the path it was first scanned under.
+    let mut scanned: HashMap<PathBuf, PathBuf> = HashMap::new();
+    // The scan may start inside a card (e.g. at its DCIM folder).
+    let mut cards: Vec<PathBuf> = dirs
+        .iter()
+        .filter_map(|dir| dir.ancestors().find_map(card::card_root))
+        .map(Path::to_path_buf)
+        .collect();
+    let mut files = PathSorter::new(memory_limit, order);
+    let mut folders: HashMap<PathBuf, usize> = HashMap::new();
+    let mut warnings = Warnings::default();
+    let mut locks = Vec::new();
+    let spill_error = |err: std::io::Error| format!("Failed to spool the file list: {}", err);
+
+    while let Some(Pending {
+        dir,
+        depth,
+        volume: parent_volume,
+        ignore,
+    }) = stack.pop()
+    {
+        let volume = if walk.one_file_system {
+            match volumes::volume_of(&dir) {
+                Ok(volume) => Some(volume),
+                Err(err) => {
+                    warnings.push(format!("Skipping directory {}: {}", dir.display(), err));
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+        if parent_volume.is_some() && volume != parent_volume {
+            warnings.push(format!(
+                "Skipping {} (another file system is mounted there)",
+                dir.display()
+            ));
+            continue;
+        }
+        if walk.symlinks == Symlinks::All
+            && let Ok(real) = winpath::resolve(&dir)
+        {
+            if let Some(first) = scanned.get(&real) {
+                warnings.push(format!(
+                    "Skipping {} (the same folder as {}, scanned already)",
+                    dir.display(),
+                    first.display()
+                ));
+                continue;
+            }
+            scanned.insert(real, dir.clone());
+        }
+        let ignore = if walk.ignore_files {
+            ignore::load(&dir, ignore.clone()).unwrap_or_else(|err| {
+                warnings.push(err);
+                ignore
+            })
+        } else {
+            None
+        };
+        let span = tracing::debug_span!(
+            "scan",
+            dir = %dir.display(),
+            files = tracing::field::Empty,
+            subdirs = tracing::field::Empty
+        )
+        .entered();
+        let entries = match dirent::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warnings.push(format!("Skipping directory {}: {}", dir.display(), err));
+                continue;
+            }
+        };
+        let entries: Box<dyn Iterator<Item = _>> = if walk.sorted {
+            let mut entries: Vec<_> = entries.collect();
+            // Listing errors keep their place after the entries.
+            entries.sort_by(|a, b| match (a, b) {
+                (Ok(a), Ok(b)) => a.path.cmp(&b.path),
+                (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+                (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+                (Err(_), Err(_)) => std::cmp::Ordering::Equal,
+            });
+            Box::new(entries.into_iter())
+        } else {
+            entries
+        };
+        let listed_before = files.len();
+        let mut subdirs = Vec::new();
+
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warnings.push(format!("Skipping entry in {}: {}", dir.display(), err));
+                    continue;
+                }
+            };
+
+            // A root's own lock is this run's.
+            if depth > 1
+                && entry.kind == Kind::File
+                && entry.path.file_name() == Some(OsStr::new(runlock::FILE_NAME))
+            {
+                locks.push(entry.path);
+                continue;
+            }
+            if entry.kind != Kind::Dir
+                && has_extension(&entry.path, extension)
+                && !filter.allows(entry.path.strip_prefix(root).unwrap_or(&entry.path))
+            {
+                continue;
+            }
+            if !walk.hidden && is_hidden(&entry.path) {
+                continue;
+            }
+            if let Some(ignore) = &ignore
+                && ignore.ignores(&entry.path, entry.kind == Kind::Dir)
+            {
+                continue;
+            }
+
+            if entry.kind != Kind::Dir
+                && compound == CompoundRule::Skip
+                && has_extension(&entry.path, extension)
+                && is_compound(&entry.path)
+            {
+                warnings.push(format!(
+                    "Skipping {} (compound extension)",
+                    entry.path.display()
+                ));
+                continue;
+            }
+
+            match entry.kind {
+                Kind::Dir
+                    if filter.prunes(entry.path.strip_prefix(root).unwrap_or(&entry.path)) => {}
+                Kind::Dir => {
+                    if let Some(card) = card::card_root(&entry.path) {
+                        cards.push(card.to_path_buf());
+                    }
+                    subdirs.push(entry.path)
+                }
+                Kind::File if has_extension(&entry.path, extension) => {
+                    files.push(entry.path).map_err(spill_error)?
+                }
+                Kind::Symlink
+                    if walk.symlinks == Symlinks::None && has_extension(&entry.path, extension) =>
+                {
+                    warnings.push(format!("Skipping symlink {}", entry.path.display()));
+                }
+                Kind::Symlink
+                    if has_extension(&entry.path, extension) || walk.symlinks == Symlinks::All =>
+                {
+                    match trace::call("stat", &entry.path, || fs::metadata(&entry.path)) {
+                        Ok(target_meta) => {
+                            if target_meta.is_dir()
+                                && walk.symlinks == Symlinks::All
+                                && !filter
+                                    .prunes(entry.path.strip_prefix(root).unwrap_or(&entry.path))
+                            {
+                                if let Some(card) = card::card_root(&entry.path) {
+                                    cards.push(card.to_path_buf());
+                                }
+                                subdirs.push(entry.path);
+                            } else if target_meta.is_file() && has_extension(&entry.path, extension)
+                            {
+                                files.push(entry.path).map_err(spill_error)?;
+                            }
+                        }
+                        Err(err) => warnings.push(format!(
+                            "Skipping symlink {}: {}",
+                            entry.path.display(),
+                            err
+                        )),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        span.record("files", files.len() - listed_before);
+        span.record("subdirs", subdirs.len());
+        if files.len() > listed_before {
+            folders.insert(dir, files.len() - listed_before);
+        }
+        if walk.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            continue;
+        }
+        // Reversed so subdirectories are visited in listing order.
+        stack.extend(subdirs.into_iter().rev().map(|subdir| Pending {
+            dir: subdir,
+            depth: depth + 1,
+            volume: volume.as_ref().cloned(),
+            ignore: ignore.clone(),
+        }));
+    }
+
+    Ok(CollectedFiles {
+        files: files.finish().map_err(spill_error)?,
+        locks,
+        folders,
+        warnings: warnings.shown,
+        suppressed_warnings: warnings.suppressed,
+        cards,
+    })
+}
+
+/// Whether a scan of `root` with `walk` would reach `path`, a file below it
+/// that the watch notifier reported: the checks `collect_files` makes on the
+/// way down, made for one path. The include and exclude patterns are left
+/// to the caller.
+fn walk_reaches(root: &Path, path: &Path, walk: Walk, filter: &Filter) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return false;
+    };
+    if walk
+        .max_depth
+        .is_some_and(|max_depth| relative.components().count() > max_depth)
+        || (!walk.hidden && relative.ancestors().any(is_hidden))
+    {
+        return false;
+    }
+    if walk.one_file_system
+        && let Some(dir) = path.parent()
+        && volumes::volume_of(dir).ok() != volumes::volume_of(root).ok()
+    {
+        return false;
+    }
+
+    let is_symlink = |path: &Path| fs::symlink_metadata(path).is_ok_and(|meta| meta.is_symlink());
+    if walk.symlinks == Symlinks::None && is_symlink(path) {
+        return false;
+    }
+    let mut ignore = if walk.ignore_files {
+        ignore::load(root, None).unwrap_or(None)
+    } else {
+        None
+    };
+    // The folders between the root and the file, from the top down.
+    let mut dirs: Vec<&Path> = relative.ancestors().skip(1).collect();
+    dirs.pop();
+    for dir in dirs.into_iter().rev() {
+        let full = winpath::join(root, dir);
+        if filter.prunes(dir)
+            || (walk.symlinks != Symlinks::All && is_symlink(&full))
+            || ignore
+                .as_ref()
+                .is_some_and(|ignore| ignore.ignores(&full, true))
+        {
+            return false;
+        }
+        if walk.ignore_files {
+            ignore = ignore::load(&full, ignore.clone()).unwrap_or(ignore);
+        }
+    }
+    !ignore.is_some_and(|ignore| ignore.ignores(path, false))
+}
+
+/// Like `collect_files`, for an explicit list of paths relative to `root`.
+/// Only listed files with `extension` are renamed; anything else the list
+/// names (sidecars, folders) is ignored, and so are hidden files unless
+/// `hidden` is set. Listed files that are missing or point outside the root
+/// are skipped with a warning.
+#[allow(clippy::too_many_arguments)]
+fn collect_listed(
+    root: &Path,
+    mut listed: Vec<PathBuf>,
+    extension: &str,
+    compound: CompoundRule,
+    filter: &Filter,
+    hidden: bool,
+    order: PathOrder,
+    memory_limit: usize,
+) -> Result<CollectedFiles, String> {
+    let mut files = PathSorter::new(memory_limit, order);
+    let mut folders: HashMap<PathBuf, usize> = HashMap::new();
+    let mut cards: Vec<PathBuf> = root
+        .ancestors()
+        .find_map(card::card_root)
+        .map(Path::to_path_buf)
+        .into_iter()
+        .collect();
+    let mut warnings = Warnings::default();
+    let spill_error = |err: std::io::Error| format!("Failed to spool the file list: {}", err);
+
+    // Paths from a plain list may start with `./`, or be absolute, as from
+    // `find /Volumes/CAM_A001`; absolute ones outside the root stay that way
+    // and are skipped below.
+    for path in &mut listed {
+        if path.is_absolute() {
+            let inside = path
+                .strip_prefix(root)
+                .ok()
+                .map(Path::to_path_buf)
+                .or_else(|| {
+                    let resolved = winpath::resolve(path).ok()?;
+                    resolved.strip_prefix(root).ok().map(Path::to_path_buf)
+                });
+            if let Some(inside) = inside {
+                *path = inside;
+            }
+        } else if let Ok(inside) = path.strip_prefix(".") {
+            *path = inside.to_path_buf();
+        }
+    }
+    listed.sort();
+    listed.dedup();
+
+    for relative in listed {
+        if !has_extension(&relative, extension)
+            || !filter.allows(&relative)
+            || relative.ancestors().skip(1).any(|dir| filter.prunes(dir))
+            || (!hidden && relative.ancestors().any(is_hidden))
+        {
+            continue;
+        }
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            warnings.push(format!(
+                "Skipping listed {} (outside {})",
+                relative.display(),
+                root.display()
+            ));
+            continue;
+        }
+
+        let path = winpath::join(root, &relative);
+        if compound == CompoundRule::Skip && is_compound(&path) {
+            warnings.push(format!("Skipping {} (compound extension)", path.display()));
+            continue;
+        }
+
+        match trace::call("stat", &path, || fs::metadata(&path)) {
+            Ok(metadata) if metadata.is_file() => {}
+            Ok(_) => continue,
+            Err(err) => {
+                warnings.push(format!("Skipping listed {}: {}", path.display(), err));
+                continue;
+            }
+        }
+
+        if let Some(dir) = path.parent() {
+            *folders.entry(dir.to_path_buf()).or_default() += 1;
+            let found = dir
+                .ancestors()
+                .take_while(|ancestor| ancestor.starts_with(root) && *ancestor != root)
+                .filter_map(card::card_root);
+            for card in found {
+                if !cards.iter().any(|known| known == card) {
+                    cards.push(card.to_path_buf());
+                }
+            }
+        }
+        files.push(path).map_err(spill_error)?;
+    }
+
+    Ok(CollectedFiles {
+        files: files.finish().map_err(spill_error)?,
+        locks: Vec::new(),
+        folders,
+        warnings: warnings.shown,
+        suppressed_warnings: warnings.suppressed,
+        cards,
+    })
+}
+
+/// The files `r3dy retry` renames again: every file of each failed clip
+/// that still carries the source extension, relative to the root. A failed
+/// segment or sidecar brings back its whole clip, which was rolled back
+/// with it.
+fn retry_files(config: &Config, failed: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
+    for path in failed {
+        let clip = clip::key(path, config.clip_folders);
+        // A sidecar is keyed by its own path, but named after its clip.
+        let sidecar = sidecar::is_sidecar(path) && clip == *path;
+        let Some(dir) = path.parent() else {
+            continue;
+        };
+        if !seen.insert(clip.clone()) {
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let file = entry.path();
+            let of_clip = if sidecar {
+                clip::name(&file) == path.file_stem()
+            } else {
+                clip::key(&file, config.clip_folders) == clip
+            };
+            if has_extension(&file, config.source_extension())
+                && of_clip
+                && let Ok(relative) = file.strip_prefix(&config.root)
+            {
+                files.push(relative.to_path_buf());
+            }
+        }
+    }
+    files
+}
+
+/// Whether a name has more than one extension (`CLIP.bak.NEV`). A leading
+/// dot marks a hidden file rather than an extension.
+fn is_compound(path: &Path) -> bool {
+    path.file_stem()
+        .map(|stem| {
+            let stem = stem.as_encoded_bytes();
+            let stem = stem.strip_prefix(b".").unwrap_or(stem);
+            stem.contains(&b'.')
+        })
+        .unwrap_or(false)
+}
+
+/// How names with several extensions are treated. Only the last extension is
+/// ever compared, so `CLIP.NEV.bak` and `CLIP.nev.partial` never match; the
+/// rule decides what happens to `CLIP.bak.NEV`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompoundRule {
+    Last,
+    Skip,
+}
+
+impl CompoundRule {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "last" => Some(CompoundRule::Last),
+            "skip" => Some(CompoundRule::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// How `collect_files` walks the tree.
+#[derive(Clone, Copy, Debug, Default)]
+struct Walk {
+    /// List each directory in name order, so warnings come out the same on
+    /// every run.
+    sorted: bool,
+    /// How many levels of folders to list, counting the directory given as
+    /// the first (`--max-depth`).
+    max_depth: Option<usize>,
+    symlinks: Symlinks,
+    /// Stay on the volume of each directory given (`--one-file-system`).
+    one_file_system: bool,
+    /// Leave alone what `.r3dyignore` files in the tree name.
+    ignore_files: bool,
+    /// Scan hidden files and folders too (`--hidden`).
+    hidden: bool,
+}
+
+/// A directory waiting to be listed by `collect_files`.
+struct Pending {
+    dir: PathBuf,
+    /// The directories given are at depth 1.
+    depth: usize,
+    /// With --one-file-system, the volume of the folder it was found in.
+    volume: Option<volumes::VolumeId>,
+    /// The `.r3dyignore` rules of the folders above it.
+    ignore: Option<Rc<Ignore>>,
+}
+
+/// Which symlinks a scan follows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Symlinks {
+    /// Links to matching files, but not links to folders.
+    #[default]
+    Files,
+    /// Links to folders too (`--follow-symlinks`). Each folder is scanned
+    /// once, however many links lead to it, so a loop cannot hang the scan.
+    All,
+    /// None, with a warning for each matching link (`--no-follow-symlinks`).
+    None,
+}
+
+/// How much a run prints to the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Verbosity {
+    /// The totals line and errors, without a progress bar.
+    Quiet,
+    Normal,
+    /// Also a line for every file renamed.
+    Verbose,
+    /// Also the debug log on stderr, unless --log-file or --log-level is
+    /// given.
+    Debug,
+}
+
+/// Checks several directories given on the command line and returns the
+/// folder they share, to use as the root, with each directory resolved.
+fn root_arguments(paths: &[PathBuf]) -> Result<(PathBuf, Vec<PathBuf>), String> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        let metadata = fs::metadata(path).map_err(|err| {
+            let mut message = format!("{} is not accessible: {}", path.display(), err);
+            if let Some(hint) = winpath::access_hint(path, &err) {
+                message.push('\n');
+                message.push_str(&hint);
+            }
+            message
+        })?;
+        if !metadata.is_dir() {
+            return Err(format!(
+                "{} is a file; give either directories or only clip files",
+                path.display()
+            ));
+        }
+        let dir = winpath::resolve(path)
+            .map_err(|err| format!("Failed to resolve {}: {}", path.display(), err))?;
+        // A folder inside another would be scanned twice.
+        if let Some(other) = dirs
+            .iter()
+            .find(|other| dir.starts_with(other) || other.starts_with(&dir))
+        {
+            return Err(if dir == *other {
+                format!("{} is given twice", path.display())
+            } else {
+                format!(
+                    "{} and {} overlap; give only the outer one",
+                    other.display(),
+                    dir.display()
+                )
+            });
+        }
+        dirs.push(dir);
+    }
+
+    let mut root = dirs[0].clone();
+    for dir in &dirs[1..] {
+        while !dir.starts_with(&root) {
+            if !root.pop() {
+                return Err("The directories given share no common folder".to_string());
+            }
+        }
+    }
+    Ok((root, dirs))
+}
+
+/// Checks clip files given on the command line and returns the folder they
+/// share, to use as the root, with each clip relative to it.
+fn clip_arguments(
+    paths: &[PathBuf],
+    (source, target): (&str, &str),
+) -> Result<(PathBuf, Vec<PathBuf>), String> {
+    let mut clips = Vec::new();
+    for path in paths {
+        let metadata = fs::metadata(path).map_err(|err| {
+            let mut message = format!("{} is not accessible: {}", path.display(), err);
+            if let Some(hint) = winpath::access_hint(path, &err) {
+                message.push('\n');
+                message.push_str(&hint);
+            }
+            message
+        })?;
+        if metadata.is_dir() {
+            return Err(format!(
+                "{} is a directory; give either directories or only .{} files",
+                path.display(),
+                source
+            ));
+        }
+        if !has_extension(path, source) {
+            let mut message = format!("{} is not a .{} file", path.display(), source);
+            if has_extension(path, target) {
+                message.push_str(&format!(
+                    " (already .{}; use --invert to rename it back)",
+                    target
+                ));
+            }
+            return Err(message);
+        }
+
+        // Resolve the folder, not the clip, so a symlinked clip is renamed
+        // rather than the file it points to.
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return Err(format!("{} is not a file", path.display()));
+        };
+        let parent = winpath::resolve(parent)
+            .map_err(|err| format!("Failed to resolve {}: {}", parent.display(), err))?;
+        clips.push(parent.join(name));
+    }
+
+    let mut root = clips[0].parent().unwrap_or(&clips[0]).to_path_buf();
+    for clip in &clips[1..] {
+        while !clip.starts_with(&root) {
+            if !root.pop() {
+                return Err("The clips given share no common folder".to_string());
+            }
+        }
+    }
+
+    let relative = clips
+        .iter()
+        .map(|clip| clip.strip_prefix(&root).unwrap_or(clip).to_path_buf())
+        .collect();
+    Ok((root, relative))
+}
+
+/// Whether a scan leaves the file or folder at `path` alone unless `--hidden`
+/// is given: dotfiles (including macOS `._` AppleDouble files and folders
+/// like `.Trashes`) and the `Thumbs.db` and `desktop.ini` files Windows
+/// leaves behind.
+fn is_hidden(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| {
+        name.as_encoded_bytes().starts_with(b".")
+            || name.eq_ignore_ascii_case("Thumbs.db")
+            || name.eq_ignore_ascii_case("desktop.ini")
+    })
+}
+
+fn has_extension(path: &Path, expected: &str) -> bool {
+    path.extension()
+        .map(|ext| pathenc::extension_eq(ext, expected))
+        .unwrap_or(false)
+}
+
+struct Config {
+    /// The folder paths are shown and reported relative to.
+    root: PathBuf,
+    /// The directories scanned: the root itself, or each directory given
+    /// when there are several.
+    roots: Vec<PathBuf>,
+    /// The extension renamed from and the one renamed to.
+    from: String,
+    to: String,
+    email: Option<EmailConfig>,
+    webhooks: Vec<Webhook>,
+    publish: Vec<publish::Target>,
+    errors_json: Option<String>,
+    summary_out: Option<String>,
+    memory_limit: usize,
+    backend: BackendKind,
+    /// What happens to a file whose new name is taken.
+    on_conflict: Conflict,
+    /// Move files to another volume by copying them when a rename cannot.
+    allow_copy: bool,
+    /// Stop copying before the destination has fewer bytes free than this.
+    min_free: Option<u64>,
+    /// What copies keep of their originals.
+    preserve: Preserve,
+    /// Clear the read-only flag of each file for its rename.
+    chmod_writable: bool,
+    /// How long to wait for a file another program has open to be let go.
+    wait_for_unlock: Option<Duration>,
+    /// Take the run lock on the root even if another run seems to hold it.
+    force_lock: bool,
+    /// Roll the whole run back through the journal once a file fails.
+    atomic: bool,
+    /// Renames in flight at once with the std backend.
+    jobs: usize,
+    trace: Option<String>,
+    /// Where to append warnings and failures; stderr when only a level is
+    /// given.
+    log_file: Option<PathBuf>,
+    /// How much to log, from --log-level or RUST_LOG; None logs nothing
+    /// without a --log-file.
+    log_level: Option<logfile::Level>,
+    log_format: logfile::Format,
+    manifest: Option<String>,
+    archive: Option<String>,
+    /// Where to write an MHL of the converted files.
+    mhl: Option<PathBuf>,
+    report: Option<PathBuf>,
+    fcpxml: Option<PathBuf>,
+    resolve_script: Option<PathBuf>,
+    resolve_bin: Option<String>,
+    premiere_csv: Option<PathBuf>,
+    compound: CompoundRule,
+    /// The --include and --exclude patterns.
+    filter: Filter,
+    run_as: Option<String>,
+    sandbox: bool,
+    heartbeat: Option<Duration>,
+    deterministic: bool,
+    dry_run: bool,
+    /// Copy each file to its new name instead of renaming it.
+    copy: bool,
+    /// Hash each file before and after renaming or copying it.
+    verify: bool,
+    /// The checksum `verify` compares.
+    verify_hash: hash::Digest,
+    /// Folder the renamed files go to, mirroring their place under the root.
+    dest: Option<PathBuf>,
+    /// Keep running and rename files as they appear.
+    watch: bool,
+    /// How long a file must stay unchanged in watch mode.
+    settle: Duration,
+    /// Folders with a settle period of their own, from `--settle
+    /// FOLDER=TIME`.
+    settle_folders: Vec<(PathBuf, Duration)>,
+    /// Folders whose files watch mode renames ahead of others, from
+    /// `--priority FOLDER=N`.
+    priorities: Vec<(PathBuf, i32)>,
+    /// Treat each RED `.RDC` folder as one clip, renamed all or nothing.
+    clip_folders: bool,
+    /// Move each clip's sidecar files along with it.
+    sidecars: bool,
+    /// Fail files without a RED header instead of renaming them.
+    check_headers: bool,
+    /// Ask before renaming each clip.
+    interactive: bool,
+    /// Show the full-screen dashboard instead of progress bars.
+    tui: bool,
+    /// Name the renamed files from their clip metadata.
+    template: Option<Template>,
+    /// Write new names in this Unicode normalization form.
+    normalize: Option<normalize::Form>,
+    /// Replace characters in new names that Windows and exFAT refuse.
+    sanitize: bool,
+    /// Whether to write the undo journal.
+    journal: bool,
+    /// The arguments the run was started with, config files included, kept
+    /// in the journal for `r3dy resume`; None for a run through [`Renamer`].
+    args: Option<Vec<String>>,
+    /// The interrupted run this one continues.
+    resumed: Option<journal::Resumed>,
+    /// Emit NDJSON events on stdout instead of the human-readable output.
+    json: bool,
+    /// Emit one tab-separated line per file on stdout instead.
+    porcelain: bool,
+    /// Write the absolute path of every renamed file to stdout, each ended
+    /// by a NUL byte, and nothing else.
+    print0: bool,
+    /// The list to work on instead of scanning, `-` for stdin.
+    files_from: Option<PathBuf>,
+    /// The plain list is NUL-delimited (`-0`).
+    files_from_null: bool,
+    /// How many levels of folders a scan lists, the root being the first.
+    max_depth: Option<usize>,
+    symlinks: Symlinks,
+    /// Do not scan into other volumes mounted inside the tree.
+    one_file_system: bool,
+    /// Honour `.r3dyignore` files; off with `--no-ignore`.
+    ignore_files: bool,
+    /// Rename hidden files too, and scan hidden folders.
+    hidden: bool,
+    /// Stop after this many files, finishing the clip in progress.
+    limit: Option<usize>,
+    /// Clip files given on the command line, relative to the root.
+    clips: Vec<PathBuf>,
+    progress: BarLayout,
+    /// The status interval in screen-reader mode.
+    screen_reader: Option<Duration>,
+    verbosity: Verbosity,
+    color: color::Choice,
+}
+
+/// The new names planned before a run starts.
+#[derive(Default)]
+struct Names {
+    /// The target of each file `--template` named.
+    named: HashMap<PathBuf, PathBuf>,
+    /// The names `--sanitize` changed, by source, for the manifest.
+    sanitized: HashMap<PathBuf, OsString>,
+}
+
+enum ConfigError {
+    /// The arguments do not parse; clap's error, with usage.
+    Args(clap::Error),
+    Message(String),
+}
+
+impl Config {
+    /// Parses the options of `r3dy rename` in `args`, which are kept for
+    /// the journal.
+    fn from_args(args: Vec<String>) -> Result<Self, ConfigError> {
+        let options = args::rename_options(&args).map_err(ConfigError::Args)?;
+        Self::new(options, Some(args)).map_err(ConfigError::Message)
+    }
+
+    /// Checks `options` and resolves the paths they name. `given` are the
+    /// arguments they were parsed from, if they were.
+    fn new(options: RenameArgs, given: Option<Vec<String>>) -> Result<Self, String> {
+        let RenameArgs {
+            paths,
+            invert,
+            from,
+            to,
+            email_to,
+            email_from,
+            smtp_url,
+            smtp_user,
+            email_attach,
+            slack_webhook,
+            teams_webhook,
+            publish,
+            errors_json,
+            summary_fd,
+            summary_file,
+            max_memory,
+            backend,
+            on_conflict,
+            jobs,
+            trace,
+            log_file,
+            log_level,
+            log_format,
+            manifest,
+            archive,
+            mhl,
+            report,
+            fcpxml,
+            resolve_script,
+            resolve_bin,
+            premiere_csv,
+            compound,
+            include,
+            exclude,
+            exclude_dir,
+            limit,
+            mut max_depth,
+            no_recursive,
+            follow_symlinks,
+            no_follow_symlinks,
+            hidden,
+            no_ignore,
+            one_file_system,
+            wait_for_path,
+            run_as,
+            sandbox,
+            heartbeat,
+            deterministic,
+            copy,
+            verify,
+            verify_hash,
+            dest,
+            allow_copy,
+            min_free,
+            preserve_times,
+            preserve,
+            no_zone_identifier,
+            chmod_writable,
+            wait_for_unlock,
+            force_lock,
+            atomic,
+            watch,
+            settle,
+            priority,
+            clip_folders,
+            sidecars,
+            check_headers,
+            template,
+            exiftool,
+            normalize,
+            sanitize,
+            interactive,
+            tui,
+            // Already applied by `settings::with_defaults`.
+            profile: _,
+            no_config: _,
+            dry_run,
+            no_journal,
+            color,
+            quiet,
+            verbose,
+            json,
+            porcelain,
+            print0,
+            files_from,
+            null: files_from_null,
+            progress_template,
+            progress_refresh,
+            screen_reader,
+            status_interval,
+        } = options;
+
+        let backend = backend.unwrap_or(BackendKind::Std);
+        let jobs = jobs.unwrap_or(1);
+        let journal = !no_journal;
+        let porcelain = porcelain.is_some();
+        let mut webhooks: Vec<Webhook> = slack_webhook
+            .iter()
+            .map(|value| Webhook::parse(WebhookKind::Slack, value))
+            .chain(
+                teams_webhook
+                    .iter()
+                    .map(|value| Webhook::parse(WebhookKind::Teams, value)),
+            )
+            .collect();
+        let summary_out = match (summary_fd, summary_file) {
+            (Some(fd), _) => Some(fd.to_string()),
+            // Keep numeric names from being read as descriptors.
+            (None, Some(path)) if path.parse::<i32>().is_ok() => Some(format!("./{}", path)),
+            (None, path) => path,
+        };
+        let mut filter = Filter::default();
+        for pattern in &include {
+            filter.include(pattern)?;
+        }
+        for pattern in &exclude {
+            filter.exclude(pattern)?;
+        }
+        for name in &exclude_dir {
+            filter.exclude_dir(name)?;
+        }
+        let mut progress = BarLayout::default();
+        if let Some(template) = &progress_template {
+            progress.set_template(template)?;
+        }
+        if let Some(refresh) = &progress_refresh {
+            progress.set_refresh(refresh)?;
+        }
+        let mut preserve = preserve.unwrap_or_default();
+
+        if sandbox && (!email_to.is_empty() || !webhooks.is_empty() || !publish.is_empty()) {
+            return Err(
+                "--sandbox cannot be combined with email, webhook or --publish delivery, which run helper programs".to_string(),
+            );
+        }
+
+        if copy && backend != BackendKind::Std {
+            return Err("--copy only works with the std backend".to_string());
+        }
+
+        if verify_hash.is_some() && !verify {
+            return Err("--verify-hash requires --verify".to_string());
+        }
+
+        if verify && backend != BackendKind::Std {
+            return Err("--verify only works with the std backend".to_string());
+        }
+
+        if dest.is_some() && backend != BackendKind::Std {
+            return Err("--dest only works with the std backend".to_string());
+        }
+
+        if atomic && !journal {
+            return Err(
+                "--atomic rolls back through the undo journal, so it cannot be combined with --no-journal".to_string(),
+            );
+        }
+
+        if atomic && (manifest.is_some() || archive.is_some() || mhl.is_some()) {
+            return Err(
+                "--atomic cannot be combined with --manifest, --archive or --mhl, which list files as they are renamed".to_string(),
+            );
+        }
+
+        if dest.is_some() && (manifest.is_some() || archive.is_some()) {
+            return Err(
+                "--dest cannot be combined with --manifest or --archive, whose paths are relative to the scanned tree".to_string(),
+            );
+        }
+
+        if jobs > 1 && backend != BackendKind::Std {
+            return Err(
+                "--jobs only applies to the std backend; uring already keeps many renames in flight"
+                    .to_string(),
+            );
+        }
+
+        preserve.times |= preserve_times;
+        preserve.drop_zone_identifier = no_zone_identifier;
+        if preserve != Preserve::default() && !copy && !allow_copy {
+            return Err(
+                "--preserve, --preserve-times and --no-zone-identifier only apply with --copy or --allow-copy; a rename keeps everything"
+                    .to_string(),
+            );
+        }
+
+        if min_free.is_some() && !copy && !allow_copy {
+            return Err(
+                "--min-free only applies with --copy or --allow-copy; a rename takes no space"
+                    .to_string(),
+            );
+        }
+
+        if chmod_writable && copy {
+            return Err(
+                "--chmod-writable does not apply with --copy, which leaves the originals as they are"
+                    .to_string(),
+            );
+        }
+
+        if !settle.is_empty() && !watch {
+            return Err("--settle only applies with --watch".to_string());
+        }
+        if !priority.is_empty() && !watch {
+            return Err("--priority only applies with --watch".to_string());
+        }
+
+        if log_format.is_some()
+            && log_file.is_none()
+            && log_level.is_none()
+            && logfile::env_filter().is_none()
+        {
+            return Err("--log-format requires --log-file, --log-level or RUST_LOG".to_string());
+        }
+
+        if resolve_bin.is_some() && resolve_script.is_none() {
+            return Err("--resolve-bin requires --resolve-script".to_string());
+        }
+        if no_recursive {
+            max_depth = Some(1);
+        }
+        if files_from_null && files_from.is_none() {
+            return Err("-0 requires --files-from".to_string());
+        }
+
+        if json || porcelain {
+            progress.hide();
+        }
+        if tui {
+            progress.hide();
+        }
+        if interactive && files_from.as_deref() == Some(Path::new("-")) {
+            return Err(
+                "--interactive cannot be combined with --files-from - (it reads the answers from stdin)"
+                    .to_string(),
+            );
+        }
+
+        let verbosity = match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Debug,
+        };
+        if quiet {
+            progress.hide();
+        }
+
+        if status_interval.is_some() && !screen_reader {
+            return Err("--status-interval only applies with --screen-reader".to_string());
+        }
+
+        let (from, to) = extension_pair(invert, from, to)?;
+        let template = match template {
+            Some(text) => {
+                let mut template = Template::parse(&text, &to)?;
+                template.set_exiftool(exiftool);
+                Some(template)
+            }
+            None if exiftool => {
+                return Err("--exiftool only applies with --template".to_string());
+            }
+            None => None,
+        };
+        let red = |extension: &str| ["R3D", "NEV"].contains(&extension.to_uppercase().as_str());
+        if check_headers && !(red(&from) && red(&to)) {
+            return Err(
+                "--check-headers only applies when renaming between .NEV and .R3D".to_string(),
+            );
+        }
+
+        let cwd = env::current_dir()
+            .map_err(|err| format!("Failed to determine current directory: {}", err))?;
+
+        let paths: Vec<PathBuf> = paths
+            .into_iter()
+            .map(|path| {
+                if path.is_absolute() {
+                    path
+                } else {
+                    cwd.join(path)
+                }
+            })
+            .collect();
+
+        // Directories are scanned; otherwise every argument must be a clip.
+        // Several directories or clips are renamed relative to the folder
+        // they share, which becomes the root.
+        let mut clips = Vec::new();
+        let mut roots = Vec::new();
+        // With --wait-for-path, a directory may not be mounted yet.
+        let several_dirs = paths.len() > 1
+            && paths
+                .iter()
+                .all(|path| path.is_dir() || (wait_for_path.is_some() && !path.exists()));
+        let root = if several_dirs {
+            if files_from.is_some() {
+                return Err("--files-from cannot be combined with path arguments".to_string());
+            }
+            if watch {
+                return Err("--watch takes a single directory".to_string());
+            }
+            if let Some(timeout) = wait_for_path {
+                for path in &paths {
+                    wait::wait_for_dir(path, timeout)?;
+                }
+            }
+            let (root, dirs) = root_arguments(&paths)?;
+            roots = dirs;
+            root
+        } else if paths.len() > 1 || paths.first().is_some_and(|path| path.is_file()) {
+            if files_from.is_some() {
+                return Err("--files-from cannot be combined with file arguments".to_string());
+            }
+            if wait_for_path.is_some() {
+                return Err("--wait-for-path takes a directory, not file arguments".to_string());
+            }
+            let (root, files) = clip_arguments(&paths, (&from, &to))?;
+            clips = files;
+            root
+        } else {
+            match paths.into_iter().next() {
+                Some(path) => path,
+                // MHL paths are relative to the MHL's own folder.
+                None => match &files_from {
+                    Some(list) if has_extension(list, "mhl") => mhl::default_root(list)?,
+                    _ => cwd.clone(),
+                },
+            }
+        };
+
+        if let Some(timeout) = wait_for_path
+            && roots.is_empty()
+        {
+            wait::wait_for_dir(&root, timeout)?;
+        }
+
+        let metadata = fs::metadata(&root).map_err(|err| {
+            let mut message = format!("{} is not accessible: {}", root.display(), err);
+            if let Some(hint) = winpath::access_hint(&root, &err) {
+                message.push('\n');
+                message.push_str(&hint);
+            }
+            message
+        })?;
+
+        if !metadata.is_dir() {
+            return Err(format!("{} is not a directory", root.display()));
+        }
+
+        let resolved = winpath::resolve(&root)
+            .map_err(|err| format!("Failed to resolve {}: {}", root.display(), err))?;
+        if roots.is_empty() {
+            roots.push(resolved.clone());
+        }
+
+        // Options for one folder, such as a webhook that hears only about
+        // the files in it, must name a folder the run covers.
+        let under_root = |folder: PathBuf, what: &str| {
+            let folder = if folder.is_absolute() {
+                folder
+            } else {
+                cwd.join(folder)
+            };
+            winpath::resolve(&folder)
+                .ok()
+                .filter(|folder| folder.is_dir() && folder.starts_with(&resolved))
+                .ok_or_else(|| {
+                    format!(
+                        "The {} folder {} is not a folder under {}",
+                        what,
+                        folder.display(),
+                        resolved.display()
+                    )
+                })
+        };
+        for webhook in &mut webhooks {
+            if let Some(folder) = webhook.folder.take() {
+                webhook.folder = Some(under_root(folder, "webhook")?);
+            }
+        }
+
+        // The last plain --settle wins, as a config file's comes first.
+        let mut settle_folders = Vec::new();
+        let mut default_settle = watch::DEFAULT_SETTLE;
+        for value in settle {
+            match value.folder {
+                Some(folder) => {
+                    settle_folders.push((under_root(folder, "--settle")?, value.period))
+                }
+                None => default_settle = value.period,
+            }
+        }
+        let priorities = priority
+            .into_iter()
+            .map(|value| Ok((under_root(value.folder, "--priority")?, value.rank)))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        // A missing destination is created when the first file lands there,
+        // after --run-as has taken effect.
+        let dest = match dest {
+            Some(dest) => {
+                let dest = if dest.is_absolute() {
+                    dest
+                } else {
+                    cwd.join(dest)
+                };
+                let dest = match fs::metadata(&dest) {
+                    Ok(metadata) if !metadata.is_dir() => {
+                        return Err(format!("{} is not a directory", dest.display()));
+                    }
+                    Ok(_) => winpath::resolve(&dest)
+                        .map_err(|err| format!("Failed to resolve {}: {}", dest.display(), err))?,
+                    Err(_) => winpath::extended(&dest)
+                        .map_err(|err| format!("Failed to resolve {}: {}", dest.display(), err))?,
+                };
+                if !copy && !allow_copy && !volumes::same_volume(&resolved, &dest) {
+                    return Err(format!(
+                        "{} is on another volume than {}, so files cannot be renamed into it; add --allow-copy to move them by copying, or --copy to keep the originals",
+                        dest.display(),
+                        resolved.display()
+                    ));
+                }
+                Some(dest)
+            }
+            None => None,
+        };
+
+        // MHL paths are relative to the MHL's folder, so everything it lists
+        // must be below it.
+        let mhl = match mhl {
+            Some(path) => {
+                if deterministic {
+                    return Err(
+                        "--mhl records when and where each file was hashed, so it cannot be combined with --deterministic".to_string(),
+                    );
+                }
+                let path = if path.is_absolute() {
+                    path
+                } else {
+                    cwd.join(path)
+                };
+                let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+                    return Err(format!("{} is not a file path", path.display()));
+                };
+                let parent = winpath::resolve(parent)
+                    .map_err(|err| format!("Failed to resolve {}: {}", parent.display(), err))?;
+                let listed = dest.as_deref().unwrap_or(&resolved);
+                if !listed.starts_with(&parent) {
+                    return Err(format!(
+                        "The MHL must be written in {} or a folder above it, since its paths are relative to its own folder",
+                        listed.display()
+                    ));
+                }
+                Some(parent.join(name))
+            }
+            None => None,
+        };
+
+        let email = if email_to.is_empty() {
+            None
+        } else {
+            Some(EmailConfig {
+                smtp_url: smtp_url.unwrap_or_else(|| "smtp://localhost:25".to_string()),
+                smtp_user,
+                from: email_from.unwrap_or_else(|| "r3dy@localhost".to_string()),
+                to: email_to,
+                attachment: email_attach.unwrap_or(AttachmentFormat::Csv),
+            })
+        };
+
+        Ok(Self {
+            root: resolved,
+            roots,
+            from,
+            to,
+            email,
+            webhooks,
+            publish,
+            errors_json,
+            summary_out,
+            memory_limit: max_memory.unwrap_or(spool::DEFAULT_MEMORY_LIMIT),
+            backend,
+            on_conflict: on_conflict.unwrap_or(Conflict::Skip),
+            allow_copy,
+            min_free,
+            preserve,
+            chmod_writable,
+            wait_for_unlock,
+            force_lock,
+            atomic,
+            jobs,
+            trace,
+            log_file,
+            log_level,
+            log_format: log_format.unwrap_or_default(),
+            manifest,
+            archive,
+            mhl,
+            report,
+            fcpxml,
+            resolve_script,
+            resolve_bin,
+            premiere_csv,
+            compound: compound.unwrap_or(CompoundRule::Last),
+            filter,
+            run_as,
+            sandbox,
+            heartbeat,
+            deterministic,
+            dry_run,
+            copy,
+            verify,
+            verify_hash: verify_hash.unwrap_or_default(),
+            dest,
+            watch,
+            settle: default_settle,
+            settle_folders,
+            priorities,
+            clip_folders,
+            sidecars,
+            check_headers,
+            interactive,
+            tui,
+            template,
+            normalize,
+            sanitize,
+            journal,
+            args: given,
+            resumed: None,
+            json,
+            porcelain,
+            print0,
+            files_from,
+            files_from_null,
+            max_depth,
+            symlinks: match (follow_symlinks, no_follow_symlinks) {
+                (true, _) => Symlinks::All,
+                (_, true) => Symlinks::None,
+                _ => Symlinks::Files,
+            },
+            one_file_system,
+            ignore_files: !no_ignore,
+            hidden,
+            limit,
+            clips,
+            progress,
+            screen_reader: screen_reader
+                .then(|| status_interval.unwrap_or(progress::DEFAULT_STATUS_INTERVAL)),
+            verbosity,
+            color: color.unwrap_or_default(),
+        })
+    }
+
+    /// The first flag given that only makes sense for an in-place rename.
+    fn rename_only_flag(&self) -> Option<&'static str> {
+        [
+            (self.email.is_some(), "--email-to"),
+            (!self.webhooks.is_empty(), "--slack-webhook/--teams-webhook"),
+            (!self.publish.is_empty(), "--publish"),
+            (self.errors_json.is_some(), "--errors-json"),
+            (self.summary_out.is_some(), "--summary-fd/--summary-file"),
+            (self.backend != BackendKind::Std, "--backend"),
+            (self.jobs > 1, "--jobs"),
+            (self.manifest.is_some(), "--manifest"),
+            (self.archive.is_some(), "--archive"),
+            (self.mhl.is_some(), "--mhl"),
+            (self.report.is_some(), "--report"),
+            (self.fcpxml.is_some(), "--fcpxml"),
+            (self.resolve_script.is_some(), "--resolve-script"),
+            (self.premiere_csv.is_some(), "--premiere-csv"),
+            (self.sandbox, "--sandbox"),
+            (self.verify, "--verify"),
+            (self.print0, "--print0"),
+        ]
+        .into_iter()
+        .find_map(|(given, flag)| given.then_some(flag))
+    }
+
+    /// The first flag given that needs the run to end, which a watch never
+    /// does: the end-of-run outputs, and file lists that are fixed up front.
+    fn end_of_run_flag(&self) -> Option<&'static str> {
+        [
+            (self.dry_run, "--dry-run"),
+            (self.json, "--json"),
+            (self.porcelain, "--porcelain"),
+            (self.print0, "--print0"),
+            (self.limit.is_some(), "--limit"),
+            (self.files_from.is_some(), "--files-from"),
+            (!self.clips.is_empty(), "clip arguments"),
+            (self.errors_json.is_some(), "--errors-json"),
+            (self.summary_out.is_some(), "--summary-fd/--summary-file"),
+            (self.manifest.is_some(), "--manifest"),
+            (self.archive.is_some(), "--archive"),
+            (self.mhl.is_some(), "--mhl"),
+            (self.report.is_some(), "--report"),
+            (self.fcpxml.is_some(), "--fcpxml"),
+            (self.resolve_script.is_some(), "--resolve-script"),
+            (self.premiere_csv.is_some(), "--premiere-csv"),
+            (self.atomic, "--atomic"),
+        ]
+        .into_iter()
+        .find_map(|(given, flag)| given.then_some(flag))
+    }
+
+    /// The paths `--files-from` lists, if given.
+    fn read_list(&self) -> Result<Option<Vec<PathBuf>>, String> {
+        self.files_from
+            .as_deref()
+            .map(|list| listing::read(list, self.files_from_null))
+            .transpose()
+    }
+
+    /// The files to rename: the clips given as arguments, those `listed` by
+    /// --files-from (from `read_list`), otherwise every match under the root.
+    fn collect(
+        &self,
+        order: PathOrder,
+        memory_limit: usize,
+        listed: Option<Vec<PathBuf>>,
+    ) -> Result<CollectedFiles, String> {
+        if !self.clips.is_empty() {
+            return collect_listed(
+                &self.root,
+                self.clips.clone(),
+                self.source_extension(),
+                self.compound,
+                &self.filter,
+                self.hidden,
+                order,
+                memory_limit,
+            );
+        }
+        match listed {
+            Some(listed) => collect_listed(
+                &self.root,
+                listed,
+                self.source_extension(),
+                self.compound,
+                &self.filter,
+                self.hidden,
+                order,
+                memory_limit,
+            ),
+            None => collect_files(
+                &self.root,
+                &self.roots,
+                self.source_extension(),
+                self.compound,
+                &self.filter,
+                order,
+                self.walk(),
+                memory_limit,
+            ),
+        }
+    }
+
+    /// Where `path` is renamed to.
+    fn target_of(&self, path: &Path) -> PathBuf {
+        self.planned_target(path).0
+    }
+
+    /// Where `path` is renamed to, and the name it would have had when
+    /// `--sanitize` changed it.
+    fn planned_target(&self, path: &Path) -> (PathBuf, Option<OsString>) {
+        self.finish(target_path(
+            &self.root,
+            self.dest.as_deref(),
+            path,
+            self.target_extension(),
+        ))
+    }
+
+    /// Like `planned_target`, when `--template` names `path` `name`.
+    fn target_named(&self, path: &Path, name: &str) -> (PathBuf, Option<OsString>) {
+        let target = target_path(
+            &self.root,
+            self.dest.as_deref(),
+            path,
+            self.target_extension(),
+        );
+        self.finish(target.with_file_name(name))
+    }
+
+    /// The new name of every file in `listed` with `--template`, and the
+    /// names `--sanitize` changed, by source. With `--on-conflict fail`,
+    /// fails if any target is taken, before anything is renamed.
+    fn plan_names(&self, listed: &[PathBuf]) -> Result<Names, String> {
+        let sanitized = RefCell::new(HashMap::new());
+        let named = match &self.template {
+            Some(template) => template::plan(&self.root, template, listed, |path, name| {
+                let (target, original) = self.target_named(path, name);
+                if let Some(original) = original {
+                    sanitized.borrow_mut().insert(path.to_path_buf(), original);
+                }
+                target
+            })?,
+            None => HashMap::new(),
+        };
+        if self.on_conflict == Conflict::Fail {
+            check_conflicts(
+                &self.root,
+                listed.iter().map(|path| {
+                    let target = named
+                        .get(path)
+                        .cloned()
+                        .unwrap_or_else(|| self.target_of(path));
+                    (path.as_path(), target)
+                }),
+            )?;
+        }
+        Ok(Names {
+            named,
+            sanitized: sanitized.into_inner(),
+        })
+    }
+
+    /// `target` with its file name in the `--normalize` form and, with
+    /// `--sanitize`, valid on Windows and exFAT volumes, plus the name it
+    /// had before sanitizing when that changed it. Under `--dest` the
+    /// mirrored folders are created, so they are sanitized too.
+    fn finish(&self, target: PathBuf) -> (PathBuf, Option<OsString>) {
+        let target = match (self.normalize, target.file_name()) {
+            (Some(form), Some(name)) => target.with_file_name(normalize::name(name, form)),
+            _ => target,
+        };
+        if !self.sanitize {
+            return (target, None);
+        }
+        let under_dest = self
+            .dest
+            .as_deref()
+            .and_then(|dest| Some((dest, target.strip_prefix(dest).ok()?)));
+        let sanitized = match under_dest {
+            Some((dest, relative)) => sanitize::path(relative).map(|clean| dest.join(clean)),
+            None => target
+                .file_name()
+                .and_then(sanitize::name)
+                .map(|name| target.with_file_name(name)),
+        };
+        match sanitized {
+            Some(clean) => {
+                let original = target
+                    .file_name()
+                    .filter(|&name| Some(name) != clean.file_name())
+                    .map(OsStr::to_os_string);
+                (clean, original)
+            }
+            None => (target, None),
+        }
+    }
+
+    /// How a scan walks the tree.
+    fn walk(&self) -> Walk {
+        Walk {
+            sorted: self.deterministic,
+            max_depth: self.max_depth,
+            symlinks: self.symlinks,
+            one_file_system: self.one_file_system,
+            ignore_files: self.ignore_files,
+            hidden: self.hidden,
+        }
+    }
+
+    fn source_extension(&self) -> &str {
+        &self.from
+    }
+
+    fn target_extension(&self) -> &str {
+        &self.to
+    }
+}
+
+/// The (source, target) extension pair for a rename in either direction.
+fn extensions(invert: bool) -> (&'static str, &'static str) {
+    if invert {
+        ("R3D", "NEV")
+    } else {
+        ("NEV", "R3D")
+    }
+}
+
+/// The (source, target) pair from `--from`/`--to`, which must be given
+/// together, or the default pair. `--invert` swaps either.
+fn extension_pair(
+    invert: bool,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<(String, String), String> {
+    let (from, to) = match (from, to) {
+        (Some(from), Some(to)) => (
+            parse_extension("--from", &from)?,
+            parse_extension("--to", &to)?,
+        ),
+        (None, None) => {
+            let (from, to) = extensions(false);
+            (from.to_string(), to.to_string())
+        }
+        _ => return Err("--from and --to must be given together".to_string()),
+    };
+    if from == to {
+        return Err(format!("--from and --to are the same extension ({})", from));
+    }
+
+    Ok(if invert { (to, from) } else { (from, to) })
+}
+
+/// An extension as given on the command line, without its leading dot.
+fn parse_extension(flag: &str, value: &str) -> Result<String, String> {
+    let extension = value.strip_prefix('.').unwrap_or(value);
+    if extension.is_empty() || extension.contains(['.', '/', '\\']) {
+        return Err(format!(
+            "Invalid {} extension: {} (a single extension such as mxf)",
+            flag, value
+        ));
+    }
+    Ok(extension.to_string())
+}
+
+struct CollectedFiles {
+    files: SortedPaths,
+    /// Lock files of other runs found in folders below the roots.
+    locks: Vec<PathBuf>,
+    /// How many matching files each directory holds.
+    folders: HashMap<PathBuf, usize>,
+    warnings: Vec<String>,
+    suppressed_warnings: usize,
+    /// Roots of the memory cards found in the tree.
+    cards: Vec<PathBuf>,
+}
+
+/// Keeps the first `MAX_WARNINGS` scan warnings and only counts the rest, so
+/// a tree full of unreadable entries cannot grow memory without bound.
+#[derive(Default)]
+struct Warnings {
+    shown: Vec<String>,
+    suppressed: usize,
+}
+
+const MAX_WARNINGS: usize = 1000;
+
+impl Warnings {
+    fn push(&mut self, warning: String) {
+        if self.shown.len() < MAX_WARNINGS {
+            self.shown.push(warning);
+        } else {
+            self.suppressed += 1;
+        }
+    }
+}
+
+/// What happened to one file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Converted,
+    Skipped,
+    Failed,
+    /// Failed because macOS privacy protection blocked access. Counted as a
+    /// failure everywhere, but reported separately so it can be told apart.
+    Denied,
+    /// Left alone because the file vanished or was written to after the
+    /// scan. Counted as skipped.
+    Changed,
+}
+
+impl Status {
+    /// The name used in reports and events.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Converted => "converted",
+            Status::Skipped => "skipped",
+            Status::Failed => "failed",
+            Status::Denied => "denied",
+            Status::Changed => "changed",
+        }
+    }
+
+    pub fn is_failure(self) -> bool {
+        matches!(self, Status::Failed | Status::Denied)
+    }
+}
+
+/// The result for one file: its path, the name it was (or would be) renamed
+/// to, and why it was not when it was not.
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub status: Status,
+    pub error: Option<String>,
+    /// The file's size in bytes, when it could be read.
+    pub size: Option<u64>,
+    /// When r3dy was done with the file.
+    pub time: SystemTime,
+    /// The XXH64 of the contents, checked before and after with
+    /// `--verify-hash xxh64`.
+    pub xxh64: Option<u64>,
+    /// The XXH3 of the contents, checked before and after with --verify.
+    pub xxh3: Option<u64>,
+}
+
+impl Outcome {
+    fn new(source: PathBuf, target: PathBuf, status: Status, error: Option<String>) -> Self {
+        Self {
+            source,
+            target,
+            status,
+            error,
+            size: None,
+            time: SystemTime::now(),
+            xxh64: None,
+            xxh3: None,
+        }
+    }
+}
+
+impl Record for Outcome {
+    fn fields(&self) -> Vec<Cow<'_, [u8]>> {
+        let status: &[u8] = match self.status {
+            Status::Converted => b"c",
+            Status::Skipped => b"s",
+            Status::Failed => b"f",
+            Status::Denied => b"d",
+            Status::Changed => b"m",
+        };
+        let number = |value: Option<u64>| {
+            Cow::Owned(value.map_or_else(Vec::new, |value| value.to_string().into_bytes()))
+        };
+        let time = self
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+
+        let mut fields = vec![
+            Cow::Borrowed(self.source.as_os_str().as_encoded_bytes()),
+            Cow::Borrowed(self.target.as_os_str().as_encoded_bytes()),
+            Cow::Borrowed(status),
+            number(self.size),
+            number(Some(time)),
+            number(self.xxh64),
+            number(self.xxh3),
+        ];
+        if let Some(error) = &self.error {
+            fields.push(Cow::Borrowed(error.as_bytes()));
+        }
+        fields
+    }
+
+    fn from_fields(mut fields: Vec<Vec<u8>>) -> Option<Self> {
+        let error = if fields.len() == 8 {
+            Some(String::from_utf8(fields.pop()?).ok()?)
+        } else {
+            None
+        };
+        let number = |field: Vec<u8>| -> Option<Option<u64>> {
+            if field.is_empty() {
+                return Some(None);
+            }
+            String::from_utf8(field).ok()?.parse().ok().map(Some)
+        };
+        let xxh3 = number(fields.pop()?)?;
+        let xxh64 = number(fields.pop()?)?;
+        let time = number(fields.pop()?)?.unwrap_or_default();
+        let size = number(fields.pop()?)?;
+        let status = match fields.pop()?.as_slice() {
+            b"c" => Status::Converted,
+            b"s" => Status::Skipped,
+            b"f" => Status::Failed,
+            b"d" => Status::Denied,
+            b"m" => Status::Changed,
+            _ => return None,
+        };
+        let target = PathBuf::from_fields(vec![fields.pop()?])?;
+        let source = PathBuf::from_fields(vec![fields.pop()?])?;
+
+        Some(Self {
+            size,
+            time: SystemTime::UNIX_EPOCH + Duration::from_millis(time),
+            xxh64,
+            xxh3,
+            ..Self::new(source, target, status, error)
+        })
+    }
+}
+
+/// Per-run counters (overall and per card) plus every file outcome, spooled
+/// to disk past the memory budget.
+struct Summary {
+    converted: usize,
+    skipped: usize,
+    failed: usize,
+    /// The part of `failed` blocked by macOS privacy protection.
+    denied: usize,
+    /// The part of `skipped` that changed during the run.
+    changed: usize,
+    /// The part of `skipped` left alone because the target was taken.
+    conflicts: usize,
+    /// The part of `converted` moved to another volume by copying.
+    moved: usize,
+    unhashed: usize,
+    /// Why --min-free stopped the run early, if it did.
+    low_space: Option<String>,
+    cards: Cards,
+    outcomes: Spool<Outcome>,
+}
+
+impl Summary {
+    fn new(memory_limit: usize, cards: Cards) -> Self {
+        Self {
+            converted: 0,
+            skipped: 0,
+            failed: 0,
+            denied: 0,
+            changed: 0,
+            conflicts: 0,
+            moved: 0,
+            unhashed: 0,
+            low_space: None,
+            cards,
+            outcomes: Spool::new(memory_limit),
+        }
+    }
+
+    fn record(&mut self, outcome: Outcome) -> std::io::Result<()> {
+        match outcome.status {
+            Status::Converted => self.converted += 1,
+            Status::Skipped => {
+                self.skipped += 1;
+                if outcome.error.as_deref() == Some(TARGET_EXISTS) {
+                    self.conflicts += 1;
+                }
+            }
+            Status::Failed => self.failed += 1,
+            Status::Denied => {
+                self.failed += 1;
+                self.denied += 1;
+            }
+            Status::Changed => {
+                self.skipped += 1;
+                self.changed += 1;
+            }
+        }
+
+        self.cards.record(&outcome.source, outcome.status);
+        self.outcomes.push(outcome)
+    }
+
+    fn outcomes(&self) -> impl Iterator<Item = Outcome> + '_ {
+        self.outcomes.iter()
+    }
+
+    fn failures(&self) -> impl Iterator<Item = Outcome> + '_ {
+        self.outcomes()
+            .filter(|outcome| outcome.status.is_failure())
+    }
+}
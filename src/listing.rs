@@ -0,0 +1,96 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::{mhl, pathenc, report};
+
+/// Reads the files to work on from `--files-from`: an MHL from the offload
+/// (MHL 1.x or ASC MHL), the CSV or JSON report of an earlier run, or a
+/// plain list with one path per line, such as the output of `find`. `-`
+/// reads the list from stdin, and `null` splits it on NUL bytes instead of
+/// lines, as `find -print0` writes it. Paths come back relative to the scan
+/// root, except absolute ones from a plain list. For a report, both the
+/// source and the target of every row are listed; whichever carries the
+/// extension being renamed is the one that gets picked up.
+pub fn read(list: &Path, null: bool) -> Result<Vec<PathBuf>, String> {
+    let bytes = if list == Path::new("-") {
+        let mut bytes = Vec::new();
+        io::stdin()
+            .lock()
+            .read_to_end(&mut bytes)
+            .map_err(|err| format!("Failed to read the file list from stdin: {}", err))?;
+        bytes
+    } else {
+        fs::read(list).map_err(|err| format!("Failed to read {}: {}", list.display(), err))?
+    };
+    if null {
+        return plain(list, bytes.split(|&byte| byte == 0));
+    }
+
+    let text = match std::str::from_utf8(&bytes) {
+        Ok(text) if is_structured(text) => text,
+        _ => {
+            let lines = bytes
+                .split(|&byte| byte == b'\n')
+                .map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+            return plain(list, lines);
+        }
+    };
+
+    if text.trim_start().starts_with('<') {
+        let entries = mhl::parse(text).map_err(|err| format!("{}: {}", list.display(), err))?;
+        return Ok(entries
+            .iter()
+            .map(|entry| entry.path().split('/').collect())
+            .collect());
+    }
+
+    let mut paths = Vec::new();
+    for entry in report::parse(text).map_err(|err| format!("{} {}", list.display(), err))? {
+        for encoded in [&entry.source, &entry.target] {
+            if encoded.is_empty() {
+                continue;
+            }
+            paths.push(
+                pathenc::decode(encoded).map_err(|err| format!("{}: {}", list.display(), err))?,
+            );
+        }
+    }
+    Ok(paths)
+}
+
+/// An MHL, or a report with its JSON object or CSV header, rather than a
+/// plain list of paths.
+fn is_structured(text: &str) -> bool {
+    let start = text.trim_start();
+    start.starts_with('<')
+        || start.starts_with('{')
+        || start
+            .lines()
+            .next()
+            .is_some_and(|header| header.split(',').any(|column| column == "source"))
+}
+
+/// The paths of a plain list, skipping empty entries.
+fn plain<'a>(list: &Path, entries: impl Iterator<Item = &'a [u8]>) -> Result<Vec<PathBuf>, String> {
+    entries
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| path_from_bytes(entry).map_err(|err| format!("{}: {}", list.display(), err)))
+        .collect()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> Result<PathBuf, String> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> Result<PathBuf, String> {
+    std::str::from_utf8(bytes).map(PathBuf::from).map_err(|_| {
+        format!(
+            "{} is not a valid path on this system",
+            String::from_utf8_lossy(bytes)
+        )
+    })
+}
@@ -0,0 +1,108 @@
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, LineWriter};
+use std::path::Path;
+use std::sync::Mutex;
+
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+/// How much goes into the log, from least to most.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Failed files and errors that stopped the run.
+    Error,
+    /// Also directories and entries that could not be read.
+    Warn,
+    /// Also the start and totals of each run, and every file renamed or
+    /// skipped.
+    Info,
+    /// Also how long each directory took to scan and each batch to rename.
+    Debug,
+}
+
+impl Level {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "error" => Some(Self::Error),
+            "warn" => Some(Self::Warn),
+            "info" => Some(Self::Info),
+            // There is nothing finer than debug to log.
+            "debug" | "trace" => Some(Self::Debug),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+        }
+    }
+}
+
+/// How each log line is written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Time, level, the spans it happened in and the message.
+    #[default]
+    Text,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+impl Format {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "text" => Some(Self::Text),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// The `RUST_LOG` filter, when one is set.
+pub fn env_filter() -> Option<String> {
+    env::var("RUST_LOG")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+/// Starts the tracing subscriber, writing events up to `level` (else as
+/// `RUST_LOG` says, else warnings) to the file at `path`, appending so that
+/// earlier runs stay in it, or to stderr without a path. Each span logs its
+/// duration when it closes.
+pub fn init(path: Option<&Path>, level: Option<Level>, format: Format) -> Result<(), String> {
+    let filter = match (level, env_filter()) {
+        (Some(level), _) => EnvFilter::new(format!("r3dy={}", level.as_str())),
+        (None, Some(directives)) => EnvFilter::try_new(&directives)
+            .map_err(|err| format!("Invalid RUST_LOG {}: {}", directives, err))?,
+        (None, None) => EnvFilter::new(format!("r3dy={}", Level::Warn.as_str())),
+    };
+    let writer = match path {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+            BoxMakeWriter::new(Mutex::new(LineWriter::new(file)))
+        }
+        None => BoxMakeWriter::new(io::stderr),
+    };
+
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(false)
+        .with_target(false)
+        .with_span_events(FmtSpan::CLOSE);
+    let installed = match format {
+        Format::Text => subscriber.try_init(),
+        Format::Json => subscriber.json().with_span_list(true).try_init(),
+    };
+    installed.map_err(|err| format!("Failed to start the log: {}", err))
+}
@@ -0,0 +1,162 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::{Outcome, Status, encode_relative, hash, output, pathenc, trace};
+
+/// The layout of a manifest.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    /// `xxhsum` lines (`<hash>  <path>`), checkable with `xxhsum -c`.
+    Xxhsum,
+    /// Tab-separated `<hash>\t<size>\t<path>` lines with a `# <dir>/` header
+    /// before each directory and byte totals at the end, for handing the
+    /// tree to an LTFS tape archiver.
+    Archive,
+}
+
+/// Starts the comment line that lists a file the run could not rename.
+const FAILED: &str = "# failed";
+
+/// Hashes each file as the rename pass reaches it, so a checksum manifest
+/// comes out of the same walk instead of a second read of the whole tree.
+/// Paths are relative to the root, so the manifest can be checked from there.
+pub struct Manifest {
+    out: Box<dyn Write + Send>,
+    format: ManifestFormat,
+    dir: Option<PathBuf>,
+    files: u64,
+    bytes: u64,
+}
+
+impl Manifest {
+    pub fn create(spec: &str, format: ManifestFormat) -> Result<Self, String> {
+        let mut out = output::open(spec)?;
+        if format == ManifestFormat::Archive {
+            writeln!(out, "# r3dy archive manifest\n# xxh64\tsize\tpath")
+                .map_err(|err| format!("Failed to write manifest: {}", err))?;
+        }
+
+        Ok(Self {
+            out,
+            format,
+            dir: None,
+            files: 0,
+            bytes: 0,
+        })
+    }
+
+    /// Hashes the file behind `outcome` under the name it has after the run.
+    /// A file that could not be renamed is also listed on a `# failed`
+    /// comment line, for `r3dy retry --manifest`.
+    pub fn add(&mut self, root: &Path, outcome: &Outcome) -> Result<(), String> {
+        if matches!(outcome.status, Status::Failed | Status::Denied) {
+            writeln!(
+                self.out,
+                "{}\t{}",
+                FAILED,
+                encode_relative(root, &outcome.source)
+            )
+            .map_err(|err| format!("Failed to write manifest: {}", err))?;
+        }
+        let path = match outcome.status {
+            Status::Converted => &outcome.target,
+            Status::Skipped | Status::Failed | Status::Denied => &outcome.source,
+            // Its contents are not settled; a hash now would not hold.
+            Status::Changed => {
+                return Err(format!(
+                    "Left {} out of the manifest: it changed during the run",
+                    crate::display_relative(root, &outcome.source)
+                ));
+            }
+        };
+        let failed = |err: std::io::Error| {
+            format!(
+                "Failed to hash {}: {}",
+                crate::display_relative(root, path),
+                err
+            )
+        };
+
+        let digest = hash::xxh64_file(path).map_err(failed)?;
+
+        let written = match self.format {
+            ManifestFormat::Xxhsum => {
+                writeln!(self.out, "{:016x}  {}", digest, encode_relative(root, path))
+            }
+            ManifestFormat::Archive => {
+                let size = trace::call("stat", path, || fs::metadata(path))
+                    .map_err(failed)?
+                    .len();
+
+                let dir = path.parent().map(Path::to_path_buf);
+                if dir != self.dir {
+                    self.dir = dir;
+                    if let Some(dir) = &self.dir {
+                        let name = encode_relative(root, dir);
+                        writeln!(self.out, "# {}/", if name.is_empty() { "." } else { &name })
+                            .map_err(|err| format!("Failed to write manifest: {}", err))?;
+                    }
+                }
+
+                self.files += 1;
+                self.bytes += size;
+                writeln!(
+                    self.out,
+                    "{:016x}\t{}\t{}",
+                    digest,
+                    size,
+                    encode_relative(root, path)
+                )
+            }
+        };
+
+        written.map_err(|err| format!("Failed to write manifest: {}", err))
+    }
+
+    /// Notes that `--sanitize` changed the new name of the file now at
+    /// `target` from `original`, on a `#` comment line like the archive
+    /// manifest's folder headers.
+    pub fn sanitized(
+        &mut self,
+        root: &Path,
+        original: &OsStr,
+        target: &Path,
+    ) -> Result<(), String> {
+        writeln!(
+            self.out,
+            "# sanitized\t{}\t{}",
+            pathenc::encode_relative(Path::new(original)),
+            encode_relative(root, target)
+        )
+        .map_err(|err| format!("Failed to write manifest: {}", err))
+    }
+
+    pub fn finish(mut self) -> Result<(), String> {
+        if self.format == ManifestFormat::Archive {
+            writeln!(
+                self.out,
+                "# total\t{} file{}\t{} bytes",
+                self.files,
+                if self.files == 1 { "" } else { "s" },
+                self.bytes
+            )
+            .map_err(|err| format!("Failed to write manifest: {}", err))?;
+        }
+
+        self.out
+            .flush()
+            .map_err(|err| format!("Failed to write manifest: {}", err))
+    }
+}
+
+/// The files a manifest lists as failed, relative to the root of its run.
+pub fn failures(path: &Path) -> Result<Vec<PathBuf>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    text.lines()
+        .filter_map(|line| line.strip_prefix(FAILED)?.strip_prefix('\t'))
+        .map(|name| pathenc::decode(name).map_err(|err| format!("{}: {}", path.display(), err)))
+        .collect()
+}
@@ -0,0 +1,927 @@
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use console::style;
+
+use crate::notify::{self, Digest, Webhook, WebhookKind};
+use crate::progress::{self, Announcer};
+use crate::publish::{self, Publisher};
+use crate::spool::{Record, Spool};
+use crate::{
+    Outcome, Status, args, datetime, display_relative, extension_pair, fcpxml, has_extension, hash,
+    heartbeat, interrupt, progress_bar, trace,
+};
+
+/// A checksum algorithm as named in MHL files.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    /// `xxhash64be` (MHL 1.1) and `xxh64` (ASC MHL): canonical hex.
+    Xxh64,
+    /// The deprecated MHL 1.0 `xxhash64`, written with its bytes reversed.
+    Xxh64Le,
+    Md5,
+}
+
+impl Algorithm {
+    fn from_element(name: &str) -> Option<Self> {
+        match name {
+            "xxhash64be" | "xxh64" => Some(Algorithm::Xxh64),
+            "xxhash64" => Some(Algorithm::Xxh64Le),
+            "md5" => Some(Algorithm::Md5),
+            _ => None,
+        }
+    }
+
+    fn digest(self, path: &Path) -> std::io::Result<String> {
+        Ok(match self {
+            Algorithm::Xxh64 => format!("{:016x}", hash::xxh64_file(path)?),
+            Algorithm::Xxh64Le => format!("{:016x}", hash::xxh64_file(path)?.swap_bytes()),
+            Algorithm::Md5 => hash::md5_file(path)?
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+        })
+    }
+}
+
+/// One file listed in an MHL.
+pub struct Entry {
+    /// Path relative to the MHL's root, `/`-separated.
+    path: String,
+    size: Option<u64>,
+    /// Lowercase hex digest in the first algorithm r3dy can check, if any.
+    hash: Option<(Algorithm, String)>,
+}
+
+impl Entry {
+    /// The listed path, relative to the MHL's root and `/`-separated.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// Reads the `<hash>` entries of an MHL 1.x or ASC MHL 2.0 file, as written
+/// by Hedge, ShotPut Pro, Silverstack and similar offload tools.
+pub fn parse(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    let mut current: Option<Entry> = None;
+    let mut text_buffer = String::new();
+    let mut attributes: Vec<(String, String)> = Vec::new();
+
+    for token in Tokens::new(text) {
+        match token? {
+            Token::Start(name, attrs) => {
+                if name == "hash" {
+                    current = Some(Entry {
+                        path: String::new(),
+                        size: None,
+                        hash: None,
+                    });
+                }
+                text_buffer.clear();
+                attributes = attrs;
+            }
+            Token::Text(text) => text_buffer.push_str(&text),
+            Token::End(name) if name == "hash" => {
+                entries.extend(current.take().filter(|entry| !entry.path.is_empty()));
+            }
+            Token::End(name) => {
+                let Some(entry) = current.as_mut() else {
+                    continue;
+                };
+                let value = text_buffer.trim();
+
+                match name.as_str() {
+                    // MHL 1.x names the file in <file>; ASC MHL uses <path>
+                    // with the size as an attribute.
+                    "file" | "path" => {
+                        entry.path = value.to_string();
+                        if let Some((_, size)) = attributes.iter().find(|(key, _)| key == "size") {
+                            entry.size = size.parse().ok();
+                        }
+                    }
+                    "size" => entry.size = value.parse().ok(),
+                    other => {
+                        if entry.hash.is_none()
+                            && let Some(algorithm) = Algorithm::from_element(other)
+                        {
+                            entry.hash = Some((algorithm, value.to_ascii_lowercase()));
+                        }
+                    }
+                }
+                text_buffer.clear();
+            }
+        }
+    }
+
+    if entries.is_empty() {
+        return Err("no file entries found (is this an MHL file?)".to_string());
+    }
+    Ok(entries)
+}
+
+/// How one MHL entry compares with the renamed tree.
+enum Check {
+    Verified,
+    /// Present with the listed size; left unhashed by --verify-sample.
+    SizeMatches,
+    Mismatch(String),
+    Missing,
+    Unreadable(String),
+    Unsupported,
+}
+
+/// The arguments of `r3dy verify`.
+#[derive(clap::Args)]
+pub struct VerifyArgs {
+    /// The MHL to check the tree against
+    #[arg(long, value_name = "FILE")]
+    mhl: PathBuf,
+    /// The clips were renamed .R3D to .NEV
+    #[arg(long)]
+    invert: bool,
+    /// The extension the clips were renamed from, with --to
+    #[arg(long, value_name = "EXT")]
+    from: Option<String>,
+    /// The extension the clips were renamed to, with --from
+    #[arg(long, value_name = "EXT")]
+    to: Option<String>,
+    /// Hash only N% of the files, picked at random but always including the
+    /// first, the last and the largest; the others are checked for presence
+    /// and size
+    #[arg(long, value_name = "N%", value_parser = percent)]
+    verify_sample: Option<f64>,
+    /// Check the tree again every TIME (e.g. 24h) until interrupted, to catch
+    /// bit rot
+    #[arg(long, value_name = "TIME", value_parser = every)]
+    every: Option<Duration>,
+    /// Read at most SIZE per second while hashing (e.g. 100M), to leave the
+    /// storage room for other work
+    #[arg(long, value_name = "SIZE", value_parser = args::size)]
+    max_rate: Option<u64>,
+    /// Alert a Slack incoming webhook about files that no longer match
+    #[arg(long, value_name = "URL")]
+    slack_webhook: Vec<String>,
+    /// Alert a Microsoft Teams incoming webhook about files that no longer
+    /// match
+    #[arg(long, value_name = "URL")]
+    teams_webhook: Vec<String>,
+    /// Publish an event for each file that no longer matches to sns:<arn>,
+    /// sqs:<url> or pubsub:<topic>
+    #[arg(long, value_name = "TARGET", value_parser = args::one_of(publish::Target::parse, "sns:<arn>, sqs:<url> or pubsub:<topic>"))]
+    publish: Vec<publish::Target>,
+    /// Log progress through large clips every TIME (e.g. 30s)
+    #[arg(long, value_name = "TIME", value_parser = heartbeat::parse_interval)]
+    heartbeat: Option<Duration>,
+    /// Replace the progress bar with a plain status sentence every 15s
+    #[arg(long)]
+    screen_reader: bool,
+    /// How often --screen-reader reports progress
+    #[arg(long, value_name = "TIME", value_parser = progress::parse_status_interval)]
+    status_interval: Option<Duration>,
+    /// The renamed tree (default: the MHL's folder)
+    #[arg(value_name = "PATH")]
+    root: Option<PathBuf>,
+}
+
+/// Entry point for `r3dy verify --mhl FILE [--invert] [path]`. With
+/// `--every`, checks the tree again on that cadence until Ctrl-C or
+/// SIGTERM, alerting the webhooks and `--publish` targets about each file
+/// that no longer matches.
+pub fn verify_command(args: VerifyArgs) -> Result<(), String> {
+    let VerifyArgs {
+        mhl,
+        invert,
+        from,
+        to,
+        verify_sample: sample,
+        every,
+        max_rate,
+        slack_webhook,
+        teams_webhook,
+        publish,
+        heartbeat,
+        screen_reader,
+        status_interval,
+        root,
+    } = args;
+    heartbeat::init(heartbeat);
+    if screen_reader {
+        progress::enable_screen_reader(
+            status_interval.unwrap_or(progress::DEFAULT_STATUS_INTERVAL),
+        );
+    } else if status_interval.is_some() {
+        return Err("--status-interval only applies with --screen-reader".to_string());
+    }
+    if let Some(rate) = max_rate {
+        hash::limit_reads(rate);
+    }
+    let text = fs::read_to_string(&mhl)
+        .map_err(|err| format!("Failed to read {}: {}", mhl.display(), err))?;
+    let entries = parse(&text).map_err(|err| format!("{}: {}", mhl.display(), err))?;
+
+    let root = match root {
+        Some(root) => root,
+        None => default_root(&mhl)?,
+    };
+    let (source, target) = extension_pair(invert, from, to)?;
+    let sweep = Sweep {
+        mhl: &mhl,
+        entries: &entries,
+        root: &root,
+        source: &source,
+        target: &target,
+        sample,
+    };
+    let webhooks: Vec<Webhook> = slack_webhook
+        .into_iter()
+        .map(|url| (WebhookKind::Slack, url))
+        .chain(
+            teams_webhook
+                .into_iter()
+                .map(|url| (WebhookKind::Teams, url)),
+        )
+        .map(|(kind, url)| Webhook {
+            kind,
+            url,
+            folder: None,
+        })
+        .collect();
+    let publisher = Publisher::start(&publish);
+
+    let result = match every {
+        None => sweep.run().and_then(|swept| {
+            sweep.alert(&swept, &webhooks, publisher.as_ref());
+            swept.result()
+        }),
+        Some(every) => {
+            interrupt::catch();
+            println!(
+                "Checking {} against {} every {}; press Ctrl-C to stop",
+                root.display(),
+                mhl.display(),
+                heartbeat::format_elapsed(every)
+            );
+            let mut result = Ok(());
+            while result.is_ok() && !interrupt::stopped() {
+                result = sweep.run().map(|swept| {
+                    sweep.alert(&swept, &webhooks, publisher.as_ref());
+                });
+                let next = Instant::now() + every;
+                while result.is_ok() && !interrupt::stopped() && Instant::now() < next {
+                    thread::sleep(TICK.min(next - Instant::now()));
+                }
+            }
+            result
+        }
+    };
+    if let Some(publisher) = publisher {
+        for err in publisher.finish() {
+            eprintln!("{}", crate::color::failure(&err));
+        }
+    }
+    result
+}
+
+/// How often a `--every` wait checks for Ctrl-C.
+const TICK: Duration = Duration::from_secs(1);
+
+/// One check of the tree against an MHL.
+struct Sweep<'a> {
+    mhl: &'a Path,
+    entries: &'a [Entry],
+    root: &'a Path,
+    /// The extensions the clips were renamed from and to.
+    source: &'a str,
+    target: &'a str,
+    sample: Option<f64>,
+}
+
+/// What a sweep found.
+struct Swept {
+    /// The files that matched, by hash or, when not sampled, by size.
+    matched: usize,
+    total: usize,
+    /// The files that are missing, unreadable or no longer match, with
+    /// what is wrong.
+    problems: Vec<(PathBuf, String)>,
+}
+
+impl Swept {
+    fn result(&self) -> Result<(), String> {
+        if self.matched < self.total {
+            return Err("the tree does not match the MHL".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Sweep<'_> {
+    /// Checks every entry, printing each problem and the totals. Stops
+    /// early when interrupted.
+    fn run(&self) -> Result<Swept, String> {
+        let Sweep {
+            mhl,
+            entries,
+            root,
+            source,
+            target,
+            sample,
+        } = *self;
+        // Clips are looked up under their converted name first; anything
+        // the rename left alone (sidecars, skipped files) under its listed
+        // name.
+        let locate = |entry: &Entry| {
+            let listed = root.join(entry.path.split('/').collect::<PathBuf>());
+            let renamed = listed.with_extension(target);
+            if has_extension(&listed, source) && renamed.exists() {
+                renamed
+            } else {
+                listed
+            }
+        };
+
+        let hashed: Vec<bool> = match sample {
+            Some(percent) => {
+                let sizes: Vec<u64> = entries
+                    .iter()
+                    .map(|entry| {
+                        entry.size.unwrap_or_else(|| {
+                            fs::metadata(locate(entry)).map_or(0, |metadata| metadata.len())
+                        })
+                    })
+                    .collect();
+                choose_sample(&sizes, percent)
+            }
+            None => vec![true; entries.len()],
+        };
+        let sampled = hashed.iter().filter(|hashed| **hashed).count();
+        if sample.is_some() {
+            println!(
+                "Hashing {} of {} file{}; the rest are checked for presence and size only",
+                sampled,
+                entries.len(),
+                if entries.len() == 1 { "" } else { "s" }
+            );
+        }
+
+        let progress = progress_bar(entries.len())?;
+        let mut announcer = Announcer::new(entries.len(), "Verified");
+        let mut verified = 0usize;
+        let mut size_matched = 0usize;
+        let mut mismatched = 0usize;
+        let mut missing = 0usize;
+        let mut unreadable = 0usize;
+        let mut unsupported = 0usize;
+        let mut problems = Vec::new();
+
+        for (entry, hash) in entries.iter().zip(hashed) {
+            if interrupt::stopped() {
+                break;
+            }
+            progress.set_message(entry.path.clone());
+            let path = locate(entry);
+            let shown = display_relative(root, &path);
+
+            let problem = match check(entry, &path, hash) {
+                Check::Verified => {
+                    verified += 1;
+                    None
+                }
+                Check::SizeMatches => {
+                    size_matched += 1;
+                    None
+                }
+                Check::Mismatch(reason) => {
+                    mismatched += 1;
+                    problems.push((path.clone(), format!("mismatch: {}", reason)));
+                    Some(format!(
+                        "{} {}: {}",
+                        style("MISMATCH").red().bold(),
+                        shown,
+                        reason
+                    ))
+                }
+                Check::Missing => {
+                    missing += 1;
+                    problems.push((path.clone(), "missing".to_string()));
+                    Some(format!("{} {}", style("MISSING").red().bold(), shown))
+                }
+                Check::Unreadable(err) => {
+                    unreadable += 1;
+                    problems.push((path.clone(), format!("unreadable: {}", err)));
+                    Some(format!(
+                        "{} {}: {}",
+                        style("UNREADABLE").red().bold(),
+                        shown,
+                        err
+                    ))
+                }
+                Check::Unsupported => {
+                    unsupported += 1;
+                    Some(format!(
+                        "{} {}: no MD5 or XXH64 hash listed",
+                        style("UNCHECKED").yellow().bold(),
+                        shown
+                    ))
+                }
+            };
+
+            // Shown even when stderr is not a terminal and the bar is hidden.
+            if let Some(problem) = problem {
+                progress.suspend(|| eprintln!("{}", problem));
+            }
+            progress.inc(1);
+            announcer.update(
+                progress.position() as usize,
+                mismatched + missing + unreadable,
+            );
+        }
+        progress.finish_with_message("verification complete");
+        announcer.finish();
+
+        if sample.is_some() {
+            println!(
+                "Verified {} of {} sampled file{} and the size of {} more against {} (mismatched: {}, missing: {}, unreadable: {}, unchecked: {})",
+                verified,
+                sampled,
+                if sampled == 1 { "" } else { "s" },
+                size_matched,
+                mhl.display(),
+                mismatched,
+                missing,
+                unreadable,
+                unsupported
+            );
+        } else {
+            println!(
+                "Verified {} of {} file{} against {} (mismatched: {}, missing: {}, unreadable: {}, unchecked: {})",
+                verified,
+                entries.len(),
+                if entries.len() == 1 { "" } else { "s" },
+                mhl.display(),
+                mismatched,
+                missing,
+                unreadable,
+                unsupported
+            );
+        }
+
+        Ok(Swept {
+            matched: verified + size_matched,
+            total: entries.len(),
+            problems,
+        })
+    }
+
+    /// Tells the webhooks and `--publish` targets about the files of
+    /// `swept` that no longer match. A clean sweep says nothing.
+    fn alert(&self, swept: &Swept, webhooks: &[Webhook], publisher: Option<&Publisher>) {
+        if swept.problems.is_empty() {
+            return;
+        }
+        for webhook in webhooks {
+            let failed = swept.problems.iter().map(|(path, problem)| {
+                Outcome::new(
+                    path.clone(),
+                    path.clone(),
+                    Status::Failed,
+                    Some(problem.clone()),
+                )
+            });
+            let digest = Digest::verification(webhook, self.root, swept.matched, failed);
+            if let Err(err) = notify::post_webhook(webhook, &digest) {
+                eprintln!("{}", crate::color::failure(&err));
+            }
+        }
+        if let Some(publisher) = publisher {
+            for (path, problem) in &swept.problems {
+                publisher.verify_event(self.root, self.mhl, path, problem);
+            }
+            for err in publisher.failures() {
+                eprintln!("{}", crate::color::failure(&err));
+            }
+        }
+    }
+}
+
+/// Checks that `path` exists with the listed size and, if `hash`, that its
+/// contents match the listed hash.
+fn check(entry: &Entry, path: &Path, hash: bool) -> Check {
+    let metadata = match trace::call("stat", path, || fs::metadata(path)) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Check::Missing,
+        Err(err) => return Check::Unreadable(err.to_string()),
+    };
+
+    if let Some(size) = entry.size
+        && size != metadata.len()
+    {
+        return Check::Mismatch(format!("size {} bytes, expected {}", metadata.len(), size));
+    }
+    if !hash {
+        return Check::SizeMatches;
+    }
+
+    let Some((algorithm, expected)) = &entry.hash else {
+        return Check::Unsupported;
+    };
+    match algorithm.digest(path) {
+        Ok(actual) if actual == *expected => Check::Verified,
+        Ok(actual) => Check::Mismatch(format!("hash {}, expected {}", actual, expected)),
+        Err(err) => Check::Unreadable(err.to_string()),
+    }
+}
+
+/// Parses a `--every` cadence such as `12h`, which must be above zero.
+fn every(value: &str) -> Result<Duration, String> {
+    datetime::parse_duration(value)
+        .filter(|every| !every.is_zero())
+        .ok_or_else(|| "expected a time such as 30m or 24h".to_string())
+}
+
+/// Parses `10%` or `10` as a percentage in (0, 100].
+fn percent(value: &str) -> Result<f64, String> {
+    value
+        .strip_suffix('%')
+        .unwrap_or(value)
+        .parse()
+        .ok()
+        .filter(|percent| *percent > 0.0 && *percent <= 100.0)
+        .ok_or_else(|| "expected a percentage such as 10%".to_string())
+}
+
+/// Picks which of the files with `sizes` get hashed: `percent` of them at
+/// random, always including the first, the last and the largest. Systemic
+/// corruption (a bad reader, a flaky cable) shows up in any sample; the
+/// largest clip spends longest in transit, and the ends of the listing catch
+/// a copy that started or stopped early.
+fn choose_sample(sizes: &[u64], percent: f64) -> Vec<bool> {
+    let mut chosen = vec![false; sizes.len()];
+    if sizes.is_empty() {
+        return chosen;
+    }
+
+    let wanted = ((sizes.len() as f64 * percent / 100.0).ceil() as usize).min(sizes.len());
+    let largest = (0..sizes.len())
+        .max_by_key(|&index| sizes[index])
+        .unwrap_or(0);
+    for index in [0, sizes.len() - 1, largest] {
+        chosen[index] = true;
+    }
+
+    // Partial Fisher-Yates over the remaining files, seeded from the clock
+    // so repeated sweeps cover different files.
+    let mut rest: Vec<usize> = (0..sizes.len()).filter(|&index| !chosen[index]).collect();
+    let mut state = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+        ^ u64::from(std::process::id()) << 32;
+    let already = chosen.iter().filter(|chosen| **chosen).count();
+    for slot in 0..wanted.saturating_sub(already).min(rest.len()) {
+        let pick = slot + (splitmix64(&mut state) % (rest.len() - slot) as u64) as usize;
+        rest.swap(slot, pick);
+        chosen[rest[slot]] = true;
+    }
+
+    chosen
+}
+
+pub fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// MHL paths are relative to the folder holding the file; ASC MHL keeps its
+/// files in an `ascmhl` folder at the root of the tree.
+pub fn default_root(mhl: &Path) -> Result<PathBuf, String> {
+    let mhl = if mhl.is_absolute() {
+        mhl.to_path_buf()
+    } else {
+        env::current_dir()
+            .map_err(|err| format!("Failed to determine current directory: {}", err))?
+            .join(mhl)
+    };
+
+    let dir = mhl.parent().unwrap_or(Path::new("/"));
+    let is_ascmhl = dir
+        .file_name()
+        .is_some_and(|name| name.eq_ignore_ascii_case("ascmhl"));
+    Ok(match dir.parent() {
+        Some(parent) if is_ascmhl => parent.to_path_buf(),
+        _ => dir.to_path_buf(),
+    })
+}
+
+/// Writes an MHL 1.1 file listing the files a run renamed or copied, with
+/// their XXH64 hashes, for offload and verification tools. Entries are
+/// hashed as the run reaches each file and kept in a spool, since the
+/// header carries the finish time and comes first.
+pub struct Writer {
+    path: PathBuf,
+    out: fs::File,
+    /// The folder the listed paths are relative to: the MHL's own.
+    base: PathBuf,
+    started: SystemTime,
+    entries: Spool<Listed>,
+}
+
+/// One rendered `<hash>` element.
+#[derive(Clone)]
+struct Listed(String);
+
+impl Record for Listed {
+    fn fields(&self) -> Vec<Cow<'_, [u8]>> {
+        vec![Cow::Borrowed(self.0.as_bytes())]
+    }
+
+    fn from_fields(mut fields: Vec<Vec<u8>>) -> Option<Self> {
+        String::from_utf8(fields.pop()?).ok().map(Listed)
+    }
+}
+
+impl Writer {
+    /// Creates the MHL at `path` for files below its folder. It is opened
+    /// up front so a sandboxed run can still write it.
+    pub fn create(path: &Path, started: SystemTime, memory_limit: usize) -> Result<Self, String> {
+        let out = fs::File::create(path)
+            .map_err(|err| format!("Failed to create {}: {}", path.display(), err))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            out,
+            base: path.parent().unwrap_or(Path::new("/")).to_path_buf(),
+            started,
+            entries: Spool::new(memory_limit),
+        })
+    }
+
+    /// Hashes `file` and adds it to the list.
+    pub fn add(&mut self, file: &Path) -> Result<(), String> {
+        let relative = file.strip_prefix(&self.base).map_err(|_| {
+            format!(
+                "Left {} out of the MHL: it is not below {}",
+                file.display(),
+                self.base.display()
+            )
+        })?;
+        let name = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let failed = |err: std::io::Error| format!("Failed to hash {}: {}", file.display(), err);
+
+        let metadata = trace::call("stat", file, || fs::metadata(file)).map_err(failed)?;
+        let digest = hash::xxh64_file(file).map_err(failed)?;
+
+        let mut entry = format!(
+            "  <hash>\n    <file>{}</file>\n    <size>{}</size>\n",
+            fcpxml::escape(&name),
+            metadata.len()
+        );
+        if let Ok(created) = metadata.created() {
+            entry.push_str(&format!(
+                "    <creationdate>{}</creationdate>\n",
+                datetime::rfc3339(created)
+            ));
+        }
+        if let Ok(modified) = metadata.modified() {
+            entry.push_str(&format!(
+                "    <lastmodificationdate>{}</lastmodificationdate>\n",
+                datetime::rfc3339(modified)
+            ));
+        }
+        entry.push_str(&format!(
+            "    <xxhash64be>{:016x}</xxhash64be>\n    <hashdate>{}</hashdate>\n  </hash>\n",
+            digest,
+            datetime::rfc3339(SystemTime::now())
+        ));
+
+        self.entries
+            .push(Listed(entry))
+            .map_err(|err| format!("Failed to spool the MHL entries: {}", err))
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        let failed =
+            |err: std::io::Error| format!("Failed to write {}: {}", self.path.display(), err);
+        let user = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_default();
+
+        let mut out = std::io::BufWriter::new(&self.out);
+        write!(
+            out,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<hashlist version=\"1.1\">\n  <creatorinfo>\n    <name>{user}</name>\n    <username>{user}</username>\n    <hostname>{}</hostname>\n    <tool>r3dy {}</tool>\n    <startdate>{}</startdate>\n    <finishdate>{}</finishdate>\n  </creatorinfo>\n",
+            fcpxml::escape(&hostname()),
+            env!("CARGO_PKG_VERSION"),
+            datetime::rfc3339(self.started),
+            datetime::rfc3339(SystemTime::now()),
+            user = fcpxml::escape(&user),
+        )
+        .map_err(failed)?;
+        for Listed(entry) in self.entries.iter() {
+            out.write_all(entry.as_bytes()).map_err(failed)?;
+        }
+        out.write_all(b"</hashlist>\n").map_err(failed)?;
+        out.flush().map_err(failed)
+    }
+}
+
+/// The name of this machine, as MHL files and run locks record it.
+#[cfg(unix)]
+pub fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    // SAFETY: gethostname writes at most buffer.len() bytes into buffer.
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    if result != 0 {
+        return String::new();
+    }
+    let len = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..len]).into_owned()
+}
+
+#[cfg(not(unix))]
+pub fn hostname() -> String {
+    env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+enum Token {
+    Start(String, Vec<(String, String)>),
+    End(String),
+    Text(String),
+}
+
+/// A minimal XML tokenizer, enough for MHL files: elements, attributes, text
+/// and the predefined and numeric entities. Comments, processing
+/// instructions and declarations are skipped; self-closing tags produce a
+/// start and an end.
+struct Tokens<'a> {
+    rest: &'a str,
+    pending_end: Option<String>,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            rest: text,
+            pending_end: None,
+        }
+    }
+
+    fn skip_past(&mut self, marker: &str) -> Result<(), String> {
+        let end = self
+            .rest
+            .find(marker)
+            .ok_or_else(|| format!("unterminated markup (expected {})", marker))?;
+        self.rest = &self.rest[end + marker.len()..];
+        Ok(())
+    }
+
+    fn tag(&mut self) -> Result<Token, String> {
+        let end = self
+            .rest
+            .find('>')
+            .ok_or_else(|| "unterminated tag".to_string())?;
+        let inner = &self.rest[1..end];
+        self.rest = &self.rest[end + 1..];
+
+        if let Some(name) = inner.strip_prefix('/') {
+            return Ok(Token::End(local_name(name.trim()).to_string()));
+        }
+
+        let (inner, self_closing) = match inner.strip_suffix('/') {
+            Some(inner) => (inner, true),
+            None => (inner, false),
+        };
+        let name_end = inner
+            .find(|ch: char| ch.is_whitespace())
+            .unwrap_or(inner.len());
+        let name = local_name(&inner[..name_end]).to_string();
+        let attributes = parse_attributes(&inner[name_end..])?;
+
+        if self_closing {
+            self.pending_end = Some(name.clone());
+        }
+        Ok(Token::Start(name, attributes))
+    }
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Result<Token, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(name) = self.pending_end.take() {
+            return Some(Ok(Token::End(name)));
+        }
+
+        loop {
+            if self.rest.is_empty() {
+                return None;
+            }
+
+            if !self.rest.starts_with('<') {
+                let end = self.rest.find('<').unwrap_or(self.rest.len());
+                let text = &self.rest[..end];
+                self.rest = &self.rest[end..];
+                return Some(unescape(text).map(Token::Text));
+            }
+
+            let skipped = if self.rest.starts_with("<!--") {
+                self.skip_past("-->")
+            } else if self.rest.starts_with("<![CDATA[") {
+                let end = match self.rest.find("]]>") {
+                    Some(end) => end,
+                    None => return Some(Err("unterminated CDATA section".to_string())),
+                };
+                let text = self.rest["<![CDATA[".len()..end].to_string();
+                self.rest = &self.rest[end + 3..];
+                return Some(Ok(Token::Text(text)));
+            } else if self.rest.starts_with("<?") {
+                self.skip_past("?>")
+            } else if self.rest.starts_with("<!") {
+                self.skip_past(">")
+            } else {
+                return Some(self.tag());
+            };
+
+            if let Err(err) = skipped {
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+/// Drops a namespace prefix (`mhl:hash` -> `hash`).
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn parse_attributes(mut text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut attributes = Vec::new();
+
+    loop {
+        text = text.trim_start();
+        if text.is_empty() {
+            return Ok(attributes);
+        }
+
+        let eq = text
+            .find('=')
+            .ok_or_else(|| format!("malformed attribute: {}", text))?;
+        let key = local_name(text[..eq].trim()).to_string();
+        let value_text = text[eq + 1..].trim_start();
+
+        let quote = value_text
+            .chars()
+            .next()
+            .filter(|ch| *ch == '"' || *ch == '\'')
+            .ok_or_else(|| format!("unquoted attribute value for {}", key))?;
+        let close = value_text[1..]
+            .find(quote)
+            .ok_or_else(|| format!("unterminated attribute value for {}", key))?;
+
+        attributes.push((key, unescape(&value_text[1..1 + close])?));
+        text = &value_text[close + 2..];
+    }
+}
+
+fn unescape(text: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(';')
+            .ok_or_else(|| "unterminated entity".to_string())?;
+        let entity = &rest[start + 1..start + end];
+
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        out.push(decoded.ok_or_else(|| format!("unknown entity &{};", entity))?);
+        rest = &rest[start + end + 1..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
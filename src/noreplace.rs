@@ -0,0 +1,224 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::normalize::{self, Form};
+use crate::trace;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const ATOMIC_RENAME: &str = "renameat2";
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const ATOMIC_RENAME: &str = "renamex_np";
+#[cfg(windows)]
+const ATOMIC_RENAME: &str = "MoveFileExW";
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    windows
+)))]
+const ATOMIC_RENAME: &str = "rename_exclusive";
+
+/// Renames `from` to `to`, failing with `ErrorKind::AlreadyExists` instead of
+/// replacing an existing target. Where the OS offers an atomic primitive the
+/// check and the rename happen in one step, so a target created concurrently
+/// by another process can never be clobbered. Filesystems that reject the
+/// primitive fall back to checking first.
+///
+/// A rename that only changes the case of the name, such as `CLIP.r3d` to
+/// `CLIP.R3D`, finds the file itself in the way on a case-insensitive volume
+/// (APFS, NTFS, exFAT), so it goes through a temporary name instead. So does
+/// one that only changes its Unicode normalization, which APFS ignores too.
+pub fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    if same_file(from, to) {
+        return rename_case(from, to);
+    }
+    match trace::call2(ATOMIC_RENAME, from, Some(to), || atomic_rename(from, to)) {
+        Err(err) if unsupported(&err) => checked_rename(from, to),
+        result => result,
+    }
+}
+
+/// Whether `to` is taken by a file other than `from`, so renaming `from` to
+/// it would fail.
+pub fn taken(from: &Path, to: &Path) -> bool {
+    trace::call("lstat", to, || fs::symlink_metadata(to)).is_ok() && !same_file(from, to)
+}
+
+/// Whether `from` and `to` differ only in case or Unicode normalization and
+/// name the same file, as they do on a case-insensitive volume or on APFS.
+pub fn same_file(from: &Path, to: &Path) -> bool {
+    let (Some(name), Some(new_name)) = (from.file_name(), to.file_name()) else {
+        return false;
+    };
+    if from.parent() != to.parent() || name == new_name {
+        return false;
+    }
+    let composed = |name: &OsStr| normalize::name(name, Form::Nfc);
+    if !composed(name).eq_ignore_ascii_case(composed(new_name)) {
+        return false;
+    }
+    let (Ok(before), Ok(after)) = (
+        trace::call("lstat", from, || fs::symlink_metadata(from)),
+        trace::call("lstat", to, || fs::symlink_metadata(to)),
+    ) else {
+        return false;
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        before.dev() == after.dev() && before.ino() == after.ino()
+    }
+
+    // Without inode numbers, the folder is listed: on a case-sensitive
+    // volume the new name is there as it is spelt.
+    #[cfg(not(unix))]
+    {
+        let _ = (before, after);
+        let dir = match from.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        trace::call("readdir", dir, || fs::read_dir(dir)).is_ok_and(|entries| {
+            !entries
+                .filter_map(Result::ok)
+                .any(|entry| entry.file_name() == new_name)
+        })
+    }
+}
+
+/// Renames `from` to `to`, which differs only in case, in two steps through
+/// a hidden temporary name, and puts the file back if the second step fails.
+fn rename_case(from: &Path, to: &Path) -> io::Result<()> {
+    let mut temporary = std::ffi::OsString::from(".");
+    temporary.push(from.file_name().unwrap_or_default());
+    temporary.push(".r3dy-case");
+    let temporary = from.with_file_name(temporary);
+
+    rename(from, &temporary)?;
+    rename(&temporary, to).inspect_err(|_| {
+        let _ = rename(&temporary, from);
+    })
+}
+
+fn checked_rename(from: &Path, to: &Path) -> io::Result<()> {
+    if trace::call("lstat", to, || fs::symlink_metadata(to)).is_ok() {
+        return Err(already_exists());
+    }
+    trace::call2("rename", from, Some(to), || fs::rename(from, to))
+}
+
+fn already_exists() -> io::Error {
+    io::Error::from(io::ErrorKind::AlreadyExists)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn atomic_rename(from: &Path, to: &Path) -> io::Result<()> {
+    let from = c_path(from)?;
+    let to = c_path(to)?;
+
+    // Called through syscall() so musl builds, which lack a renameat2
+    // wrapper, behave the same as glibc ones.
+    // SAFETY: both paths are valid NUL-terminated strings for the call.
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            from.as_ptr(),
+            libc::AT_FDCWD,
+            to.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn atomic_rename(from: &Path, to: &Path) -> io::Result<()> {
+    let from = c_path(from)?;
+    let to = c_path(to)?;
+
+    // SAFETY: both paths are valid NUL-terminated strings for the call.
+    if unsafe { libc::renamex_np(from.as_ptr(), to.as_ptr(), libc::RENAME_EXCL) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(windows)]
+fn atomic_rename(from: &Path, to: &Path) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn MoveFileExW(existing: *const u16, new: *const u16, flags: u32) -> i32;
+    }
+
+    let wide =
+        |path: &Path| -> Vec<u16> { path.as_os_str().encode_wide().chain(Some(0)).collect() };
+    let from = wide(from);
+    let to = wide(to);
+
+    // Without MOVEFILE_REPLACE_EXISTING the move fails if the target exists.
+    // SAFETY: both buffers are NUL-terminated UTF-16 strings.
+    if unsafe { MoveFileExW(from.as_ptr(), to.as_ptr(), 0) } != 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    windows
+)))]
+fn atomic_rename(_from: &Path, _to: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios"
+))]
+fn c_path(path: &Path) -> io::Result<std::ffi::CString> {
+    use std::os::unix::ffi::OsStrExt;
+
+    std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a NUL byte"))
+}
+
+/// Errors meaning "this filesystem cannot do an exclusive rename", as opposed
+/// to a real failure of the rename itself.
+fn unsupported(err: &io::Error) -> bool {
+    if err.kind() == io::ErrorKind::Unsupported {
+        return true;
+    }
+
+    #[cfg(unix)]
+    {
+        matches!(
+            err.raw_os_error(),
+            Some(libc::EINVAL) | Some(libc::ENOSYS) | Some(libc::ENOTSUP)
+        )
+    }
+
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
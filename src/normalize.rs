@@ -0,0 +1,50 @@
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+
+use unicode_normalization::UnicodeNormalization;
+
+/// A Unicode normalization form for new names, for `--normalize`. macOS
+/// writes names decomposed (`e` then a combining accent) and most other
+/// systems composed, so a card offloaded on one and renamed on the other
+/// can hold names that look the same but are not.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Form {
+    /// Composed, as Windows and Linux tools write names.
+    Nfc,
+    /// Decomposed, as macOS writes names.
+    Nfd,
+}
+
+impl Form {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "nfc" => Some(Form::Nfc),
+            "nfd" => Some(Form::Nfd),
+            _ => None,
+        }
+    }
+}
+
+/// `name` in `form`. Names that are not valid UTF-8 are left as they are.
+pub fn name(name: &OsStr, form: Form) -> OsString {
+    match name.to_str() {
+        Some(text) => OsString::from(normalize(text, form).into_owned()),
+        None => name.to_os_string(),
+    }
+}
+
+/// `text` in `form`, borrowed when it is already; plain ASCII always is.
+pub fn normalize(text: &str, form: Form) -> Cow<'_, str> {
+    if text.is_ascii() {
+        return Cow::Borrowed(text);
+    }
+    let normalized: String = match form {
+        Form::Nfc => text.nfc().collect(),
+        Form::Nfd => text.nfd().collect(),
+    };
+    if normalized == text {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(normalized)
+    }
+}
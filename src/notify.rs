@@ -0,0 +1,597 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+use crate::{Outcome, Status, Summary, datetime, json, report, winpath};
+
+const BOUNDARY: &str = "r3dy-report-boundary";
+
+/// Where and how to mail the end-of-run report.
+pub struct EmailConfig {
+    pub smtp_url: String,
+    pub smtp_user: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    pub attachment: AttachmentFormat,
+}
+
+#[derive(Clone, Copy)]
+pub enum AttachmentFormat {
+    Csv,
+    Json,
+}
+
+impl AttachmentFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Sends the run summary through curl's SMTP client so TLS (`smtps://`) and
+/// authentication work without r3dy carrying its own mail stack. The password
+/// is read from `R3DY_SMTP_PASSWORD`, and reaches curl through its config
+/// file rather than its arguments, to keep it out of the process list. A
+/// watch session mails each batch of renames as it is done, with `batch`
+/// set.
+pub fn send_email(
+    config: &EmailConfig,
+    root: &Path,
+    summary: &Summary,
+    batch: bool,
+) -> Result<(), String> {
+    let message = build_message(config, root, summary, batch);
+
+    let mut options = vec![
+        ("url", config.smtp_url.clone()),
+        ("mail-from", config.from.clone()),
+    ];
+
+    for recipient in &config.to {
+        options.push(("mail-rcpt", recipient.clone()));
+    }
+
+    if let Some(user) = &config.smtp_user {
+        let password = env::var("R3DY_SMTP_PASSWORD").unwrap_or_default();
+        options.push(("user", format!("{}:{}", user, password)));
+    }
+
+    options.push(("upload-file", "-".to_string()));
+
+    curl(&options, &message).map_err(|err| format!("Could not email the report: {}", err))
+}
+
+/// Chat services that accept an incoming-webhook POST.
+#[derive(Clone, Copy)]
+pub enum WebhookKind {
+    Slack,
+    Teams,
+}
+
+pub struct Webhook {
+    pub kind: WebhookKind,
+    pub url: String,
+    /// Only files in this folder, at or below the root, are reported to it.
+    pub folder: Option<PathBuf>,
+}
+
+impl Webhook {
+    /// Parses the value of `--slack-webhook` or `--teams-webhook`: a URL, or
+    /// `FOLDER=URL` to post only about the files in `FOLDER`.
+    pub fn parse(kind: WebhookKind, value: &str) -> Self {
+        let (folder, url) = match value.split_once('=') {
+            Some((folder, url)) if !folder.contains("://") => {
+                (Some(PathBuf::from(folder)), url.to_string())
+            }
+            _ => (None, value.to_string()),
+        };
+        Self { kind, url, folder }
+    }
+
+    fn covers(&self, path: &Path) -> bool {
+        self.folder
+            .as_deref()
+            .is_none_or(|folder| path.starts_with(folder))
+    }
+}
+
+impl WebhookKind {
+    fn name(self) -> &'static str {
+        match self {
+            WebhookKind::Slack => "Slack",
+            WebhookKind::Teams => "Teams",
+        }
+    }
+}
+
+/// What a webhook is told: the totals for the files in its folder, and the
+/// first of their failures.
+pub struct Digest<'a> {
+    /// The folder reported on: the webhook's, or the root.
+    folder: &'a Path,
+    /// Whether this is one batch of a watch session rather than a whole run.
+    batch: bool,
+    /// Whether this is a `r3dy verify` sweep, whose files were checked
+    /// rather than renamed: `converted` counts those that matched.
+    verify: bool,
+    converted: usize,
+    skipped: usize,
+    failed: usize,
+    failures: Vec<String>,
+}
+
+/// How many failures a message lists before saying how many more there are.
+const FAILURES_LISTED: usize = 20;
+
+impl<'a> Digest<'a> {
+    /// Totals up the `outcomes` in `webhook`'s folder. A watch session
+    /// passes each batch of renames as it is done, with `batch` set.
+    pub fn new(
+        webhook: &'a Webhook,
+        root: &'a Path,
+        outcomes: impl Iterator<Item = Outcome>,
+        batch: bool,
+    ) -> Self {
+        let mut digest = Digest {
+            folder: webhook.folder.as_deref().unwrap_or(root),
+            batch,
+            verify: false,
+            converted: 0,
+            skipped: 0,
+            failed: 0,
+            failures: Vec::new(),
+        };
+        for outcome in outcomes.filter(|outcome| webhook.covers(&outcome.source)) {
+            match outcome.status {
+                Status::Converted => digest.converted += 1,
+                Status::Skipped | Status::Changed => digest.skipped += 1,
+                Status::Failed | Status::Denied => {
+                    digest.failed += 1;
+                    if digest.failures.len() < FAILURES_LISTED {
+                        digest.failures.push(format!(
+                            "{}: {}",
+                            crate::display_relative(root, &outcome.source),
+                            outcome.error.as_deref().unwrap_or("unknown error")
+                        ));
+                    }
+                }
+            }
+        }
+        digest
+    }
+
+    /// The files of a `r3dy verify` sweep under `root` that no longer match
+    /// their hashes, as failed `outcomes`, after `verified` that did.
+    pub fn verification(
+        webhook: &'a Webhook,
+        root: &'a Path,
+        verified: usize,
+        outcomes: impl Iterator<Item = Outcome>,
+    ) -> Self {
+        let mut digest = Self::new(webhook, root, outcomes, false);
+        digest.verify = true;
+        digest.converted = verified;
+        digest
+    }
+
+    /// Whether none of the files were in the webhook's folder, so a batch
+    /// has nothing to say to it.
+    pub fn is_empty(&self) -> bool {
+        self.converted + self.skipped + self.failed == 0
+    }
+}
+
+/// Posts the totals in `digest` to a Slack or Teams incoming webhook. Runs
+/// with failures are sent as alerts that list the affected files.
+pub fn post_webhook(webhook: &Webhook, digest: &Digest) -> Result<(), String> {
+    let payload = match webhook.kind {
+        WebhookKind::Slack => slack_payload(digest),
+        WebhookKind::Teams => teams_payload(digest),
+    };
+
+    let args = vec![
+        ("url", webhook.url.clone()),
+        ("header", "Content-Type: application/json".to_string()),
+        ("fail", String::new()),
+        ("data-binary", "@-".to_string()),
+    ];
+
+    curl(&args, &payload)
+        .map_err(|err| format!("Could not notify {}: {}", webhook.kind.name(), err))
+}
+
+fn headline(digest: &Digest) -> String {
+    let folder = winpath::display(digest.folder);
+    if digest.verify {
+        format!(
+            "r3dy: {} file{} failed verification under {}",
+            digest.failed,
+            if digest.failed == 1 { "" } else { "s" },
+            folder
+        )
+    } else if digest.failed > 0 {
+        format!(
+            "r3dy: {} file{} failed under {}",
+            digest.failed,
+            if digest.failed == 1 { "" } else { "s" },
+            folder
+        )
+    } else if digest.batch {
+        format!(
+            "r3dy converted {} new file{} under {}",
+            digest.converted,
+            if digest.converted == 1 { "" } else { "s" },
+            folder
+        )
+    } else {
+        format!("r3dy finished {}", folder)
+    }
+}
+
+/// The totals a message shows, by label.
+fn totals(digest: &Digest) -> Vec<(&'static str, usize)> {
+    if digest.verify {
+        vec![("Verified", digest.converted), ("Failed", digest.failed)]
+    } else {
+        vec![
+            ("Converted", digest.converted),
+            ("Skipped", digest.skipped),
+            ("Failed", digest.failed),
+        ]
+    }
+}
+
+fn failure_lines(digest: &Digest) -> Vec<String> {
+    let mut lines = digest.failures.clone();
+    if digest.failed > lines.len() {
+        lines.push(format!("...and {} more", digest.failed - lines.len()));
+    }
+    lines
+}
+
+fn slack_payload(digest: &Digest) -> String {
+    let totals: Vec<String> = totals(digest)
+        .into_iter()
+        .map(|(label, count)| format!("{}: {}", label, count))
+        .collect();
+    let mut text = format!(
+        "{}*{}*\n{}",
+        if digest.failed > 0 { ":warning: " } else { "" },
+        headline(digest),
+        totals.join("  ")
+    );
+
+    let failures = failure_lines(digest);
+    if !failures.is_empty() {
+        text.push_str(&format!("\n```\n{}\n```", failures.join("\n")));
+    }
+
+    format!("{{\"text\":{}}}", json::string(&text))
+}
+
+fn teams_payload(digest: &Digest) -> String {
+    let totals: Vec<String> = totals(digest)
+        .into_iter()
+        .map(|(label, count)| format!("{}: **{}**", label, count))
+        .collect();
+    let mut text = totals.join(" &nbsp; ");
+
+    let failures = failure_lines(digest);
+    if !failures.is_empty() {
+        text.push_str("\n\n");
+        for line in failures {
+            text.push_str(&format!("- {}\n", line));
+        }
+    }
+
+    format!(
+        "{{\"@type\":\"MessageCard\",\"@context\":\"https://schema.org/extensions\",\"themeColor\":{},\"summary\":{},\"title\":{},\"text\":{}}}",
+        json::string(if digest.failed > 0 {
+            "D7263D"
+        } else {
+            "2EB67D"
+        }),
+        json::string(&headline(digest)),
+        json::string(&headline(digest)),
+        json::string(&text)
+    )
+}
+
+/// Runs curl with `options` (long option names without the dashes, and their
+/// values, empty for switches) and `input` on stdin, returning its error
+/// output on failure. The options go in a config file only this user can
+/// read, as they hold passwords and webhook URLs that double as tokens, and
+/// anything on the command line shows in the process list.
+pub fn curl(options: &[(&str, String)], input: &str) -> Result<(), String> {
+    let mut text = String::new();
+    for (name, value) in options {
+        text.push_str(name);
+        if !value.is_empty() {
+            text.push_str(" = ");
+            text.push_str(&quote(value));
+        }
+        text.push('\n');
+    }
+    let config = PrivateFile::create("curlrc", &text)
+        .map_err(|err| format!("failed to write curl's options: {}", err))?;
+    let mut child = Command::new("curl")
+        .arg("--silent")
+        .arg("--show-error")
+        .arg("--config")
+        .arg(&config.path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("failed to run curl: {}", err))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(input.as_bytes())
+            .map_err(|err| format!("failed to write to curl: {}", err))?;
+    }
+
+    let output = child
+        .wait_with_output()
+        .map_err(|err| format!("failed to run curl: {}", err))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// A file in the temp directory that only this user can read, for handing
+/// a helper program what should not be on its command line. Removed when
+/// dropped.
+pub struct PrivateFile {
+    pub path: PathBuf,
+}
+
+impl PrivateFile {
+    pub fn create(extension: &str, contents: &str) -> std::io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = env::temp_dir().join(format!(
+            "r3dy-{}-{}.{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            extension
+        ));
+        let mut open = File::options();
+        open.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+
+            open.mode(0o600);
+        }
+        let mut file = open.open(&path)?;
+        let created = Self { path };
+        file.write_all(contents.as_bytes())?;
+        Ok(created)
+    }
+}
+
+impl Drop for PrivateFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// `value` as a double-quoted curl config value.
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn build_message(config: &EmailConfig, root: &Path, summary: &Summary, batch: bool) -> String {
+    let (attachment, name, mime) = match config.attachment {
+        AttachmentFormat::Csv => (
+            report::to_csv(root, summary, true),
+            "r3dy-report.csv",
+            "text/csv",
+        ),
+        AttachmentFormat::Json => (
+            report::to_json(root, summary, Some(SystemTime::now())),
+            "r3dy-report.json",
+            "application/json",
+        ),
+    };
+
+    let subject = format!(
+        "r3dy: {} converted, {} skipped, {} failed ({})",
+        summary.converted,
+        summary.skipped,
+        summary.failed,
+        winpath::display(root)
+    );
+
+    let mut body = format!(
+        "{} {}\n\nConverted: {}\nSkipped: {}\nFailed: {}\n",
+        if batch {
+            "r3dy renamed a batch of new files under"
+        } else {
+            "r3dy finished processing"
+        },
+        winpath::display(root),
+        summary.converted,
+        summary.skipped,
+        summary.failed
+    );
+
+    if !summary.cards.is_empty() {
+        body.push_str("\nCards:\n");
+        for tally in summary.cards.tallies() {
+            body.push_str(&format!(
+                "  {}: {} converted, {} skipped, {} failed\n",
+                tally.name(root),
+                tally.converted,
+                tally.skipped,
+                tally.failed
+            ));
+        }
+    }
+
+    if summary.failed > 0 {
+        body.push_str("\nFailures:\n");
+        for outcome in summary.failures().take(FAILURES_LISTED) {
+            body.push_str(&format!(
+                "  {}: {}\n",
+                crate::display_relative(root, &outcome.source),
+                outcome.error.as_deref().unwrap_or("unknown error")
+            ));
+        }
+        if summary.failed > FAILURES_LISTED {
+            body.push_str(&format!(
+                "  ...and {} more, listed in the attached report\n",
+                summary.failed - FAILURES_LISTED
+            ));
+        }
+    }
+
+    let mut message = String::new();
+    message.push_str(&format!("From: {}\r\n", config.from));
+    message.push_str(&format!("To: {}\r\n", config.to.join(", ")));
+    message.push_str(&format!("Subject: {}\r\n", encode_header(&subject)));
+    message.push_str(&format!(
+        "Date: {}\r\n",
+        datetime::rfc2822(SystemTime::now())
+    ));
+    message.push_str("MIME-Version: 1.0\r\n");
+    message.push_str(&format!(
+        "Content-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n",
+        BOUNDARY
+    ));
+
+    message.push_str(&format!("--{}\r\n", BOUNDARY));
+    message.push_str("Content-Type: text/plain; charset=utf-8\r\n\r\n");
+    message.push_str(&body.replace('\n', "\r\n"));
+    message.push_str("\r\n");
+
+    message.push_str(&format!("--{}\r\n", BOUNDARY));
+    message.push_str(&format!("Content-Type: {}; name=\"{}\"\r\n", mime, name));
+    message.push_str("Content-Transfer-Encoding: base64\r\n");
+    message.push_str(&format!(
+        "Content-Disposition: attachment; filename=\"{}\"\r\n\r\n",
+        name
+    ));
+    message.push_str(&base64_lines(attachment.as_bytes()));
+    message.push_str(&format!("--{}--\r\n", BOUNDARY));
+
+    message
+}
+
+/// `text` for a header line: control characters, which could end the
+/// header early and start another, become spaces, and anything other than
+/// printable ASCII is sent as RFC 2047 encoded words, folded onto lines of
+/// their own so none passes the 76 characters the RFC allows.
+fn encode_header(text: &str) -> String {
+    let text: String = text
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    if text.bytes().all(|byte| (b' '..=b'~').contains(&byte)) {
+        return text;
+    }
+    // 45 bytes make 60 base64 characters, 72 with the =?UTF-8?B?...?=.
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for c in text.chars() {
+        if word.len() + c.len_utf8() > 45 {
+            words.push(format!("=?UTF-8?B?{}?=", base64(word.as_bytes())));
+            word.clear();
+        }
+        word.push(c);
+    }
+    words.push(format!("=?UTF-8?B?{}?=", base64(word.as_bytes())));
+    words.join("\r\n ")
+}
+
+fn base64_lines(data: &[u8]) -> String {
+    let encoded = base64(data);
+    let mut out = String::with_capacity(encoded.len() + encoded.len() / 38);
+    for line in encoded.as_bytes().chunks(76) {
+        out.push_str(std::str::from_utf8(line).unwrap_or_default());
+        out.push_str("\r\n");
+    }
+    out
+}
+
+pub fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(data.len() * 4 / 3 + 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+
+        encoded.push(ALPHABET[(n >> 18) as usize & 63] as char);
+        encoded.push(ALPHABET[(n >> 12) as usize & 63] as char);
+        encoded.push(if chunk.len() > 1 {
+            ALPHABET[(n >> 6) as usize & 63] as char
+        } else {
+            '='
+        });
+        encoded.push(if chunk.len() > 2 {
+            ALPHABET[n as usize & 63] as char
+        } else {
+            '='
+        });
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_cannot_start_another_header() {
+        assert_eq!(
+            encode_header("r3dy: 1 converted (/Volumes/A\r\nBcc: x@example.com)"),
+            "r3dy: 1 converted (/Volumes/A  Bcc: x@example.com)"
+        );
+    }
+
+    #[test]
+    fn header_outside_ascii_is_encoded() {
+        let encoded = encode_header(&format!(
+            "r3dy: 1 converted (/Volumes/Café{})",
+            "_".repeat(60)
+        ));
+        let lines: Vec<&str> = encoded.split("\r\n ").collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "=?UTF-8?B?cjNkeTogMSBjb252ZXJ0ZWQgKC9Wb2x1bWVzL0NhZsOpX19fX19fX19fX19f?="
+        );
+        for line in lines {
+            assert!(line.starts_with("=?UTF-8?B?") && line.ends_with("?=") && line.len() <= 75);
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs::File;
+use std::io::{self, Write};
+
+/// Opens a destination given as either a file path or a bare file descriptor
+/// number (e.g. `3`) inherited from the parent process. Use `./3` to write to
+/// a file literally named `3`.
+pub fn open(spec: &str) -> Result<Box<dyn Write + Send>, String> {
+    if let Ok(fd) = spec.parse::<i32>() {
+        return open_fd(fd);
+    }
+
+    File::create(spec)
+        .map(|file| Box::new(io::LineWriter::new(file)) as Box<dyn Write + Send>)
+        .map_err(|err| format!("Failed to create {}: {}", spec, err))
+}
+
+#[cfg(unix)]
+fn open_fd(fd: i32) -> Result<Box<dyn Write + Send>, String> {
+    use std::os::fd::FromRawFd;
+
+    match fd {
+        1 => Ok(Box::new(io::stdout())),
+        2 => Ok(Box::new(io::stderr())),
+        fd if fd > 2 => {
+            // Refuse descriptors the parent did not actually hand us, rather
+            // than adopting one that std may reuse internally.
+            // SAFETY: fcntl(F_GETFD) only queries the descriptor table.
+            if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+                return Err(format!("File descriptor {} is not open", fd));
+            }
+            // SAFETY: the descriptor is open and ownership passes to the File.
+            let file = unsafe { File::from_raw_fd(fd) };
+            Ok(Box::new(io::LineWriter::new(file)))
+        }
+        _ => Err(format!("File descriptor {} cannot be used for output", fd)),
+    }
+}
+
+#[cfg(not(unix))]
+fn open_fd(fd: i32) -> Result<Box<dyn Write + Send>, String> {
+    match fd {
+        1 => Ok(Box::new(io::stdout())),
+        2 => Ok(Box::new(io::stderr())),
+        _ => Err(format!(
+            "Writing to file descriptor {} is only supported on Unix",
+            fd
+        )),
+    }
+}
@@ -0,0 +1,121 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::normalize::{self, Form};
+
+static FORWARD_SLASHES: AtomicBool = AtomicBool::new(false);
+
+/// Writes relative paths with `/` between components on every platform, so
+/// a report written on Windows matches one from Linux or macOS byte for byte.
+pub fn use_forward_slashes() {
+    FORWARD_SLASHES.store(true, Ordering::Relaxed);
+}
+
+/// Like `encode`, for a path relative to the scan root.
+pub fn encode_relative(path: &Path) -> String {
+    if cfg!(windows) && path.is_relative() && FORWARD_SLASHES.load(Ordering::Relaxed) {
+        return path
+            .components()
+            .map(|component| encode(Path::new(component.as_os_str())))
+            .collect::<Vec<_>>()
+            .join("/");
+    }
+    encode(path)
+}
+
+/// Encodes a path as text without losing information. Bytes that are not
+/// valid UTF-8 (and `%` itself) are percent-escaped, so names restored from
+/// old Linux systems survive a round trip through CSV and JSON reports.
+pub fn encode(path: &Path) -> String {
+    let bytes = path.as_os_str().as_encoded_bytes();
+    let mut out = String::with_capacity(bytes.len());
+    let mut rest = bytes;
+
+    while !rest.is_empty() {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                push_escaping_percent(&mut out, valid);
+                break;
+            }
+            Err(err) => {
+                let (valid, invalid) = rest.split_at(err.valid_up_to());
+                // SAFETY: from_utf8 reported these bytes as valid UTF-8.
+                push_escaping_percent(&mut out, unsafe { std::str::from_utf8_unchecked(valid) });
+
+                let bad = err.error_len().unwrap_or(invalid.len());
+                for byte in &invalid[..bad] {
+                    out.push_str(&format!("%{:02X}", byte));
+                }
+                rest = &invalid[bad..];
+            }
+        }
+    }
+
+    out
+}
+
+/// Reverses `encode`. Fails on bytes the platform cannot hold in a path.
+pub fn decode(text: &str) -> Result<PathBuf, String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(value) => {
+                bytes.push(value);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        Ok(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+    }
+
+    #[cfg(not(unix))]
+    {
+        String::from_utf8(bytes)
+            .map(PathBuf::from)
+            .map_err(|_| format!("{} is not a valid path on this system", text))
+    }
+}
+
+fn push_escaping_percent(out: &mut String, text: &str) {
+    for ch in text.chars() {
+        if ch == '%' {
+            out.push_str("%25");
+        } else {
+            out.push(ch);
+        }
+    }
+}
+
+/// Compares an extension byte-wise, ignoring ASCII case, so names that are
+/// not valid UTF-8 still match. An extension with accents also matches when
+/// one side is decomposed, as names written on macOS are.
+pub fn extension_eq(extension: &OsStr, expected: &str) -> bool {
+    if extension
+        .as_encoded_bytes()
+        .eq_ignore_ascii_case(expected.as_bytes())
+    {
+        return true;
+    }
+    match extension.to_str() {
+        Some(extension) if !extension.is_ascii() || !expected.is_ascii() => {
+            normalize::normalize(extension, Form::Nfc)
+                .eq_ignore_ascii_case(&normalize::normalize(expected, Form::Nfc))
+        }
+        _ => false,
+    }
+}
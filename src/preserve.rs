@@ -0,0 +1,178 @@
+use std::fs::{self, File, FileTimes, Permissions};
+use std::io;
+use std::path::Path;
+
+use crate::trace;
+
+mod streams;
+mod xattr;
+
+/// What a copy keeps of the original besides its contents. A rename keeps
+/// everything anyway; this is for `--copy` and moves to another volume.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Preserve {
+    /// Modification and access times, and the creation time where it can be
+    /// set (macOS and Windows), for `--preserve-times`.
+    pub times: bool,
+    /// Permissions, and the owner and group when running as root.
+    pub perms: bool,
+    /// Extended attributes such as Finder tags, except the quarantine flag.
+    pub xattrs: bool,
+    /// Leave the Zone.Identifier stream off copies on Windows, for
+    /// `--no-zone-identifier`. Other alternate data streams are always
+    /// copied, like the contents.
+    pub drop_zone_identifier: bool,
+}
+
+impl Preserve {
+    /// Everything a copy can keep.
+    pub const ALL: Preserve = Preserve {
+        times: true,
+        perms: true,
+        xattrs: true,
+        drop_zone_identifier: false,
+    };
+
+    /// Parses the `--preserve` list: `all`, `none`, or any of `perms`,
+    /// `xattrs` and `times`, separated by commas.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut preserve = Preserve::default();
+        for item in value.split(',') {
+            match item.trim().to_ascii_lowercase().as_str() {
+                "all" => preserve = Preserve::ALL,
+                "none" => preserve = Preserve::default(),
+                "perms" => preserve.perms = true,
+                "xattrs" => preserve.xattrs = true,
+                "times" => preserve.times = true,
+                _ => return None,
+            }
+        }
+        Some(preserve)
+    }
+}
+
+/// What `Preserve` keeps of one original, read before the copy reads the
+/// file and moves its access time.
+pub struct Kept {
+    times: Option<FileTimes>,
+    permissions: Option<Permissions>,
+    /// The owner and group, kept only when running as root, as no one else
+    /// may give a file away.
+    owner: Option<(u32, u32)>,
+    xattrs: Option<Vec<xattr::Attribute>>,
+}
+
+pub use streams::copy as copy_streams;
+
+/// Reads what `preserve` keeps of `source`.
+pub fn read(source: &Path, preserve: Preserve) -> io::Result<Kept> {
+    let mut kept = Kept {
+        times: None,
+        permissions: None,
+        owner: None,
+        xattrs: None,
+    };
+    if preserve == Preserve::default() {
+        return Ok(kept);
+    }
+    let metadata = trace::call("stat", source, || fs::metadata(source))?;
+
+    if preserve.times {
+        let mut times = FileTimes::new();
+        if let Ok(modified) = metadata.modified() {
+            times = times.set_modified(modified);
+        }
+        if let Ok(accessed) = metadata.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        #[cfg(target_os = "macos")]
+        {
+            use std::os::macos::fs::FileTimesExt;
+
+            if let Ok(created) = metadata.created() {
+                times = times.set_created(created);
+            }
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::FileTimesExt;
+
+            if let Ok(created) = metadata.created() {
+                times = times.set_created(created);
+            }
+        }
+        kept.times = Some(times);
+    }
+
+    if preserve.perms {
+        kept.permissions = Some(metadata.permissions());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            // SAFETY: geteuid has no preconditions and cannot fail.
+            if unsafe { libc::geteuid() } == 0 {
+                kept.owner = Some((metadata.uid(), metadata.gid()));
+            }
+        }
+    }
+
+    if preserve.xattrs {
+        kept.xattrs = Some(trace::call("listxattr", source, || xattr::read(source))?);
+    }
+    Ok(kept)
+}
+
+impl Kept {
+    /// Gives the copy at `target` what was kept of its original. The times
+    /// go last, as setting the others must not be what the copy is dated by.
+    pub fn apply(&self, target: &Path) -> io::Result<()> {
+        if self.times.is_none()
+            && self.permissions.is_none()
+            && self.owner.is_none()
+            && self.xattrs.is_none()
+        {
+            return Ok(());
+        }
+        let file = trace::call("open", target, || open_for_times(target))?;
+
+        if let Some(xattrs) = &self.xattrs {
+            trace::call("setxattr", target, || xattr::write(&file, xattrs))?;
+        }
+
+        #[cfg(unix)]
+        if let Some((uid, gid)) = self.owner {
+            trace::call("fchown", target, || {
+                std::os::unix::fs::fchown(&file, Some(uid), Some(gid))
+            })?;
+        }
+        // After the owner, as changing it clears the setuid and setgid bits.
+        if let Some(permissions) = &self.permissions {
+            trace::call("chmod", target, || {
+                file.set_permissions(permissions.clone())
+            })?;
+        }
+
+        if let Some(times) = self.times {
+            trace::call("futimens", target, || file.set_times(times))?;
+        }
+        Ok(())
+    }
+}
+
+/// Opens `target` so its times and attributes can be set even when the copy
+/// came out read-only, as copies of read-only camera files do.
+fn open_for_times(target: &Path) -> io::Result<File> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        // FILE_WRITE_ATTRIBUTES, which the read-only attribute allows.
+        File::options().access_mode(0x100).open(target)
+    }
+    // The owner may set times through any descriptor.
+    #[cfg(not(windows))]
+    {
+        File::open(target)
+    }
+}
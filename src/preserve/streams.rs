@@ -0,0 +1,143 @@
+//! NTFS alternate data streams, which Windows tools and asset trackers hang
+//! metadata on. A copy carries them over like the contents; elsewhere files
+//! have none.
+
+use std::io;
+use std::path::Path;
+
+use super::Preserve;
+
+/// The stream Windows marks downloaded files with, left off copies with
+/// `--no-zone-identifier`.
+#[cfg(windows)]
+const ZONE_IDENTIFIER: &str = ":Zone.Identifier:$DATA";
+
+/// Copies the alternate data streams of `source` to its copy at `target`,
+/// replacing any the copy already got. A copy of a read-only file is made
+/// writable for as long as that takes. A target volume that cannot hold
+/// streams, such as exFAT, fails the copy rather than losing them.
+#[cfg(windows)]
+pub fn copy(source: &Path, target: &Path, preserve: Preserve) -> io::Result<()> {
+    use std::ffi::OsString;
+    use std::fs::{self, File};
+
+    let names = list(source)?;
+    if names.is_empty() && !preserve.drop_zone_identifier {
+        return Ok(());
+    }
+    let with_stream = |path: &Path, name: &OsString| {
+        let mut path = path.as_os_str().to_os_string();
+        path.push(name);
+        path
+    };
+    let zone = |name: &OsString| {
+        name.to_str()
+            .is_some_and(|name| name.eq_ignore_ascii_case(ZONE_IDENTIFIER))
+    };
+
+    let permissions = fs::metadata(target)?.permissions();
+    let read_only = permissions.readonly();
+    if read_only {
+        let mut writable = permissions.clone();
+        // On Windows this only clears the read-only attribute.
+        #[allow(clippy::permissions_set_readonly_false)]
+        writable.set_readonly(false);
+        fs::set_permissions(target, writable)?;
+    }
+    let result = (|| {
+        if preserve.drop_zone_identifier {
+            match fs::remove_file(with_stream(target, &OsString::from(ZONE_IDENTIFIER))) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+        for name in names
+            .iter()
+            .filter(|name| !(preserve.drop_zone_identifier && zone(name)))
+        {
+            let mut from = File::open(with_stream(source, name))?;
+            let mut to = File::create(with_stream(target, name))?;
+            io::copy(&mut from, &mut to)?;
+        }
+        Ok(())
+    })();
+    if read_only {
+        fs::set_permissions(target, permissions)?;
+    }
+    result
+}
+
+#[cfg(not(windows))]
+pub fn copy(_source: &Path, _target: &Path, _preserve: Preserve) -> io::Result<()> {
+    Ok(())
+}
+
+/// The names of the alternate streams of `path`, as `:name:$DATA`, leaving
+/// out the file's own contents (`::$DATA`).
+#[cfg(windows)]
+fn list(path: &Path) -> io::Result<Vec<std::ffi::OsString>> {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+
+    #[repr(C)]
+    struct FindStreamData {
+        size: i64,
+        name: [u16; 260 + 36],
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn FindFirstStreamW(
+            file: *const u16,
+            level: i32,
+            data: *mut FindStreamData,
+            flags: u32,
+        ) -> isize;
+        fn FindNextStreamW(find: isize, data: *mut FindStreamData) -> i32;
+        fn FindClose(find: isize) -> i32;
+    }
+    const INVALID_HANDLE_VALUE: isize = -1;
+    const ERROR_HANDLE_EOF: i32 = 38;
+    const ERROR_INVALID_PARAMETER: i32 = 87;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut data = FindStreamData {
+        size: 0,
+        name: [0; 296],
+    };
+    // SAFETY: the path is NUL-terminated and `data` is the
+    // WIN32_FIND_STREAM_DATA that FindStreamInfoStandard (0) fills.
+    let find = unsafe { FindFirstStreamW(wide.as_ptr(), 0, &mut data, 0) };
+    if find == INVALID_HANDLE_VALUE {
+        let err = io::Error::last_os_error();
+        // No streams at all, or a volume such as FAT that has none.
+        return match err.raw_os_error() {
+            Some(ERROR_HANDLE_EOF | ERROR_INVALID_PARAMETER) => Ok(Vec::new()),
+            _ => Err(err),
+        };
+    }
+
+    let mut names = Vec::new();
+    let result = loop {
+        let length = data
+            .name
+            .iter()
+            .position(|&unit| unit == 0)
+            .unwrap_or(data.name.len());
+        let name = OsString::from_wide(&data.name[..length]);
+        if name != "::$DATA" {
+            names.push(name);
+        }
+        // SAFETY: `find` is the open search handle from FindFirstStreamW.
+        if unsafe { FindNextStreamW(find, &mut data) } == 0 {
+            let err = io::Error::last_os_error();
+            break match err.raw_os_error() {
+                Some(ERROR_HANDLE_EOF) => Ok(names),
+                _ => Err(err),
+            };
+        }
+    };
+    // SAFETY: as above; the handle is not used again.
+    unsafe { FindClose(find) };
+    result
+}
@@ -0,0 +1,210 @@
+//! Extended attributes, for `--preserve xattrs`. Linux copies the `user.`
+//! ones, as the others hold ACLs and security labels a copy should get from
+//! where it lands; macOS copies them all. Elsewhere there are none to copy.
+
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// One attribute of the original: its name and value.
+pub struct Attribute {
+    #[cfg_attr(not(any(target_os = "linux", target_os = "macos")), allow(dead_code))]
+    name: CString,
+    #[cfg_attr(not(any(target_os = "linux", target_os = "macos")), allow(dead_code))]
+    value: Vec<u8>,
+}
+
+/// The flag macOS sets on downloaded files. It is left off copies, so media
+/// pulled from a shared drive does not open behind a Gatekeeper prompt.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn quarantine(name: &[u8]) -> bool {
+    name.ends_with(b"com.apple.quarantine")
+}
+
+/// The attributes of `path` worth copying, without following a symlink.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn read(path: &Path) -> io::Result<Vec<Attribute>> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a NUL byte"))?;
+    let names = match sized(|buffer| sys::list(&path, buffer)) {
+        Ok(names) => names,
+        // A filesystem without attributes has none to copy.
+        Err(err) if err.raw_os_error() == Some(libc::ENOTSUP) => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut attributes = Vec::new();
+    for name in names
+        .split(|&byte| byte == 0)
+        .filter(|name| !name.is_empty())
+    {
+        if quarantine(name) || !sys::copied(name) {
+            continue;
+        }
+        let name = CString::new(name).expect("split on NUL");
+        let value = sized(|buffer| sys::get(&path, &name, buffer))?;
+        attributes.push(Attribute { name, value });
+    }
+    Ok(attributes)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn read(_path: &Path) -> io::Result<Vec<Attribute>> {
+    Ok(Vec::new())
+}
+
+/// Sets `attributes` on the copy open as `file`. A copy that came out
+/// read-only is made writable by its owner for as long as that takes, as
+/// Linux asks for write permission to set them. On macOS the quarantine
+/// flag the copy itself may have carried over is removed.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn write(file: &File, attributes: &[Attribute]) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::PermissionsExt;
+
+    let fd = file.as_raw_fd();
+    #[cfg(target_os = "macos")]
+    {
+        // SAFETY: the name is NUL-terminated; a missing flag is not an error.
+        unsafe { libc::fremovexattr(fd, c"com.apple.quarantine".as_ptr(), 0) };
+    }
+    if attributes.is_empty() {
+        return Ok(());
+    }
+
+    let permissions = file.metadata()?.permissions();
+    let read_only = permissions.mode() & 0o200 == 0;
+    if read_only {
+        let mut writable = permissions.clone();
+        writable.set_mode(permissions.mode() | 0o200);
+        file.set_permissions(writable)?;
+    }
+    let result = attributes
+        .iter()
+        .try_for_each(|attribute| sys::set(fd, &attribute.name, &attribute.value));
+    if read_only {
+        file.set_permissions(permissions)?;
+    }
+    result
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn write(_file: &File, _attributes: &[Attribute]) -> io::Result<()> {
+    Ok(())
+}
+
+/// Calls `fill` first to size a buffer and then to fill it, again if the
+/// attribute grew in between.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn sized(fill: impl Fn(&mut [u8]) -> isize) -> io::Result<Vec<u8>> {
+    loop {
+        let size = fill(&mut []);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buffer = vec![0; size as usize];
+        let filled = fill(&mut buffer);
+        if filled >= 0 {
+            buffer.truncate(filled as usize);
+            return Ok(buffer);
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::ffi::CStr;
+    use std::io;
+    use std::os::fd::RawFd;
+
+    pub fn copied(name: &[u8]) -> bool {
+        name.starts_with(b"user.")
+    }
+
+    pub fn list(path: &CStr, buffer: &mut [u8]) -> isize {
+        // SAFETY: the buffer is valid for its length, and may be empty to
+        // ask for the size.
+        unsafe { libc::llistxattr(path.as_ptr(), buffer.as_mut_ptr().cast(), buffer.len()) }
+    }
+
+    pub fn get(path: &CStr, name: &CStr, buffer: &mut [u8]) -> isize {
+        // SAFETY: as for `list`; both strings are NUL-terminated.
+        unsafe {
+            libc::lgetxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                buffer.as_mut_ptr().cast(),
+                buffer.len(),
+            )
+        }
+    }
+
+    pub fn set(fd: RawFd, name: &CStr, value: &[u8]) -> io::Result<()> {
+        // SAFETY: the name is NUL-terminated and the value valid for its
+        // length.
+        let result =
+            unsafe { libc::fsetxattr(fd, name.as_ptr(), value.as_ptr().cast(), value.len(), 0) };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod sys {
+    use std::ffi::CStr;
+    use std::io;
+    use std::os::fd::RawFd;
+
+    pub fn copied(_name: &[u8]) -> bool {
+        true
+    }
+
+    pub fn list(path: &CStr, buffer: &mut [u8]) -> isize {
+        // SAFETY: the buffer is valid for its length, and may be empty to
+        // ask for the size.
+        unsafe {
+            libc::listxattr(
+                path.as_ptr(),
+                buffer.as_mut_ptr().cast(),
+                buffer.len(),
+                libc::XATTR_NOFOLLOW,
+            )
+        }
+    }
+
+    pub fn get(path: &CStr, name: &CStr, buffer: &mut [u8]) -> isize {
+        // SAFETY: as for `list`; both strings are NUL-terminated.
+        unsafe {
+            libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                buffer.as_mut_ptr().cast(),
+                buffer.len(),
+                0,
+                libc::XATTR_NOFOLLOW,
+            )
+        }
+    }
+
+    pub fn set(fd: RawFd, name: &CStr, value: &[u8]) -> io::Result<()> {
+        // SAFETY: the name is NUL-terminated and the value valid for its
+        // length.
+        let result =
+            unsafe { libc::fsetxattr(fd, name.as_ptr(), value.as_ptr().cast(), value.len(), 0, 0) };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::path::{MAIN_SEPARATOR, Path, PathBuf};
+
+use console::style;
+
+use crate::display_relative;
+
+/// The `--dry-run` listing of planned renames: grouped under a heading for
+/// each folder, with the current and proposed names in two aligned columns
+/// and the part of each name that changes highlighted. A folder gets one
+/// heading when its files come together, as in `PathOrder::Grouped`.
+#[derive(Default)]
+pub struct Preview {
+    /// The folder being listed.
+    dir: Option<PathBuf>,
+    /// Its planned renames, current name first.
+    rows: Vec<(String, String)>,
+}
+
+impl Preview {
+    /// Adds the rename of `source` to `target`. Returns the lines of the
+    /// folder before, once `source` starts a new one.
+    pub fn add(&mut self, root: &Path, source: &Path, target: &Path) -> Vec<String> {
+        let dir = source.parent().unwrap_or(root);
+        let lines = if self.dir.as_deref() == Some(dir) {
+            Vec::new()
+        } else {
+            let lines = self.finish(root);
+            self.dir = Some(dir.to_path_buf());
+            lines
+        };
+        let name = |path: &Path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        // A target in another folder (--dest) is shown with its folder.
+        let proposed = if target.parent() == Some(dir) {
+            name(target)
+        } else {
+            display_relative(root, target)
+        };
+        self.rows.push((name(source), proposed));
+        lines
+    }
+
+    /// The lines of the folder being listed: its path relative to `root`,
+    /// then a row for each rename.
+    pub fn finish(&mut self, root: &Path) -> Vec<String> {
+        let Some(dir) = self.dir.take() else {
+            return Vec::new();
+        };
+        let heading = match display_relative(root, &dir) {
+            relative if relative.is_empty() => format!(".{}", MAIN_SEPARATOR),
+            relative => format!("{}{}", relative, MAIN_SEPARATOR),
+        };
+        let width = self
+            .rows
+            .iter()
+            .map(|(current, _)| console::measure_text_width(current))
+            .max()
+            .unwrap_or(0);
+        let mut lines = vec![style(heading).bold().to_string()];
+        for (current, proposed) in self.rows.drain(..) {
+            let padding = " ".repeat(width - console::measure_text_width(&current));
+            let (current, proposed) = highlight(&current, &proposed);
+            lines.push(format!("  {}{}  \u{2192} {}", current, padding, proposed));
+        }
+        lines
+    }
+}
+
+/// `current` and `proposed` with the part that differs between them in
+/// colour: red for what goes, green for what comes.
+fn highlight<'a>(current: &'a str, proposed: &'a str) -> (String, String) {
+    let prefix: usize = current
+        .chars()
+        .zip(proposed.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    let suffix: usize = current[prefix..]
+        .chars()
+        .rev()
+        .zip(proposed[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    let parts = |name: &'a str| {
+        let end = name.len() - suffix;
+        (&name[..prefix], &name[prefix..end], &name[end..])
+    };
+    let (start, gone, end) = parts(current);
+    let current = format!("{}{}{}", start, style(gone).red(), end);
+    let (start, new, end) = parts(proposed);
+    let proposed = format!("{}{}{}", start, style(new).green(), end);
+    (current, proposed)
+}
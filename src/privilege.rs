@@ -0,0 +1,157 @@
+/// Switches a process started as root to `spec` (`user` or `user:group`,
+/// names or numeric ids) and returns a description of the new identity for
+/// the log. Supplementary groups are reset to the user's own, and the switch
+/// is checked to be irreversible. `HOME` becomes the user's home and the
+/// `XDG_*` directories are cleared, so the journal and settings are looked
+/// for where the user can write rather than in root's home. Must be called
+/// before any other thread starts, as it changes the environment.
+#[cfg(unix)]
+pub fn drop_to(spec: &str) -> Result<String, String> {
+    use std::ffi::{CStr, CString};
+
+    let (user, group) = match spec.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (spec, None),
+    };
+
+    let c_user = CString::new(user).map_err(|_| format!("Invalid user name: {}", user))?;
+    // SAFETY: getpwnam returns null or a pointer to static storage, which is
+    // read before any other passwd lookup; nothing else runs concurrently at
+    // startup.
+    let passwd = unsafe { libc::getpwnam(c_user.as_ptr()) };
+    let (uid, user_gid, user_name, home) = if passwd.is_null() {
+        let uid = user
+            .parse::<libc::uid_t>()
+            .map_err(|_| format!("Unknown user: {}", user))?;
+        (uid, None, None, None)
+    } else {
+        // SAFETY: non-null, see above.
+        let passwd = unsafe { &*passwd };
+        let name = unsafe { CStr::from_ptr(passwd.pw_name) }.to_owned();
+        let home = unsafe { CStr::from_ptr(passwd.pw_dir) }.to_owned();
+        (passwd.pw_uid, Some(passwd.pw_gid), Some(name), Some(home))
+    };
+
+    let gid = match group {
+        Some(group) => group_id(group)?,
+        None => user_gid.ok_or_else(|| {
+            format!(
+                "User {} has no passwd entry; give a group as {}:GROUP",
+                user, user
+            )
+        })?,
+    };
+
+    // Without root there is nothing to drop; that is fine as long as the
+    // process already runs as the requested identity.
+    if unsafe { libc::geteuid() } != 0 {
+        if identity() == (uid, gid) {
+            return Ok(describe(user, uid, gid));
+        }
+        return Err(format!(
+            "--run-as {} needs r3dy to be started as root",
+            spec
+        ));
+    }
+
+    // Group changes first: once the uid is dropped they are no longer
+    // allowed.
+    // SAFETY: `name` is a valid C string; the id calls take plain integers.
+    let groups_set = match &user_name {
+        Some(name) => unsafe { libc::initgroups(name.as_ptr(), gid as _) },
+        None => unsafe { libc::setgroups(1, &gid) },
+    };
+    if groups_set != 0 {
+        return Err(format!(
+            "Failed to set supplementary groups: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    if unsafe { libc::setgid(gid) } != 0 {
+        return Err(format!(
+            "Failed to switch to group {}: {}",
+            gid,
+            std::io::Error::last_os_error()
+        ));
+    }
+    if unsafe { libc::setuid(uid) } != 0 {
+        return Err(format!(
+            "Failed to switch to user {}: {}",
+            uid,
+            std::io::Error::last_os_error()
+        ));
+    }
+    if uid != 0 && unsafe { libc::setuid(0) } == 0 {
+        return Err("Dropped privileges could be regained; refusing to continue".to_string());
+    }
+    switch_environment(user_name.as_deref(), home.as_deref());
+
+    Ok(describe(user, uid, gid))
+}
+
+/// Points the per-user environment at the user switched to. A user without
+/// a passwd entry has no home, so `HOME` is removed rather than left at
+/// root's.
+#[cfg(unix)]
+fn switch_environment(name: Option<&std::ffi::CStr>, home: Option<&std::ffi::CStr>) {
+    use std::env;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    // SAFETY: called at startup before any other thread exists, as
+    // `drop_to` requires, so nothing reads the environment concurrently.
+    unsafe {
+        match home.filter(|home| !home.is_empty()) {
+            Some(home) => env::set_var("HOME", OsStr::from_bytes(home.to_bytes())),
+            None => env::remove_var("HOME"),
+        }
+        for var in ["USER", "LOGNAME"] {
+            match name {
+                Some(name) => env::set_var(var, OsStr::from_bytes(name.to_bytes())),
+                None => env::remove_var(var),
+            }
+        }
+        for var in [
+            "XDG_CONFIG_HOME",
+            "XDG_DATA_HOME",
+            "XDG_STATE_HOME",
+            "XDG_CACHE_HOME",
+            "XDG_RUNTIME_DIR",
+        ] {
+            env::remove_var(var);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn drop_to(_spec: &str) -> Result<String, String> {
+    Err("--run-as is only supported on Unix".to_string())
+}
+
+#[cfg(unix)]
+fn group_id(group: &str) -> Result<libc::gid_t, String> {
+    let c_group =
+        std::ffi::CString::new(group).map_err(|_| format!("Invalid group name: {}", group))?;
+    // SAFETY: getgrnam returns null or a pointer to static storage that is
+    // read immediately.
+    let entry = unsafe { libc::getgrnam(c_group.as_ptr()) };
+    if entry.is_null() {
+        group
+            .parse::<libc::gid_t>()
+            .map_err(|_| format!("Unknown group: {}", group))
+    } else {
+        Ok(unsafe { (*entry).gr_gid })
+    }
+}
+
+/// The effective uid and gid.
+#[cfg(unix)]
+fn identity() -> (libc::uid_t, libc::gid_t) {
+    // SAFETY: plain syscalls without arguments.
+    unsafe { (libc::geteuid(), libc::getegid()) }
+}
+
+#[cfg(unix)]
+fn describe(user: &str, uid: libc::uid_t, gid: libc::gid_t) -> String {
+    format!("{} (uid {}, gid {})", user, uid, gid)
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+use crate::{datetime, display_relative};
+
+static SCREEN_READER: OnceLock<Duration> = OnceLock::new();
+
+pub const DEFAULT_STATUS_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Parses a `--status-interval` such as `15s` or `1m`.
+pub fn parse_status_interval(value: &str) -> Result<Duration, String> {
+    match datetime::parse_duration(value) {
+        Some(interval) if !interval.is_zero() => Ok(interval),
+        _ => Err(format!(
+            "Invalid --status-interval: {} (e.g. 15s or 1m)",
+            value
+        )),
+    }
+}
+
+/// Switches to screen-reader output: bars never draw, and `Announcer`s say
+/// how the run is going in a plain sentence every `interval` instead.
+pub fn enable_screen_reader(interval: Duration) {
+    let _ = SCREEN_READER.set(interval);
+}
+
+/// Prints a line above `bar`. Bars are hidden in screen-reader mode (and
+/// when stderr is not a terminal), which would swallow the line; in
+/// screen-reader mode it is printed on its own.
+pub fn println(bar: &ProgressBar, line: impl AsRef<str>) {
+    if SCREEN_READER.get().is_some() {
+        eprintln!("{}", line.as_ref());
+    } else {
+        bar.println(line);
+    }
+}
+
+/// Periodic status sentences for screen-reader mode; silent otherwise.
+pub struct Announcer {
+    interval: Option<Duration>,
+    last: Instant,
+    total: usize,
+    verb: &'static str,
+}
+
+impl Announcer {
+    /// `verb` describes the work in the past tense ("Renamed", "Verified").
+    pub fn new(total: usize, verb: &'static str) -> Self {
+        let interval = SCREEN_READER.get().copied();
+        if interval.is_some() {
+            eprintln!(
+                "Started: {} file{} to go.",
+                total,
+                if total == 1 { "" } else { "s" }
+            );
+        }
+        Self {
+            interval,
+            last: Instant::now(),
+            total,
+            verb,
+        }
+    }
+
+    /// Announces progress if the interval has passed since the last update.
+    pub fn update(&mut self, done: usize, failed: usize) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        if self.last.elapsed() < interval {
+            return;
+        }
+        self.last = Instant::now();
+        eprintln!(
+            "{} {} of {} files, {} failed.",
+            self.verb, done, self.total, failed
+        );
+    }
+
+    pub fn finish(&self) {
+        if self.interval.is_some() {
+            eprintln!("Finished.");
+        }
+    }
+}
+
+/// The built-in bar layouts for `--progress-template`.
+const LAYOUTS: [(&str, &str); 4] = [
+    (
+        "default",
+        "{spinner:.green} [{elapsed_precise}] {wide_bar:.cyan/blue} {pos}/{len} {msg}",
+    ),
+    (
+        "eta",
+        "{spinner:.green} [{elapsed_precise}] {wide_bar:.cyan/blue} {pos}/{len} ETA {eta} {msg}",
+    ),
+    ("compact", "{pos}/{len} ({percent}%) {msg}"),
+    (
+        "failures",
+        "{spinner:.green} [{elapsed_precise}] {wide_bar:.cyan/blue} {pos}/{len} failed: {failed} {msg}",
+    ),
+];
+
+/// How the main progress bar looks and how often it redraws.
+#[derive(Clone)]
+pub struct BarLayout {
+    template: String,
+    refresh_hz: Option<u8>,
+    /// Never drawn, because stdout and stderr are carrying something else.
+    hidden: bool,
+}
+
+impl Default for BarLayout {
+    fn default() -> Self {
+        Self {
+            template: LAYOUTS[0].1.to_string(),
+            refresh_hz: None,
+            hidden: false,
+        }
+    }
+}
+
+impl BarLayout {
+    /// Takes a built-in layout name or an indicatif template. Besides the
+    /// usual keys, templates can show `{failed}`, the failures so far.
+    pub fn set_template(&mut self, spec: &str) -> Result<(), String> {
+        let template = match LAYOUTS.iter().find(|(name, _)| *name == spec) {
+            Some((_, template)) => template.to_string(),
+            None if spec.contains('{') => spec.to_string(),
+            None => {
+                let names: Vec<&str> = LAYOUTS.iter().map(|(name, _)| *name).collect();
+                return Err(format!(
+                    "Unknown progress layout: {} (use {} or a template such as \"{{pos}}/{{len}} {{msg}}\")",
+                    spec,
+                    names.join(", ")
+                ));
+            }
+        };
+        ProgressStyle::with_template(&template)
+            .map_err(|err| format!("Invalid progress template: {}", err))?;
+        self.template = template;
+        Ok(())
+    }
+
+    /// Redraws at most `hz` times a second (indicatif's default is 20).
+    pub fn set_refresh(&mut self, hz: &str) -> Result<(), String> {
+        match hz.parse::<u8>() {
+            Ok(hz) if hz > 0 => {
+                self.refresh_hz = Some(hz);
+                Ok(())
+            }
+            _ => Err(format!(
+                "Invalid --progress-refresh rate: {} (redraws per second, 1-255)",
+                hz
+            )),
+        }
+    }
+
+    /// A bar of `len` files in this layout; `failed` feeds `{failed}`.
+    pub fn bar(&self, len: usize, failed: Arc<AtomicUsize>) -> Result<ProgressBar, String> {
+        let bar = ProgressBar::with_draw_target(Some(len as u64), self.draw_target());
+        bar.set_style(self.style(failed)?);
+        Ok(bar)
+    }
+
+    /// Where bars in this layout draw; used for the `MultiProgress` that
+    /// holds them.
+    pub fn hide(&mut self) {
+        self.hidden = true;
+    }
+
+    pub fn draw_target(&self) -> ProgressDrawTarget {
+        if self.hidden || SCREEN_READER.get().is_some() {
+            return ProgressDrawTarget::hidden();
+        }
+        match self.refresh_hz {
+            Some(hz) => ProgressDrawTarget::stderr_with_hz(hz),
+            None => ProgressDrawTarget::stderr(),
+        }
+    }
+
+    fn style(&self, failed: Arc<AtomicUsize>) -> Result<ProgressStyle, String> {
+        ProgressStyle::with_template(&self.template)
+            .map(|style| {
+                style.with_key(
+                    "failed",
+                    move |_: &indicatif::ProgressState, out: &mut dyn Write| {
+                        let _ = write!(out, "{}", failed.load(Ordering::Relaxed));
+                    },
+                )
+            })
+            .map_err(|err| format!("Invalid progress template: {}", err))
+    }
+}
+
+/// A second progress line under the global bar that follows the folder the
+/// run is in: its name, how many of its clips are done, and how many folders
+/// still have work left. DITs track a run card by card, not by a flat count.
+pub struct FolderProgress {
+    bar: ProgressBar,
+    /// Done and total matching files per directory.
+    folders: HashMap<PathBuf, (usize, usize)>,
+    remaining: usize,
+    current: Option<PathBuf>,
+}
+
+impl FolderProgress {
+    /// Adds the folder line below `multi`'s bars. `folders` maps every
+    /// directory holding matching files to how many it holds.
+    pub fn new(multi: &MultiProgress, folders: HashMap<PathBuf, usize>) -> Result<Self, String> {
+        let style = ProgressStyle::with_template(
+            "  {prefix:.bold} {bar:20.cyan/blue} {pos}/{len} in folder, {msg}",
+        )
+        .map_err(|err| err.to_string())?;
+
+        let bar = multi.add(ProgressBar::new(0));
+        bar.set_style(style);
+
+        Ok(Self {
+            bar,
+            remaining: folders.len(),
+            folders: folders
+                .into_iter()
+                .map(|(dir, total)| (dir, (0, total)))
+                .collect(),
+            current: None,
+        })
+    }
+
+    /// Counts `path` as handled and shows its folder.
+    pub fn record(&mut self, root: &Path, path: &Path) {
+        let Some(dir) = path.parent() else {
+            return;
+        };
+        let Some((done, total)) = self.folders.get_mut(dir) else {
+            return;
+        };
+
+        *done += 1;
+        if done == total {
+            self.remaining -= 1;
+        }
+
+        if self.current.as_deref() != Some(dir) {
+            self.current = Some(dir.to_path_buf());
+            let name = display_relative(root, dir);
+            self.bar.set_prefix(if name.is_empty() {
+                ".".to_string()
+            } else {
+                name
+            });
+            self.bar.set_length(*total as u64);
+        }
+        self.bar.set_position(*done as u64);
+        self.bar.set_message(format!(
+            "{} folder{} left",
+            self.remaining,
+            if self.remaining == 1 { "" } else { "s" }
+        ));
+    }
+
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
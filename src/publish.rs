@@ -0,0 +1,366 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::notify::{self, PrivateFile};
+use crate::{Outcome, Summary, encode_relative, json, pathenc};
+
+/// How many events wait for the publisher before the rename loop waits for
+/// it in turn, so a stalled CLI cannot pile up events without limit.
+const QUEUE: usize = 1024;
+
+/// How long `Publisher::finish` waits for queued events to go out.
+const FINISH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A cloud messaging destination for run and per-file events.
+#[derive(Clone)]
+pub enum Target {
+    Sns(String),
+    Sqs(String),
+    PubSub(String),
+}
+
+impl Target {
+    /// Parses `sns:<topic-arn>`, `sqs:<queue-url>` or `pubsub:<topic>`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (scheme, rest) = value.split_once(':')?;
+        if rest.is_empty() {
+            return None;
+        }
+
+        match scheme.to_ascii_lowercase().as_str() {
+            "sns" => Some(Target::Sns(rest.to_string())),
+            "sqs" => Some(Target::Sqs(rest.to_string())),
+            "pubsub" => Some(Target::PubSub(rest.to_string())),
+            _ => None,
+        }
+    }
+
+    /// How many events one upload carries: the limit of the SNS and SQS
+    /// batch calls, and a size Pub/Sub takes in one request.
+    fn batch_size(&self) -> usize {
+        match self {
+            Target::Sns(_) | Target::Sqs(_) => 10,
+            Target::PubSub(_) => 100,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Target::Sns(arn) => format!("SNS topic {}", arn),
+            Target::Sqs(url) => format!("SQS queue {}", url),
+            Target::PubSub(topic) => format!("Pub/Sub topic {}", topic),
+        }
+    }
+}
+
+/// The events one target could not take since failures were last read: how
+/// many, and the first error.
+type Failures = Vec<(usize, String)>;
+
+/// Publishes events from a background thread so slow cloud CLIs never hold up
+/// the rename loop for long. Events queue up to `QUEUE` deep and go out to
+/// each target in batches of whatever has queued, through each provider's
+/// CLI (`aws`, or `gcloud` for the token Pub/Sub's API is called with),
+/// which picks up credentials the usual way.
+pub struct Publisher {
+    /// Taken by `finish`, which lets the worker run out of events.
+    sender: Option<SyncSender<String>>,
+    targets: Vec<Target>,
+    failures: Arc<Mutex<Failures>>,
+    done: Receiver<()>,
+}
+
+impl Publisher {
+    pub fn start(targets: &[Target]) -> Option<Self> {
+        if targets.is_empty() {
+            return None;
+        }
+
+        let targets = targets.to_vec();
+        let (sender, receiver) = mpsc::sync_channel::<String>(QUEUE);
+        let (finished, done) = mpsc::channel();
+        let failures = Arc::new(Mutex::new(vec![(0, String::new()); targets.len()]));
+
+        let worker_targets = targets.clone();
+        let worker_failures = Arc::clone(&failures);
+        thread::spawn(move || {
+            let largest = worker_targets
+                .iter()
+                .map(Target::batch_size)
+                .max()
+                .unwrap_or(1);
+            let mut project = None;
+            while let Ok(first) = receiver.recv() {
+                let mut batch = vec![first];
+                batch.extend(receiver.try_iter().take(largest - 1));
+
+                for (index, target) in worker_targets.iter().enumerate() {
+                    for events in batch.chunks(target.batch_size()) {
+                        let failed = match deliver(target, events, &mut project) {
+                            Ok(Rejected { count: 0, .. }) => continue,
+                            Ok(Rejected { count, reason }) => (count, reason),
+                            Err(err) => (events.len(), err),
+                        };
+                        let mut failures =
+                            worker_failures.lock().unwrap_or_else(|e| e.into_inner());
+                        let failure = &mut failures[index];
+                        if failure.0 == 0 {
+                            failure.1 = failed.1;
+                        }
+                        failure.0 += failed.0;
+                    }
+                }
+            }
+            let _ = finished.send(());
+        });
+
+        Some(Self {
+            sender: Some(sender),
+            targets,
+            failures,
+            done,
+        })
+    }
+
+    pub fn file_event(&self, root: &Path, outcome: &Outcome) {
+        let error = match &outcome.error {
+            Some(error) => json::string(error),
+            None => "null".to_string(),
+        };
+
+        self.send(format!(
+            "{{\"event\":\"file\",\"root\":{},\"source\":{},\"target\":{},\"status\":{},\"error\":{}}}",
+            json::string(&pathenc::encode(root)),
+            json::string(&encode_relative(root, &outcome.source)),
+            json::string(&encode_relative(root, &outcome.target)),
+            json::string(outcome.status.as_str()),
+            error
+        ));
+    }
+
+    /// A file `r3dy verify` found no longer matching `mhl`.
+    pub fn verify_event(&self, root: &Path, mhl: &Path, path: &Path, error: &str) {
+        self.send(format!(
+            "{{\"event\":\"verify\",\"root\":{},\"mhl\":{},\"path\":{},\"error\":{}}}",
+            json::string(&pathenc::encode(root)),
+            json::string(&pathenc::encode(mhl)),
+            json::string(&encode_relative(root, path)),
+            json::string(error)
+        ));
+    }
+
+    pub fn run_event(&self, root: &Path, summary: &Summary) {
+        self.send(format!(
+            "{{\"event\":\"run\",\"root\":{},\"converted\":{},\"skipped\":{},\"failed\":{}}}",
+            json::string(&pathenc::encode(root)),
+            summary.converted,
+            summary.skipped,
+            summary.failed
+        ));
+    }
+
+    /// The deliveries that failed since this was last asked, one message per
+    /// target. A watch session asks after each batch, as it never finishes.
+    pub fn failures(&self) -> Vec<String> {
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        self.targets
+            .iter()
+            .zip(failures.iter_mut())
+            .filter(|(_, (count, _))| *count > 0)
+            .map(|(target, (count, err))| {
+                let message = format!(
+                    "Could not publish {} event{} to {}: {}",
+                    count,
+                    if *count == 1 { "" } else { "s" },
+                    target.describe(),
+                    err
+                );
+                *count = 0;
+                message
+            })
+            .collect()
+    }
+
+    /// Waits up to `FINISH_TIMEOUT` for queued events to be delivered and
+    /// returns any failures. A CLI that hangs is left behind rather than
+    /// holding the run open.
+    pub fn finish(mut self) -> Vec<String> {
+        self.sender = None;
+        let waited = self.done.recv_timeout(FINISH_TIMEOUT);
+        let mut messages = self.failures();
+        match waited {
+            Ok(()) => {}
+            Err(RecvTimeoutError::Timeout) => messages.push(format!(
+                "Gave up publishing events after {}s; the last of them may not have been delivered",
+                FINISH_TIMEOUT.as_secs()
+            )),
+            Err(RecvTimeoutError::Disconnected) => {
+                messages.push("Event publisher panicked".to_string())
+            }
+        }
+        messages
+    }
+
+    fn send(&self, message: String) {
+        // The worker only exits once the sender is dropped, so this cannot
+        // fail; it blocks while the queue is full.
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(message);
+        }
+    }
+}
+
+/// The events of a batch the service turned away, and why the first was.
+struct Rejected {
+    count: usize,
+    reason: String,
+}
+
+/// Sends `events` to `target` in one call, returning those the service
+/// turned away. `project` caches the Google Cloud project Pub/Sub topics
+/// without one are looked up in.
+fn deliver(
+    target: &Target,
+    events: &[String],
+    project: &mut Option<String>,
+) -> Result<Rejected, String> {
+    match target {
+        Target::Sns(arn) => {
+            let entries = entries(events, "Message");
+            aws_batch(
+                &["sns", "publish-batch", "--topic-arn", arn],
+                "--publish-batch-request-entries",
+                &entries,
+            )
+        }
+        Target::Sqs(url) => {
+            let entries = entries(events, "MessageBody");
+            aws_batch(
+                &["sqs", "send-message-batch", "--queue-url", url],
+                "--entries",
+                &entries,
+            )
+        }
+        Target::PubSub(topic) => {
+            let topic = if topic.starts_with("projects/") {
+                topic.clone()
+            } else {
+                if project.is_none() {
+                    *project = Some(gcloud(&["config", "get-value", "project"])?);
+                }
+                format!(
+                    "projects/{}/topics/{}",
+                    project.as_deref().unwrap_or_default(),
+                    topic
+                )
+            };
+            let token = gcloud(&["auth", "print-access-token"])?;
+            let messages: Vec<String> = events
+                .iter()
+                .map(|event| {
+                    format!(
+                        "{{\"data\":{}}}",
+                        json::string(&notify::base64(event.as_bytes()))
+                    )
+                })
+                .collect();
+            notify::curl(
+                &[
+                    (
+                        "url",
+                        format!("https://pubsub.googleapis.com/v1/{}:publish", topic),
+                    ),
+                    ("header", format!("Authorization: Bearer {}", token)),
+                    ("header", "Content-Type: application/json".to_string()),
+                    ("fail", String::new()),
+                    ("data-binary", "@-".to_string()),
+                ],
+                &format!("{{\"messages\":[{}]}}", messages.join(",")),
+            )?;
+            Ok(Rejected {
+                count: 0,
+                reason: String::new(),
+            })
+        }
+    }
+}
+
+/// The batch entries of the SNS and SQS calls, with the event under `field`.
+fn entries(events: &[String], field: &str) -> String {
+    let entries: Vec<String> = events
+        .iter()
+        .enumerate()
+        .map(|(id, event)| {
+            format!(
+                "{{\"Id\":\"{}\",\"{}\":{}}}",
+                id,
+                field,
+                json::string(event)
+            )
+        })
+        .collect();
+    format!("[{}]", entries.join(","))
+}
+
+/// Runs an `aws` batch call with `entries` read from a file, as a batch can
+/// be longer than a command line may be. The call succeeds even when some
+/// entries are rejected, and lists each of those with a `SenderFault` and a
+/// `Message`.
+fn aws_batch(args: &[&str], entries_flag: &str, entries: &str) -> Result<Rejected, String> {
+    let file = PrivateFile::create("json", entries).map_err(|err| err.to_string())?;
+    let mut uri = String::from("file://");
+    uri.push_str(&file.path.to_string_lossy());
+    let output = Command::new("aws")
+        .args(args)
+        .arg(entries_flag)
+        .arg(uri)
+        .args(["--output", "json"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| err.to_string())?;
+
+    if output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(Rejected {
+            count: stdout.matches("\"SenderFault\"").count(),
+            reason: first_message(&stdout).unwrap_or_else(|| "rejected".to_string()),
+        })
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// The first `"Message"` string in a batch call's JSON output.
+fn first_message(output: &str) -> Option<String> {
+    let (_, rest) = output.split_once("\"Message\"")?;
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let mut chars = rest.strip_prefix('"')?.chars();
+    let mut message = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(message),
+            '\\' => message.extend(chars.next()),
+            c => message.push(c),
+        }
+    }
+    None
+}
+
+/// Runs `gcloud` and returns what it printed.
+fn gcloud(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("gcloud")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| err.to_string())?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
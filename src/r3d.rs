@@ -0,0 +1,291 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::glob::Filter;
+use crate::spool::{self, PathOrder};
+use crate::{
+    CompoundRule, Walk, clip, collect_files, display_relative, exiftool, print_warnings, trace,
+};
+
+/// How much of a file the RED1 header fields span: the header atom's size
+/// and `RED1` tag, then the version, timescale, image size, frame rate and
+/// the 257-byte clip file name at offset 67.
+const RED1_LEN: usize = 324;
+
+/// Metadata from the header at the start of an R3D file.
+pub struct Header {
+    /// `RED1` or `RED2`, the tag of the header atom, or `unknown` when only
+    /// ExifTool could read the file.
+    pub format: &'static str,
+    /// The decoded fields; r3dy decodes only RED1 headers itself.
+    pub details: Option<Details>,
+}
+
+/// What filled in the [`Details`] of a header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Provider {
+    /// r3dy's own header parser.
+    Native,
+    /// `exiftool -json`, for headers the parser cannot decode (`--exiftool`).
+    ExifTool,
+}
+
+pub struct Details {
+    pub version: (u8, u8),
+    pub width: u32,
+    pub height: u32,
+    /// Frames per second as a fraction, e.g. 24000/1001.
+    pub frame_rate: (u16, u16),
+    /// The file name the camera recorded, e.g. `A001_C001_0101AB_001.R3D`.
+    pub file_name: String,
+    pub provider: Provider,
+}
+
+impl Header {
+    /// The clip name from the recorded file name, without the segment
+    /// number and extension.
+    pub fn clip(&self) -> Option<String> {
+        let details = self.details.as_ref()?;
+        let name = clip::name(Path::new(&details.file_name))?;
+        Some(name.to_string_lossy().into_owned())
+    }
+}
+
+/// Reads the header of the R3D file at `path`. A file that does not start
+/// with a RED header atom fails with `InvalidData`.
+pub fn read(path: &Path) -> io::Result<Header> {
+    let mut bytes = Vec::with_capacity(RED1_LEN);
+    trace::call("read", path, || {
+        File::open(path)?
+            .take(RED1_LEN as u64)
+            .read_to_end(&mut bytes)
+    })?;
+    parse(&bytes)
+}
+
+/// Reads the header like [`read`], and with `exiftool` fills in what the
+/// parser could not decode, such as RED2 headers, from ExifTool. ExifTool
+/// is skipped quietly when it is not installed.
+pub fn read_metadata(path: &Path, exiftool: bool) -> io::Result<Header> {
+    let native = read(path);
+    if !exiftool || native.as_ref().is_ok_and(|header| header.details.is_some()) {
+        return native;
+    }
+    match exiftool::details(path) {
+        Some(details) => Ok(Header {
+            format: native.map_or("unknown", |header| header.format),
+            details: Some(details),
+        }),
+        None => native,
+    }
+}
+
+/// Checks that `path` starts with a RED header, as `--check-headers` does
+/// before renaming a file to or from `.R3D`.
+pub fn check(path: &Path) -> io::Result<()> {
+    read(path).map(|_| ())
+}
+
+fn parse(bytes: &[u8]) -> io::Result<Header> {
+    let format = match bytes.get(4..8) {
+        Some(b"RED1") => "RED1",
+        Some(b"RED2") => "RED2",
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an R3D file: no RED header at the start",
+            ));
+        }
+    };
+    let atom_len = u32_at(bytes, 0) as usize;
+    let details =
+        (format == "RED1" && atom_len >= RED1_LEN && bytes.len() >= RED1_LEN).then(|| {
+            let name = &bytes[67..RED1_LEN];
+            let end = name
+                .iter()
+                .position(|byte| *byte == 0)
+                .unwrap_or(name.len());
+            Details {
+                version: (bytes[8], bytes[9]),
+                width: u32_at(bytes, 52),
+                height: u32_at(bytes, 56),
+                frame_rate: (u16_at(bytes, 62), u16_at(bytes, 64)),
+                file_name: String::from_utf8_lossy(&name[..end]).into_owned(),
+                provider: Provider::Native,
+            }
+        });
+    Ok(Header { format, details })
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([bytes[at], bytes[at + 1]])
+}
+
+/// The reel of a clip named the RED way, `A001_C001_0101AB`: the camera
+/// letter and magazine number before the first `_`.
+pub fn reel(clip: &str) -> Option<&str> {
+    let (reel, _) = clip.split_once('_')?;
+    let mut chars = reel.chars();
+    let camera = chars.next()?;
+    let digits = chars.as_str();
+    (camera.is_ascii_alphabetic()
+        && digits.len() == 3
+        && digits.bytes().all(|byte| byte.is_ascii_digit()))
+    .then_some(reel)
+}
+
+/// The month and day a clip named the RED way was recorded, `0101` in
+/// `A001_C001_0101AB`. The year is not part of the name.
+pub fn clip_date(clip: &str) -> Option<&str> {
+    let date = clip.split('_').nth(2)?.get(..4)?;
+    date.bytes()
+        .all(|byte| byte.is_ascii_digit())
+        .then_some(date)
+}
+
+/// A frame rate for display, e.g. `24` or `23.976`.
+pub fn format_frame_rate((numerator, denominator): (u16, u16)) -> Option<String> {
+    if denominator == 0 || numerator == 0 {
+        return None;
+    }
+    if numerator % denominator == 0 {
+        return Some((numerator / denominator).to_string());
+    }
+    let rate = format!("{:.3}", f64::from(numerator) / f64::from(denominator));
+    Some(rate.trim_end_matches('0').to_string())
+}
+
+/// The arguments of `r3dy inspect`.
+#[derive(clap::Args)]
+pub struct InspectArgs {
+    /// Fall back to exiftool, when it is installed, for headers r3dy cannot
+    /// decode, such as RED2 headers; such clips are marked as read by it
+    #[arg(long)]
+    exiftool: bool,
+    /// A folder to inspect the clips under, or one clip (default: the
+    /// current directory)
+    #[arg(value_name = "PATH")]
+    path: Option<PathBuf>,
+}
+
+/// `r3dy inspect [path]`.
+pub fn inspect_command(args: InspectArgs) -> Result<(), String> {
+    let InspectArgs { exiftool, path } = args;
+    let path = path.unwrap_or_else(|| PathBuf::from("."));
+
+    let (root, files) = if path.is_dir() {
+        let files = clip_files(&path)?;
+        (path, files)
+    } else if path.is_file() {
+        let root = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        (root, vec![path])
+    } else {
+        return Err(format!("{} does not exist", path.display()));
+    };
+    if files.is_empty() {
+        println!("No .R3D or .NEV files found under {}", root.display());
+        return Ok(());
+    }
+
+    let mut clips = 0usize;
+    let mut unreadable = 0usize;
+    let mut index = 0;
+    while index < files.len() {
+        let first = &files[index];
+        let key = clip::key(first, false);
+        let segments = files[index..]
+            .iter()
+            .take_while(|path| clip::key(path, false) == key)
+            .count();
+        index += segments;
+
+        if clips + unreadable > 0 {
+            println!();
+        }
+        println!("{}", display_relative(&root, first));
+        match read_metadata(first, exiftool) {
+            Ok(header) => {
+                clips += 1;
+                print_header(&header, first, segments);
+            }
+            Err(err) => {
+                unreadable += 1;
+                println!("  {}", err);
+            }
+        }
+    }
+
+    if unreadable > 0 {
+        return Err(format!(
+            "{} of {} clip{} could not be read as R3D",
+            unreadable,
+            clips + unreadable,
+            if clips + unreadable == 1 { "" } else { "s" }
+        ));
+    }
+    Ok(())
+}
+
+fn print_header(header: &Header, path: &Path, segments: usize) {
+    let clip = header
+        .clip()
+        .or_else(|| clip::name(path).map(|name| name.to_string_lossy().into_owned()));
+    if let Some(clip) = &clip {
+        println!("  Clip:        {}", clip);
+        if let Some(reel) = reel(clip) {
+            println!("  Reel:        {}", reel);
+        }
+    }
+    if segments > 1 {
+        println!("  Segments:    {}", segments);
+    }
+    match &header.details {
+        Some(details) => {
+            println!("  Resolution:  {} x {}", details.width, details.height);
+            if let Some(rate) = format_frame_rate(details.frame_rate) {
+                println!("  Frame rate:  {} fps", rate);
+            }
+            match details.provider {
+                Provider::Native => println!(
+                    "  Format:      {} {}.{}",
+                    header.format, details.version.0, details.version.1
+                ),
+                Provider::ExifTool => {
+                    println!("  Format:      {} (read by exiftool)", header.format)
+                }
+            }
+        }
+        None => println!("  Format:      {} (not decoded yet)", header.format),
+    }
+}
+
+/// Every `.R3D` and `.NEV` file under `root`, in path order.
+fn clip_files(root: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for extension in ["R3D", "NEV"] {
+        let collected = collect_files(
+            root,
+            &[root.to_path_buf()],
+            extension,
+            CompoundRule::Last,
+            &Filter::default(),
+            PathOrder::Path,
+            Walk {
+                sorted: true,
+                ..Walk::default()
+            },
+            spool::DEFAULT_MEMORY_LIMIT,
+        )?;
+        print_warnings(&collected, false);
+        for file in collected.files {
+            files.push(file.map_err(|err| format!("Failed to read back the file list: {}", err))?);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,84 @@
+#[cfg(any(windows, target_os = "macos"))]
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[cfg(any(windows, target_os = "macos"))]
+use crate::trace;
+
+/// What `unlock` cleared on a file, to be put back once it is renamed.
+pub struct Locked {
+    #[cfg(target_os = "macos")]
+    flags: u32,
+}
+
+/// Clears what stops `path` being renamed, for `--chmod-writable`: the
+/// read-only attribute on Windows, and the immutable flag Finder sets on
+/// locked files on macOS. Returns `None` if the file was not read-only.
+/// Elsewhere the permissions of a file do not stop a rename, so there is
+/// nothing to clear.
+#[cfg(windows)]
+pub fn unlock(path: &Path) -> io::Result<Option<Locked>> {
+    let mut permissions = trace::call("stat", path, || fs::metadata(path))?.permissions();
+    if !permissions.readonly() {
+        return Ok(None);
+    }
+    // On Windows this only clears the read-only attribute.
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    trace::call("chmod", path, || fs::set_permissions(path, permissions))?;
+    Ok(Some(Locked {}))
+}
+
+#[cfg(target_os = "macos")]
+pub fn unlock(path: &Path) -> io::Result<Option<Locked>> {
+    use std::os::macos::fs::MetadataExt;
+
+    let flags = trace::call("stat", path, || fs::metadata(path))?.st_flags();
+    if flags & libc::UF_IMMUTABLE == 0 {
+        return Ok(None);
+    }
+    trace::call("chflags", path, || {
+        chflags(path, flags & !libc::UF_IMMUTABLE)
+    })?;
+    Ok(Some(Locked { flags }))
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn unlock(_path: &Path) -> io::Result<Option<Locked>> {
+    Ok(None)
+}
+
+impl Locked {
+    /// Makes the file, now at `path`, read-only again.
+    #[cfg(windows)]
+    pub fn restore(&self, path: &Path) -> io::Result<()> {
+        let mut permissions = trace::call("stat", path, || fs::metadata(path))?.permissions();
+        permissions.set_readonly(true);
+        trace::call("chmod", path, || fs::set_permissions(path, permissions))
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn restore(&self, path: &Path) -> io::Result<()> {
+        trace::call("chflags", path, || chflags(path, self.flags))
+    }
+
+    #[cfg(not(any(windows, target_os = "macos")))]
+    pub fn restore(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn chflags(path: &Path, flags: u32) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a NUL byte"))?;
+    // SAFETY: the path is a valid NUL-terminated string for the call.
+    if unsafe { libc::chflags(path.as_ptr(), flags as _) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
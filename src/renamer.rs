@@ -0,0 +1,624 @@
+use std::fs::{self, Metadata};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use crate::args::RenameArgs;
+use crate::backend::{self, Backend, Conflict};
+use crate::clip;
+use crate::glob::Filter;
+use crate::inuse::Probe;
+use crate::journal::Journal;
+use crate::normalize::Form;
+use crate::preserve::Preserve;
+use crate::r3d;
+use crate::sidecar::Sidecars;
+use crate::spool::PathOrder;
+use crate::{
+    CompoundRule, Config, Outcome, RenameResult, Status, changed, extension_pair, extensions,
+    outcome_of,
+};
+
+/// The renaming engine of the `r3dy` command, for embedding: the same scan,
+/// the same checks and renames, and the same undo journal, without
+/// progress bars, reports or notifications.
+pub struct Renamer {
+    root: PathBuf,
+    from: String,
+    to: String,
+    compound: CompoundRule,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    exclude_dir: Vec<String>,
+    jobs: usize,
+    copy: bool,
+    verify: bool,
+    on_conflict: Conflict,
+    allow_copy: bool,
+    preserve: Preserve,
+    chmod_writable: bool,
+    wait_for_unlock: Option<Duration>,
+    dest: Option<PathBuf>,
+    clip_folders: bool,
+    sidecars: bool,
+    check_headers: bool,
+    template: Option<String>,
+    exiftool: bool,
+    normalize: Option<Form>,
+    sanitize: bool,
+    journal: bool,
+    dry_run: bool,
+}
+
+/// The files a scan found, ready for [`Renamer::execute`].
+pub struct Scan {
+    /// The resolved root the scan started from.
+    pub root: PathBuf,
+    /// Matching files in path order.
+    pub files: Vec<PathBuf>,
+    /// Entries that were skipped while scanning, as human-readable lines.
+    pub warnings: Vec<String>,
+    started: SystemTime,
+    config: Config,
+}
+
+/// The results of [`Renamer::execute`]: an outcome for each scanned file,
+/// and with [`set_sidecars`](Renamer::set_sidecars) for each sidecar that
+/// went along, in the order they were renamed, plus the totals.
+pub struct Execution {
+    pub outcomes: Vec<Outcome>,
+    pub converted: usize,
+    /// Includes files left alone because they changed after the scan or
+    /// another program had them open.
+    pub skipped: usize,
+    pub failed: usize,
+    /// The journal `r3dy undo` reverses the run with, when one was kept.
+    pub journal: Option<PathBuf>,
+    /// Problems that did not stop the run, such as a journal that could
+    /// not be written to partway through.
+    pub warnings: Vec<String>,
+}
+
+impl Renamer {
+    /// Renames `.NEV` to `.R3D` under `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let (from, to) = extensions(false);
+        Self {
+            root: root.into(),
+            from: from.to_string(),
+            to: to.to_string(),
+            compound: CompoundRule::Last,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            exclude_dir: Vec::new(),
+            jobs: 1,
+            copy: false,
+            verify: false,
+            on_conflict: Conflict::Skip,
+            allow_copy: false,
+            preserve: Preserve::default(),
+            chmod_writable: false,
+            wait_for_unlock: None,
+            dest: None,
+            clip_folders: false,
+            sidecars: false,
+            check_headers: false,
+            template: None,
+            exiftool: false,
+            normalize: None,
+            sanitize: false,
+            journal: false,
+            dry_run: false,
+        }
+    }
+
+    /// Renames from `from` to `to` instead, as `--from`/`--to` do; `invert`
+    /// swaps the pair like `--invert`.
+    pub fn set_extensions(&mut self, from: &str, to: &str, invert: bool) -> Result<(), String> {
+        (self.from, self.to) =
+            extension_pair(invert, Some(from.to_string()), Some(to.to_string()))?;
+        Ok(())
+    }
+
+    pub fn set_compound(&mut self, compound: CompoundRule) {
+        self.compound = compound;
+    }
+
+    /// Only renames files matching `pattern`, like `--include`. May be
+    /// called several times.
+    pub fn include(&mut self, pattern: &str) -> Result<(), String> {
+        Filter::default().include(pattern)?;
+        self.include.push(pattern.to_string());
+        Ok(())
+    }
+
+    /// Leaves files matching `pattern` alone, like `--exclude`.
+    pub fn exclude(&mut self, pattern: &str) -> Result<(), String> {
+        Filter::default().exclude(pattern)?;
+        self.exclude.push(pattern.to_string());
+        Ok(())
+    }
+
+    /// Does not scan folders matching `pattern`, like `--exclude-dir`.
+    pub fn exclude_dir(&mut self, pattern: &str) -> Result<(), String> {
+        Filter::default().exclude_dir(pattern)?;
+        self.exclude_dir.push(pattern.to_string());
+        Ok(())
+    }
+
+    /// Keeps up to `jobs` renames in flight at once, like `--jobs`.
+    pub fn set_jobs(&mut self, jobs: usize) {
+        self.jobs = jobs.max(1);
+    }
+
+    /// Copies each file to its new name and leaves the original, like
+    /// `--copy`.
+    pub fn set_copy(&mut self, copy: bool) {
+        self.copy = copy;
+    }
+
+    /// Hashes each file before and after, like `--verify`; a mismatch
+    /// fails the file.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// Decides what happens to a file whose new name is taken, like
+    /// `--on-conflict`. With [`Conflict::Fail`], [`execute`](Self::execute)
+    /// checks every target first and fails before renaming anything if one
+    /// is taken.
+    pub fn set_on_conflict(&mut self, conflict: Conflict) {
+        self.on_conflict = conflict;
+    }
+
+    /// Moves files that cannot be renamed because their target is on
+    /// another volume by copying them, checking the copy and removing the
+    /// original, like `--allow-copy`.
+    pub fn set_allow_copy(&mut self, allow_copy: bool) {
+        self.allow_copy = allow_copy;
+    }
+
+    /// Gives copies their original's modification, access and creation
+    /// times, like `--preserve-times`.
+    pub fn set_preserve_times(&mut self, times: bool) {
+        self.preserve.times = times;
+    }
+
+    /// Sets everything copies keep of their originals, like `--preserve`
+    /// and `--no-zone-identifier` together.
+    pub fn set_preserve(&mut self, preserve: Preserve) {
+        self.preserve = preserve;
+    }
+
+    /// Clears the read-only flag of each file to rename it and sets it
+    /// again afterwards, like `--chmod-writable`.
+    pub fn set_chmod_writable(&mut self, writable: bool) {
+        self.chmod_writable = writable;
+    }
+
+    /// Waits up to `timeout` for a file another program has open, like
+    /// `--wait-for-unlock`. Without it such files are skipped straight
+    /// away.
+    pub fn set_wait_for_unlock(&mut self, timeout: Option<Duration>) {
+        self.wait_for_unlock = timeout;
+    }
+
+    /// Puts the renamed files under `dest`, mirroring their folders under
+    /// the root, like `--dest`. Missing folders are created as needed.
+    pub fn set_dest(&mut self, dest: impl Into<PathBuf>) {
+        self.dest = Some(dest.into());
+    }
+
+    /// Treats each `.RDC` folder as one clip, renamed all or nothing, like
+    /// `--clip-folders`.
+    pub fn set_clip_folders(&mut self, clip_folders: bool) {
+        self.clip_folders = clip_folders;
+    }
+
+    /// Moves each clip's `.RMD`, `.rtn`, `.xml` and `.cube` files along
+    /// with it, like `--sidecars`. They get outcomes of their own.
+    pub fn set_sidecars(&mut self, sidecars: bool) {
+        self.sidecars = sidecars;
+    }
+
+    /// Fails files that do not start with a RED header instead of renaming
+    /// them, like `--check-headers`.
+    pub fn set_check_headers(&mut self, check_headers: bool) {
+        self.check_headers = check_headers;
+    }
+
+    /// Names the renamed files from their clip metadata, like `--template`,
+    /// e.g. `{reel}_{clip}_{segment}.R3D`. [`execute`](Self::execute) fails
+    /// before renaming anything if the template cannot name every file.
+    pub fn set_template(&mut self, template: &str) {
+        self.template = Some(template.to_string());
+    }
+
+    /// Reads the clip metadata the template needs with ExifTool when r3dy
+    /// cannot decode the header itself, like `--exiftool`.
+    pub fn set_exiftool(&mut self, exiftool: bool) {
+        self.exiftool = exiftool;
+    }
+
+    /// Writes new names composed (`nfc`) or decomposed (`nfd`), like
+    /// `--normalize`.
+    pub fn set_normalize(&mut self, form: &str) -> Result<(), String> {
+        let form = Form::parse(form).ok_or_else(|| format!("{} is not nfc or nfd", form))?;
+        self.normalize = Some(form);
+        Ok(())
+    }
+
+    /// Replaces the characters Windows and exFAT refuse in new names, like
+    /// `--sanitize`.
+    pub fn set_sanitize(&mut self, sanitize: bool) {
+        self.sanitize = sanitize;
+    }
+
+    /// Records the renames for `r3dy undo`, as the command does unless
+    /// given `--no-journal`. Off by default, since a library caller may
+    /// keep its own record.
+    pub fn set_journal(&mut self, journal: bool) {
+        self.journal = journal;
+    }
+
+    /// Predicts the outcomes without renaming anything, like `--dry-run`.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// The settings as the options of `r3dy rename`, so both check them
+    /// and resolve the paths the same way.
+    fn config(&self) -> Result<Config, String> {
+        let options = RenameArgs {
+            paths: vec![self.root.clone()],
+            from: Some(self.from.clone()),
+            to: Some(self.to.clone()),
+            compound: Some(self.compound),
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+            exclude_dir: self.exclude_dir.clone(),
+            jobs: Some(self.jobs),
+            copy: self.copy,
+            verify: self.verify,
+            on_conflict: Some(self.on_conflict),
+            allow_copy: self.allow_copy,
+            preserve: Some(self.preserve),
+            no_zone_identifier: self.preserve.drop_zone_identifier,
+            chmod_writable: self.chmod_writable,
+            wait_for_unlock: self.wait_for_unlock,
+            dest: self.dest.clone(),
+            clip_folders: self.clip_folders,
+            sidecars: self.sidecars,
+            check_headers: self.check_headers,
+            template: self.template.clone(),
+            exiftool: self.exiftool,
+            normalize: self.normalize,
+            sanitize: self.sanitize,
+            no_journal: !self.journal,
+            dry_run: self.dry_run,
+            quiet: true,
+            ..RenameArgs::default()
+        };
+        Config::new(options, None)
+    }
+
+    /// Lists the files to rename under the root, leaving out what its
+    /// `.r3dyignore` files name.
+    pub fn scan(&self) -> Result<Scan, String> {
+        let metadata = fs::metadata(&self.root)
+            .map_err(|err| format!("{} is not accessible: {}", self.root.display(), err))?;
+        if !metadata.is_dir() {
+            return Err(format!("{} is not a directory", self.root.display()));
+        }
+        let config = self.config()?;
+
+        let started = SystemTime::now();
+        let collected = config.collect(PathOrder::Path, config.memory_limit, None)?;
+        let mut warnings = collected.warnings;
+        if collected.suppressed_warnings > 0 {
+            warnings.push(format!(
+                "...and {} more warnings",
+                collected.suppressed_warnings
+            ));
+        }
+        let files = collected
+            .files
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("Failed to read back the file list: {}", err))?;
+
+        Ok(Scan {
+            root: config.root.clone(),
+            files,
+            warnings,
+            started,
+            config,
+        })
+    }
+
+    /// Renames the scanned files as `r3dy rename` would. Files that changed
+    /// since the scan are left alone with the status [`Status::Changed`],
+    /// and files another program has open are skipped. The segments of a
+    /// spanned clip (and with [`set_clip_folders`](Self::set_clip_folders),
+    /// the files of an `.RDC` folder) are renamed all or nothing: when one
+    /// cannot be, the others are renamed back and reported as skipped.
+    pub fn execute(&self, scan: &Scan) -> Result<Execution, String> {
+        let config = &scan.config;
+        let mut named = config.plan_names(&scan.files)?.named;
+        let mut journal = if config.journal && !config.dry_run {
+            Some(Journal::create(
+                &config.root,
+                &config.roots,
+                config.source_extension(),
+                config.target_extension(),
+                config.copy,
+                None,
+            )?)
+        } else {
+            None
+        };
+        let mut engine = Engine::open(config, scan.started)?;
+        let mut execution = Execution {
+            outcomes: Vec::with_capacity(scan.files.len()),
+            converted: 0,
+            skipped: 0,
+            failed: 0,
+            journal: None,
+            warnings: Vec::new(),
+        };
+
+        for chunk in clip::batches(&scan.files, engine.batch_size(), config.clip_folders) {
+            let mut planned = Vec::with_capacity(chunk.len());
+            for path in chunk {
+                let target = named.remove(path).unwrap_or_else(|| config.target_of(path));
+                planned.extend(engine.plan(path.clone(), target));
+            }
+            let mut outcomes: Vec<Outcome> = engine
+                .rename(planned)?
+                .into_iter()
+                .map(|renamed| renamed.outcome)
+                .collect();
+            clip::roll_back_partial(
+                &config.root,
+                &mut outcomes,
+                config.clip_folders,
+                config.copy,
+                config.dry_run,
+            );
+
+            for mut outcome in outcomes {
+                if let Some(warning) = record(config, &mut journal, &mut outcome) {
+                    execution.warnings.push(warning);
+                }
+                match outcome.status {
+                    Status::Converted => execution.converted += 1,
+                    Status::Skipped | Status::Changed => execution.skipped += 1,
+                    Status::Failed | Status::Denied => execution.failed += 1,
+                }
+                execution.outcomes.push(outcome);
+            }
+        }
+
+        execution.journal = journal.and_then(Journal::finish);
+        Ok(execution)
+    }
+}
+
+/// Renames files batch by batch for `r3dy rename` and [`Renamer`]. Each
+/// file is checked once more just before its batch is renamed, so one
+/// that changed or was opened since the scan is left alone.
+pub(crate) struct Engine<'a> {
+    config: &'a Config,
+    backend: Box<dyn Backend>,
+    scan_started: SystemTime,
+    sidecars: Option<Sidecars>,
+    probe: Probe,
+    /// The files waiting to be renamed, in order.
+    batch: Vec<(PathBuf, PathBuf)>,
+}
+
+/// A file of the batch being renamed.
+pub(crate) struct Planned {
+    /// What it was just before it joined the batch, when that could be
+    /// looked up.
+    pub metadata: Option<Metadata>,
+    /// Whether it is a sidecar following its clip.
+    pub sidecar: bool,
+    /// Its outcome when that was settled before renaming (it failed the
+    /// header check, changed since the scan or is in use); None while it
+    /// waits in the batch.
+    pub settled: Option<Outcome>,
+}
+
+/// A file of a batch that has been renamed.
+pub(crate) struct Renamed {
+    pub outcome: Outcome,
+    pub metadata: Option<Metadata>,
+    pub sidecar: bool,
+    /// Whether the backend renamed it, rather than it being settled before.
+    pub attempted: bool,
+    /// Whether it was moved to another volume by copying.
+    pub moved: bool,
+}
+
+impl<'a> Engine<'a> {
+    /// Opens the backend `config` asks for. Files that changed after
+    /// `scan_started` are left alone.
+    pub fn open(config: &'a Config, scan_started: SystemTime) -> Result<Self, String> {
+        let backend = if config.dry_run {
+            backend::dry_run(config.on_conflict)
+        } else {
+            backend::open(
+                config.backend,
+                config.jobs,
+                config.copy,
+                config.verify.then_some(config.verify_hash),
+                config.on_conflict,
+                config.allow_copy,
+                config.preserve,
+                config.chmod_writable,
+            )?
+        };
+        Ok(Self {
+            config,
+            backend,
+            scan_started,
+            sidecars: config.sidecars.then(Sidecars::default),
+            probe: Probe::default(),
+            batch: Vec::new(),
+        })
+    }
+
+    /// How many files to plan before renaming them.
+    pub fn batch_size(&self) -> usize {
+        self.backend.batch_size()
+    }
+
+    /// The files waiting to be renamed and their targets.
+    pub fn batch(&self) -> &[(PathBuf, PathBuf)] {
+        &self.batch
+    }
+
+    /// Adds `path`, to be renamed to `target`, to the batch, followed by
+    /// its sidecars with `--sidecars`. Returns an entry for each, settled
+    /// when it fails `--check-headers`, changed since the scan or is open
+    /// in another program (after `--wait-for-unlock`).
+    pub fn plan(&mut self, path: PathBuf, target: PathBuf) -> Vec<Planned> {
+        let followers = match self.sidecars.as_mut() {
+            Some(sidecars) => sidecars.pairs(&path, &target),
+            None => Vec::new(),
+        };
+        let files = std::iter::once((path, target, false)).chain(
+            followers
+                .into_iter()
+                .map(|(path, target)| (path, target, true)),
+        );
+        let mut planned = Vec::new();
+        for (path, target, sidecar) in files {
+            if self.config.check_headers
+                && !sidecar
+                && let Err(err) = r3d::check(&path)
+            {
+                planned.push(Planned {
+                    metadata: None,
+                    sidecar,
+                    settled: Some(outcome_of(path, target, RenameResult::Failed(err))),
+                });
+                continue;
+            }
+            let stat = changed::stat(&path);
+            let skipped = match changed::since_scan(&stat, self.scan_started) {
+                Some(reason) => Some((Status::Changed, reason.to_string())),
+                None => stat
+                    .as_ref()
+                    .ok()
+                    .and_then(|metadata| match self.config.wait_for_unlock {
+                        Some(timeout) => self.probe.wait(&path, metadata, timeout),
+                        None => self.probe.in_use(&path, metadata),
+                    })
+                    .map(|reason| (Status::Skipped, reason)),
+            };
+            let settled = match skipped {
+                Some((status, reason)) => Some(Outcome::new(path, target, status, Some(reason))),
+                None => {
+                    self.batch.push((path, target));
+                    None
+                }
+            };
+            planned.push(Planned {
+                metadata: stat.ok(),
+                sidecar,
+                settled,
+            });
+        }
+        planned
+    }
+
+    /// Leaves the files waiting in the batch alone, skipped for `reason`.
+    pub fn hold(&mut self, planned: &mut [Planned], reason: &str) {
+        let waiting = planned
+            .iter_mut()
+            .filter(|planned| planned.settled.is_none());
+        for (planned, (path, target)) in waiting.zip(self.batch.drain(..)) {
+            planned.settled = Some(Outcome::new(
+                path,
+                target,
+                Status::Skipped,
+                Some(reason.to_string()),
+            ));
+        }
+    }
+
+    /// Renames the batch `planned` was added to, and returns the outcome of
+    /// each file in order. Partly renamed clips are not rolled back yet.
+    pub fn rename(&mut self, planned: Vec<Planned>) -> Result<Vec<Renamed>, String> {
+        let results = if self.batch.is_empty() {
+            Vec::new()
+        } else {
+            self.backend.rename_batch(&self.batch)
+        };
+        let mut renamed = self.batch.drain(..).zip(results);
+
+        let mut outcomes = Vec::with_capacity(planned.len());
+        for Planned {
+            metadata,
+            sidecar,
+            settled,
+        } in planned
+        {
+            let attempted = settled.is_none();
+            let mut moved = false;
+            let mut outcome = match settled {
+                Some(outcome) => outcome,
+                None => {
+                    let ((path, target), result) = renamed
+                        .next()
+                        .ok_or("Rename backend returned too few results")?;
+                    moved = result.moved();
+                    outcome_of(path, target, result)
+                }
+            };
+            outcome.size = metadata.as_ref().map(Metadata::len);
+            outcomes.push(Renamed {
+                outcome,
+                metadata,
+                sidecar,
+                attempted,
+                moved,
+            });
+        }
+        Ok(outcomes)
+    }
+}
+
+/// Records `outcome` in the run's journal: a rename for `r3dy undo`, a
+/// failure for `r3dy retry`. When a rename cannot be recorded, `--atomic`
+/// puts the file back and fails it, since it could not be rolled back
+/// later; otherwise the journal is dropped and the warning returned.
+pub(crate) fn record(
+    config: &Config,
+    journal: &mut Option<Journal>,
+    outcome: &mut Outcome,
+) -> Option<String> {
+    let open = journal.as_mut()?;
+    if outcome.status == Status::Converted
+        && let Err(err) = open.record(&outcome.source, &outcome.target)
+    {
+        if !config.atomic {
+            *journal = None;
+            return Some(format!(
+                "Warning: {}; the rest of this run cannot be undone",
+                err
+            ));
+        }
+        let err = match backend::revert(&outcome.source, &outcome.target, config.copy) {
+            Ok(()) => err,
+            Err(revert) => format!("{}; rolling it back failed too: {}", err, revert),
+        };
+        outcome.status = Status::Failed;
+        outcome.error = Some(err);
+    }
+    // A failure that cannot be recorded only has to be found by a rescan.
+    if outcome.status.is_failure() {
+        let _ = open.record_failure(&outcome.source, &outcome.target);
+    }
+    None
+}
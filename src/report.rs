@@ -0,0 +1,388 @@
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::{Outcome, Status, Summary, datetime, encode_relative, json, pathenc};
+
+/// Renders one row per file, suitable for attaching to a camera report.
+/// The time column stays empty without `times`, for byte-comparable
+/// reports.
+pub fn to_csv(root: &Path, summary: &Summary, times: bool) -> String {
+    let mut out = String::from("source,target,status,error,card,size,time,xxh64,xxh3\n");
+
+    for outcome in summary.outcomes() {
+        let time = if times {
+            datetime::rfc3339(outcome.time)
+        } else {
+            String::new()
+        };
+        out.push_str(&csv_row(&[
+            &encode_relative(root, &outcome.source),
+            &encode_relative(root, &outcome.target),
+            outcome.status.as_str(),
+            outcome.error.as_deref().unwrap_or(""),
+            &summary.cards.card_name(root, &outcome.source),
+            &outcome
+                .size
+                .map(|size| size.to_string())
+                .unwrap_or_default(),
+            &time,
+            &outcome.xxh64.map(hex).unwrap_or_default(),
+            &outcome.xxh3.map(hex).unwrap_or_default(),
+        ]));
+    }
+
+    out
+}
+
+/// An XXH64 or XXH3 digest as `xxhsum` prints it.
+fn hex(digest: u64) -> String {
+    format!("{:016x}", digest)
+}
+
+/// Renders the converted clips in the column layout Premiere Pro's metadata
+/// import expects. Rows are keyed by the new file name, the card stands in
+/// for the tape name, and the log note records the name the clip had on the
+/// card.
+pub fn to_premiere_csv(root: &Path, summary: &Summary) -> String {
+    let mut out = String::from("Clip Name,Tape Name,Description,Log Note\n");
+
+    let converted = summary
+        .outcomes()
+        .filter(|outcome| outcome.status == Status::Converted);
+    for outcome in converted {
+        let file_name = |path: &Path| {
+            path.file_name()
+                .map(|name| pathenc::encode(Path::new(name)))
+                .unwrap_or_default()
+        };
+
+        out.push_str(&csv_row(&[
+            &file_name(&outcome.target),
+            &summary.cards.card_name(root, &outcome.source),
+            &encode_relative(root, &outcome.target),
+            &format!("Renamed from {} by r3dy", file_name(&outcome.source)),
+        ]));
+    }
+
+    out
+}
+
+/// Renders the summary and every file outcome as a single JSON document.
+/// `generated_at` and the time of each file are left out when None, for
+/// byte-comparable reports.
+pub fn to_json(root: &Path, summary: &Summary, generated_at: Option<SystemTime>) -> String {
+    let mut files = String::new();
+    for outcome in summary.outcomes() {
+        if !files.is_empty() {
+            files.push(',');
+        }
+        let time = match generated_at {
+            Some(_) => json::string(&datetime::rfc3339(outcome.time)),
+            None => "null".to_string(),
+        };
+        let number = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+        files.push_str(&format!(
+            "{{{},\"card\":{},\"size\":{},\"time\":{},\"xxh64\":{},\"xxh3\":{}}}",
+            outcome_fields(root, &outcome),
+            card_json(root, summary, &outcome),
+            number(outcome.size.map(|size| size.to_string())),
+            time,
+            number(outcome.xxh64.map(|digest| json::string(&hex(digest)))),
+            number(outcome.xxh3.map(|digest| json::string(&hex(digest))))
+        ));
+    }
+
+    let generated_at = generated_at
+        .map(|time| {
+            format!(
+                "\"generated_at\":{},",
+                json::string(&datetime::rfc3339(time))
+            )
+        })
+        .unwrap_or_default();
+
+    format!(
+        "{{\"root\":{},{}\"converted\":{},\"skipped\":{},\"failed\":{},\"cards\":[{}],\"files\":[{}]}}\n",
+        json::string(&pathenc::encode(root)),
+        generated_at,
+        summary.converted,
+        summary.skipped,
+        summary.failed,
+        cards_json(root, summary),
+        files
+    )
+}
+
+/// Renders the end-of-run counts plus the failed files as a JSON document.
+pub fn summary_json(root: &Path, summary: &Summary) -> String {
+    format!("{{{}}}\n", summary_fields(root, summary))
+}
+
+fn summary_fields(root: &Path, summary: &Summary) -> String {
+    let failures: Vec<String> = summary
+        .failures()
+        .map(|outcome| file_json(root, summary, &outcome))
+        .collect();
+
+    format!(
+        "\"root\":{},\"converted\":{},\"skipped\":{},\"failed\":{},\"cards\":[{}],\"failures\":[{}]",
+        json::string(&pathenc::encode(root)),
+        summary.converted,
+        summary.skipped,
+        summary.failed,
+        cards_json(root, summary),
+        failures.join(",")
+    )
+}
+
+/// Per-card counts; empty when no cards were detected.
+fn cards_json(root: &Path, summary: &Summary) -> String {
+    if summary.cards.is_empty() {
+        return String::new();
+    }
+
+    let cards: Vec<String> = summary
+        .cards
+        .tallies()
+        .map(|tally| tally.to_json(root))
+        .collect();
+    cards.join(",")
+}
+
+/// Like `outcome_json`, plus the card the file was found on.
+fn file_json(root: &Path, summary: &Summary, outcome: &Outcome) -> String {
+    format!(
+        "{{{},\"card\":{}}}",
+        outcome_fields(root, outcome),
+        card_json(root, summary, outcome)
+    )
+}
+
+/// The card `outcome`'s file was found on, or `null`.
+fn card_json(root: &Path, summary: &Summary, outcome: &Outcome) -> String {
+    let card = summary.cards.card_name(root, &outcome.source);
+    if card.is_empty() {
+        "null".to_string()
+    } else {
+        json::string(&card)
+    }
+}
+
+/// Renders a single file outcome as a JSON object.
+pub fn outcome_json(root: &Path, outcome: &Outcome) -> String {
+    format!("{{{}}}", outcome_fields(root, outcome))
+}
+
+/// `--json` line for a file found by the scan, before it is renamed.
+pub fn discovered_event(root: &Path, path: &Path) -> String {
+    format!(
+        "{{\"event\":\"discovered\",\"source\":{}}}",
+        json::string(&encode_relative(root, path))
+    )
+}
+
+/// `--json` line for a file's outcome: `renamed`, `skipped` or `failed`
+/// (`planned` for a file a dry run would rename), with the detailed status.
+pub fn outcome_event(root: &Path, outcome: &Outcome, dry_run: bool) -> String {
+    format!(
+        "{{\"event\":\"{}\",{}}}",
+        event_name(outcome, dry_run),
+        outcome_fields(root, outcome)
+    )
+}
+
+/// `--porcelain` line for a file's outcome: the `--json` event name, source
+/// and target, separated by tabs. The format is fixed; a new one would come
+/// as `--porcelain=v2`. Tabs and line breaks in names are percent-escaped
+/// along with everything `pathenc` escapes.
+pub fn porcelain_line(root: &Path, outcome: &Outcome, dry_run: bool) -> String {
+    format!(
+        "{}\t{}\t{}",
+        event_name(outcome, dry_run),
+        porcelain_path(root, &outcome.source),
+        porcelain_path(root, &outcome.target)
+    )
+}
+
+fn porcelain_path(root: &Path, path: &Path) -> String {
+    encode_relative(root, path)
+        .replace('\t', "%09")
+        .replace('\n', "%0A")
+        .replace('\r', "%0D")
+}
+
+fn event_name(outcome: &Outcome, dry_run: bool) -> &'static str {
+    match outcome.status {
+        Status::Converted if dry_run => "planned",
+        Status::Converted => "renamed",
+        Status::Skipped | Status::Changed => "skipped",
+        Status::Failed | Status::Denied => "failed",
+    }
+}
+
+/// `--json` line closing the run, with the same fields as `summary_json`.
+pub fn summary_event(root: &Path, summary: &Summary) -> String {
+    format!(
+        "{{\"event\":\"summary\",{}}}",
+        summary_fields(root, summary)
+    )
+}
+
+fn outcome_fields(root: &Path, outcome: &Outcome) -> String {
+    let error = match &outcome.error {
+        Some(error) => json::string(error),
+        None => "null".to_string(),
+    };
+
+    format!(
+        "\"source\":{},\"target\":{},\"status\":{},\"error\":{}",
+        json::string(&encode_relative(root, &outcome.source)),
+        json::string(&encode_relative(root, &outcome.target)),
+        json::string(outcome.status.as_str()),
+        error
+    )
+}
+
+fn csv_row(fields: &[&str]) -> String {
+    let escaped: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+    format!("{}\n", escaped.join(","))
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Whether a status read back from a saved report is a failure of any kind.
+pub fn is_failure(status: &str) -> bool {
+    status == "failed" || status == "denied"
+}
+
+/// A single file row read back from a saved CSV or JSON report.
+pub struct Entry {
+    pub source: String,
+    pub target: String,
+    pub status: String,
+    pub error: String,
+}
+
+/// Loads a report previously written by r3dy, detecting CSV or JSON by content.
+pub fn load(path: &Path) -> Result<Vec<Entry>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    parse(&text).map_err(|err| format!("{} {}", path.display(), err))
+}
+
+/// Parses the text of a CSV or JSON report, as `load` does.
+pub fn parse(text: &str) -> Result<Vec<Entry>, String> {
+    let parsed = if text.trim_start().starts_with('{') {
+        parse_json_report(text)
+    } else {
+        parse_csv_report(text)
+    };
+    parsed.map_err(|err| format!("is not an r3dy report: {}", err))
+}
+
+fn parse_json_report(text: &str) -> Result<Vec<Entry>, String> {
+    let document = json::parse(text)?;
+    let files = document
+        .get("files")
+        .and_then(json::Value::as_array)
+        .ok_or_else(|| "missing \"files\" array".to_string())?;
+
+    files
+        .iter()
+        .map(|file| {
+            let field = |name: &str| {
+                file.get(name)
+                    .and_then(json::Value::as_str)
+                    .unwrap_or_default()
+                    .to_string()
+            };
+
+            let source = field("source");
+            if source.is_empty() {
+                return Err("file entry without a source".to_string());
+            }
+
+            Ok(Entry {
+                source,
+                target: field("target"),
+                status: field("status"),
+                error: field("error"),
+            })
+        })
+        .collect()
+}
+
+fn parse_csv_report(text: &str) -> Result<Vec<Entry>, String> {
+    let mut rows = parse_csv(text).into_iter();
+    let header = rows.next().ok_or_else(|| "empty file".to_string())?;
+
+    let column = |name: &str| header.iter().position(|field| field == name);
+    let source = column("source").ok_or_else(|| "missing source column".to_string())?;
+    let status = column("status").ok_or_else(|| "missing status column".to_string())?;
+    let target = column("target");
+    let error = column("error");
+
+    let cell = |row: &[String], index: Option<usize>| {
+        index
+            .and_then(|index| row.get(index))
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    Ok(rows
+        .filter(|row| row.iter().any(|field| !field.is_empty()))
+        .map(|row| Entry {
+            source: cell(&row, Some(source)),
+            target: cell(&row, target),
+            status: cell(&row, Some(status)),
+            error: cell(&row, error),
+        })
+        .collect())
+}
+
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if in_quotes {
+            match ch {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => in_quotes = false,
+                _ => field.push(ch),
+            }
+            continue;
+        }
+
+        match ch {
+            '"' => in_quotes = true,
+            ',' => row.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(ch),
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows
+}
@@ -0,0 +1,61 @@
+use std::path::Path;
+
+use crate::{Status, Summary, json};
+
+/// Renders a Python script for DaVinci Resolve's scripting API that imports
+/// the converted clips into the Media Pool bin `bin` (`/`-separated, created
+/// as needed) of the current project. It runs from Resolve's own console or
+/// with the `DaVinciResolveScript` module on the Python path.
+pub fn render(summary: &Summary, bin: &str) -> String {
+    let clips: Vec<String> = summary
+        .outcomes()
+        .filter(|outcome| outcome.status == Status::Converted)
+        .map(|outcome| format!("    {},\n", json::string(&outcome.target.to_string_lossy())))
+        .collect();
+    let bin: Vec<String> = bin
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .map(json::string)
+        .collect();
+
+    format!(
+        r#"#!/usr/bin/env python3
+# Written by r3dy: imports the clips renamed in this run into a Media Pool bin.
+
+BIN = [{bin}]
+CLIPS = [
+{clips}]
+
+resolve = globals().get("resolve")
+if resolve is None:
+    import DaVinciResolveScript
+
+    resolve = DaVinciResolveScript.scriptapp("Resolve")
+if resolve is None:
+    raise SystemExit("DaVinci Resolve is not running or external scripting is disabled")
+
+project = resolve.GetProjectManager().GetCurrentProject()
+if project is None:
+    raise SystemExit("Open a project in DaVinci Resolve first")
+
+media_pool = project.GetMediaPool()
+folder = media_pool.GetRootFolder()
+for name in BIN:
+    existing = [sub for sub in folder.GetSubFolderList() if sub.GetName() == name]
+    folder = existing[0] if existing else media_pool.AddSubFolder(folder, name)
+media_pool.SetCurrentFolder(folder)
+
+imported = media_pool.ImportMedia(CLIPS) if CLIPS else []
+print("Imported {{}} of {{}} clips into {{}}".format(len(imported or []), len(CLIPS), "/".join(BIN) or "Master"))
+"#,
+        bin = bin.join(", "),
+        clips = clips.concat(),
+    )
+}
+
+/// The bin used when `--resolve-bin` is not given: the scanned folder's name.
+pub fn default_bin(root: &Path) -> String {
+    root.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "r3dy".to_string())
+}
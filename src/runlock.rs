@@ -0,0 +1,322 @@
+use std::env;
+use std::fs::{self, File, TryLockError};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use xxhash_rust::xxh64::xxh64;
+
+use crate::mhl::hostname;
+use crate::{datetime, pathenc, trace};
+
+/// The name of the lock file a run leaves in the root while it works.
+pub const FILE_NAME: &str = ".r3dy.lock";
+
+/// Keeps other r3dy runs off the trees of a run until dropped, when the
+/// lock files are removed.
+pub struct RunLock {
+    _held: Vec<Held>,
+}
+
+/// One lock file. It says which run holds it, and is also locked through
+/// the OS, so a run that died without removing it is told apart on the
+/// same machine.
+struct Held {
+    path: PathBuf,
+    _file: File,
+}
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        let _ = trace::call("unlink", &self.path, || fs::remove_file(&self.path));
+    }
+}
+
+/// The run named in a lock file.
+struct Holder {
+    pid: String,
+    host: String,
+    started: String,
+    /// The folder it locks, `pathenc`-encoded; missing from locks whose
+    /// file sits in that folder.
+    root: String,
+}
+
+/// Takes the lock on each of `roots` for this run: `.r3dy.lock` in the
+/// root, so runs on other machines sharing the volume see it too, or a file
+/// in the temp directory keyed by the root's path when the root cannot be
+/// written to. Fails if another run holds one of them, or works on a folder
+/// above one of the roots; runs on folders below them are found by
+/// `check_below` as the tree is scanned. A lock left by a run on this
+/// machine that has ended is taken over; one left on another machine is
+/// only broken with `force`, for `--force-lock`, which also skips the
+/// checks above and below.
+pub fn acquire(roots: &[PathBuf], force: bool) -> Result<RunLock, String> {
+    let canonical: Vec<PathBuf> = roots
+        .iter()
+        .map(|root| fs::canonicalize(root).unwrap_or_else(|_| root.clone()))
+        .collect();
+    if !force {
+        for canonical in &canonical {
+            check_above(canonical)?;
+        }
+        check_elsewhere(&canonical)?;
+    }
+    let mut held = Vec::with_capacity(roots.len());
+    for (root, canonical) in roots.iter().zip(&canonical) {
+        held.push(acquire_one(root, canonical, force)?);
+    }
+    Ok(RunLock { _held: held })
+}
+
+/// Fails if a live run holds any of the lock files the scan found in
+/// folders below the roots, unless `--force-lock` says to go ahead.
+pub fn check_below(locks: &[PathBuf], force: bool) -> Result<(), String> {
+    if force {
+        return Ok(());
+    }
+    for path in locks {
+        if let Some(holder) = live(path) {
+            let dir = path.parent().unwrap_or(path);
+            return Err(held(dir, holder.as_ref()));
+        }
+    }
+    Ok(())
+}
+
+/// Fails if a live run holds the lock of a folder above `canonical`.
+fn check_above(canonical: &Path) -> Result<(), String> {
+    for dir in canonical.ancestors().skip(1) {
+        for path in [dir.join(FILE_NAME), in_temp(dir)] {
+            if let Some(holder) = live(&path) {
+                return Err(held(dir, holder.as_ref()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fails if a live run holds a lock in the temp directory on a folder
+/// below one of `roots`, as those are not in the tree for the scan to find.
+fn check_elsewhere(roots: &[PathBuf]) -> Result<(), String> {
+    let Ok(entries) = fs::read_dir(env::temp_dir()) else {
+        return Ok(());
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if !(name.starts_with("r3dy-") && name.ends_with(".lock")) {
+            continue;
+        }
+        let path = entry.path();
+        let Ok(mut file) = File::open(&path) else {
+            continue;
+        };
+        let Some(locked) = read_holder(&mut file)
+            .and_then(|holder| pathenc::decode(&holder.root).ok())
+        else {
+            continue;
+        };
+        if roots
+            .iter()
+            .any(|root| locked != *root && locked.starts_with(root))
+            && let Some(holder) = live(&path)
+        {
+            return Err(held(&locked, holder.as_ref()));
+        }
+    }
+    Ok(())
+}
+
+/// Where the lock on `canonical` goes when the folder cannot be written to.
+fn in_temp(canonical: &Path) -> PathBuf {
+    let hash = xxh64(canonical.as_os_str().as_encoded_bytes(), 0);
+    env::temp_dir().join(format!("r3dy-{:016x}.lock", hash))
+}
+
+fn acquire_one(root: &Path, canonical: &Path, force: bool) -> Result<Held, String> {
+    let in_root = root.join(FILE_NAME);
+    match take(&in_root, root, canonical, force) {
+        Err(Fallback::Unwritable) => {}
+        Err(Fallback::Failed(err)) => return Err(err),
+        Ok(lock) => return Ok(lock),
+    }
+    let elsewhere = in_temp(canonical);
+    take(&elsewhere, root, canonical, force).map_err(|err| match err {
+        Fallback::Unwritable => format!(
+            "Failed to create the lock file {}: permission denied",
+            elsewhere.display()
+        ),
+        Fallback::Failed(err) => err,
+    })
+}
+
+enum Fallback {
+    /// The lock file cannot be created here; try elsewhere.
+    Unwritable,
+    Failed(String),
+}
+
+fn take(path: &Path, root: &Path, canonical: &Path, force: bool) -> Result<Held, Fallback> {
+    // Another run may remove the file between our open and our lock, so the
+    // lock only counts if the path still names the file that was locked.
+    for _ in 0..3 {
+        let mut file = match trace::call("open", path, || {
+            File::options()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+        }) {
+            Ok(file) => file,
+            // A lock file this user cannot open is still another run's.
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem
+                ) && fs::symlink_metadata(path).is_err() =>
+            {
+                return Err(Fallback::Unwritable);
+            }
+            Err(err) => {
+                return Err(Fallback::Failed(format!(
+                    "Failed to create the lock file {}: {}",
+                    path.display(),
+                    err
+                )));
+            }
+        };
+
+        match file.try_lock() {
+            Ok(()) => {
+                if !still_named(&file, path) {
+                    continue;
+                }
+                // Locked, so no run on this machine holds it; one elsewhere
+                // still may, as OS locks do not reach every shared volume.
+                if let Some(holder) = read_holder(&mut file)
+                    && holder.host != hostname()
+                    && !force
+                {
+                    return Err(Fallback::Failed(held(root, Some(&holder))));
+                }
+            }
+            Err(TryLockError::WouldBlock) => {
+                if !force {
+                    return Err(Fallback::Failed(held(
+                        root,
+                        read_holder(&mut file).as_ref(),
+                    )));
+                }
+                drop(file);
+                let _ = trace::call("unlink", path, || fs::remove_file(path));
+                continue;
+            }
+            // The volume does not lock files; the contents are all there is.
+            Err(TryLockError::Error(_)) => {
+                if let Some(holder) = read_holder(&mut file)
+                    && !force
+                {
+                    return Err(Fallback::Failed(held(root, Some(&holder))));
+                }
+            }
+        }
+
+        let written = file
+            .set_len(0)
+            .and_then(|()| file.rewind())
+            .and_then(|()| {
+                write!(
+                    file,
+                    "pid {}\nhost {}\nstarted {}\nroot {}\n",
+                    std::process::id(),
+                    hostname(),
+                    datetime::rfc3339(SystemTime::now()),
+                    pathenc::encode(canonical)
+                )
+            })
+            .and_then(|()| file.flush());
+        if let Err(err) = written {
+            return Err(Fallback::Failed(format!(
+                "Failed to write the lock file {}: {}",
+                path.display(),
+                err
+            )));
+        }
+        return Ok(Held {
+            path: path.to_path_buf(),
+            _file: file,
+        });
+    }
+    Err(Fallback::Failed(format!(
+        "Failed to take the lock on {}: other runs kept replacing {}",
+        root.display(),
+        path.display()
+    )))
+}
+
+fn held(root: &Path, holder: Option<&Holder>) -> String {
+    let by = match holder {
+        Some(holder) => format!(
+            " (pid {} on {}, started {})",
+            holder.pid, holder.host, holder.started
+        ),
+        None => String::new(),
+    };
+    format!(
+        "Another r3dy run is working on {}{}; wait for it to finish, or add --force-lock if it is no longer running",
+        root.display(),
+        by
+    )
+}
+
+fn read_holder(file: &mut File) -> Option<Holder> {
+    let mut text = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut text).ok()?;
+    let field = |key: &str| {
+        text.lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix(' '))
+            .map(str::to_string)
+    };
+    Some(Holder {
+        pid: field("pid")?,
+        host: field("host").unwrap_or_default(),
+        started: field("started").unwrap_or_default(),
+        root: field("root").unwrap_or_default(),
+    })
+}
+
+/// Whether another run is still working under the lock file at `path`,
+/// and which, if the file says. Like `take`, a lock from this machine that
+/// the OS no longer holds is from a run that has ended.
+fn live(path: &Path) -> Option<Option<Holder>> {
+    let mut file = File::open(path).ok()?;
+    match file.try_lock_shared() {
+        Ok(()) => {
+            let holder = read_holder(&mut file)?;
+            (holder.host != hostname()).then_some(Some(holder))
+        }
+        Err(TryLockError::WouldBlock) => Some(read_holder(&mut file)),
+        Err(TryLockError::Error(_)) => read_holder(&mut file).map(Some),
+    }
+}
+
+/// Whether `path` still names the open `file`.
+#[cfg(unix)]
+fn still_named(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(open), Ok(named)) => open.dev() == named.dev() && open.ino() == named.ino(),
+        _ => false,
+    }
+}
+
+/// Without inode numbers to compare, a lock on a file another run has just
+/// removed is trusted.
+#[cfg(not(unix))]
+fn still_named(_file: &File, _path: &Path) -> bool {
+    true
+}
@@ -0,0 +1,126 @@
+use std::path::Path;
+
+/// Confines the rest of the process, and anything it starts, to `dirs` with
+/// Landlock: files elsewhere can no longer be opened, created, removed or
+/// executed. Descriptors that are already open (reports, manifests, traces)
+/// keep working. Fails when the kernel does not support Landlock rather
+/// than carrying on unconfined.
+#[cfg(target_os = "linux")]
+pub fn confine(dirs: &[&Path]) -> Result<(), String> {
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::fs::OpenOptionsExt;
+
+    const CREATE_RULESET_VERSION: libc::c_uint = 1;
+    const RULE_PATH_BENEATH: libc::c_int = 1;
+    // Every filesystem right of Landlock ABI 1 (execute .. make_sym).
+    const ACCESS_ABI_1: u64 = (1 << 13) - 1;
+    const ACCESS_REFER: u64 = 1 << 13;
+    const ACCESS_TRUNCATE: u64 = 1 << 14;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    let unavailable = |err: io::Error| format!("Landlock is not available on this kernel: {}", err);
+
+    // SAFETY: a null attribute with the version flag only queries the ABI.
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0usize,
+            CREATE_RULESET_VERSION,
+        )
+    };
+    if abi < 0 {
+        return Err(unavailable(io::Error::last_os_error()));
+    }
+
+    let mut access = ACCESS_ABI_1;
+    if abi >= 2 {
+        access |= ACCESS_REFER;
+    }
+    if abi >= 3 {
+        access |= ACCESS_TRUNCATE;
+    }
+
+    let attr = RulesetAttr {
+        handled_access_fs: access,
+    };
+    // SAFETY: attr is a valid ruleset attribute of the size passed.
+    let ruleset = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            std::mem::size_of::<RulesetAttr>(),
+            0u32,
+        )
+    };
+    if ruleset < 0 {
+        return Err(unavailable(io::Error::last_os_error()));
+    }
+    // SAFETY: landlock_create_ruleset returned a fresh descriptor we now own.
+    let ruleset = unsafe { OwnedFd::from_raw_fd(ruleset as i32) };
+
+    for dir in dirs {
+        let handle = File::options()
+            .read(true)
+            .custom_flags(libc::O_PATH)
+            .open(dir)
+            .map_err(|err| format!("Failed to open {} for the sandbox: {}", dir.display(), err))?;
+        let rule = PathBeneathAttr {
+            allowed_access: access,
+            parent_fd: handle.as_raw_fd(),
+        };
+        // SAFETY: both descriptors are open and rule is a valid path-beneath
+        // attribute.
+        let added = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                RULE_PATH_BENEATH,
+                &rule as *const PathBeneathAttr,
+                0u32,
+            )
+        };
+        if added < 0 {
+            return Err(format!(
+                "Failed to allow {} in the sandbox: {}",
+                dir.display(),
+                io::Error::last_os_error()
+            ));
+        }
+    }
+
+    // SAFETY: plain prctl and syscall on our own ruleset descriptor.
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(format!(
+                "Failed to enter the sandbox: {}",
+                io::Error::last_os_error()
+            ));
+        }
+        if libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0u32) != 0 {
+            return Err(format!(
+                "Failed to enter the sandbox: {}",
+                io::Error::last_os_error()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn confine(_dirs: &[&Path]) -> Result<(), String> {
+    Err("--sandbox is only supported on Linux".to_string())
+}
@@ -0,0 +1,68 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Component, Path, PathBuf};
+
+/// What replaces each character a name cannot hold.
+const REPLACEMENT: char = '_';
+
+/// Characters Windows refuses in names, and so do NTFS and exFAT volumes
+/// wherever they are mounted; `/` is left out, as it never gets this far.
+const INVALID: &[char] = &['<', '>', ':', '"', '\\', '|', '?', '*'];
+
+/// Device names Windows reserves whatever the extension: `CON.R3D` cannot
+/// be opened either.
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// `name` made valid on Windows, NTFS and exFAT, for `--sanitize`, or `None`
+/// if it is already. Invalid and control characters become `_`, trailing
+/// dots and spaces (which Windows drops) are removed, and a reserved device
+/// name gets a `_`. Names that are not valid UTF-8 are left as they are.
+pub fn name(name: &OsStr) -> Option<OsString> {
+    let text = name.to_str()?;
+    let mut clean: String = text
+        .chars()
+        .map(|ch| {
+            if INVALID.contains(&ch) || ch.is_control() {
+                REPLACEMENT
+            } else {
+                ch
+            }
+        })
+        .collect();
+    clean.truncate(clean.trim_end_matches(['.', ' ']).len());
+    if clean.is_empty() {
+        clean.push(REPLACEMENT);
+    }
+
+    let base = clean.split('.').next().unwrap_or_default();
+    if RESERVED
+        .iter()
+        .any(|reserved| base.trim_end().eq_ignore_ascii_case(reserved))
+    {
+        clean.insert(base.len(), REPLACEMENT);
+    }
+
+    (clean != text).then(|| OsString::from(clean))
+}
+
+/// `relative` with every folder and file name made valid as by `name`, or
+/// `None` if all of them are already.
+pub fn path(relative: &Path) -> Option<PathBuf> {
+    let mut changed = false;
+    let clean = relative
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => match name(part) {
+                Some(clean) => {
+                    changed = true;
+                    clean
+                }
+                None => part.to_os_string(),
+            },
+            other => other.as_os_str().to_os_string(),
+        })
+        .collect();
+    changed.then_some(clean)
+}
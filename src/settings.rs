@@ -0,0 +1,479 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Args};
+
+use crate::args::RenameArgs;
+
+const FILE_NAME: &str = "config.toml";
+const PROJECT_FILE_NAME: &str = ".r3dy.toml";
+
+/// Puts the defaults from the config files in front of `args`, as the flags
+/// they stand for. The user's `config.toml` comes first and the nearest
+/// `.r3dy.toml` at or above the current directory second, then the
+/// `[profile.NAME]` tables of both for `--profile NAME`; each replaces what
+/// came before it for the same flag, or a flag it conflicts with, and
+/// anything given on the command line replaces them all. Repeatable flags
+/// add up instead. `--no-config` skips the files.
+///
+/// The keys are the options of `r3dy rename`; `A` are the options of the
+/// command being run, and settings for options it does not have are left
+/// out, so `r3dy archive` takes the scan settings and ignores the rest.
+pub fn with_defaults<A: Args>(args: Vec<String>) -> Result<Vec<String>, String> {
+    let keys = options::<RenameArgs>();
+    let command = options::<A>();
+    // Errors are reported by the parse after the files are applied.
+    let Ok(matches) = command
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches_from(iter::once("r3dy").chain(args.iter().map(String::as_str)))
+    else {
+        return Ok(args);
+    };
+    if matches.get_flag("no_config") {
+        return Ok(args);
+    }
+    let profile = matches.get_one::<String>("profile").cloned();
+
+    let mut settings = Vec::new();
+    let mut profiled = Vec::new();
+    let mut found = false;
+    let files = user_file()
+        .into_iter()
+        .chain(env::current_dir().ok().and_then(|cwd| project_file(&cwd)));
+    for file in files {
+        let text = match fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(format!("Failed to read {}: {}", file.display(), err)),
+        };
+        let file_settings = parse(&keys, &text, profile.as_deref())
+            .map_err(|err| format!("{}: {}", file.display(), err))?;
+        let applies = |setting: &Setting| arg(&command, &setting.id).is_some();
+        for setting in file_settings.flags.into_iter().filter(applies) {
+            apply(&command, &mut settings, setting);
+        }
+        if let Some(flags) = file_settings.profile {
+            found = true;
+            profiled.extend(flags.into_iter().filter(applies));
+        }
+    }
+    if let Some(profile) = &profile
+        && !found
+    {
+        return Err(format!(
+            "No [profile.{}] in config.toml or .r3dy.toml",
+            profile
+        ));
+    }
+    for setting in profiled {
+        apply(&command, &mut settings, setting);
+    }
+
+    let given = given(&command, &matches);
+    let mut defaults: Vec<String> = settings
+        .into_iter()
+        .filter(|setting| {
+            !given.iter().any(|id| {
+                (id == &setting.id && !repeats(&command, id))
+                    || conflicting(&command, id, &setting.id)
+            })
+        })
+        .flat_map(|setting| setting.flags)
+        .collect();
+    defaults.extend(args);
+    Ok(defaults)
+}
+
+/// The options in `A` as a command, to look settings up in.
+fn options<A: Args>() -> clap::Command {
+    let mut command = A::augment_args(clap::Command::new("r3dy"));
+    command.build();
+    command
+}
+
+/// The option of `command` with the id `id`.
+fn arg<'a>(command: &'a clap::Command, id: &str) -> Option<&'a clap::Arg> {
+    command.get_arguments().find(|arg| arg.get_id() == id)
+}
+
+/// Adds `setting` after those of earlier files and tables, dropping theirs
+/// for the same flag, unless it repeats, and for flags it conflicts with.
+/// A `false` drops the flag and adds nothing.
+fn apply(command: &clap::Command, settings: &mut Vec<Setting>, setting: Setting) {
+    let off = setting.flags.is_empty();
+    settings.retain(|earlier| {
+        if earlier.id == setting.id {
+            !off && repeats(command, &setting.id)
+        } else {
+            off || !conflicting(command, &earlier.id, &setting.id)
+        }
+    });
+    if !off {
+        settings.push(setting);
+    }
+}
+
+/// The ids of the options given on the command line.
+fn given(command: &clap::Command, matches: &ArgMatches) -> Vec<String> {
+    command
+        .get_arguments()
+        .map(|arg| arg.get_id().as_str())
+        .filter(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+        .map(String::from)
+        .collect()
+}
+
+/// Whether the option adds up when repeated, like `--exclude`.
+fn repeats(command: &clap::Command, id: &str) -> bool {
+    command
+        .get_arguments()
+        .find(|arg| arg.get_id() == id)
+        .is_some_and(|arg| matches!(arg.get_action(), ArgAction::Append))
+}
+
+/// Whether the options `a` and `b` cannot be given together.
+fn conflicting(command: &clap::Command, a: &str, b: &str) -> bool {
+    let conflicts = |a: &str, b: &str| {
+        command
+            .get_arguments()
+            .find(|arg| arg.get_id() == a)
+            .is_some_and(|arg| {
+                command
+                    .get_arg_conflicts_with(arg)
+                    .iter()
+                    .any(|other| other.get_id() == b)
+            })
+    };
+    conflicts(a, b) || conflicts(b, a)
+}
+
+/// `config.toml` in the user's config directory: `$XDG_CONFIG_HOME/r3dy`
+/// or `~/.config/r3dy`, or `%APPDATA%\r3dy` on Windows.
+fn user_file() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        PathBuf::from(env::var_os("APPDATA")?)
+    } else {
+        match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        }
+    };
+    Some(dir.join("r3dy").join(FILE_NAME))
+}
+
+fn project_file(cwd: &Path) -> Option<PathBuf> {
+    cwd.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE_NAME))
+        .find(|file| file.is_file())
+}
+
+/// The flags one config file stands for.
+struct Settings {
+    /// From the top-level keys.
+    flags: Vec<Setting>,
+    /// From the selected profile's table, if the file has one.
+    profile: Option<Vec<Setting>>,
+}
+
+/// The flags one key stands for.
+struct Setting {
+    /// The id of the option the key names.
+    id: String,
+    /// None for `false`.
+    flags: Vec<String>,
+}
+
+/// Turns `key = value` lines into flags: `jobs = 4` is `--jobs 4`,
+/// `verify = true` is `--verify`, `verify = false` turns it off and
+/// `exclude = ["a", "b"]` repeats the flag. Keys are the long flags of
+/// `command`, with `_` or `-`; a flag takes only `true` or `false`, and an
+/// option only a value. Keys under a `[profile.NAME]` header count
+/// only for that profile, but are checked either way.
+fn parse(command: &clap::Command, text: &str, profile: Option<&str>) -> Result<Settings, String> {
+    let mut settings = Settings {
+        flags: Vec::new(),
+        profile: None,
+    };
+    // The table the lines belong to: `None` at the top level, otherwise
+    // whether it is the selected profile.
+    let mut table: Option<bool> = None;
+    for (index, line) in text.lines().enumerate() {
+        let at = |err: String| format!("line {}: {}", index + 1, err);
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let name = header
+                .strip_suffix(']')
+                .and_then(|header| header.trim().strip_prefix("profile."))
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .ok_or_else(|| {
+                    at(format!(
+                        "unsupported table {}; only [profile.NAME] tables are read",
+                        line
+                    ))
+                })?;
+            let selected = profile == Some(name);
+            if selected {
+                settings.profile.get_or_insert_with(Vec::new);
+            }
+            table = Some(selected);
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| at(format!("expected key = value, found {}", line)))?;
+        let long = key.trim().replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .filter(|arg| !matches!(arg.get_id().as_str(), "help" | "no_config" | "profile"))
+            .ok_or_else(|| at(format!("unknown setting {}", key.trim())))?;
+        let id = arg.get_id().to_string();
+        let flag = format!("--{}", long);
+        let mut ignored = Vec::new();
+        let flags = match table {
+            None => &mut settings.flags,
+            Some(true) => settings.profile.get_or_insert_with(Vec::new),
+            Some(false) => &mut ignored,
+        };
+
+        let mut setting = Setting {
+            id,
+            flags: Vec::new(),
+        };
+        let value = parse_value(value.trim()).map_err(at)?;
+        match (&value, arg.get_action().takes_values()) {
+            (Value::Bool(_), true) => {
+                return Err(at(format!(
+                    "{} takes a value, not true or false",
+                    key.trim()
+                )));
+            }
+            (Value::Text(_) | Value::List(_), false) => {
+                return Err(at(format!("{} is true or false", key.trim())));
+            }
+            _ => {}
+        }
+        match value {
+            Value::Bool(true) => setting.flags.push(flag),
+            Value::Bool(false) => {}
+            Value::List(items) => {
+                for item in items {
+                    setting.flags.push(flag.clone());
+                    setting.flags.push(item);
+                }
+            }
+            Value::Text(text) => {
+                setting.flags.push(flag);
+                setting.flags.push(text);
+            }
+        }
+        flags.push(setting);
+    }
+    Ok(settings)
+}
+
+enum Value {
+    Bool(bool),
+    Text(String),
+    List(Vec<String>),
+}
+
+/// A TOML string, integer, float, boolean or single-line array of those.
+fn parse_value(value: &str) -> Result<Value, String> {
+    if let Some(inner) = value.strip_prefix('[') {
+        let inner = inner
+            .strip_suffix(']')
+            .ok_or("arrays must close on the same line")?;
+        let mut items = Vec::new();
+        let mut rest = inner.trim();
+        while !rest.is_empty() {
+            let (item, after) = scalar(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            rest = match rest.strip_prefix(',') {
+                Some(after) => after.trim_start(),
+                None if rest.is_empty() => rest,
+                None => return Err(format!("expected , in array, found {}", rest)),
+            };
+        }
+        return Ok(Value::List(items));
+    }
+    match value {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => {}
+    }
+    let (text, rest) = scalar(value)?;
+    if !rest.trim().is_empty() {
+        return Err(format!("unexpected {} after the value", rest.trim()));
+    }
+    Ok(Value::Text(text))
+}
+
+/// Reads one string or number from the start of `text`, returning it and
+/// what follows.
+fn scalar(text: &str) -> Result<(String, &str), String> {
+    if let Some(rest) = text.strip_prefix('\'') {
+        let end = rest.find('\'').ok_or("unclosed string")?;
+        return Ok((rest[..end].to_string(), &rest[end + 1..]));
+    }
+    if let Some(rest) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((at, c)) = chars.next() {
+            match c {
+                '"' => return Ok((value, &rest[at + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some(other) => return Err(format!("unsupported escape \\{}", other)),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        return Err("unclosed string".to_string());
+    }
+    let end = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '+' | '-')))
+        .unwrap_or(text.len());
+    let number = &text[..end];
+    if number.is_empty() || number.replace('_', "").parse::<f64>().is_err() {
+        return Err(format!(
+            "{} is not a value; quote strings, e.g. \"{}\"",
+            text, text
+        ));
+    }
+    Ok((number.replace('_', ""), &text[end..]))
+}
+
+/// `line` without a `#` comment, leaving `#` inside strings alone.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (at, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(open), c) if c == open && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..at],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(text: &str) -> Result<Vec<String>, String> {
+        let settings = parse(&options::<RenameArgs>(), text, None)?;
+        Ok(settings
+            .flags
+            .into_iter()
+            .flat_map(|setting| setting.flags)
+            .collect())
+    }
+
+    #[test]
+    fn reads_quoted_values() {
+        assert_eq!(
+            flags("include = 'C:\\Card #1\\*'\nexclude = [\"a \\\"b\\\"\", 'c',]\n").unwrap(),
+            [
+                "--include",
+                "C:\\Card #1\\*",
+                "--exclude",
+                "a \"b\"",
+                "--exclude",
+                "c"
+            ]
+        );
+        assert_eq!(flags("jobs = 1_000").unwrap(), ["--jobs", "1000"]);
+        assert!(
+            flags("include = \"*.NEV")
+                .unwrap_err()
+                .contains("unclosed string")
+        );
+        assert!(flags("exclude = [\"a\"").unwrap_err().contains("same line"));
+    }
+
+    #[test]
+    fn skips_comments() {
+        let text = "# defaults\n\nverify = true # always\nexclude = \"#1\" # not this\n";
+        assert_eq!(flags(text).unwrap(), ["--verify", "--exclude", "#1"]);
+    }
+
+    #[test]
+    fn refuses_unknown_keys() {
+        assert_eq!(
+            flags("colour = \"never\"").unwrap_err(),
+            "line 1: unknown setting colour"
+        );
+        assert!(
+            flags("profile = \"x\"")
+                .unwrap_err()
+                .contains("unknown setting")
+        );
+        assert!(flags("[output]").unwrap_err().contains("unsupported table"));
+        // Keys are checked in every profile, selected or not.
+        assert!(
+            flags("[profile.other]\nnope = 1")
+                .unwrap_err()
+                .starts_with("line 2:")
+        );
+    }
+
+    #[test]
+    fn refuses_values_of_the_wrong_type() {
+        assert_eq!(
+            flags("verify = \"yes\"").unwrap_err(),
+            "line 1: verify is true or false"
+        );
+        assert_eq!(
+            flags("jobs = true").unwrap_err(),
+            "line 1: jobs takes a value, not true or false"
+        );
+        assert!(flags("jobs = four").unwrap_err().contains("quote strings"));
+        assert!(flags("jobs = 4 4").unwrap_err().contains("after the value"));
+    }
+
+    #[test]
+    fn false_overrides_an_earlier_true() {
+        let command = options::<RenameArgs>();
+        let mut settings = Vec::new();
+        for text in [
+            "verify = true\nquiet = true",
+            "[profile.check]\nverify = false",
+        ] {
+            let file = parse(&command, text, Some("check")).unwrap();
+            for setting in file
+                .flags
+                .into_iter()
+                .chain(file.profile.into_iter().flatten())
+            {
+                apply(&command, &mut settings, setting);
+            }
+        }
+        let flags: Vec<String> = settings
+            .into_iter()
+            .flat_map(|setting| setting.flags)
+            .collect();
+        assert_eq!(flags, ["--quiet"]);
+    }
+}
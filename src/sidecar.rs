@@ -0,0 +1,84 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::clip;
+use crate::dirent::{self, Kind};
+use crate::pathenc;
+
+/// Extensions of the files that belong with a clip for `--sidecars`: RED
+/// metadata (`.RMD`), REDCINE-X looks (`.rtn`), XML metadata and LUTs
+/// (`.cube`).
+const EXTENSIONS: [&str; 4] = ["RMD", "rtn", "xml", "cube"];
+
+/// Finds the sidecars of the files being renamed: files in the same folder
+/// named after the clip with a sidecar extension, such as
+/// `A001_C001_0101AB.RMD` next to `A001_C001_0101AB_001.NEV`. Each folder
+/// is listed once, so ask in path order.
+#[derive(Default)]
+pub struct Sidecars {
+    folder: Option<PathBuf>,
+    /// Sidecars in `folder` not yet claimed by a clip.
+    unclaimed: Vec<PathBuf>,
+}
+
+impl Sidecars {
+    /// The sidecars of `path`, each paired with its path next to `target`
+    /// under the target's clip name. A spanned clip's sidecars come with its
+    /// first segment. Sidecars that would keep their path are left out, as
+    /// when only the extension of the clip changes.
+    pub fn pairs(&mut self, path: &Path, target: &Path) -> Vec<(PathBuf, PathBuf)> {
+        let Some(folder) = path.parent() else {
+            return Vec::new();
+        };
+        if self.folder.as_deref() != Some(folder) {
+            self.unclaimed = list(folder);
+            self.folder = Some(folder.to_path_buf());
+        }
+        let (Some(clip), Some(renamed)) = (clip::name(path), clip::name(target)) else {
+            return Vec::new();
+        };
+
+        let mut pairs = Vec::new();
+        self.unclaimed.retain(|sidecar| {
+            if sidecar == path || sidecar.file_stem() != Some(clip) {
+                return true;
+            }
+            let mut name = OsString::from(renamed);
+            if let Some(extension) = sidecar.extension() {
+                name.push(".");
+                name.push(extension);
+            }
+            let moved = target.with_file_name(name);
+            if &moved != sidecar {
+                pairs.push((sidecar.clone(), moved));
+            }
+            false
+        });
+        pairs
+    }
+}
+
+/// Whether `path` has a sidecar extension.
+pub fn is_sidecar(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        EXTENSIONS
+            .iter()
+            .any(|sidecar| pathenc::extension_eq(ext, sidecar))
+    })
+}
+
+/// The sidecar files directly in `folder`. A folder that cannot be listed
+/// has none; its clips still fail or succeed on their own.
+fn list(folder: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = dirent::read_dir(folder) else {
+        return Vec::new();
+    };
+    let mut sidecars: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.kind == Kind::File)
+        .map(|entry| entry.path)
+        .filter(|path| is_sidecar(path))
+        .collect();
+    sidecars.sort();
+    sidecars
+}
@@ -0,0 +1,179 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{args, extensions, format_size, mhl};
+
+/// Real content written per file in `truncated` mode.
+const TRUNCATED_BYTES: u64 = 64 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Content {
+    /// Full-size files with no data written; free on filesystems with holes.
+    Sparse,
+    /// Only the first 64 KiB of each file, so sizes are small but real.
+    Truncated,
+    /// Full-size files of random data, for hashing benchmarks.
+    Full,
+}
+
+#[derive(clap::Subcommand)]
+pub enum SimulateCommand {
+    /// Create a synthetic RED footage tree
+    ///
+    /// Creates a synthetic RED footage tree in OUT, which must be missing or
+    /// empty: .RDM magazines holding .RDC clip folders of .NEV segments
+    /// (.R3D with --invert), for benchmarking settings and trying out
+    /// options without real media.
+    Generate(GenerateArgs),
+}
+
+/// The arguments of `r3dy simulate generate`.
+#[derive(clap::Args)]
+pub struct GenerateArgs {
+    /// Magazines to create
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = args::above_zero)]
+    cards: usize,
+    /// Clips per magazine
+    #[arg(long, value_name = "N", default_value_t = 20, value_parser = args::above_zero)]
+    clips: usize,
+    /// Segments per clip; the last one is shorter
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = args::above_zero)]
+    spans: usize,
+    /// Size of a full segment, e.g. 512M or 4G
+    #[arg(long, value_name = "SIZE", default_value = "256M", value_parser = args::size)]
+    size: u64,
+    /// Add an .RMD metadata sidecar to every clip
+    #[arg(long)]
+    sidecars: bool,
+    /// What the files hold
+    #[arg(long, value_name = "KIND", value_enum, default_value_t = Content::Sparse)]
+    content: Content,
+    /// Seed for names and sizes; the same seed gives the same tree
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    seed: u64,
+    /// Create .R3D segments instead of .NEV
+    #[arg(long)]
+    invert: bool,
+    /// The folder to create the tree in
+    #[arg(value_name = "OUT")]
+    out: PathBuf,
+}
+
+pub fn simulate_command(command: SimulateCommand) -> Result<(), String> {
+    let SimulateCommand::Generate(options) = command;
+    let (files, bytes) = generate(&options)?;
+    println!(
+        "Generated {} file{} ({}) in {} (seed {})",
+        files,
+        if files == 1 { "" } else { "s" },
+        format_size(bytes),
+        options.out.display(),
+        options.seed
+    );
+    Ok(())
+}
+
+/// Writes the tree and returns the number of files and their total size.
+fn generate(options: &GenerateArgs) -> Result<(usize, u64), String> {
+    let in_use = fs::read_dir(&options.out).is_ok_and(|mut entries| entries.next().is_some());
+    if in_use {
+        return Err(format!(
+            "{} is not empty; simulate only writes into a new or empty folder",
+            options.out.display()
+        ));
+    }
+
+    let (extension, _) = extensions(options.invert);
+    let mut rng = options.seed;
+    let mut files = 0;
+    let mut bytes = 0;
+
+    // RED names: reel A001, clip C001, then the month and day and a
+    // two-letter camera suffix.
+    let suffix: String = (0..2)
+        .map(|_| (b'A' + (mhl::splitmix64(&mut rng) % 26) as u8) as char)
+        .collect();
+    let month = 1 + mhl::splitmix64(&mut rng) % 12;
+    let day = 1 + mhl::splitmix64(&mut rng) % 28;
+    let stamp = format!("{:02}{:02}{}", month, day, suffix);
+
+    for card in 1..=options.cards {
+        let reel = format!("A{:03}", card);
+        let magazine = options.out.join(format!("{}_{}.RDM", reel, stamp));
+        for clip in 1..=options.clips {
+            let name = format!("{}_C{:03}_{}", reel, clip, stamp);
+            let folder = magazine.join(format!("{}.RDC", name));
+            create_dir(&folder)?;
+
+            // Every segment but the last is full size, as on camera.
+            let last = options.size / 4 + mhl::splitmix64(&mut rng) % (options.size / 4 * 3 + 1);
+            for span in 1..=options.spans {
+                let size = if span == options.spans {
+                    last
+                } else {
+                    options.size
+                };
+                let path = folder.join(format!("{}_{:03}.{}", name, span, extension));
+                bytes += write_segment(&path, size, options.content, &mut rng)?;
+                files += 1;
+            }
+
+            if options.sidecars {
+                let path = folder.join(format!("{}.RMD", name));
+                let text = format!(
+                    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rmd clip=\"{}\" reel=\"{}\" segments=\"{}\"/>\n",
+                    name, reel, options.spans
+                );
+                fs::write(&path, &text)
+                    .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+                bytes += text.len() as u64;
+                files += 1;
+            }
+        }
+    }
+
+    Ok((files, bytes))
+}
+
+fn create_dir(path: &Path) -> Result<(), String> {
+    fs::create_dir_all(path).map_err(|err| format!("Failed to create {}: {}", path.display(), err))
+}
+
+/// Creates one segment of nominal `size` and returns the bytes it holds.
+fn write_segment(path: &Path, size: u64, content: Content, rng: &mut u64) -> Result<u64, String> {
+    let mut write = || -> io::Result<u64> {
+        let mut file = File::create(path)?;
+        match content {
+            Content::Sparse => {
+                file.set_len(size)?;
+                Ok(size)
+            }
+            Content::Truncated => {
+                let len = size.min(TRUNCATED_BYTES);
+                write_random(&mut file, len, rng)?;
+                Ok(len)
+            }
+            Content::Full => {
+                write_random(&mut file, size, rng)?;
+                Ok(size)
+            }
+        }
+    };
+    write().map_err(|err| format!("Failed to write {}: {}", path.display(), err))
+}
+
+fn write_random(file: &mut File, len: u64, rng: &mut u64) -> io::Result<()> {
+    let mut buffer = vec![0u8; 1024 * 1024];
+    let mut remaining = len;
+    while remaining > 0 {
+        let chunk = remaining.min(buffer.len() as u64) as usize;
+        for word in buffer[..chunk].chunks_mut(8) {
+            let bytes = mhl::splitmix64(rng).to_le_bytes();
+            word.copy_from_slice(&bytes[..word.len()]);
+        }
+        file.write_all(&buffer[..chunk])?;
+        remaining -= chunk as u64;
+    }
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use std::io;
+use std::path::Path;
+
+use crate::{format_size, trace};
+
+/// Why writing `files` (where each goes, and its size in bytes) would take
+/// its volume below `min_free` bytes, for `--min-free`. Each file is
+/// counted along with the ones before it, so a clip is checked as a whole.
+/// A volume whose free space cannot be read does not stop anything.
+pub fn short<P: AsRef<Path>>(
+    files: impl IntoIterator<Item = (P, u64)>,
+    min_free: u64,
+) -> Option<String> {
+    let mut needed = 0u64;
+    for (target, bytes) in files {
+        needed = needed.saturating_add(bytes);
+        let Some(folder) = target
+            .as_ref()
+            .ancestors()
+            .skip(1)
+            .find(|folder| folder.is_dir())
+        else {
+            continue;
+        };
+        let Ok(free) = trace::call("statvfs", folder, || available(folder)) else {
+            continue;
+        };
+        if free < min_free.saturating_add(needed) {
+            return Some(format!(
+                "only {} free on {}, and the next {} would leave less than --min-free {}",
+                format_size(free),
+                folder.display(),
+                format_size(needed),
+                format_size(min_free)
+            ));
+        }
+    }
+    None
+}
+
+/// The bytes an unprivileged user can still write to the volume holding
+/// `folder`.
+#[cfg(unix)]
+fn available(folder: &Path) -> io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(folder.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a NUL byte"))?;
+    // SAFETY: statvfs only writes into the zeroed struct we hand it.
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Ok((stats.f_bavail as u64).saturating_mul(stats.f_frsize as u64))
+}
+
+#[cfg(windows)]
+fn available(folder: &Path) -> io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetDiskFreeSpaceExW(
+            directory: *const u16,
+            available: *mut u64,
+            total: *mut u64,
+            free: *mut u64,
+        ) -> i32;
+    }
+
+    let wide: Vec<u16> = folder.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0u64;
+    // SAFETY: a NUL-terminated UTF-16 path, and the totals we do not want
+    // may be null.
+    if unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    } != 0
+    {
+        Ok(available)
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn available(_folder: &Path) -> io::Result<u64> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
@@ -0,0 +1,392 @@
+use std::borrow::Cow;
+use std::cmp::{Ordering as CmpOrdering, Reverse};
+use std::collections::BinaryHeap;
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::vec;
+
+/// Memory budget used when `--max-memory` is not given.
+pub const DEFAULT_MEMORY_LIMIT: usize = 512 * 1024 * 1024;
+
+// Rough per-entry bookkeeping cost on top of the raw bytes we account for.
+const ENTRY_OVERHEAD: usize = 64;
+
+/// Values that can be written to and read back from a spill file.
+pub trait Record: Sized {
+    fn fields(&self) -> Vec<Cow<'_, [u8]>>;
+    fn from_fields(fields: Vec<Vec<u8>>) -> Option<Self>;
+
+    fn approximate_size(&self) -> usize {
+        self.fields().iter().map(|field| field.len()).sum::<usize>() + ENTRY_OVERHEAD
+    }
+}
+
+impl Record for PathBuf {
+    fn fields(&self) -> Vec<Cow<'_, [u8]>> {
+        vec![Cow::Borrowed(self.as_os_str().as_encoded_bytes())]
+    }
+
+    fn from_fields(mut fields: Vec<Vec<u8>>) -> Option<Self> {
+        let bytes = fields.pop()?;
+        // SAFETY: spill files are private to this process and only ever hold
+        // bytes produced by `as_encoded_bytes` above.
+        Some(PathBuf::from(unsafe {
+            OsStr::from_encoded_bytes_unchecked(&bytes)
+        }))
+    }
+}
+
+/// An append-only list that keeps at most `limit` bytes in memory and moves
+/// the rest to a temporary file, preserving insertion order on iteration.
+pub struct Spool<T: Record> {
+    memory: Vec<T>,
+    memory_bytes: usize,
+    limit: usize,
+    spilled: Option<SpillFile>,
+}
+
+impl<T: Record> Spool<T> {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            memory: Vec::new(),
+            memory_bytes: 0,
+            limit,
+            spilled: None,
+        }
+    }
+
+    pub fn push(&mut self, item: T) -> io::Result<()> {
+        self.memory_bytes += item.approximate_size();
+        self.memory.push(item);
+
+        if self.memory_bytes > self.limit {
+            let spill = match &mut self.spilled {
+                Some(spill) => spill,
+                None => self.spilled.insert(SpillFile::create()?),
+            };
+
+            for item in self.memory.drain(..) {
+                spill.write(&item)?;
+            }
+            spill.writer.flush()?;
+            self.memory_bytes = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Iterates over every item in insertion order. Items that were spilled
+    /// are read back from disk; a read failure ends the iteration early.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_
+    where
+        T: Clone,
+    {
+        let spilled = self
+            .spilled
+            .as_ref()
+            .and_then(|spill| spill.reader().ok())
+            .into_iter()
+            .flat_map(|reader| reader.map_while(Result::ok));
+
+        spilled.chain(self.memory.iter().cloned())
+    }
+}
+
+/// The order `PathSorter` hands paths back in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PathOrder {
+    /// Plain path order, where a folder's files and subfolders interleave.
+    Path,
+    /// Every file of a directory before any file of its subdirectories, so
+    /// each directory is handled (and later written to tape) in one stretch.
+    Grouped,
+}
+
+impl PathOrder {
+    fn compare(self, a: &PathBuf, b: &PathBuf) -> CmpOrdering {
+        match self {
+            PathOrder::Path => a.cmp(b),
+            PathOrder::Grouped => a
+                .parent()
+                .cmp(&b.parent())
+                .then_with(|| a.file_name().cmp(&b.file_name())),
+        }
+    }
+}
+
+/// Collects paths and hands them back in sorted order. Once the in-memory
+/// batch exceeds its budget it is sorted and written out as a run; the runs
+/// are merged lazily when iterating, so huge trees never sit in RAM at once.
+pub struct PathSorter {
+    batch: Vec<PathBuf>,
+    batch_bytes: usize,
+    limit: usize,
+    order: PathOrder,
+    runs: Vec<SpillFile>,
+    len: usize,
+}
+
+impl PathSorter {
+    pub fn new(limit: usize, order: PathOrder) -> Self {
+        Self {
+            batch: Vec::new(),
+            batch_bytes: 0,
+            limit,
+            order,
+            runs: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, path: PathBuf) -> io::Result<()> {
+        self.batch_bytes += path.approximate_size();
+        self.batch.push(path);
+        self.len += 1;
+
+        if self.batch_bytes > self.limit {
+            self.flush_batch()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn finish(mut self) -> io::Result<SortedPaths> {
+        let order = self.order;
+        self.batch.sort_by(|a, b| order.compare(a, b));
+
+        if self.runs.is_empty() {
+            return Ok(SortedPaths {
+                len: self.len,
+                memory: self.batch.into_iter(),
+                readers: Vec::new(),
+                heap: BinaryHeap::new(),
+                _runs: Vec::new(),
+            });
+        }
+
+        self.flush_batch()?;
+
+        let mut readers = Vec::with_capacity(self.runs.len());
+        let mut heap = BinaryHeap::new();
+        for (index, run) in self.runs.iter().enumerate() {
+            let mut reader = run.reader::<PathBuf>()?;
+            if let Some(first) = reader.next() {
+                heap.push(Reverse(RunHead {
+                    path: first?,
+                    run: index,
+                    order,
+                }));
+            }
+            readers.push(reader);
+        }
+
+        Ok(SortedPaths {
+            len: self.len,
+            memory: Vec::new().into_iter(),
+            readers,
+            heap,
+            _runs: self.runs,
+        })
+    }
+
+    fn flush_batch(&mut self) -> io::Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let order = self.order;
+        self.batch.sort_by(|a, b| order.compare(a, b));
+        let mut run = SpillFile::create()?;
+        for path in self.batch.drain(..) {
+            run.write(&path)?;
+        }
+        run.writer.flush()?;
+
+        self.runs.push(run);
+        self.batch_bytes = 0;
+        Ok(())
+    }
+}
+
+pub struct SortedPaths {
+    len: usize,
+    memory: vec::IntoIter<PathBuf>,
+    readers: Vec<RecordReader<PathBuf>>,
+    heap: BinaryHeap<Reverse<RunHead>>,
+    // Keeps the run files alive (and deletes them on drop).
+    _runs: Vec<SpillFile>,
+}
+
+impl SortedPaths {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Iterator for SortedPaths {
+    type Item = io::Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.readers.is_empty() {
+            return self.memory.next().map(Ok);
+        }
+
+        let Reverse(head) = self.heap.pop()?;
+        match self.readers[head.run].next() {
+            Some(Ok(next)) => self.heap.push(Reverse(RunHead { path: next, ..head })),
+            Some(Err(err)) => return Some(Err(err)),
+            None => {}
+        }
+
+        Some(Ok(head.path))
+    }
+}
+
+/// The next unmerged path of one sorted run.
+struct RunHead {
+    path: PathBuf,
+    run: usize,
+    order: PathOrder,
+}
+
+impl Ord for RunHead {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.order
+            .compare(&self.path, &other.path)
+            .then_with(|| self.run.cmp(&other.run))
+    }
+}
+
+impl PartialOrd for RunHead {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for RunHead {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for RunHead {}
+
+/// A temporary file of length-prefixed records, removed when dropped.
+struct SpillFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl SpillFile {
+    fn create() -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = env::temp_dir().join(format!(
+            "r3dy-{}-{}.spool",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+        })
+    }
+
+    fn write<T: Record>(&mut self, item: &T) -> io::Result<()> {
+        let fields = item.fields();
+        self.writer
+            .write_all(&(fields.len() as u32).to_le_bytes())?;
+        for field in fields {
+            self.writer.write_all(&(field.len() as u32).to_le_bytes())?;
+            self.writer.write_all(&field)?;
+        }
+        Ok(())
+    }
+
+    fn reader<T: Record>(&self) -> io::Result<RecordReader<T>> {
+        Ok(RecordReader {
+            reader: BufReader::new(File::open(&self.path)?),
+            _record: std::marker::PhantomData,
+        })
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+struct RecordReader<T> {
+    reader: BufReader<File>,
+    _record: std::marker::PhantomData<T>,
+}
+
+impl<T: Record> RecordReader<T> {
+    fn read_u32(&mut self) -> io::Result<Option<u32>> {
+        let mut buf = [0u8; 4];
+        match self.reader.read_exact(&mut buf) {
+            Ok(()) => Ok(Some(u32::from_le_bytes(buf))),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<T>> {
+        let Some(count) = self.read_u32()? else {
+            return Ok(None);
+        };
+
+        let mut fields = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let len = self.read_u32()?.ok_or(io::ErrorKind::UnexpectedEof)?;
+            let mut field = vec![0u8; len as usize];
+            self.reader.read_exact(&mut field)?;
+            fields.push(field);
+        }
+
+        T::from_fields(fields)
+            .map(Some)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "corrupt spool record"))
+    }
+}
+
+impl<T: Record> Iterator for RecordReader<T> {
+    type Item = io::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Parses sizes such as `512M`, `2G` or a plain byte count.
+pub fn parse_size(value: &str) -> Option<usize> {
+    let value = value.trim();
+    let (digits, multiplier) = match value.chars().last()?.to_ascii_uppercase() {
+        'K' => (&value[..value.len() - 1], 1024),
+        'M' => (&value[..value.len() - 1], 1024 * 1024),
+        'G' => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+
+    digits.trim().parse::<usize>().ok()?.checked_mul(multiplier)
+}
@@ -0,0 +1,480 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use crate::args::{RenameArgs, above_zero, one_of};
+use crate::progress::Announcer;
+use crate::spool::{self, PathOrder};
+use crate::{
+    CompoundRule, Config, Verbosity, changed, color, datetime, display_relative, has_extension,
+    heartbeat, pathenc, print_warnings, progress, trace, winpath,
+};
+
+const BLOCK: usize = 512;
+// Largest size the 11 octal digits of a ustar header can hold (8 GiB - 1).
+const USTAR_MAX_SIZE: u64 = 0o77_777_777_777;
+
+/// The arguments of `r3dy archive`: the scan options of `r3dy rename`,
+/// which pick the clips, and where to write them. Their long flags are also
+/// the keys of the config files.
+#[derive(clap::Args)]
+pub struct ArchiveArgs {
+    /// Archive to write
+    #[arg(short, long, value_name = "OUT")]
+    output: String,
+    /// Compress with zstd (implied when OUT ends in .zst or .tzst; needs the
+    /// zstd CLI)
+    #[arg(long)]
+    zstd: bool,
+    /// Directories to scan, or clips to archive (default: the current directory)
+    #[arg(value_name = "PATH")]
+    paths: Vec<PathBuf>,
+    /// Archive .R3D files as .NEV (or swap --from and --to)
+    #[arg(long)]
+    invert: bool,
+    /// Archive another extension pair, e.g. --from mxf_tmp --to mxf
+    #[arg(long, value_name = "EXT")]
+    from: Option<String>,
+    /// The extension --from files are archived under
+    #[arg(long, value_name = "EXT")]
+    to: Option<String>,
+    /// Memory budget for the file list before spilling to a temp file (default 512M)
+    #[arg(long, value_name = "SIZE", value_parser = one_of(spool::parse_size, "a size such as 256M or 2G"))]
+    max_memory: Option<usize>,
+    /// Log every filesystem call with its timing to a file path or fd number
+    #[arg(long, value_name = "DEST")]
+    trace: Option<String>,
+    /// Names like CLIP.bak.NEV: last (default) matches on the final extension, skip leaves them out
+    #[arg(long, value_name = "RULE", value_parser = one_of(CompoundRule::parse, "last or skip"))]
+    compound: Option<CompoundRule>,
+    /// Only archive files matching GLOB, e.g. 'A00*/**' (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+    /// Leave out files matching GLOB, e.g. '*_proxy.NEV' (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Do not scan folders named NAME, e.g. .Trashes or '*_proxy' (repeatable)
+    #[arg(long, value_name = "NAME")]
+    exclude_dir: Vec<String>,
+    /// Scan N levels of folders: 1 is just the files in the path itself
+    #[arg(long, value_name = "N", value_parser = above_zero)]
+    max_depth: Option<usize>,
+    /// Archive only the files directly in the path, like --max-depth 1
+    #[arg(long, conflicts_with = "max_depth")]
+    no_recursive: bool,
+    /// Also scan folders that symlinks point to, each folder once
+    #[arg(long, conflicts_with = "no_follow_symlinks")]
+    follow_symlinks: bool,
+    /// Skip symlinks to files too, with a warning
+    #[arg(long)]
+    no_follow_symlinks: bool,
+    /// Also archive dotfiles such as ._CLIP.NEV and scan hidden folders
+    #[arg(long)]
+    hidden: bool,
+    /// Scan what .r3dyignore files in the tree say to leave alone
+    #[arg(long)]
+    no_ignore: bool,
+    /// Do not scan into other file systems mounted inside the path, such as network shares
+    #[arg(short = 'x', long)]
+    one_file_system: bool,
+    /// While archiving a large file, log its progress every TIME (e.g. 30s)
+    #[arg(long, value_name = "TIME", value_parser = heartbeat::parse_interval)]
+    heartbeat: Option<Duration>,
+    /// Keep the archive byte-identical across runs and platforms for the same tree
+    #[arg(long)]
+    deterministic: bool,
+    /// Also apply the [profile.NAME] settings of config.toml and .r3dy.toml
+    #[arg(long, value_name = "NAME", conflicts_with = "no_config")]
+    profile: Option<String>,
+    /// Ignore config.toml and .r3dy.toml
+    #[arg(long)]
+    no_config: bool,
+    /// Colour warnings, failures and totals: auto (default), always or never
+    #[arg(long, value_name = "WHEN", value_parser = one_of(color::Choice::parse, "auto, always or never"))]
+    color: Option<color::Choice>,
+    /// Print only the totals line and errors, with no progress bar
+    #[arg(short, long, conflicts_with = "screen_reader")]
+    quiet: bool,
+    /// Archive the files listed in an MHL, an r3dy report or one path per line (- for stdin) instead of scanning the path
+    #[arg(long, value_name = "LIST")]
+    files_from: Option<PathBuf>,
+    /// The --files-from list is NUL-delimited, as from find -print0
+    #[arg(short = '0', long)]
+    null: bool,
+    /// Progress bar layout: default, eta, compact, failures, or an indicatif template ({failed} shows failures so far)
+    #[arg(long, value_name = "T")]
+    progress_template: Option<String>,
+    /// Redraw the progress bar at most HZ times a second (default 20)
+    #[arg(long, value_name = "HZ")]
+    progress_refresh: Option<String>,
+    /// No progress bars; report progress in plain sentences instead
+    #[arg(long)]
+    screen_reader: bool,
+    /// How often --screen-reader reports progress (default 15s)
+    #[arg(long, value_name = "TIME", value_parser = progress::parse_status_interval)]
+    status_interval: Option<Duration>,
+}
+
+/// Entry point for `r3dy archive -o OUT [options] [path]`.
+pub fn archive_command(args: ArchiveArgs) -> Result<(), String> {
+    let ArchiveArgs {
+        output,
+        mut zstd,
+        paths,
+        invert,
+        from,
+        to,
+        max_memory,
+        trace,
+        compound,
+        include,
+        exclude,
+        exclude_dir,
+        max_depth,
+        no_recursive,
+        follow_symlinks,
+        no_follow_symlinks,
+        hidden,
+        no_ignore,
+        one_file_system,
+        heartbeat,
+        deterministic,
+        profile,
+        no_config,
+        color,
+        quiet,
+        files_from,
+        null,
+        progress_template,
+        progress_refresh,
+        screen_reader,
+        status_interval,
+    } = args;
+    zstd |= has_extension(Path::new(&output), "zst") || has_extension(Path::new(&output), "tzst");
+
+    // The scan is the one `r3dy rename` makes with the same options.
+    let options = RenameArgs {
+        paths,
+        invert,
+        from,
+        to,
+        max_memory,
+        trace,
+        compound,
+        include,
+        exclude,
+        exclude_dir,
+        max_depth,
+        no_recursive,
+        follow_symlinks,
+        no_follow_symlinks,
+        hidden,
+        no_ignore,
+        one_file_system,
+        heartbeat,
+        deterministic,
+        profile,
+        no_config,
+        color,
+        quiet,
+        files_from,
+        null,
+        progress_template,
+        progress_refresh,
+        screen_reader,
+        status_interval,
+        ..RenameArgs::default()
+    };
+    let config = Config::new(options, None)?;
+    let quiet = config.verbosity == Verbosity::Quiet;
+    color::init(config.color);
+    if let Some(spec) = &config.trace {
+        trace::init(spec)?;
+    }
+    heartbeat::init(config.heartbeat);
+    if config.deterministic {
+        pathenc::use_forward_slashes();
+    }
+    if let Some(interval) = config.screen_reader {
+        progress::enable_screen_reader(interval);
+    }
+
+    let scan_started = SystemTime::now();
+    let collected = config.collect(PathOrder::Grouped, config.memory_limit, config.read_list()?)?;
+    print_warnings(&collected, quiet);
+
+    if collected.files.is_empty() {
+        println!(
+            "No .{} files found under {}",
+            config.source_extension(),
+            winpath::display(&config.root)
+        );
+        return Ok(());
+    }
+
+    let mut tar = TarWriter::create(&output, zstd)?;
+    let failed_so_far = Arc::new(AtomicUsize::new(0));
+    let progress = config
+        .progress
+        .bar(collected.files.len(), failed_so_far.clone())?;
+    let mut announcer = Announcer::new(collected.files.len(), "Archived");
+    let mut archived = 0usize;
+    let mut bytes = 0u64;
+    let mut skipped = 0usize;
+    let mut failed = 0usize;
+
+    // Files come grouped by directory, so duplicate names only need checking
+    // within the current one.
+    let mut dir: Option<PathBuf> = None;
+    let mut names: HashSet<PathBuf> = HashSet::new();
+
+    for path in collected.files {
+        let path = path.map_err(|err| format!("Failed to read back the file list: {}", err))?;
+        let target = path.with_extension(config.target_extension());
+        let display_path = display_relative(&config.root, &path);
+        progress.set_message(display_path.clone());
+
+        if path.parent() != dir.as_deref() {
+            dir = path.parent().map(Path::to_path_buf);
+            names.clear();
+        }
+        // --quiet leaves skips to the totals, like `r3dy rename`.
+        if !names.insert(target.clone()) {
+            if !quiet {
+                progress::println(
+                    &progress,
+                    format!(
+                        "Skipping {} ({} is already in the archive)",
+                        display_path,
+                        display_relative(&config.root, &target)
+                    ),
+                );
+            }
+            skipped += 1;
+            progress.inc(1);
+            continue;
+        }
+
+        if let Some(reason) = changed::since_scan(&changed::stat(&path), scan_started) {
+            if !quiet {
+                progress::println(&progress, format!("Skipping {} ({})", display_path, reason));
+            }
+            skipped += 1;
+            progress.inc(1);
+            continue;
+        }
+
+        match tar.append(&path, &member_name(&config.root, &target)) {
+            Ok(size) => {
+                archived += 1;
+                bytes += size;
+            }
+            Err(AppendError::Source(err)) => {
+                progress::println(
+                    &progress,
+                    format!("Failed to archive {}: {}", display_path, err),
+                );
+                failed += 1;
+                failed_so_far.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(AppendError::Archive(err)) => {
+                return Err(format!("Failed to write {}: {}", output, err));
+            }
+        }
+        progress.inc(1);
+        announcer.update(progress.position() as usize, failed);
+    }
+
+    tar.finish()
+        .map_err(|err| format!("Failed to write {}: {}", output, err))?;
+    progress.finish_with_message("archive complete");
+    announcer.finish();
+
+    println!(
+        "Archived {} file{} ({} bytes) to {} (skipped: {}, failed: {})",
+        archived,
+        if archived == 1 { "" } else { "s" },
+        bytes,
+        output,
+        skipped,
+        failed
+    );
+
+    if failed > 0 {
+        return Err(format!(
+            "{} file{} could not be archived",
+            failed,
+            if failed == 1 { "" } else { "s" }
+        ));
+    }
+    Ok(())
+}
+
+/// The archive path of `target`: relative to the root, `/`-separated, with
+/// the raw bytes of every component so non-UTF-8 names survive.
+fn member_name(root: &Path, target: &Path) -> Vec<u8> {
+    let mut name = Vec::new();
+    for component in target.strip_prefix(root).unwrap_or(target).components() {
+        if let Component::Normal(part) = component {
+            if !name.is_empty() {
+                name.push(b'/');
+            }
+            name.extend_from_slice(part.as_encoded_bytes());
+        }
+    }
+    name
+}
+
+enum AppendError {
+    /// The clip could not be read; the archive is still intact.
+    Source(io::Error),
+    /// Writing the archive failed, or a clip changed mid-copy and left a
+    /// truncated entry behind.
+    Archive(io::Error),
+}
+
+/// Streams a POSIX (ustar + pax) tar archive, optionally through `zstd`.
+struct TarWriter {
+    out: BufWriter<Box<dyn Write>>,
+    compressor: Option<Child>,
+}
+
+impl TarWriter {
+    fn create(path: &str, zstd: bool) -> Result<Self, String> {
+        if !zstd {
+            let file =
+                File::create(path).map_err(|err| format!("Failed to create {}: {}", path, err))?;
+            return Ok(Self {
+                out: BufWriter::new(Box::new(file)),
+                compressor: None,
+            });
+        }
+
+        let mut child = Command::new("zstd")
+            .args(["-q", "-f", "-T0", "-o", path])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("Failed to run zstd: {}", err))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+
+        Ok(Self {
+            out: BufWriter::new(Box::new(stdin)),
+            compressor: Some(child),
+        })
+    }
+
+    /// Copies the file at `source` into the archive as `name` and returns its
+    /// size.
+    fn append(&mut self, source: &Path, name: &[u8]) -> Result<u64, AppendError> {
+        let file =
+            trace::call("open", source, || File::open(source)).map_err(AppendError::Source)?;
+        let metadata = file.metadata().map_err(AppendError::Source)?;
+        let size = metadata.len();
+        let mtime = metadata.modified().map(datetime::unix_seconds).unwrap_or(0);
+
+        self.write_header(name, size, mtime)
+            .map_err(AppendError::Archive)?;
+
+        let mut reader = heartbeat::Reader::new(file.take(size), "archiving", source, size);
+        let copied = io::copy(&mut reader, &mut self.out).map_err(AppendError::Archive)?;
+        if copied < size {
+            return Err(AppendError::Archive(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} shrank while it was being archived", source.display()),
+            )));
+        }
+        self.pad(size).map_err(AppendError::Archive)?;
+
+        Ok(size)
+    }
+
+    fn write_header(&mut self, name: &[u8], size: u64, mtime: i64) -> io::Result<()> {
+        // Long names and clips past 8 GiB go in a pax header that precedes
+        // the entry; the ustar fields then only carry placeholders.
+        let mut pax = Vec::new();
+        if name.len() > 100 {
+            pax.extend(pax_record(b"path", name));
+        }
+        if size > USTAR_MAX_SIZE {
+            pax.extend(pax_record(b"size", size.to_string().as_bytes()));
+        }
+
+        if !pax.is_empty() {
+            let header = ustar_header(b"././@PaxHeader", pax.len() as u64, mtime, b'x');
+            self.out.write_all(&header)?;
+            self.out.write_all(&pax)?;
+            self.pad(pax.len() as u64)?;
+        }
+
+        let short_name = &name[..name.len().min(100)];
+        let header = ustar_header(short_name, size.min(USTAR_MAX_SIZE), mtime, b'0');
+        self.out.write_all(&header)
+    }
+
+    fn pad(&mut self, len: u64) -> io::Result<()> {
+        let rest = (BLOCK - (len % BLOCK as u64) as usize) % BLOCK;
+        self.out.write_all(&[0; BLOCK][..rest])
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.out.write_all(&[0; 2 * BLOCK])?;
+        self.out.flush()?;
+        // Closing the pipe lets zstd finish the frame.
+        drop(self.out);
+
+        if let Some(mut child) = self.compressor {
+            let status = child.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("zstd exited with {}", status)));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn ustar_header(name: &[u8], size: u64, mtime: i64, kind: u8) -> [u8; BLOCK] {
+    let mut header = [0u8; BLOCK];
+    header[..name.len()].copy_from_slice(name);
+    octal(&mut header[100..108], 0o644);
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], size);
+    octal(&mut header[136..148], mtime.max(0) as u64);
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is computed with its own field filled with spaces.
+    header[148..156].fill(b' ');
+    let checksum: u32 = header.iter().map(|&byte| u32::from(byte)).sum();
+    header[148..155].copy_from_slice(format!("{:06o}\0", checksum).as_bytes());
+    header
+}
+
+/// Writes `value` as zero-padded octal followed by a NUL.
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}\0", value, width = field.len() - 1);
+    field.copy_from_slice(digits.as_bytes());
+}
+
+/// One `<len> <key>=<value>\n` pax record; `len` counts the whole record,
+/// including its own digits.
+fn pax_record(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let body = key.len() + value.len() + 3;
+    let mut len = body + 1;
+    while body + len.to_string().len() != len {
+        len += 1;
+    }
+
+    let mut record = format!("{} ", len).into_bytes();
+    record.extend_from_slice(key);
+    record.push(b'=');
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
@@ -0,0 +1,70 @@
+use std::io;
+use std::path::Path;
+
+/// Whether a failed rename was blocked by macOS privacy protection (TCC)
+/// rather than by ordinary permissions. TCC denials surface as EPERM, which
+/// macOS otherwise only returns for files or folders locked in the Finder;
+/// those are ruled out by checking the flags. Always false elsewhere.
+pub fn is_privacy_denial(err: &io::Error, path: &Path) -> bool {
+    #[cfg(target_os = "macos")]
+    {
+        err.raw_os_error() == Some(libc::EPERM)
+            && !is_locked(path)
+            && !path.parent().is_some_and(is_locked)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = (err, path);
+        false
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn is_locked(path: &Path) -> bool {
+    use std::os::macos::fs::MetadataExt;
+
+    const LOCK_FLAGS: u32 =
+        libc::UF_IMMUTABLE | libc::UF_APPEND | libc::SF_IMMUTABLE | libc::SF_APPEND;
+    std::fs::symlink_metadata(path).is_ok_and(|meta| meta.st_flags() & LOCK_FLAGS != 0)
+}
+
+/// Steps to grant the access that was missing, naming the app r3dy runs in
+/// when the terminal reports it.
+pub fn remediation(root: &Path) -> Vec<String> {
+    let app = std::env::var("TERM_PROGRAM")
+        .ok()
+        .map(|program| match program.as_str() {
+            "Apple_Terminal" => "Terminal".to_string(),
+            "iTerm.app" => "iTerm".to_string(),
+            _ => program,
+        })
+        .unwrap_or_else(|| "the app you run r3dy from".to_string());
+
+    let setting = if !has_full_disk_access() {
+        "Full Disk Access"
+    } else if root.starts_with("/Volumes") {
+        "Files and Folders, then Removable Volumes or Network Volumes"
+    } else {
+        "Files and Folders"
+    };
+
+    vec![
+        format!(
+            "1. Open System Settings > Privacy & Security > {}.",
+            setting
+        ),
+        format!("2. Turn access on for {}.", app),
+        format!("3. Quit and reopen {}, then run r3dy again.", app),
+    ]
+}
+
+/// Only processes with Full Disk Access can read the user's TCC database.
+fn has_full_disk_access() -> bool {
+    std::env::var_os("HOME").is_some_and(|home| {
+        std::fs::File::open(
+            Path::new(&home).join("Library/Application Support/com.apple.TCC/TCC.db"),
+        )
+        .is_ok()
+    })
+}
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::{clip, display_relative, r3d};
+
+/// The placeholders `--template` understands.
+const FIELDS: &str = "{clip}, {reel}, {camera}, {date}, {segment}, {width}, {height}, {fps}, \
+                      {extensions}, {extension}";
+
+/// A file name pattern for `--template`, such as `{reel}_{clip}_{date}.R3D`,
+/// filled in from each clip's header and RED clip name.
+pub struct Template {
+    text: String,
+    parts: Vec<Part>,
+    /// Read what the header parser cannot decode with ExifTool.
+    exiftool: bool,
+}
+
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    /// The clip name, `A001_C001_0101AB`.
+    Clip,
+    /// The camera letter and magazine, `A001`.
+    Reel,
+    /// The camera letter, `A`.
+    Camera,
+    /// The month and day from the clip name, `0101`.
+    Date,
+    /// The segment number of a spanned clip, `001`.
+    Segment,
+    Width,
+    Height,
+    Fps,
+    /// Every extension of the file name, `.bak.NEV`.
+    Extensions,
+    /// The last extension of the file name, `.NEV`.
+    Extension,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "clip" => Field::Clip,
+            "reel" => Field::Reel,
+            "camera" => Field::Camera,
+            "date" => Field::Date,
+            "segment" => Field::Segment,
+            "width" => Field::Width,
+            "height" => Field::Height,
+            "fps" => Field::Fps,
+            "extensions" => Field::Extensions,
+            "extension" => Field::Extension,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Clip => "clip",
+            Field::Reel => "reel",
+            Field::Camera => "camera",
+            Field::Date => "date",
+            Field::Segment => "segment",
+            Field::Width => "width",
+            Field::Height => "height",
+            Field::Fps => "fps",
+            Field::Extensions => "extensions",
+            Field::Extension => "extension",
+        }
+    }
+}
+
+impl Template {
+    /// Parses a template, which must be a plain file name ending in
+    /// `.extension` or in `{extensions}` or `{extension}`, which carry over
+    /// the file's own.
+    pub fn parse(text: &str, extension: &str) -> Result<Self, String> {
+        if text.contains(['/', '\\']) {
+            return Err(format!(
+                "Invalid --template {}: it names a file, so it cannot contain / or \\",
+                text
+            ));
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = text;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(Part::Text(rest[..open].to_string()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| format!("Invalid --template {}: unclosed {{", text))?;
+            let name = &rest[open + 1..open + close];
+            let field = Field::parse(name).ok_or_else(|| {
+                format!(
+                    "Invalid --template {}: unknown placeholder {{{}}} (use {})",
+                    text, name, FIELDS
+                )
+            })?;
+            parts.push(Part::Field(field));
+            rest = &rest[open + close + 1..];
+        }
+        if rest.contains('}') {
+            return Err(format!("Invalid --template {}: unmatched }}", text));
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        let carried = matches!(
+            parts.last(),
+            Some(Part::Field(Field::Extensions | Field::Extension))
+        );
+        if !carried && !crate::has_extension(Path::new(text), extension) {
+            return Err(format!(
+                "Invalid --template {}: it must end in .{}, the extension being renamed to, \
+                 or in {{extensions}} or {{extension}}",
+                text, extension
+            ));
+        }
+        Ok(Self {
+            text: text.to_string(),
+            parts,
+            exiftool: false,
+        })
+    }
+
+    /// Fills in the header fields of clips whose header r3dy cannot decode
+    /// from ExifTool, when it is installed, like `--exiftool`.
+    pub fn set_exiftool(&mut self, exiftool: bool) {
+        self.exiftool = exiftool;
+    }
+
+    /// The file name `path` gets, or why a placeholder has no value for it.
+    pub fn render(&self, path: &Path) -> Result<String, String> {
+        let mut clip: Option<Clip> = None;
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => name.push_str(text),
+                Part::Field(field) => {
+                    let clip = clip.get_or_insert_with(|| Clip::read(path, self.exiftool));
+                    let value = clip.field(*field, path)?;
+                    if value.is_empty() || value.contains(['/', '\\']) {
+                        return Err(format!("{} is not usable in a file name", value));
+                    }
+                    name.push_str(&value);
+                }
+            }
+        }
+        Ok(name)
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// What the placeholders of one file are filled in from.
+struct Clip {
+    header: Option<r3d::Header>,
+    name: String,
+}
+
+impl Clip {
+    fn read(path: &Path, exiftool: bool) -> Self {
+        let header = r3d::read_metadata(path, exiftool).ok();
+        // The recorded name survives earlier renames; the file name is the
+        // fallback for headers that are not decoded.
+        let name = header
+            .as_ref()
+            .and_then(r3d::Header::clip)
+            .or_else(|| clip::name(&plain(path)).map(|name| name.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+        Self { header, name }
+    }
+
+    fn field(&self, field: Field, path: &Path) -> Result<String, String> {
+        let not_red = || format!("{{{}}}: {} is not a RED clip name", field.name(), self.name);
+        let details = || {
+            let header = self
+                .header
+                .as_ref()
+                .ok_or_else(|| format!("{{{}}}: no RED header", field.name()))?;
+            header.details.as_ref().ok_or_else(|| {
+                format!(
+                    "{{{}}}: {} headers are not decoded yet (--exiftool can read them)",
+                    field.name(),
+                    header.format
+                )
+            })
+        };
+        Ok(match field {
+            Field::Clip => self.name.clone(),
+            Field::Reel => r3d::reel(&self.name).ok_or_else(not_red)?.to_string(),
+            Field::Camera => r3d::reel(&self.name).ok_or_else(not_red)?[..1].to_string(),
+            Field::Date => r3d::clip_date(&self.name).ok_or_else(not_red)?.to_string(),
+            Field::Segment => clip::segment(&plain(path))
+                .ok_or_else(|| "{segment}: not a segment of a spanned clip".to_string())?
+                .to_string(),
+            Field::Width => details()?.width.to_string(),
+            Field::Height => details()?.height.to_string(),
+            Field::Fps => r3d::format_frame_rate(details()?.frame_rate)
+                .ok_or_else(|| "{fps}: the header has no frame rate".to_string())?,
+            Field::Extensions => extensions(path)
+                .ok_or_else(|| format!("{{{}}}: the name has no extension", field.name()))?,
+            Field::Extension => path
+                .extension()
+                .map(|extension| format!(".{}", extension.to_string_lossy()))
+                .ok_or_else(|| format!("{{{}}}: the name has no extension", field.name()))?,
+        })
+    }
+}
+
+/// Every extension of `path`'s name with its leading dot, `.bak.NEV` for
+/// `CLIP.bak.NEV`. A leading dot marks a hidden file rather than an
+/// extension, as for `is_compound`.
+fn extensions(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_string_lossy();
+    let start = usize::from(name.starts_with('.'));
+    let dot = name[start..].find('.')? + start;
+    Some(name[dot..].to_string())
+}
+
+/// `path` with its last extension only, `CLIP.NEV` for `CLIP.bak.NEV`, so
+/// the clip name and segment read from the file name leave out the rest.
+fn plain(path: &Path) -> PathBuf {
+    let (Some(chain), Some(extension)) = (extensions(path), path.extension()) else {
+        return path.to_path_buf();
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = &name[..name.len() - chain.len()];
+    path.with_file_name(format!("{}.{}", stem, extension.to_string_lossy()))
+}
+
+/// Names every file in `files` before anything is renamed, so a template
+/// that cannot name one of them, or names two the same, stops the run with
+/// the tree untouched. `target` gives the path a file gets when the template
+/// names it.
+pub fn plan(
+    root: &Path,
+    template: &Template,
+    files: &[PathBuf],
+    target: impl Fn(&Path, &str) -> PathBuf,
+) -> Result<HashMap<PathBuf, PathBuf>, String> {
+    let mut targets = HashMap::with_capacity(files.len());
+    let mut sources: HashMap<PathBuf, &Path> = HashMap::with_capacity(files.len());
+    let mut problems = Vec::new();
+    for path in files {
+        let named = match template.render(path) {
+            Ok(name) => target(path, &name),
+            Err(err) => {
+                problems.push(format!("{}: {}", display_relative(root, path), err));
+                continue;
+            }
+        };
+        if let Some(other) = sources.insert(named.clone(), path) {
+            problems.push(format!(
+                "{} and {} would both be named {}",
+                display_relative(root, other),
+                display_relative(root, path),
+                display_relative(root, &named)
+            ));
+        }
+        targets.insert(path.clone(), named);
+    }
+
+    if problems.is_empty() {
+        return Ok(targets);
+    }
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    Err(format!(
+        "--template {} has {} problem{}; nothing was renamed",
+        template.text(),
+        problems.len(),
+        if problems.len() == 1 { "" } else { "s" }
+    ))
+}
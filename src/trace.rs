@@ -0,0 +1,95 @@
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{datetime, output};
+
+struct Tracer {
+    started: Instant,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+static TRACER: OnceLock<Tracer> = OnceLock::new();
+
+/// Starts writing a syscall-level trace to `spec` (a path or fd number).
+/// Each line is tab-separated: seconds since start, operation, duration in
+/// microseconds, result, path, and a second path for two-path calls.
+pub fn init(spec: &str) -> Result<(), String> {
+    // `output::open` line-buffers, so every entry is on disk even if the
+    // process is killed mid-run.
+    let mut out = output::open(spec)?;
+    writeln!(
+        out,
+        "# r3dy trace started {}\n# elapsed_s\top\tduration_us\tresult\tpath\tpath2",
+        datetime::rfc3339(SystemTime::now())
+    )
+    .map_err(|err| format!("Failed to write trace: {}", err))?;
+
+    let _ = TRACER.set(Tracer {
+        started: Instant::now(),
+        out: Mutex::new(out),
+    });
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    TRACER.get().is_some()
+}
+
+/// Runs `call`, logging it to the trace when tracing is on.
+pub fn call<T>(op: &str, path: &Path, call: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    call2(op, path, None, call)
+}
+
+/// Like `call`, for operations that take two paths (rename, copy).
+pub fn call2<T>(
+    op: &str,
+    path: &Path,
+    path2: Option<&Path>,
+    call: impl FnOnce() -> io::Result<T>,
+) -> io::Result<T> {
+    if !enabled() {
+        return call();
+    }
+
+    let started = Instant::now();
+    let result = call();
+    let outcome = match &result {
+        Ok(_) => "ok".to_string(),
+        Err(err) => err.to_string(),
+    };
+    record(op, path, path2, started.elapsed(), &outcome);
+    result
+}
+
+/// Logs an operation whose timing was measured by the caller (e.g. one entry
+/// of a batched submission, which shares the batch's duration).
+pub fn record(
+    op: &str,
+    path: &Path,
+    path2: Option<&Path>,
+    duration: Duration,
+    result: &dyn Display,
+) {
+    let Some(tracer) = TRACER.get() else {
+        return;
+    };
+
+    let line = format!(
+        "{:.6}\t{}\t{}\t{}\t{}\t{}\n",
+        tracer.started.elapsed().as_secs_f64(),
+        op,
+        duration.as_micros(),
+        result,
+        path.display(),
+        path2
+            .map(|path| path.display().to_string())
+            .unwrap_or_default()
+    );
+
+    if let Ok(mut out) = tracer.out.lock() {
+        let _ = out.write_all(line.as_bytes());
+    }
+}
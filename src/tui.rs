@@ -0,0 +1,475 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{self, IsTerminal, Stderr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, List, ListItem, Paragraph};
+use ratatui::{Frame, Terminal};
+
+use crate::{Outcome, Status, display_relative, winpath};
+
+/// How often the screen is redrawn when nothing is pressed.
+const FRAME: Duration = Duration::from_millis(100);
+/// Failures kept for the log; older ones scroll away.
+const FAILURES_KEPT: usize = 500;
+/// In-flight renames listed before the rest are counted.
+const IN_FLIGHT_SHOWN: usize = 5;
+
+/// The list the arrow keys scroll.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Folders,
+    Failures,
+}
+
+/// What the dashboard shows.
+struct State {
+    root: PathBuf,
+    verb: &'static str,
+    total: usize,
+    done: usize,
+    converted: usize,
+    skipped: usize,
+    failed: usize,
+    started: Instant,
+    in_flight: Vec<String>,
+    /// Done and total matching files per folder.
+    folders: BTreeMap<PathBuf, (usize, usize)>,
+    failures: VecDeque<String>,
+    focus: Focus,
+    /// The first folder with work left that is shown.
+    folders_top: usize,
+    /// How far the failures log is scrolled back from the newest; at 0 it
+    /// follows new failures as they come.
+    failures_back: usize,
+    /// Rows each list had when last drawn, for PageUp and PageDown.
+    folder_rows: usize,
+    failure_rows: usize,
+}
+
+impl State {
+    /// Moves the focused list by `rows`, down (towards newer failures)
+    /// when positive. Drawing clamps it to the list.
+    fn scroll(&mut self, rows: isize) {
+        match self.focus {
+            Focus::Folders => self.folders_top = self.folders_top.saturating_add_signed(rows),
+            Focus::Failures => {
+                self.failures_back = self.failures_back.saturating_add_signed(-rows);
+            }
+        }
+    }
+
+    fn page(&self) -> isize {
+        let rows = match self.focus {
+            Focus::Folders => self.folder_rows,
+            Focus::Failures => self.failure_rows,
+        };
+        rows.max(1) as isize
+    }
+}
+
+/// The terminal and what it shows, shared with the thread that reads keys
+/// and redraws.
+struct Shared {
+    screen: Mutex<(Terminal<CrosstermBackend<Stderr>>, State)>,
+    paused: AtomicBool,
+    aborted: AtomicBool,
+    stop: AtomicBool,
+}
+
+impl Shared {
+    fn screen(&self) -> MutexGuard<'_, (Terminal<CrosstermBackend<Stderr>>, State)> {
+        self.screen.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn key(&self, code: KeyCode, modifiers: KeyModifiers) {
+        let mut screen = self.screen();
+        let state = &mut screen.1;
+        match code {
+            KeyCode::Char('p' | 'P' | ' ') => {
+                self.paused.fetch_xor(true, Ordering::Relaxed);
+            }
+            KeyCode::Char('q' | 'Q') => self.aborted.store(true, Ordering::Relaxed),
+            // Ctrl-C arrives as a key while the terminal is raw.
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                self.aborted.store(true, Ordering::Relaxed)
+            }
+            KeyCode::Tab | KeyCode::BackTab => {
+                state.focus = match state.focus {
+                    Focus::Folders => Focus::Failures,
+                    Focus::Failures => Focus::Folders,
+                };
+            }
+            KeyCode::Up | KeyCode::Char('k') => state.scroll(-1),
+            KeyCode::Down | KeyCode::Char('j') => state.scroll(1),
+            KeyCode::PageUp => state.scroll(-state.page()),
+            KeyCode::PageDown => state.scroll(state.page()),
+            KeyCode::Home => state.scroll(-isize::MAX),
+            KeyCode::End => state.scroll(isize::MAX),
+            _ => {}
+        }
+    }
+
+    fn draw(&self) {
+        let status = if self.aborted.load(Ordering::Relaxed) {
+            "aborting"
+        } else if self.paused.load(Ordering::Relaxed) {
+            "PAUSED"
+        } else {
+            "running"
+        };
+        let mut screen = self.screen();
+        let (terminal, state) = &mut *screen;
+        // Drawing also picks up a new terminal size.
+        let _ = terminal.draw(|frame| render(frame, state, status));
+    }
+}
+
+/// The full-screen view of a run for `--tui`: overall progress, the renames
+/// in flight, progress per folder and the failures so far. `p` or space
+/// pauses before the next batch and `q` aborts, leaving the files not yet
+/// started alone; tab picks the folder or failure list for the arrow keys
+/// and PageUp/PageDown to scroll.
+pub struct Dashboard {
+    shared: Arc<Shared>,
+    keys: Option<thread::JoinHandle<()>>,
+}
+
+impl Dashboard {
+    /// Switches the terminal to the dashboard. `folders` maps every
+    /// directory holding matching files to how many it holds.
+    pub fn start(
+        root: &Path,
+        total: usize,
+        folders: &HashMap<PathBuf, usize>,
+        verb: &'static str,
+    ) -> Result<Self, String> {
+        if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+            return Err("--tui needs a terminal on stdin and stderr".to_string());
+        }
+        // Raw mode turns off line buffering, echo and signal keys, so each
+        // key arrives as it is pressed and Ctrl-C aborts like `q`.
+        enable_raw_mode()
+            .map_err(|err| format!("Failed to read keys from the terminal: {}", err))?;
+        let mut terminal = match Terminal::new(CrosstermBackend::new(io::stderr())) {
+            Ok(terminal) => terminal,
+            Err(err) => {
+                let _ = disable_raw_mode();
+                return Err(format!("Failed to start the dashboard: {}", err));
+            }
+        };
+        // The alternate screen keeps the shell's scrollback as it was.
+        let _ = execute!(terminal.backend_mut(), EnterAlternateScreen);
+        let _ = terminal.hide_cursor();
+        let _ = terminal.clear();
+
+        let state = State {
+            root: root.to_path_buf(),
+            verb,
+            total,
+            done: 0,
+            converted: 0,
+            skipped: 0,
+            failed: 0,
+            started: Instant::now(),
+            in_flight: Vec::new(),
+            folders: folders
+                .iter()
+                .map(|(dir, total)| (dir.clone(), (0, *total)))
+                .collect(),
+            failures: VecDeque::new(),
+            focus: Focus::Folders,
+            folders_top: 0,
+            failures_back: 0,
+            folder_rows: 0,
+            failure_rows: 0,
+        };
+        let shared = Arc::new(Shared {
+            screen: Mutex::new((terminal, state)),
+            paused: AtomicBool::new(false),
+            aborted: AtomicBool::new(false),
+            stop: AtomicBool::new(false),
+        });
+        shared.draw();
+
+        // Keys and resizes are handled, and redrawn, as they arrive, even
+        // while a long copy holds up the run; otherwise the screen is
+        // redrawn every frame.
+        let keys = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let mut drawn = Instant::now();
+                while !shared.stop.load(Ordering::Relaxed) {
+                    match event::poll(FRAME.saturating_sub(drawn.elapsed())) {
+                        Ok(true) => match event::read() {
+                            Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => {
+                                shared.key(key.code, key.modifiers);
+                            }
+                            Ok(Event::Resize(..)) => {}
+                            Ok(_) => continue,
+                            Err(_) => break,
+                        },
+                        Ok(false) => {}
+                        Err(_) => break,
+                    }
+                    shared.draw();
+                    drawn = Instant::now();
+                }
+            })
+        };
+        Ok(Self {
+            shared,
+            keys: Some(keys),
+        })
+    }
+
+    /// Shows `batch` as the renames in flight.
+    pub fn in_flight(&mut self, batch: &[(PathBuf, PathBuf)]) {
+        let mut screen = self.shared.screen();
+        let state = &mut screen.1;
+        state.in_flight = batch
+            .iter()
+            .map(|(source, _)| display_relative(&state.root, source))
+            .collect();
+    }
+
+    /// Counts `outcome`. Sidecars only show up when they fail, as they are
+    /// not counted among the files.
+    pub fn record(&mut self, outcome: &Outcome, sidecar: bool) {
+        let mut screen = self.shared.screen();
+        let state = &mut screen.1;
+        if outcome.status.is_failure() {
+            state.failures.push_back(format!(
+                "{}: {}",
+                display_relative(&state.root, &outcome.source),
+                outcome.error.as_deref().unwrap_or("unknown error")
+            ));
+            if state.failures.len() > FAILURES_KEPT {
+                state.failures.pop_front();
+            }
+            // Keep the failures scrolled back to showing the same ones.
+            if state.failures_back > 0 {
+                state.failures_back += 1;
+            }
+        }
+        if !sidecar {
+            state.done += 1;
+            match outcome.status {
+                Status::Converted => state.converted += 1,
+                Status::Skipped | Status::Changed => state.skipped += 1,
+                Status::Failed | Status::Denied => state.failed += 1,
+            }
+            if let Some(dir) = outcome.source.parent()
+                && let Some((done, _)) = state.folders.get_mut(dir)
+            {
+                *done += 1;
+            }
+        }
+    }
+
+    /// Waits while the run is paused. False once it has been aborted.
+    pub fn proceed(&mut self) -> bool {
+        self.shared.screen().1.in_flight.clear();
+        while self.shared.paused.load(Ordering::Relaxed) && !self.aborted() {
+            thread::sleep(FRAME);
+        }
+        !self.aborted()
+    }
+
+    pub fn aborted(&self) -> bool {
+        self.shared.aborted.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(keys) = self.keys.take() {
+            let _ = keys.join();
+        }
+        let mut screen = self.shared.screen();
+        let terminal = &mut screen.0;
+        let _ = terminal.show_cursor();
+        let _ = execute!(terminal.backend_mut(), LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
+
+fn render(frame: &mut Frame, state: &mut State, status: &str) {
+    let in_flight_rows = state.in_flight.len().min(IN_FLIGHT_SHOWN)
+        + usize::from(state.in_flight.len() > IN_FLIGHT_SHOWN);
+    let [header, overall, in_flight, folders, failures, footer] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(1),
+        Constraint::Length(in_flight_rows.max(1) as u16 + 2),
+        Constraint::Fill(1),
+        Constraint::Fill(1),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let elapsed = state.started.elapsed();
+    frame.render_widget(
+        Paragraph::new(format!(
+            "r3dy  {}  [{}]  {}",
+            winpath::display(&state.root),
+            status,
+            clock(elapsed)
+        )),
+        header,
+    );
+    let rate = state.done as f64 / elapsed.as_secs_f64().max(0.001);
+    frame.render_widget(
+        Gauge::default()
+            .gauge_style(Style::new().fg(Color::Cyan).bg(Color::DarkGray))
+            .ratio(if state.total == 0 {
+                1.0
+            } else {
+                (state.done as f64 / state.total as f64).min(1.0)
+            })
+            .label(format!(
+                "{} {}/{} files ({:.0}/s)  converted {}  skipped {}  failed {}",
+                state.verb,
+                state.done,
+                state.total,
+                rate,
+                state.converted,
+                state.skipped,
+                state.failed
+            )),
+        overall,
+    );
+
+    let mut names: Vec<ListItem> = state
+        .in_flight
+        .iter()
+        .take(IN_FLIGHT_SHOWN)
+        .map(|name| ListItem::new(name.as_str()))
+        .collect();
+    if state.in_flight.len() > IN_FLIGHT_SHOWN {
+        names.push(ListItem::new(format!(
+            "and {} more",
+            state.in_flight.len() - IN_FLIGHT_SHOWN
+        )));
+    }
+    frame.render_widget(
+        List::new(names)
+            .block(Block::bordered().title(format!("In flight ({})", state.in_flight.len()))),
+        in_flight,
+    );
+
+    // Folders with work left, in path order.
+    let open: Vec<_> = state
+        .folders
+        .iter()
+        .filter(|(_, (done, total))| done < total)
+        .collect();
+    let rows = inner_rows(folders);
+    state.folder_rows = rows;
+    state.folders_top = state.folders_top.min(open.len().saturating_sub(rows));
+    let items: Vec<ListItem> = open
+        .iter()
+        .skip(state.folders_top)
+        .take(rows)
+        .map(|(dir, (done, total))| {
+            let name = display_relative(&state.root, dir);
+            ListItem::new(format!(
+                "{} {}/{}  {}",
+                bar(*done, *total, 20),
+                done,
+                total,
+                if name.is_empty() { "." } else { &name }
+            ))
+        })
+        .collect();
+    let mut block = list_block(
+        format!(
+            "Folders ({} of {} done)",
+            state.folders.len() - open.len(),
+            state.folders.len()
+        ),
+        state.focus == Focus::Folders,
+    );
+    if open.len() > rows {
+        block = block.title_bottom(format!(
+            "{}-{} of {} left",
+            state.folders_top + 1,
+            (state.folders_top + rows).min(open.len()),
+            open.len()
+        ));
+    }
+    frame.render_widget(List::new(items).block(block), folders);
+
+    let rows = inner_rows(failures);
+    state.failure_rows = rows;
+    state.failures_back = state
+        .failures_back
+        .min(state.failures.len().saturating_sub(rows));
+    let end = state.failures.len() - state.failures_back;
+    let items: Vec<ListItem> = state
+        .failures
+        .range(end.saturating_sub(rows)..end)
+        .map(|failure| ListItem::new(failure.as_str()))
+        .collect();
+    let mut block = list_block(
+        format!("Failures ({})", state.failed),
+        state.focus == Focus::Failures,
+    );
+    if state.failures_back > 0 {
+        block = block.title_bottom(format!("{} newer", state.failures_back));
+    }
+    frame.render_widget(List::new(items).block(block), failures);
+
+    frame.render_widget(
+        Paragraph::new(
+            "p pause/resume   q abort   tab switch list   \u{2191}\u{2193} PgUp PgDn scroll",
+        ),
+        footer,
+    );
+}
+
+/// A bordered list, its title bold while the arrow keys scroll it.
+fn list_block(title: String, focused: bool) -> Block<'static> {
+    let style = if focused {
+        Style::new().add_modifier(Modifier::BOLD)
+    } else {
+        Style::new()
+    };
+    Block::bordered().title(Line::styled(title, style))
+}
+
+/// The rows inside a bordered block drawn in `area`.
+fn inner_rows(area: Rect) -> usize {
+    area.height.saturating_sub(2) as usize
+}
+
+/// `elapsed` as `HH:MM:SS`.
+fn clock(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn bar(done: usize, total: usize, width: usize) -> String {
+    let filled = (done * width)
+        .checked_div(total)
+        .unwrap_or(width)
+        .min(width);
+    format!("[{}{}]", "#".repeat(filled), ".".repeat(width - filled))
+}
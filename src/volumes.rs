@@ -0,0 +1,185 @@
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::{format_size, trace, winpath};
+
+/// Per-volume counters for the end-of-run summary. A tree that spans
+/// several drives (a cart of card readers under one folder, a SAN mount
+/// inside a local tree) shows which drive is slow or failing.
+#[derive(Default)]
+pub struct Volumes {
+    volumes: Vec<Volume>,
+}
+
+struct Volume {
+    id: VolumeId,
+    mount: PathBuf,
+    files: usize,
+    bytes: u64,
+    failed: usize,
+    /// Time spent renaming this volume's files.
+    busy: Duration,
+    hashed_bytes: u64,
+    hashing: Duration,
+}
+
+#[cfg(unix)]
+pub type VolumeId = u64;
+#[cfg(not(unix))]
+pub type VolumeId = String;
+
+impl Volumes {
+    /// The volume `path` lives on, as an index for `record`.
+    pub fn volume_of(&mut self, path: &Path, metadata: &Metadata) -> usize {
+        let id = volume_id(path, metadata);
+        if let Some(index) = self.volumes.iter().position(|volume| volume.id == id) {
+            return index;
+        }
+
+        self.volumes.push(Volume {
+            mount: mount_point(path, &id),
+            id,
+            files: 0,
+            bytes: 0,
+            failed: 0,
+            busy: Duration::ZERO,
+            hashed_bytes: 0,
+            hashing: Duration::ZERO,
+        });
+        self.volumes.len() - 1
+    }
+
+    /// Counts one file of `bytes` that took `busy` to rename and, when it
+    /// was hashed, `hashing` to read.
+    pub fn record(
+        &mut self,
+        volume: usize,
+        bytes: u64,
+        busy: Duration,
+        hashing: Option<Duration>,
+        failed: bool,
+    ) {
+        let Some(volume) = self.volumes.get_mut(volume) else {
+            return;
+        };
+        volume.files += 1;
+        volume.bytes += bytes;
+        volume.busy += busy;
+        if failed {
+            volume.failed += 1;
+        }
+        if let Some(hashing) = hashing {
+            volume.hashed_bytes += bytes;
+            volume.hashing += hashing;
+        }
+    }
+
+    /// One summary line per volume; nothing when the tree is on one volume.
+    pub fn lines(&self) -> Vec<String> {
+        if self.volumes.len() < 2 {
+            return Vec::new();
+        }
+
+        let mut volumes: Vec<&Volume> = self.volumes.iter().collect();
+        volumes.sort_by(|a, b| a.mount.cmp(&b.mount));
+        volumes
+            .into_iter()
+            .map(|volume| {
+                let mut line = format!(
+                    "  {}: {} file{}, {}, {}",
+                    winpath::display(&volume.mount),
+                    volume.files,
+                    if volume.files == 1 { "" } else { "s" },
+                    format_size(volume.bytes),
+                    rate(volume.files as f64, volume.busy, "files/s")
+                );
+                if volume.hashed_bytes > 0 {
+                    line.push_str(&format!(
+                        ", {} read",
+                        rate(
+                            volume.hashed_bytes as f64 / 1048576.0,
+                            volume.hashing,
+                            "MiB/s"
+                        )
+                    ));
+                }
+                line.push_str(&format!(
+                    ", {} failed ({:.1}%)",
+                    volume.failed,
+                    volume.failed as f64 * 100.0 / volume.files.max(1) as f64
+                ));
+                line
+            })
+            .collect()
+    }
+}
+
+fn rate(amount: f64, elapsed: Duration, unit: &str) -> String {
+    let seconds = elapsed.as_secs_f64();
+    if seconds == 0.0 {
+        return format!("- {}", unit);
+    }
+    format!("{:.1} {}", amount / seconds, unit)
+}
+
+/// Whether `a` and `b` are on the same volume, so a file can be renamed
+/// from one to the other. Paths that do not exist yet are judged by their
+/// nearest existing folder; when neither can be read, the answer is yes and
+/// the rename itself reports the problem.
+pub fn same_volume(a: &Path, b: &Path) -> bool {
+    let id = |path: &Path| {
+        path.ancestors().find_map(|ancestor| {
+            std::fs::metadata(ancestor)
+                .ok()
+                .map(|metadata| volume_id(ancestor, &metadata))
+        })
+    };
+    match (id(a), id(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    }
+}
+
+/// The volume the directory at `path` is on, for `--one-file-system`.
+pub fn volume_of(path: &Path) -> std::io::Result<VolumeId> {
+    let metadata = trace::call("stat", path, || std::fs::metadata(path))?;
+    Ok(volume_id(path, &metadata))
+}
+
+#[cfg(unix)]
+fn volume_id(_path: &Path, metadata: &Metadata) -> VolumeId {
+    use std::os::unix::fs::MetadataExt;
+    metadata.dev()
+}
+
+/// The drive letter or `\\server\share` the path is on.
+#[cfg(not(unix))]
+fn volume_id(path: &Path, _metadata: &Metadata) -> VolumeId {
+    match path.components().next() {
+        Some(std::path::Component::Prefix(prefix)) => {
+            prefix.as_os_str().to_string_lossy().to_uppercase()
+        }
+        _ => String::new(),
+    }
+}
+
+/// The topmost directory above `path` on the same volume.
+#[cfg(unix)]
+fn mount_point(path: &Path, id: &VolumeId) -> PathBuf {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut mount = path.parent().unwrap_or(path);
+    for ancestor in path.ancestors().skip(2) {
+        match std::fs::metadata(ancestor) {
+            Ok(metadata) if metadata.dev() == *id => mount = ancestor,
+            _ => break,
+        }
+    }
+    mount.to_path_buf()
+}
+
+#[cfg(not(unix))]
+fn mount_point(path: &Path, _id: &VolumeId) -> PathBuf {
+    path.ancestors().last().unwrap_or(path).to_path_buf()
+}
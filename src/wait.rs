@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::trace;
+
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Waits up to `timeout` for `path` to be a readable, non-empty directory,
+/// retrying with exponential backoff. An empty directory counts as not ready
+/// because it is usually the mount point of a volume that is not attached
+/// yet.
+pub fn wait_for_dir(path: &Path, timeout: Duration) -> Result<(), String> {
+    let deadline = Instant::now() + timeout;
+    let mut delay = FIRST_DELAY;
+    let mut announced = false;
+
+    loop {
+        let problem = match trace::call("readdir", path, || fs::read_dir(path)) {
+            Ok(mut entries) => {
+                if entries.next().is_some() {
+                    return Ok(());
+                }
+                "is empty".to_string()
+            }
+            Err(err) => format!("is not accessible: {}", err),
+        };
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(format!(
+                "{} {} (gave up after waiting {}s)",
+                path.display(),
+                problem,
+                timeout.as_secs()
+            ));
+        }
+
+        if !announced {
+            eprintln!(
+                "Waiting up to {}s for {} to become available...",
+                timeout.as_secs(),
+                path.display()
+            );
+            announced = true;
+        }
+
+        thread::sleep(delay.min(deadline - now));
+        delay = (delay * 2).min(MAX_DELAY);
+    }
+}
@@ -0,0 +1,818 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::backend::{self, Backend};
+use crate::card::Cards;
+use crate::clip;
+use crate::interrupt;
+use crate::journal::Journal;
+use crate::notify::{self, Digest};
+use crate::publish::Publisher;
+use crate::r3d;
+use crate::runlock;
+use crate::sidecar::Sidecars;
+use crate::space;
+use crate::spool::PathOrder;
+use crate::{
+    CompoundRule, Config, Outcome, Status, Summary, Verbosity, changed, collect_files, color,
+    datetime, display_relative, has_extension, is_compound, outcome_of, walk_reaches,
+};
+
+/// How long a new file must stay unchanged before it is renamed, unless
+/// `--settle` says otherwise.
+pub const DEFAULT_SETTLE: Duration = Duration::from_secs(5);
+
+/// A `--settle` value: how long files must stay unchanged, or with
+/// `FOLDER=TIME` the files in one folder.
+#[derive(Clone, Debug)]
+pub struct Settle {
+    pub folder: Option<PathBuf>,
+    pub period: Duration,
+}
+
+impl Settle {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (folder, time) = match value.rsplit_once('=') {
+            Some(("", _)) => return None,
+            Some((folder, time)) => (Some(PathBuf::from(folder)), time),
+            None => (None, value),
+        };
+        Some(Self {
+            folder,
+            period: datetime::parse_duration(time)?,
+        })
+    }
+}
+
+/// A `--priority FOLDER=N` value: files in the folder are renamed ahead of
+/// those with a lower number.
+#[derive(Clone, Debug)]
+pub struct Priority {
+    pub folder: PathBuf,
+    pub rank: i32,
+}
+
+impl Priority {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (folder, rank) = value.rsplit_once('=')?;
+        if folder.is_empty() {
+            return None;
+        }
+        Some(Self {
+            folder: PathBuf::from(folder),
+            rank: rank.trim().parse().ok()?,
+        })
+    }
+}
+
+/// How many settled files a batch takes with `--priority`, so files that
+/// settle meanwhile are ranked before the rest are renamed.
+const PRIORITY_BATCH: usize = 100;
+
+/// How often pending files are checked again.
+const TICK: Duration = Duration::from_secs(1);
+
+/// How often the whole tree is scanned again. Notifications miss changes
+/// made by other machines on a network share, and platforms without a
+/// notification backend rely on the scan alone.
+#[cfg(target_os = "linux")]
+const RESCAN: Duration = Duration::from_secs(60);
+#[cfg(not(target_os = "linux"))]
+const RESCAN: Duration = Duration::from_secs(5);
+
+/// A file seen but not renamed yet, with the size and modification time it
+/// had at the last check.
+struct Pending {
+    len: u64,
+    modified: Option<SystemTime>,
+    /// When the size or modification time last changed.
+    changed: Instant,
+    /// Whether a check has found it unchanged since.
+    steady: bool,
+    /// When it was first seen, for ranking with `--priority`.
+    arrived: Instant,
+}
+
+impl Pending {
+    /// A file not checked yet, so its first check counts as a change.
+    fn new() -> Self {
+        Self {
+            len: u64::MAX,
+            modified: None,
+            changed: Instant::now(),
+            steady: false,
+            arrived: Instant::now(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Totals {
+    converted: usize,
+    skipped: usize,
+    failed: usize,
+}
+
+/// How long `path` must stay unchanged: the period of the deepest folder
+/// with its own that holds it, or `--settle`.
+fn settle_period(config: &Config, path: &Path) -> Duration {
+    deepest(&config.settle_folders, path).unwrap_or(config.settle)
+}
+
+/// The `--priority` of `path`: that of the deepest folder given one that
+/// holds it, or 0.
+fn priority(config: &Config, path: &Path) -> i32 {
+    deepest(&config.priorities, path).unwrap_or(0)
+}
+
+/// The value of the deepest of `folders` that holds `path`.
+fn deepest<T: Copy>(folders: &[(PathBuf, T)], path: &Path) -> Option<T> {
+    folders
+        .iter()
+        .filter(|(folder, _)| path.starts_with(folder))
+        .max_by_key(|(folder, _)| folder.components().count())
+        .map(|(_, value)| *value)
+}
+
+/// Renames matching files under the root as they appear, until Ctrl-C or
+/// SIGTERM, which finish the batch in progress, close the journal and
+/// release the run lock. A file is renamed once its size and modification
+/// time have stayed the same across two checks at least `settle` apart, so
+/// files still being copied or uploaded are left alone until complete.
+pub fn watch(config: &Config) -> Result<(), String> {
+    interrupt::catch();
+    let _lock = if config.dry_run {
+        None
+    } else {
+        Some(runlock::acquire(&config.roots, config.force_lock)?)
+    };
+    crate::prepare_tree(config)?;
+
+    let mut backend = backend::open(
+        config.backend,
+        config.jobs,
+        config.copy,
+        config.verify.then_some(config.verify_hash),
+        config.on_conflict,
+        config.allow_copy,
+        config.preserve,
+        config.chmod_writable,
+    )?;
+    let publisher = Publisher::start(&config.publish);
+    let mut notifier = Notifier::new(&config.root);
+    let mut pending: HashMap<PathBuf, Pending> = HashMap::new();
+    let mut journal: Option<Journal> = None;
+    let mut journal_failed = false;
+    let mut totals = Totals::default();
+    let mut paused = false;
+
+    let folders = match config.settle_folders.len() {
+        0 => String::new(),
+        1 => ", 1 folder with its own".to_string(),
+        count => format!(", {} folders with their own", count),
+    };
+    println!(
+        "Watching {} for .{} files (settle {}s{}); press Ctrl-C to stop",
+        config.root.display(),
+        config.source_extension(),
+        config.settle.as_secs_f64(),
+        folders
+    );
+
+    let mut found = scan(config, true)?;
+    let mut scanned = Instant::now();
+    let stopped = loop {
+        if interrupt::stopped() {
+            break Ok(());
+        }
+        for path in found.drain(..) {
+            // Files already pending keep their quiet period; the checks
+            // below notice any further writes.
+            if !pending.contains_key(&path) && wanted(config, &path) {
+                pending.insert(path, Pending::new());
+            }
+        }
+
+        let (mut ready, held_back) = settle(&mut pending, config);
+        // --min-free pauses the copies until there is room again; the
+        // files wait their settle period before the next check.
+        if let Some(min_free) = config.min_free
+            && !ready.is_empty()
+        {
+            let files = ready.iter().map(|path| {
+                let bytes = fs::metadata(path).map_or(0, |metadata| metadata.len());
+                (config.target_of(path), bytes)
+            });
+            match space::short(files, min_free) {
+                Some(reason) => {
+                    if !paused {
+                        let message = format!("Paused: {}", reason);
+                        eprintln!("{}", color::warning(&message));
+                        tracing::warn!("{}", message);
+                        paused = true;
+                    }
+                    for path in ready.drain(..) {
+                        pending.insert(path, Pending::new());
+                    }
+                }
+                None if paused => {
+                    println!("Enough free space again; resuming");
+                    paused = false;
+                }
+                None => {}
+            }
+        }
+        if !ready.is_empty() {
+            rename(
+                config,
+                backend.as_mut(),
+                ready,
+                publisher.as_ref(),
+                &mut journal,
+                &mut journal_failed,
+                &mut totals,
+            );
+            println!(
+                "[{}] {} {} so far (skipped: {}, failed: {}), {} waiting to settle",
+                datetime::rfc3339(SystemTime::now()),
+                if config.copy { "Copied" } else { "Converted" },
+                color::done(totals.converted),
+                color::skipped(totals.skipped),
+                color::failed(totals.failed),
+                pending.len()
+            );
+        }
+
+        // Events go out in the background, so their failures turn up later.
+        for err in publisher.iter().flat_map(Publisher::failures) {
+            eprintln!("{}", color::failure(&err));
+            tracing::error!("{}", err);
+        }
+
+        if scanned.elapsed() >= RESCAN {
+            found = match scan(config, false) {
+                Ok(found) => found,
+                Err(err) => break Err(err),
+            };
+            scanned = Instant::now();
+        } else {
+            // Settled files held back for the next batch go straight on.
+            found = notifier.wait(if held_back { Duration::ZERO } else { TICK });
+        }
+    };
+
+    // Marks the session finished, so `r3dy resume` does not take it for an
+    // interrupted run.
+    if let Some(journal) = journal {
+        journal.finish();
+    }
+    if let Some(publisher) = publisher {
+        for err in publisher.finish() {
+            eprintln!("{}", color::failure(&err));
+            tracing::error!("{}", err);
+        }
+    }
+    if stopped.is_ok() {
+        println!(
+            "Stopped watching {}: {} {} (skipped: {}, failed: {})",
+            config.root.display(),
+            if config.copy { "copied" } else { "converted" },
+            color::done(totals.converted),
+            color::skipped(totals.skipped),
+            color::failed(totals.failed)
+        );
+    }
+    stopped
+}
+
+/// Lists every matching file under the root. Warnings are only shown, and
+/// runs on folders below checked for, the first time, so a rescan does not
+/// repeat them every minute.
+fn scan(config: &Config, show_warnings: bool) -> Result<Vec<PathBuf>, String> {
+    if !config.root.is_dir() {
+        return Err(format!("{} is no longer available", config.root.display()));
+    }
+    let collected = collect_files(
+        &config.root,
+        &config.roots,
+        config.source_extension(),
+        config.compound,
+        &config.filter,
+        PathOrder::Path,
+        config.walk(),
+        config.memory_limit,
+    )?;
+    if show_warnings {
+        crate::print_warnings(&collected, config.verbosity == Verbosity::Quiet);
+        if !config.dry_run {
+            runlock::check_below(&collected.locks, config.force_lock)?;
+        }
+    }
+    collected
+        .files
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("Failed to read back the file list: {}", err))
+}
+
+/// Whether a path reported by the notifier is a file r3dy would rename.
+fn wanted(config: &Config, path: &Path) -> bool {
+    has_extension(path, config.source_extension())
+        && !(config.compound == CompoundRule::Skip && is_compound(path))
+        && config
+            .filter
+            .allows(path.strip_prefix(&config.root).unwrap_or(path))
+        && walk_reaches(&config.root, path, config.walk(), &config.filter)
+        && fs::metadata(path).is_ok_and(|metadata| metadata.is_file())
+}
+
+/// Checks every pending file and returns, in path order, those that have
+/// settled for their folder's settle period, holding back a clip until all
+/// its files have. Files that disappeared are dropped. With `--priority`,
+/// the clips are ranked instead and only a batch of them is returned, with
+/// whether settled files were held back for the next.
+fn settle(pending: &mut HashMap<PathBuf, Pending>, config: &Config) -> (Vec<PathBuf>, bool) {
+    let mut settled = HashSet::new();
+    pending.retain(|path, file| {
+        let Ok(metadata) = changed::stat(path) else {
+            return false;
+        };
+        let modified = metadata.modified().ok();
+        if metadata.len() != file.len || modified != file.modified {
+            file.len = metadata.len();
+            file.modified = modified;
+            file.changed = Instant::now();
+            file.steady = false;
+            return true;
+        }
+        if file.steady && file.changed.elapsed() >= settle_period(config, path) {
+            settled.insert(path.clone());
+        }
+        file.steady = true;
+        true
+    });
+
+    let unsettled: HashSet<PathBuf> = pending
+        .keys()
+        .filter(|path| !settled.contains(*path))
+        .map(|path| clip::key(path, config.clip_folders))
+        .collect();
+    let mut ready: Vec<PathBuf> = settled
+        .into_iter()
+        .filter(|path| !unsettled.contains(&clip::key(path, config.clip_folders)))
+        .collect();
+    ready.sort();
+    let held_back = if config.priorities.is_empty() {
+        false
+    } else {
+        rank(config, pending, &mut ready)
+    };
+    for path in &ready {
+        pending.remove(path);
+    }
+    (ready, held_back)
+}
+
+/// Orders the settled files in `ready` by clip: the highest `--priority`
+/// first and, within one, the clip seen last first, so a fresh card is not
+/// stuck behind a long sweep. Keeps the first `PRIORITY_BATCH` files, in
+/// whole clips, and returns whether any were left for later.
+fn rank(config: &Config, pending: &HashMap<PathBuf, Pending>, ready: &mut Vec<PathBuf>) -> bool {
+    let mut by_clip: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for path in ready.drain(..) {
+        by_clip
+            .entry(clip::key(&path, config.clip_folders))
+            .or_default()
+            .push(path);
+    }
+    let mut clips: Vec<(PathBuf, Vec<PathBuf>)> = by_clip.into_iter().collect();
+    let arrived = |files: &[PathBuf]| {
+        files
+            .iter()
+            .filter_map(|path| pending.get(path))
+            .map(|file| file.arrived)
+            .max()
+    };
+    clips.sort_by_cached_key(|(key, files)| {
+        (
+            Reverse(priority(config, key)),
+            Reverse(arrived(files)),
+            key.clone(),
+        )
+    });
+
+    let mut clips = clips.into_iter().peekable();
+    while let Some((_, files)) =
+        clips.next_if(|(_, files)| ready.is_empty() || ready.len() + files.len() <= PRIORITY_BATCH)
+    {
+        ready.extend(files);
+    }
+    clips.peek().is_some()
+}
+
+fn rename(
+    config: &Config,
+    backend: &mut dyn Backend,
+    ready: Vec<PathBuf>,
+    publisher: Option<&Publisher>,
+    journal: &mut Option<Journal>,
+    journal_failed: &mut bool,
+    totals: &mut Totals,
+) {
+    // Sidecars are looked up afresh each time, as they may arrive with
+    // their clip.
+    let mut sidecars = config.sidecars.then(Sidecars::default);
+    let mut batch = Vec::with_capacity(ready.len());
+    // Files that failed before reaching the backend, for the webhooks.
+    let mut unnamed = Vec::new();
+    for path in ready {
+        if config.check_headers
+            && let Err(err) = r3d::check(&path)
+        {
+            totals.failed += 1;
+            let message = format!(
+                "Failed to {} {}: {}",
+                if config.copy { "copy" } else { "rename" },
+                display_relative(&config.root, &path),
+                err
+            );
+            eprintln!("{}", color::failure(&message));
+            tracing::error!("{}", message);
+            unnamed.push(Outcome::new(
+                path.clone(),
+                path,
+                Status::Failed,
+                Some(err.to_string()),
+            ));
+            continue;
+        }
+        let target = match &config.template {
+            Some(template) => match template.render(&path) {
+                Ok(name) => config.target_named(&path, &name).0,
+                Err(err) => {
+                    totals.failed += 1;
+                    let message = format!(
+                        "Failed to name {} with --template: {}",
+                        display_relative(&config.root, &path),
+                        err
+                    );
+                    eprintln!("{}", color::failure(&message));
+                    tracing::error!("{}", message);
+                    unnamed.push(Outcome::new(path.clone(), path, Status::Failed, Some(err)));
+                    continue;
+                }
+            },
+            None => config.target_of(&path),
+        };
+        let followers = match sidecars.as_mut() {
+            Some(sidecars) => sidecars.pairs(&path, &target),
+            None => Vec::new(),
+        };
+        batch.push((path, target));
+        batch.extend(followers);
+    }
+
+    let results = backend.rename_batch(&batch);
+    let mut outcomes: Vec<_> = batch
+        .into_iter()
+        .zip(results)
+        .map(|((path, target), result)| outcome_of(path, target, result))
+        .collect();
+    for line in clip::roll_back_partial(
+        &config.root,
+        &mut outcomes,
+        config.clip_folders,
+        config.copy,
+        false,
+    ) {
+        println!("{}", line);
+    }
+
+    for outcome in &outcomes {
+        let source = display_relative(&config.root, &outcome.source);
+        match outcome.status {
+            Status::Converted => {
+                totals.converted += 1;
+                let message = format!(
+                    "{} {} \u{2192} {}",
+                    if config.copy { "Copied" } else { "Renamed" },
+                    source,
+                    display_relative(&config.root, &outcome.target)
+                );
+                if config.verbosity > Verbosity::Quiet {
+                    println!("{}", message);
+                }
+                tracing::info!("{}", message);
+                record(
+                    config,
+                    journal,
+                    journal_failed,
+                    &outcome.source,
+                    &outcome.target,
+                );
+            }
+            Status::Skipped => {
+                totals.skipped += 1;
+                // Rolled back segments were reported above.
+                if outcome.error.as_deref() == Some(crate::TARGET_EXISTS) {
+                    let message = format!(
+                        "Skipping {} ({} already exists)",
+                        source,
+                        display_relative(&config.root, &outcome.target)
+                    );
+                    if config.verbosity > Verbosity::Quiet {
+                        println!("{}", message);
+                    }
+                    tracing::info!("{}", message);
+                }
+            }
+            // It went away between settling and renaming; if it comes
+            // back, the notifier reports it again.
+            Status::Changed => {}
+            Status::Failed | Status::Denied => {
+                totals.failed += 1;
+                let message = format!(
+                    "Failed to {} {}: {}",
+                    if config.copy { "copy" } else { "rename" },
+                    source,
+                    outcome.error.as_deref().unwrap_or("unknown error")
+                );
+                eprintln!("{}", color::failure(&message));
+                tracing::error!("{}", message);
+            }
+        }
+    }
+
+    if let Some(publisher) = publisher {
+        for outcome in unnamed.iter().chain(&outcomes) {
+            publisher.file_event(&config.root, outcome);
+        }
+    }
+
+    for webhook in &config.webhooks {
+        let done = unnamed.iter().chain(&outcomes).cloned();
+        let digest = Digest::new(webhook, &config.root, done, true);
+        if digest.is_empty() {
+            continue;
+        }
+        if let Err(err) = notify::post_webhook(webhook, &digest) {
+            eprintln!("{}", color::failure(&err));
+            tracing::error!("{}", err);
+        }
+    }
+
+    if let Some(email) = &config.email {
+        let mut summary = Summary::new(config.memory_limit / 2, Cards::new(Vec::new()));
+        for outcome in unnamed.into_iter().chain(outcomes) {
+            if let Err(err) = summary.record(outcome) {
+                let err = format!("Failed to spool the batch for email: {}", err);
+                eprintln!("{}", color::failure(&err));
+                tracing::error!("{}", err);
+                return;
+            }
+        }
+        if let Err(err) = notify::send_email(email, &config.root, &summary, true) {
+            eprintln!("{}", color::failure(&err));
+            tracing::error!("{}", err);
+        }
+    }
+}
+
+/// Adds a rename to the session's journal, starting it with the first
+/// rename so a session that renames nothing leaves no journal behind.
+fn record(
+    config: &Config,
+    journal: &mut Option<Journal>,
+    journal_failed: &mut bool,
+    source: &Path,
+    target: &Path,
+) {
+    if !config.journal || *journal_failed {
+        return;
+    }
+    if journal.is_none() {
+        match Journal::create(
+            &config.root,
+            &config.roots,
+            config.source_extension(),
+            config.target_extension(),
+            config.copy,
+            config.args.as_deref(),
+        ) {
+            Ok(created) => {
+                println!(
+                    "Journal: {} (r3dy undo reverses this session)",
+                    created.path().display()
+                );
+                *journal = Some(created);
+            }
+            Err(err) => {
+                eprintln!(
+                    "{}",
+                    color::warning(&format!("Warning: {}; this session cannot be undone", err))
+                );
+                *journal_failed = true;
+                return;
+            }
+        }
+    }
+    if let Some(open) = journal.as_mut()
+        && let Err(err) = open.record(source, target)
+    {
+        eprintln!(
+            "{}",
+            color::warning(&format!(
+                "Warning: {}; the rest of this session cannot be undone",
+                err
+            ))
+        );
+        *journal = None;
+        *journal_failed = true;
+    }
+}
+
+/// Reports paths that may have been created or finished under the root.
+/// Uses inotify on Linux; elsewhere it only waits, and the periodic rescan
+/// finds new files.
+enum Notifier {
+    #[cfg(target_os = "linux")]
+    Inotify(inotify::Inotify),
+    Rescan,
+}
+
+impl Notifier {
+    fn new(root: &Path) -> Self {
+        #[cfg(target_os = "linux")]
+        match inotify::Inotify::new(root) {
+            Ok(inotify) => return Notifier::Inotify(inotify),
+            Err(err) => eprintln!(
+                "{}",
+                color::warning(&format!(
+                    "Warning: file notifications are not available ({}); scanning every {}s instead",
+                    err,
+                    RESCAN.as_secs()
+                ))
+            ),
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = root;
+        Notifier::Rescan
+    }
+
+    /// Waits up to `timeout` and returns the paths that changed meanwhile.
+    fn wait(&mut self, timeout: Duration) -> Vec<PathBuf> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Notifier::Inotify(inotify) => inotify.wait(timeout),
+            Notifier::Rescan => {
+                std::thread::sleep(timeout);
+                Vec::new()
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::collections::HashMap;
+    use std::ffi::{CString, OsStr};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use crate::dirent::{self, Kind};
+
+    const FILE_EVENTS: u32 = libc::IN_CREATE | libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+    const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+
+    /// One watch per directory of the tree; new directories are added as
+    /// they appear.
+    pub struct Inotify {
+        fd: OwnedFd,
+        dirs: HashMap<i32, PathBuf>,
+        buffer: Vec<u8>,
+    }
+
+    impl Inotify {
+        pub fn new(root: &Path) -> io::Result<Self> {
+            // SAFETY: plain syscall; the result is checked below.
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut inotify = Self {
+                // SAFETY: fd is a freshly created descriptor we own.
+                fd: unsafe { OwnedFd::from_raw_fd(fd) },
+                dirs: HashMap::new(),
+                buffer: vec![0; 64 * 1024],
+            };
+            inotify.add(root)?;
+            let mut found = Vec::new();
+            inotify.add_below(root, &mut found);
+            Ok(inotify)
+        }
+
+        fn add(&mut self, dir: &Path) -> io::Result<()> {
+            let name = CString::new(dir.as_os_str().as_bytes())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            // SAFETY: fd is open and name is a valid C string.
+            let wd = unsafe {
+                libc::inotify_add_watch(
+                    self.fd.as_raw_fd(),
+                    name.as_ptr(),
+                    FILE_EVENTS | libc::IN_ONLYDIR,
+                )
+            };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.dirs.insert(wd, dir.to_path_buf());
+            Ok(())
+        }
+
+        /// Watches every directory below `dir` and collects the files
+        /// already there, which were written before their folder's watch.
+        fn add_below(&mut self, dir: &Path, found: &mut Vec<PathBuf>) {
+            let mut stack = vec![dir.to_path_buf()];
+            while let Some(dir) = stack.pop() {
+                let Ok(entries) = dirent::read_dir(&dir) else {
+                    continue;
+                };
+                for entry in entries.flatten() {
+                    match entry.kind {
+                        Kind::Dir => {
+                            if self.add(&entry.path).is_ok() {
+                                stack.push(entry.path);
+                            }
+                        }
+                        _ => found.push(entry.path),
+                    }
+                }
+            }
+        }
+
+        pub fn wait(&mut self, timeout: Duration) -> Vec<PathBuf> {
+            let mut poll = libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: poll reads one valid pollfd.
+            let ready = unsafe { libc::poll(&mut poll, 1, timeout.as_millis() as libc::c_int) };
+            if ready <= 0 {
+                return Vec::new();
+            }
+
+            let mut found = Vec::new();
+            loop {
+                // SAFETY: reads into our own buffer of the given length.
+                let read = unsafe {
+                    libc::read(
+                        self.fd.as_raw_fd(),
+                        self.buffer.as_mut_ptr().cast(),
+                        self.buffer.len(),
+                    )
+                };
+                if read <= 0 {
+                    break;
+                }
+                let mut events = Vec::new();
+                let mut offset = 0;
+                while offset + HEADER <= read as usize {
+                    // SAFETY: the kernel wrote a whole event at offset;
+                    // read_unaligned copes with the byte buffer's alignment.
+                    let event: libc::inotify_event = unsafe {
+                        std::ptr::read_unaligned(self.buffer.as_ptr().add(offset).cast())
+                    };
+                    let name = &self.buffer[offset + HEADER..offset + HEADER + event.len as usize];
+                    let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                    events.push((event.wd, event.mask, name.to_vec()));
+                    offset += HEADER + event.len as usize;
+                }
+
+                for (wd, mask, name) in events {
+                    if mask & libc::IN_IGNORED != 0 {
+                        self.dirs.remove(&wd);
+                        continue;
+                    }
+                    let Some(dir) = self.dirs.get(&wd) else {
+                        continue;
+                    };
+                    let path = dir.join(OsStr::from_bytes(&name));
+                    if mask & libc::IN_ISDIR != 0 {
+                        if self.add(&path).is_ok() {
+                            self.add_below(&path, &mut found);
+                        }
+                    } else {
+                        found.push(path);
+                    }
+                }
+            }
+            found
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::io;
+use std::path::{Component, Path, PathBuf, Prefix};
+
+/// Resolves the scan root to an absolute, canonical path. On Windows this is
+/// the extended-length form (`\\?\C:\...`, `\\?\UNC\server\share\...`), so
+/// deep card structures can be joined onto it past the 260-character limit.
+/// Some SMB servers cannot report a final path; for UNC roots r3dy then
+/// builds the extended-length form itself.
+pub fn resolve(root: &Path) -> io::Result<PathBuf> {
+    match root.canonicalize() {
+        Ok(resolved) => Ok(resolved),
+        Err(_) if cfg!(windows) && is_unc(root) => extended(root),
+        Err(err) => Err(err),
+    }
+}
+
+/// The extended-length form of `path`, worked out without touching the disk,
+/// for a folder that does not exist yet such as a fresh `--dest`:
+/// `D:\Ingest` becomes `\\?\D:\Ingest`, and `\\server\share` becomes
+/// `\\?\UNC\server\share`. Elsewhere there is no such limit, and the path
+/// is returned as it is.
+pub fn extended(path: &Path) -> io::Result<PathBuf> {
+    if !cfg!(windows) {
+        return Ok(path.to_path_buf());
+    }
+    let absolute = std::path::absolute(path)?;
+    let mut components = absolute.components();
+    let mut verbatim = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) => PathBuf::from(format!(r"\\?\{}:\", letter as char)),
+            Prefix::UNC(server, share) => PathBuf::from(format!(
+                r"\\?\UNC\{}\{}\",
+                server.to_string_lossy(),
+                share.to_string_lossy()
+            )),
+            // Already extended, or a device path.
+            _ => return Ok(absolute),
+        },
+        _ => return Ok(absolute),
+    };
+    verbatim.extend(components.filter(|c| *c != Component::RootDir));
+    Ok(verbatim)
+}
+
+/// `relative` joined onto `root` component by component. An extended-length
+/// root takes every character after it literally, so a `/` in a path from a
+/// file list has to become a separator before the join rather than after.
+pub fn join(root: &Path, relative: &Path) -> PathBuf {
+    let mut path = root.to_path_buf();
+    path.extend(relative.components());
+    path
+}
+
+/// Whether `path` is a network path such as `\\server\share\footage`.
+pub fn is_unc(path: &Path) -> bool {
+    matches!(
+        path.components().next(),
+        Some(Component::Prefix(prefix))
+            if matches!(prefix.kind(), Prefix::UNC(..) | Prefix::VerbatimUNC(..))
+    )
+}
+
+/// Shows a path the way users typed it, without the `\\?\` extended-length
+/// prefix: `\\?\UNC\server\share\A001` becomes `\\server\share\A001`.
+pub fn display(path: &Path) -> String {
+    let mut components = path.components();
+    let shown = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::VerbatimUNC(server, share) => {
+                let mut shown = PathBuf::from(format!(
+                    r"\\{}\{}\",
+                    server.to_string_lossy(),
+                    share.to_string_lossy()
+                ));
+                shown.extend(components.filter(|c| *c != Component::RootDir));
+                shown
+            }
+            Prefix::VerbatimDisk(letter) => {
+                let mut shown = PathBuf::from(format!(r"{}:\", letter as char));
+                shown.extend(components.filter(|c| *c != Component::RootDir));
+                shown
+            }
+            _ => path.to_path_buf(),
+        },
+        _ => path.to_path_buf(),
+    };
+    shown.display().to_string()
+}
+
+/// Advice for errors Windows returns when a share is unreachable or needs
+/// credentials the session does not have.
+pub fn access_hint(path: &Path, err: &io::Error) -> Option<String> {
+    if !cfg!(windows) || !is_unc(path) {
+        return None;
+    }
+
+    let share = share_root(path);
+    let hint = match err.raw_os_error()? {
+        // ERROR_ACCESS_DENIED, ERROR_NETWORK_ACCESS_DENIED, ERROR_LOGON_FAILURE
+        5 | 65 | 1326 => format!(
+            "Windows has no valid credentials for {share}. Connect once with `net use {share} /user:DOMAIN\\user` (or map the share in Explorer), then run r3dy again."
+        ),
+        // ERROR_SESSION_CREDENTIAL_CONFLICT
+        1219 => format!(
+            "{share} is already connected under a different user. Disconnect with `net use {share} /delete` and reconnect with the account that can reach the footage."
+        ),
+        // ERROR_BAD_NETPATH, ERROR_BAD_NET_NAME
+        53 | 67 => format!(
+            "{share} could not be found. Check the server and share names, and that the server is reachable from this machine."
+        ),
+        _ => return None,
+    };
+    Some(hint)
+}
+
+/// `\\server\share` for any path on that share.
+fn share_root(path: &Path) -> String {
+    match path.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => format!(
+                r"\\{}\{}",
+                server.to_string_lossy(),
+                share.to_string_lossy()
+            ),
+            _ => display(path),
+        },
+        _ => display(path),
+    }
+}
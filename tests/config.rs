@@ -0,0 +1,76 @@
+//! Config files and `--profile`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Runs r3dy in `dir`, which holds the project's `.r3dy.toml`, with no
+/// user config file.
+fn r3dy(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_r3dy"))
+        .args(args)
+        .current_dir(dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("APPDATA", dir.join("config"))
+        .env("R3DY_JOURNAL_DIR", dir.join("journal"))
+        .output()
+        .unwrap()
+}
+
+/// A project file whose `check` profile only plans the renames.
+fn project(name: &str) -> PathBuf {
+    let dir = scratch(name);
+    fs::create_dir(dir.join("card")).unwrap();
+    fs::write(dir.join("card").join("A.NEV"), b"clip").unwrap();
+    fs::write(
+        dir.join(".r3dy.toml"),
+        "quiet = true\n\n[profile.check]\ndry_run = true\n",
+    )
+    .unwrap();
+    dir
+}
+
+#[test]
+fn profile_is_applied_in_both_spellings() {
+    for (name, args) in [
+        (
+            "profile_separate",
+            ["--profile", "check", "card"].as_slice(),
+        ),
+        ("profile_equals", ["--profile=check", "card"].as_slice()),
+    ] {
+        let dir = project(name);
+        let output = r3dy(&dir, args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{:?}: {}", args, stderr);
+        assert!(dir.join("card").join("A.NEV").exists(), "{:?}", args);
+        assert!(!dir.join("card").join("A.R3D").exists(), "{:?}", args);
+    }
+}
+
+#[test]
+fn unknown_profile_is_refused_in_both_spellings() {
+    for (name, args) in [
+        ("unknown_separate", ["--profile", "nope", "card"].as_slice()),
+        ("unknown_equals", ["--profile=nope", "card"].as_slice()),
+    ] {
+        let dir = project(name);
+        let output = r3dy(&dir, args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(4), "{:?}: {}", args, stderr);
+        assert!(
+            stderr.contains("No [profile.nope]"),
+            "{:?}: {}",
+            args,
+            stderr
+        );
+        assert!(dir.join("card").join("A.NEV").exists(), "{:?}", args);
+    }
+}
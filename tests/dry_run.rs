@@ -0,0 +1,37 @@
+//! The `--dry-run` summary line.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Only files whose target is taken count as conflicts; the segment held
+/// back with its clip is skipped.
+#[test]
+fn conflicts_count_only_taken_targets() {
+    let dir = scratch("dry_run_conflicts");
+    let card = dir.join("card");
+    fs::create_dir(&card).unwrap();
+    fs::write(card.join("A001_C001_0101AB_001.NEV"), b"clip").unwrap();
+    fs::write(card.join("A001_C001_0101AB_002.NEV"), b"clip").unwrap();
+    fs::write(card.join("A001_C001_0101AB_002.R3D"), b"taken").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_r3dy"))
+        .args(["--no-config", "--no-journal", "--dry-run"])
+        .arg(&card)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains("Would convert 0 files (skipped: 1, conflicts: 1, failed: 0)"),
+        "{}",
+        stdout
+    );
+}
@@ -0,0 +1,70 @@
+//! Journals kept in a folder several stations share.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn r3dy(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_r3dy"))
+        .args(args)
+        .env("R3DY_JOURNAL_DIR", dir.join("journal"))
+        .env("R3DY_OPERATOR", "alice")
+        .output()
+        .unwrap()
+}
+
+/// Each rename says who made it and where, and an undo is refused once a
+/// later run on another station has renamed its files again.
+#[test]
+fn undo_is_refused_after_another_station_renamed_the_files() {
+    let dir = scratch("journal_stations");
+    let card = dir.join("card");
+    fs::create_dir(&card).unwrap();
+    fs::write(card.join("A.NEV"), b"clip").unwrap();
+
+    let output = r3dy(&dir, &["--no-config", card.to_str().unwrap()]);
+    assert!(output.status.success());
+    let journal = fs::read_dir(dir.join("journal"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .next()
+        .unwrap();
+    let text = fs::read_to_string(&journal).unwrap();
+    let rename = text
+        .lines()
+        .find(|line| line.contains("\"source\""))
+        .unwrap();
+    assert!(rename.contains("\"operator\":\"alice\""), "{}", rename);
+    assert!(rename.contains("\"host\":"), "{}", rename);
+
+    // The other station's run, as it would have written it.
+    let renamed = card.join("A.R3D");
+    let moved = card.join("B.R3D");
+    fs::rename(&renamed, &moved).unwrap();
+    fs::write(
+        dir.join("journal").join("9999-01-01T00-00-00Z-1.jsonl"),
+        format!(
+            "{{\"journal\":1,\"root\":{:?},\"started\":\"9999-01-01T00:00:00Z\",\"from\":\"R3D\",\"to\":\"R3D\",\"copy\":false,\"operator\":\"bob\",\"host\":\"elsewhere\"}}\n\
+             {{\"source\":{:?},\"target\":{:?}}}\n\
+             {{\"finished\":\"9999-01-01T00:00:01Z\"}}\n",
+            card.to_str().unwrap(),
+            renamed.to_str().unwrap(),
+            moved.to_str().unwrap()
+        ),
+    )
+    .unwrap();
+
+    let output = r3dy(&dir, &["undo", "--journal", journal.to_str().unwrap()]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.contains("by bob on elsewhere"), "{}", stderr);
+    assert!(moved.exists());
+    assert!(journal.exists());
+}
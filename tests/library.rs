@@ -0,0 +1,72 @@
+//! The `Renamer` library API renames the way `r3dy rename` does.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use r3dy::{Renamer, Status};
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A clip that fails `--check-headers` keeps its sidecar with it, and the
+/// sidecar is reported, as on the command line.
+#[test]
+fn sidecars_stay_with_a_clip_that_fails_the_header_check() {
+    let dir = scratch("library_sidecars");
+    fs::write(dir.join("A001_C001.NEV"), b"not a clip").unwrap();
+    fs::write(dir.join("A001_C001.RMD"), b"metadata").unwrap();
+    fs::create_dir(dir.join("out")).unwrap();
+
+    let mut renamer = Renamer::new(&dir);
+    renamer.set_check_headers(true);
+    renamer.set_sidecars(true);
+    renamer.set_dest(dir.join("out"));
+    let scan = renamer.scan().unwrap();
+    let execution = renamer.execute(&scan).unwrap();
+
+    let statuses: Vec<Status> = execution
+        .outcomes
+        .iter()
+        .map(|outcome| outcome.status)
+        .collect();
+    assert_eq!(statuses, [Status::Failed, Status::Skipped]);
+    assert!(dir.join("A001_C001.RMD").exists());
+    assert!(!dir.join("out").join("A001_C001.RMD").exists());
+}
+
+/// New names are sanitized as with `--sanitize`; Windows refuses the
+/// original name, so this only runs elsewhere.
+#[cfg(unix)]
+#[test]
+fn sanitize_applies_to_new_names() {
+    let dir = scratch("library_sanitize");
+    fs::write(dir.join("A001:C001.NEV"), b"clip").unwrap();
+
+    let mut renamer = Renamer::new(&dir);
+    renamer.set_sanitize(true);
+    let scan = renamer.scan().unwrap();
+    let execution = renamer.execute(&scan).unwrap();
+
+    assert_eq!(execution.converted, 1);
+    assert!(dir.join("A001_C001.R3D").exists());
+}
+
+/// A template ending in `{extensions}` keeps a backup copy's extensions,
+/// and `{clip}` leaves them out.
+#[test]
+fn template_carries_over_the_extension_chain() {
+    let dir = scratch("library_extensions");
+    fs::write(dir.join("A001_C001_0101AB.bak.NEV"), b"clip").unwrap();
+
+    let mut renamer = Renamer::new(&dir);
+    renamer.set_template("{camera}_{clip}{extensions}");
+    let scan = renamer.scan().unwrap();
+    let execution = renamer.execute(&scan).unwrap();
+
+    assert_eq!(execution.converted, 1);
+    assert!(dir.join("A_A001_C001_0101AB.bak.NEV").exists());
+}
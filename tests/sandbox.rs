@@ -0,0 +1,68 @@
+//! `--sandbox` runs end to end, on kernels that have Landlock.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A fresh folder under Cargo's temp directory for tests, which is outside
+/// the system temp directory the sandbox always allows.
+fn scratch(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn r3dy(journals: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_r3dy"))
+        .args(args)
+        .arg("--no-config")
+        .env("R3DY_JOURNAL_DIR", journals)
+        .output()
+        .unwrap()
+}
+
+fn landlock_missing(output: &Output) -> bool {
+    String::from_utf8_lossy(&output.stderr).contains("Landlock is not available")
+}
+
+/// The journal lives outside the tree, so an `--atomic` rollback under
+/// `--sandbox` must still be able to read it back and remove it.
+#[test]
+fn atomic_rolls_back_inside_the_sandbox() {
+    let dir = scratch("atomic_sandbox");
+    let tree = dir.join("card");
+    let journals = dir.join("journal");
+    fs::create_dir(&tree).unwrap();
+    // A and B have a RED header and are renamed first; C fails the check.
+    fs::write(tree.join("A.NEV"), b"\0\0\0\0RED2").unwrap();
+    fs::write(tree.join("B.NEV"), b"\0\0\0\0RED2").unwrap();
+    fs::write(tree.join("C.NEV"), b"junk").unwrap();
+
+    let output = r3dy(
+        &journals,
+        &[
+            tree.to_str().unwrap(),
+            "--sandbox",
+            "--atomic",
+            "--check-headers",
+            "-q",
+        ],
+    );
+    if landlock_missing(&output) {
+        eprintln!("skipped: no Landlock");
+        return;
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.contains("Rolled back 2 renames"), "{}", stderr);
+    let mut names: Vec<_> = fs::read_dir(&tree)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["A.NEV", "B.NEV", "C.NEV"]);
+    assert_eq!(fs::read_dir(&journals).unwrap().count(), 0);
+}
@@ -0,0 +1,91 @@
+//! `r3dy verify` checks a tree against its MHL, once or on a schedule.
+#![cfg(unix)]
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A file that rots after the MHL was written is reported on every sweep,
+/// and Ctrl-C ends the sweeps cleanly.
+#[test]
+fn every_sweep_reports_a_changed_file() {
+    let dir = scratch("verify_every");
+    let card = dir.join("card");
+    fs::create_dir(&card).unwrap();
+    fs::write(card.join("A.NEV"), b"clip").unwrap();
+    let mhl = dir.join("card.mhl");
+    let renamed = Command::new(env!("CARGO_BIN_EXE_r3dy"))
+        .args(["--no-config", "--no-journal", "--mhl"])
+        .arg(&mhl)
+        .arg(&card)
+        .output()
+        .unwrap();
+    assert!(renamed.status.success());
+    fs::write(card.join("A.R3D"), b"clap").unwrap();
+
+    let mut verify = Command::new(env!("CARGO_BIN_EXE_r3dy"))
+        .args(["verify", "--every", "1s", "--mhl"])
+        .arg(&mhl)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(verify.stdout.take().unwrap()).lines();
+    let mut sweeps = 0;
+    while sweeps < 2 {
+        let line = lines.next().unwrap().unwrap();
+        if line.starts_with("Verified 0 of 1 file") && line.contains("mismatched: 1") {
+            sweeps += 1;
+        }
+    }
+
+    let signalled = Command::new("kill")
+        .args(["-INT", &verify.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(signalled.success());
+    // Read on, so a sweep in progress is not cut off by a closed pipe.
+    lines.for_each(drop);
+    assert!(verify.wait().unwrap().success());
+}
+
+/// An MD5 MHL as offload tools write them verifies the files it lists, and
+/// fails once one of them changes.
+#[test]
+fn md5_mhl_round_trip() {
+    let dir = scratch("verify_md5");
+    fs::create_dir(dir.join("A001")).unwrap();
+    fs::write(dir.join("A001").join("A001_C001.R3D"), b"abc").unwrap();
+    let mhl = dir.join("A001.mhl");
+    fs::write(
+        &mhl,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<hashlist version=\"1.1\">\n  <hash>\n    <file>A001/A001_C001.R3D</file>\n    <size>3</size>\n    <md5>900150983CD24FB0D6963F7D28E17F72</md5>\n  </hash>\n</hashlist>\n",
+    )
+    .unwrap();
+    let verify = || {
+        Command::new(env!("CARGO_BIN_EXE_r3dy"))
+            .args(["verify", "--mhl"])
+            .arg(&mhl)
+            .output()
+            .unwrap()
+    };
+
+    let output = verify();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("Verified 1 of 1 file"), "{}", stdout);
+
+    fs::write(dir.join("A001").join("A001_C001.R3D"), b"abd").unwrap();
+    let output = verify();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success(), "{}", stdout);
+    assert!(stdout.contains("mismatched: 1"), "{}", stdout);
+}
@@ -0,0 +1,148 @@
+//! `r3dy watch` stops cleanly when interrupted.
+#![cfg(unix)]
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Waits up to 20 seconds for `done` to hold.
+fn wait_for(what: &str, done: impl Fn() -> bool) {
+    let started = Instant::now();
+    while !done() {
+        assert!(started.elapsed() < Duration::from_secs(20), "{}", what);
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Ctrl-C ends every watch session, so it must release the run lock and
+/// close the journal like the end of a run does.
+#[test]
+fn interrupt_releases_the_lock_and_finishes_the_journal() {
+    let dir = scratch("watch_interrupt");
+    let tree = dir.join("card");
+    let journals = dir.join("journal");
+    fs::create_dir(&tree).unwrap();
+    fs::write(tree.join("A.NEV"), b"clip").unwrap();
+
+    let watch = Command::new(env!("CARGO_BIN_EXE_r3dy"))
+        .args(["watch", "--no-config", "--settle", "1s"])
+        .arg(&tree)
+        .env("R3DY_JOURNAL_DIR", &journals)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let lock = tree.join(".r3dy.lock");
+    wait_for("the clip was not renamed", || tree.join("A.R3D").exists());
+    assert!(lock.exists());
+
+    let signalled = Command::new("kill")
+        .args(["-INT", &watch.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(signalled.success());
+    let output = watch.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+
+    assert!(!lock.exists());
+    let journal = fs::read_dir(&journals)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .next()
+        .unwrap();
+    let text = fs::read_to_string(journal).unwrap();
+    assert!(
+        text.lines().last().unwrap().starts_with("{\"finished\":"),
+        "{}",
+        text
+    );
+}
+
+/// A folder's settle period from the config file applies to the files in
+/// it, and `--settle` to the rest.
+#[test]
+fn folders_settle_for_their_own_period() {
+    let dir = scratch("watch_settle_folders");
+    let tree = dir.join("card");
+    fs::create_dir_all(tree.join("fast")).unwrap();
+    fs::write(tree.join("A.NEV"), b"clip").unwrap();
+    fs::write(tree.join("fast").join("B.NEV"), b"clip").unwrap();
+    fs::write(
+        dir.join(".r3dy.toml"),
+        "settle = [\"1h\", \"card/fast=1s\"]\n",
+    )
+    .unwrap();
+
+    let mut watch = Command::new(env!("CARGO_BIN_EXE_r3dy"))
+        .args(["watch", "card"])
+        .current_dir(&dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("R3DY_JOURNAL_DIR", dir.join("journal"))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    wait_for("the clip in fast was not renamed", || {
+        tree.join("fast").join("B.R3D").exists()
+    });
+    watch.kill().unwrap();
+    watch.wait().unwrap();
+    assert!(tree.join("A.NEV").exists());
+}
+
+/// Files in a folder with a higher `--priority` are renamed first, and a
+/// large drop is renamed in batches until all of it is done.
+#[test]
+fn priority_folders_are_renamed_first() {
+    let dir = scratch("watch_priority");
+    let tree = dir.join("store");
+    fs::create_dir_all(tree.join("archive")).unwrap();
+    fs::create_dir_all(tree.join("cards")).unwrap();
+    for index in 0..150 {
+        fs::write(
+            tree.join("archive").join(format!("{:03}.NEV", index)),
+            b"clip",
+        )
+        .unwrap();
+    }
+    fs::write(tree.join("cards").join("A.NEV"), b"clip").unwrap();
+
+    let mut watch = Command::new(env!("CARGO_BIN_EXE_r3dy"))
+        .args(["watch", "--no-config", "--settle", "1s", "--priority"])
+        .arg(format!("{}=10", tree.join("cards").display()))
+        .arg(&tree)
+        .env("R3DY_JOURNAL_DIR", dir.join("journal"))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(watch.stdout.take().unwrap()).lines();
+    let renamed: Vec<String> = lines
+        .by_ref()
+        .map(|line| line.unwrap())
+        .filter(|line| line.starts_with("Renamed "))
+        .take(151)
+        .collect();
+    assert!(renamed[0].contains("A.NEV"), "{}", renamed[0]);
+    assert!(tree.join("archive").join("149.R3D").exists());
+
+    let signalled = Command::new("kill")
+        .args(["-INT", &watch.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(signalled.success());
+    // Read on, so the last lines are not cut off by a closed pipe.
+    lines.for_each(drop);
+    assert!(watch.wait().unwrap().success());
+}